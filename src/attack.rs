@@ -0,0 +1,59 @@
+//! Adversarial inputs: small perturbations chosen to make a classifier wrong.
+
+use crate::explain::keeping_grads;
+use crate::loss::log_softmax;
+use crate::nn::Module;
+use crate::operators::Value;
+
+/// Fast gradient sign method: moves every feature of `input` by `epsilon` in the direction
+/// that increases the cross-entropy of the logits against class `target`, i.e.
+/// `input + epsilon * sign(d loss / d input)`. Features with a zero gradient stay put.
+/// Parameter gradients are left untouched.
+pub fn fgsm<M: Module>(model: &M, input: &[f64], target: usize, epsilon: f64) -> Vec<f64> {
+    keeping_grads(model, || {
+        let xs: Vec<Value> = input.iter().map(|&x| Value::new(x, "x")).collect();
+        let logits = model.forward(&xs);
+        assert!(target < logits.len(), "target class {} out of range for {} logits", target, logits.len());
+        let loss = -log_softmax(&logits)[target].clone();
+        loss.backward();
+        xs.iter()
+            .zip(input)
+            .map(|(x, &v)| if x.grad() == 0.0 { v } else { v + epsilon * x.grad().signum() })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Activation, Layer};
+    use crate::operators::no_grad;
+    use crate::ops::argmax;
+
+    // Cross-entropy and predicted class, evaluated without recording a graph
+    fn evaluate(model: &Layer, x: &[f64], target: usize) -> (f64, usize) {
+        no_grad(|| {
+            let xs: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+            let logits = model.forward(&xs);
+            (-log_softmax(&logits)[target].data(), argmax(&logits))
+        })
+    }
+
+    #[test]
+    fn perturbation_flips_a_linear_classifier() {
+        // class 0 when x0 > x1
+        let model = Layer::from_weights(&[vec![1.0, -1.0, 0.0], vec![-1.0, 1.0, 0.0]], &[0.0, 0.0], Activation::Linear);
+        let x = [0.6, 0.4, 5.0];
+        let (loss, class) = evaluate(&model, &x, 0);
+        assert_eq!(class, 0);
+
+        let adv = fgsm(&model, &x, 0, 0.15);
+        let expected = [0.45, 0.55, 5.0];
+        assert!(adv.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-12));
+        let (adv_loss, adv_class) = evaluate(&model, &adv, 0);
+        assert!(adv_loss > loss);
+        assert_eq!(adv_class, 1);
+        // nothing was trained along the way
+        assert!(model.parameters().iter().all(|p| p.grad() == 0.0));
+    }
+}
@@ -0,0 +1,200 @@
+//! Classical baselines to compare trained models against: k-means clustering and PCA, on plain
+//! `Vec<f64>` points rather than graph nodes.
+
+use rand::Rng;
+
+fn sq_dist(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn nearest(point: &[f64], centroids: &[Vec<f64>]) -> (usize, f64) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, sq_dist(point, c)))
+        .fold((0, f64::INFINITY), |best, cur| if cur.1 < best.1 { cur } else { best })
+}
+
+#[derive(Debug, Clone)]
+pub struct KMeans {
+    pub centroids: Vec<Vec<f64>>,
+    pub assignments: Vec<usize>,
+    /// Sum of squared distances from each point to its centroid.
+    pub inertia: f64,
+}
+
+impl KMeans {
+    pub fn predict(&self, point: &[f64]) -> usize {
+        nearest(point, &self.centroids).0
+    }
+}
+
+/// Lloyd's algorithm with k-means++ seeding, stopping early once assignments settle. With
+/// `max_iters == 0` every point is assigned to its nearest seed.
+pub fn kmeans<R: Rng>(points: &[Vec<f64>], k: usize, max_iters: usize, rng: &mut R) -> KMeans {
+    assert!(k > 0 && k <= points.len(), "kmeans needs 1 <= k <= number of points");
+    let dim = points[0].len();
+
+    let mut centroids = vec![points[rng.gen_range(0..points.len())].clone()];
+    while centroids.len() < k {
+        let weights: Vec<f64> = points.iter().map(|p| nearest(p, &centroids).1).collect();
+        let total: f64 = weights.iter().sum();
+        let next = if total == 0.0 {
+            rng.gen_range(0..points.len())
+        } else {
+            let mut target = rng.gen_range(0.0..total);
+            weights.iter().position(|w| { target -= w; target < 0.0 }).unwrap_or(points.len() - 1)
+        };
+        centroids.push(points[next].clone());
+    }
+
+    let assign = |centroids: &[Vec<f64>]| -> Vec<usize> { points.iter().map(|p| nearest(p, centroids).0).collect() };
+    let mut assignments = assign(&centroids);
+    for _ in 0..max_iters {
+        let mut sums = vec![vec![0.0; dim]; k];
+        let mut counts = vec![0usize; k];
+        for (p, &a) in points.iter().zip(&assignments) {
+            counts[a] += 1;
+            sums[a].iter_mut().zip(p).for_each(|(s, x)| *s += x);
+        }
+        for (c, (sum, count)) in centroids.iter_mut().zip(sums.into_iter().zip(counts)) {
+            // an empty cluster keeps its old centroid
+            if count > 0 {
+                *c = sum.into_iter().map(|s| s / count as f64).collect();
+            }
+        }
+
+        let next = assign(&centroids);
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+    }
+
+    let inertia = points.iter().zip(&assignments).map(|(p, &a)| sq_dist(p, &centroids[a])).sum();
+    KMeans { centroids, assignments, inertia }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pca {
+    pub mean: Vec<f64>,
+    /// Unit-length principal directions, largest variance first.
+    pub components: Vec<Vec<f64>>,
+    pub explained_variance: Vec<f64>,
+}
+
+impl Pca {
+    /// Coordinates of `point` along each principal component.
+    pub fn transform(&self, point: &[f64]) -> Vec<f64> {
+        self.components
+            .iter()
+            .map(|c| c.iter().zip(point.iter().zip(&self.mean)).map(|(w, (x, m))| w * (x - m)).sum())
+            .collect()
+    }
+}
+
+/// Top `n_components` principal components, found by power iteration with deflation on the
+/// covariance matrix. Fine for the few hundred dimensions of the bundled datasets.
+pub fn pca(points: &[Vec<f64>], n_components: usize) -> Pca {
+    assert!(points.len() > 1, "pca needs at least two points");
+    let dim = points[0].len();
+    assert!(n_components <= dim, "more components than dimensions");
+    let n = points.len() as f64;
+
+    let mean: Vec<f64> = (0..dim).map(|j| points.iter().map(|p| p[j]).sum::<f64>() / n).collect();
+    let mut cov = vec![vec![0.0; dim]; dim];
+    for p in points {
+        let centered: Vec<f64> = p.iter().zip(&mean).map(|(x, m)| x - m).collect();
+        for (row, ci) in cov.iter_mut().zip(&centered) {
+            for (c, cj) in row.iter_mut().zip(&centered) {
+                *c += ci * cj / (n - 1.0);
+            }
+        }
+    }
+
+    let mut components = Vec::with_capacity(n_components);
+    let mut explained_variance = Vec::with_capacity(n_components);
+    for _ in 0..n_components {
+        // any fixed start works unless it is exactly orthogonal to the component
+        let mut v: Vec<f64> = (0..dim).map(|i| 1.0 / (i + 1) as f64).collect();
+        let mut eigenvalue = 0.0;
+        for _ in 0..500 {
+            let w: Vec<f64> = cov.iter().map(|row| row.iter().zip(&v).map(|(a, b)| a * b).sum()).collect();
+            let norm = w.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm == 0.0 {
+                break;
+            }
+            let next: Vec<f64> = w.iter().map(|x| x / norm).collect();
+            let converged = sq_dist(&next, &v) < 1e-20;
+            v = next;
+            eigenvalue = norm;
+            if converged {
+                break;
+            }
+        }
+        // deflate so the next round finds the next direction
+        for (row, vi) in cov.iter_mut().zip(&v) {
+            for (c, vj) in row.iter_mut().zip(&v) {
+                *c -= eigenvalue * vi * vj;
+            }
+        }
+        components.push(v);
+        explained_variance.push(eigenvalue);
+    }
+
+    Pca { mean, components, explained_variance }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn kmeans_separates_blobs() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut points = Vec::new();
+        for center in [[0.0, 0.0], [10.0, 10.0], [-10.0, 10.0]] {
+            for _ in 0..30 {
+                points.push(vec![center[0] + rng.gen_range(-1.0..1.0), center[1] + rng.gen_range(-1.0..1.0)]);
+            }
+        }
+
+        let km = kmeans(&points, 3, 50, &mut rng);
+        for blob in points.chunks(30).zip(km.assignments.chunks(30)) {
+            assert!(blob.1.iter().all(|&a| a == blob.1[0]));
+        }
+        assert!(km.inertia < 90.0 * 2.0);
+        assert_eq!(km.predict(&[9.5, 10.5]), km.assignments[30]);
+
+        // no iterations: the k-means++ seeds, each point with its nearest one
+        let seeded = kmeans(&points, 3, 0, &mut rng);
+        assert!(points.iter().zip(&seeded.assignments).all(|(p, &a)| seeded.predict(p) == a));
+        assert!(seeded.inertia.is_finite());
+    }
+
+    #[test]
+    fn pca_finds_the_dominant_direction() {
+        let mut rng = StdRng::seed_from_u64(2);
+        // points spread along (1, 1) with a little noise across it
+        let points: Vec<Vec<f64>> = (0..200)
+            .map(|_| {
+                let t: f64 = rng.gen_range(-5.0..5.0);
+                let e: f64 = rng.gen_range(-0.1..0.1);
+                vec![t + e, t - e]
+            })
+            .collect();
+
+        let p = pca(&points, 2);
+        let c = &p.components[0];
+        assert!((c[0].abs() - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-3);
+        assert!(c[0] * c[1] > 0.0);
+        assert!(p.explained_variance[0] > 100.0 * p.explained_variance[1]);
+
+        let origin = p.transform(&p.mean);
+        assert!(origin.iter().all(|x| x.abs() < 1e-12));
+        let shifted: Vec<f64> = p.mean.iter().zip(c).map(|(m, c)| m + 3.0 * c).collect();
+        assert!((p.transform(&shifted)[0] - 3.0).abs() < 1e-9);
+    }
+}
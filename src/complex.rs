@@ -0,0 +1,185 @@
+//! Complex numbers on top of the real graph: a `CValue` is a pair of `Value`s for its real and
+//! imaginary parts, so complex arithmetic builds ordinary graph nodes and `backward` from any
+//! real loss works unchanged.
+//!
+//! Gradients follow the Wirtinger convention used by most autodiff libraries: for a real loss
+//! `L`, `CValue::grad` is `dL/dre + i dL/dim = 2 dL/dz̄`, the direction of steepest ascent, so
+//! `z -= lr * grad` is gradient descent just as for real parameters.
+
+use crate::operators::Value;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+#[derive(Debug, Clone)]
+pub struct CValue {
+    re: Value,
+    im: Value,
+}
+
+impl CValue {
+    /// A complex leaf; its parts are labelled `{label}.re` and `{label}.im`.
+    pub fn new(re: f64, im: f64, label: &str) -> Self {
+        CValue { re: Value::new(re, &format!("{}.re", label)), im: Value::new(im, &format!("{}.im", label)) }
+    }
+
+    pub fn from_parts(re: Value, im: Value) -> Self {
+        CValue { re, im }
+    }
+
+    pub fn re(&self) -> &Value {
+        &self.re
+    }
+
+    pub fn im(&self) -> &Value {
+        &self.im
+    }
+
+    pub fn data(&self) -> (f64, f64) {
+        (self.re.data(), self.im.data())
+    }
+
+    /// `dL/dre + i dL/dim` of the loss last backpropagated, see the module docs.
+    pub fn grad(&self) -> (f64, f64) {
+        (self.re.grad(), self.im.grad())
+    }
+
+    /// Sets both parts, e.g. for a hand-written optimizer step.
+    pub fn set_data(&self, re: f64, im: f64) {
+        self.re.set_data(re);
+        self.im.set_data(im);
+    }
+
+    pub fn conj(&self) -> CValue {
+        CValue::from_parts(self.re.clone(), -self.im.clone())
+    }
+
+    /// `|z|^2`, a real value and the usual way into a real loss.
+    pub fn norm_sqr(&self) -> Value {
+        self.re.clone() * self.re.clone() + self.im.clone() * self.im.clone()
+    }
+
+    /// `e^z = e^re (cos im + i sin im)`, as two ops over both parts.
+    pub fn exp(&self) -> CValue {
+        let (a, b) = self.data();
+        let ea = crate::math::exp(a);
+        let parents = [&self.re, &self.im];
+        // sin and cos are the platform's, also under `deterministic-math`
+        let re = Value::from_op(ea * b.cos(), "cexp.re", &parents, |out, g, xs| {
+            vec![g * out, -g * crate::math::exp(xs[0]) * xs[1].sin()]
+        })
+        .with_forward(|xs| crate::math::exp(xs[0]) * xs[1].cos());
+        let im = Value::from_op(ea * b.sin(), "cexp.im", &parents, |out, g, xs| {
+            vec![g * out, g * crate::math::exp(xs[0]) * xs[1].cos()]
+        })
+        .with_forward(|xs| crate::math::exp(xs[0]) * xs[1].sin());
+        CValue::from_parts(re, im)
+    }
+}
+
+impl From<(f64, f64)> for CValue {
+    fn from((re, im): (f64, f64)) -> Self {
+        CValue::from_parts(Value::from(re), Value::from(im))
+    }
+}
+
+impl fmt::Display for CValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (re, im) = self.data();
+        if im < 0.0 { write!(f, "{}-{}i", re, -im) } else { write!(f, "{}+{}i", re, im) }
+    }
+}
+
+impl Add for CValue {
+    type Output = CValue;
+
+    fn add(self, other: CValue) -> CValue {
+        CValue::from_parts(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for CValue {
+    type Output = CValue;
+
+    fn sub(self, other: CValue) -> CValue {
+        CValue::from_parts(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for CValue {
+    type Output = CValue;
+
+    // (a + bi)(c + di) = (ac - bd) + (ad + bc)i
+    fn mul(self, other: CValue) -> CValue {
+        let (a, b, c, d) = (self.re, self.im, other.re, other.im);
+        CValue::from_parts(a.clone() * c.clone() - b.clone() * d.clone(), a * d + b * c)
+    }
+}
+
+impl Mul<f64> for CValue {
+    type Output = CValue;
+
+    fn mul(self, k: f64) -> CValue {
+        CValue::from_parts(self.re * k, self.im * k)
+    }
+}
+
+impl Neg for CValue {
+    type Output = CValue;
+
+    fn neg(self) -> CValue {
+        CValue::from_parts(-self.re, -self.im)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12
+    }
+
+    // Complex product of plain pairs, for the expected values
+    fn cmul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+        (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+    }
+
+    #[test]
+    fn wirtinger_gradients_of_add_mul_and_exp() {
+        let (z, w) = (CValue::new(0.5, -1.5, "z"), CValue::new(2.0, 0.25, "w"));
+        let t = CValue::from((1.0, 1.0));
+        // L = |z w + t|^2 has grad 2 (z w + t) conj(w) with respect to z
+        let r = z.clone() * w.clone() + t;
+        r.norm_sqr().backward();
+        let expected = cmul(cmul((2.0, 0.0), r.data()), w.conj().data());
+        assert!(close(z.grad(), expected), "{:?} vs {:?}", z.grad(), expected);
+
+        // L = re(e^z) has grad conj(e^z), since e^z is holomorphic
+        let z = CValue::new(0.3, 0.7, "z");
+        let e = z.exp();
+        assert!(close(e.data(), (0.3f64.exp() * 0.7f64.cos(), 0.3f64.exp() * 0.7f64.sin())));
+        e.re().backward();
+        assert!(close(z.grad(), e.conj().data()));
+        assert_eq!(e.to_string(), format!("{}+{}i", e.data().0, e.data().1));
+    }
+
+    #[test]
+    fn gradient_descent_finds_a_complex_gain() {
+        // a filter that rotates and scales a signal; recover its gain from input/output pairs
+        let gain = (0.6, -0.8);
+        let signal: Vec<(f64, f64)> = (0..8).map(|k| ((k as f64 * 0.7).cos(), (k as f64 * 0.7).sin())).collect();
+        let w = CValue::new(0.0, 0.0, "w");
+        for _ in 0..100 {
+            let loss: Value = signal
+                .iter()
+                .map(|&x| (w.clone() * CValue::from(x) - CValue::from(cmul(gain, x))).norm_sqr())
+                .sum();
+            w.re().set_grad(0.0);
+            w.im().set_grad(0.0);
+            loss.backward();
+            let (g, (re, im)) = (w.grad(), w.data());
+            w.set_data(re - 0.05 * g.0, im - 0.05 * g.1);
+        }
+        assert!(close(w.data(), gain), "{}", w);
+    }
+}
@@ -0,0 +1,153 @@
+//! User-defined scalar ops. A `CustomOp` is a forward function over its parents' data plus,
+//! ideally, a backward rule; while prototyping the backward can be left out and estimated by
+//! central differences of the forward instead, inside `numeric_gradient_fallback`.
+
+use crate::operators::{is_grad_enabled, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+
+thread_local! {
+    // Finite-difference step for custom ops without a backward, when the fallback is on
+    static FALLBACK_EPS: Cell<Option<f64>> = const { Cell::new(None) };
+    // Ops already warned about, so each is reported once per thread
+    static WARNED: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Runs `f` with the numeric gradient fallback on: custom ops applied inside that have no
+/// backward rule get one that takes central differences of their forward with step `eps`,
+/// and a warning on stderr the first time each op does. Applies to the current thread only;
+/// nests.
+pub fn numeric_gradient_fallback<R>(eps: f64, f: impl FnOnce() -> R) -> R {
+    assert!(eps > 0.0, "eps must be positive");
+    struct Restore(Option<f64>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            FALLBACK_EPS.with(|e| e.set(self.0));
+        }
+    }
+    let _restore = Restore(FALLBACK_EPS.with(|e| e.replace(Some(eps))));
+    f()
+}
+
+/// The fallback step on this thread, if inside `numeric_gradient_fallback`.
+pub fn numeric_fallback_eps() -> Option<f64> {
+    FALLBACK_EPS.with(|e| e.get())
+}
+
+type Forward = Rc<dyn Fn(&[f64]) -> f64>;
+type Backward = Rc<dyn Fn(f64, f64, &[f64]) -> Vec<f64>>;
+
+/// A scalar op defined outside the crate, e.g. `softsign(x) = x / (1 + |x|)`.
+#[derive(Clone)]
+pub struct CustomOp {
+    name: String,
+    forward: Forward,
+    backward: Option<Backward>,
+}
+
+impl CustomOp {
+    /// Op computing its output from the parents' data with `forward`.
+    pub fn new(name: &str, forward: impl Fn(&[f64]) -> f64 + 'static) -> Self {
+        CustomOp { name: name.to_string(), forward: Rc::new(forward), backward: None }
+    }
+
+    /// The analytic backward rule: given the output's data and grad and the parents' data,
+    /// each parent's gradient contribution in order.
+    pub fn with_backward(mut self, backward: impl Fn(f64, f64, &[f64]) -> Vec<f64> + 'static) -> Self {
+        self.backward = Some(Rc::new(backward));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Output node of the op on `parents`. Without a backward rule this panics unless the graph
+    /// is not being recorded (`no_grad`) or the numeric fallback is on.
+    pub fn apply(&self, parents: &[&Value]) -> Value {
+        let datas: Vec<f64> = parents.iter().map(|p| p.data()).collect();
+        let data = (self.forward)(&datas);
+        let forward = self.forward.clone();
+        let backward = match (&self.backward, numeric_fallback_eps()) {
+            (Some(backward), _) => backward.clone(),
+            (None, Some(eps)) => {
+                if is_grad_enabled() && WARNED.with(|w| w.borrow_mut().insert(self.name.clone())) {
+                    eprintln!("warning: custom op {} has no backward, using finite differences (eps = {})", self.name, eps);
+                }
+                let forward = forward.clone();
+                Rc::new(move |_, grad, xs: &[f64]| central_differences(&*forward, xs, eps, grad))
+            }
+            // never called: `no_grad` drops the backward of the output
+            (None, None) if !is_grad_enabled() => Rc::new(|_, _, xs: &[f64]| vec![0.0; xs.len()]),
+            (None, None) => panic!(
+                "custom op {} has no backward; add one with `with_backward` or apply it inside `numeric_gradient_fallback`",
+                self.name
+            ),
+        };
+        Value::from_op(data, &self.name, parents, move |out, grad, xs| backward(out, grad, xs))
+            .with_forward(move |xs| forward(xs))
+    }
+}
+
+// `grad` times the central difference of `f` along each input
+fn central_differences(f: &dyn Fn(&[f64]) -> f64, xs: &[f64], eps: f64, grad: f64) -> Vec<f64> {
+    let mut point = xs.to_vec();
+    (0..xs.len())
+        .map(|i| {
+            point[i] = xs[i] + eps;
+            let up = f(&point);
+            point[i] = xs[i] - eps;
+            let down = f(&point);
+            point[i] = xs[i];
+            grad * (up - down) / (2.0 * eps)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::no_grad;
+
+    fn softsign(xs: &[f64]) -> f64 {
+        xs[0] / (1.0 + xs[0].abs())
+    }
+
+    fn grads(op: &CustomOp, xs: &[f64]) -> Vec<f64> {
+        let inputs: Vec<Value> = xs.iter().map(|&x| Value::new(x, "x")).collect();
+        let out = op.apply(&inputs.iter().collect::<Vec<_>>()) * 3.0;
+        out.backward();
+        inputs.iter().map(|x| x.grad()).collect()
+    }
+
+    #[test]
+    fn numeric_fallback_matches_the_analytic_backward() {
+        let analytic = CustomOp::new("softsign", softsign).with_backward(|_, g, xs| vec![g / (1.0 + xs[0].abs()).powi(2)]);
+        let numeric = CustomOp::new("softsign", softsign);
+        for x in [-2.0, -0.3, 0.7, 4.0] {
+            let expected = grads(&analytic, &[x]);
+            let estimated = numeric_gradient_fallback(1e-5, || grads(&numeric, &[x]));
+            assert!((expected[0] - estimated[0]).abs() < 1e-8, "{:?} vs {:?}", expected, estimated);
+        }
+
+        // several inputs, and the op replays like any other
+        let hypot = CustomOp::new("hypot", |xs| xs[0].hypot(xs[1]));
+        let g = numeric_gradient_fallback(1e-6, || grads(&hypot, &[3.0, 4.0]));
+        assert!((g[0] - 3.0 * 0.6).abs() < 1e-6 && (g[1] - 3.0 * 0.8).abs() < 1e-6);
+        let (a, b) = (Value::new(3.0, "a"), Value::new(4.0, "b"));
+        let out = numeric_gradient_fallback(1e-6, || hypot.apply(&[&a, &b]));
+        a.set_data(6.0);
+        assert!(out.recompute());
+        assert_eq!(out.data(), 6.0f64.hypot(4.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "custom op softsign has no backward")]
+    fn missing_backward_panics_outside_the_fallback() {
+        let op = CustomOp::new("softsign", softsign);
+        // fine while nothing is recorded
+        assert_eq!(no_grad(|| op.apply(&[&Value::from(1.0)])).data(), 0.5);
+        op.apply(&[&Value::from(1.0)]);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::transform::Transform;
+use rand::SeedableRng;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::collections::BTreeMap;
+
+/// Inputs and targets held as plain floats; converted to Values only when a batch is used.
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    pub inputs: Vec<Vec<f64>>,
+    pub targets: Vec<Vec<f64>>,
+}
+
+impl Dataset {
+    pub fn new(inputs: Vec<Vec<f64>>, targets: Vec<Vec<f64>>) -> Self {
+        assert_eq!(inputs.len(), targets.len(), "inputs and targets differ in length");
+        Dataset { inputs, targets }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn get(&self, i: usize) -> (&[f64], &[f64]) {
+        (&self.inputs[i], &self.targets[i])
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    pub inputs: Vec<Vec<f64>>,
+    pub targets: Vec<Vec<f64>>,
+}
+
+impl Batch {
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+/// Scores how hard a sample `(input, target)` is; lower is easier.
+pub type DifficultyFn = Box<dyn Fn(&[f64], &[f64]) -> f64>;
+
+/// How samples are drawn for an epoch when classes are imbalanced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sampling {
+    /// Every sample once.
+    Uniform,
+    /// As many draws as samples, with replacement, each sample weighted by the inverse of its
+    /// class frequency so every class is drawn equally often on average.
+    Weighted,
+    /// Every sample once, plus repeats of minority-class samples until every class is as
+    /// large as the biggest one.
+    Oversample,
+}
+
+/// Maps a sample's target to its class.
+pub type ClassFn = Box<dyn Fn(&[f64]) -> usize>;
+
+struct Curriculum {
+    score: DifficultyFn,
+    warmup_epochs: usize,
+}
+
+/// Splits a dataset into mini-batches, one epoch at a time.
+pub struct DataLoader {
+    dataset: Dataset,
+    batch_size: usize,
+    shuffle: bool,
+    rng: StdRng,
+    epoch: usize,
+    curriculum: Option<Curriculum>,
+    sampling: Sampling,
+    class_of: Option<ClassFn>,
+    transform: Option<(Box<dyn Transform>, StdRng)>,
+}
+
+impl DataLoader {
+    pub fn new(dataset: Dataset, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be positive");
+        DataLoader {
+            dataset,
+            batch_size,
+            shuffle: false,
+            rng: StdRng::seed_from_u64(0),
+            epoch: 0,
+            curriculum: None,
+            sampling: Sampling::Uniform,
+            class_of: None,
+            transform: None,
+        }
+    }
+
+    /// Augments every served input with `transform`, drawing fresh randomness each epoch from
+    /// an RNG seeded with `seed`. The dataset itself is left untouched.
+    pub fn with_transform(mut self, transform: impl Transform + 'static, seed: u64) -> Self {
+        self.transform = Some((Box::new(transform), StdRng::seed_from_u64(seed)));
+        self
+    }
+
+    /// Rebalances each epoch according to `sampling`, with classes read off the targets by `class_of`.
+    pub fn with_sampling(mut self, sampling: Sampling, class_of: impl Fn(&[f64]) -> usize + 'static) -> Self {
+        self.sampling = sampling;
+        self.class_of = Some(Box::new(class_of));
+        self
+    }
+
+    /// Shuffles the sample order every epoch, reproducibly from `seed`.
+    pub fn shuffle(mut self, seed: u64) -> Self {
+        self.shuffle = true;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Curriculum learning: samples are ranked by `score` (easiest first) and phased in over
+    /// `warmup_epochs`, so epoch `e` only sees the easiest `(e + 1) / warmup_epochs` of the data.
+    /// Without shuffling the phased-in samples are served easiest first.
+    pub fn with_curriculum(mut self, score: impl Fn(&[f64], &[f64]) -> f64 + 'static, warmup_epochs: usize) -> Self {
+        self.curriculum = Some(Curriculum { score: Box::new(score), warmup_epochs: warmup_epochs.max(1) });
+        self
+    }
+
+    pub fn dataset(&self) -> &Dataset {
+        &self.dataset
+    }
+
+    /// Number of epochs served so far.
+    pub fn epochs_served(&self) -> usize {
+        self.epoch
+    }
+
+    // Sample indices for the current epoch, before batching
+    fn epoch_order(&mut self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.dataset.len()).collect();
+
+        if let Some(c) = &self.curriculum {
+            let scores: Vec<f64> = (0..self.dataset.len())
+                .map(|i| {
+                    let (x, y) = self.dataset.get(i);
+                    (c.score)(x, y)
+                })
+                .collect();
+            order.sort_by(|&a, &b| scores[a].total_cmp(&scores[b]));
+            let frac = ((self.epoch + 1) as f64 / c.warmup_epochs as f64).min(1.0);
+            order.truncate(((frac * order.len() as f64).ceil() as usize).max(1));
+        }
+
+        if let Some(class_of) = &self.class_of {
+            let mut by_class: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+            for &i in &order {
+                by_class.entry(class_of(&self.dataset.targets[i])).or_default().push(i);
+            }
+
+            match self.sampling {
+                Sampling::Uniform => {}
+                Sampling::Weighted => {
+                    let weights: Vec<f64> = order
+                        .iter()
+                        .map(|&i| 1.0 / by_class[&class_of(&self.dataset.targets[i])].len() as f64)
+                        .collect();
+                    if let Ok(dist) = WeightedIndex::new(&weights) {
+                        order = (0..order.len()).map(|_| order[dist.sample(&mut self.rng)]).collect();
+                    }
+                }
+                Sampling::Oversample => {
+                    let largest = by_class.values().map(|c| c.len()).max().unwrap_or(0);
+                    order = by_class.values().flat_map(|c| c.iter().cycle().take(largest).copied()).collect();
+                }
+            }
+        }
+
+        if self.shuffle {
+            order.shuffle(&mut self.rng);
+        }
+        order
+    }
+
+    /// Batches for the next epoch.
+    pub fn next_epoch(&mut self) -> Vec<Batch> {
+        let order = self.epoch_order();
+        self.epoch += 1;
+
+        let mut batches: Vec<Batch> = order
+            .chunks(self.batch_size)
+            .map(|idx| Batch {
+                inputs: idx.iter().map(|&i| self.dataset.inputs[i].clone()).collect(),
+                targets: idx.iter().map(|&i| self.dataset.targets[i].clone()).collect(),
+            })
+            .collect();
+
+        if let Some((transform, rng)) = &mut self.transform {
+            for x in batches.iter_mut().flat_map(|b| b.inputs.iter_mut()) {
+                transform.apply(x, rng);
+            }
+        }
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(n: usize) -> Dataset {
+        let xs: Vec<Vec<f64>> = (0..n).map(|i| vec![i as f64]).collect();
+        let ys = xs.iter().map(|x| vec![2.0 * x[0]]).collect();
+        Dataset::new(xs, ys)
+    }
+
+    #[test]
+    fn batches_cover_the_dataset() {
+        let mut loader = DataLoader::new(line(10), 4).shuffle(3);
+        let batches = loader.next_epoch();
+        assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![4, 4, 2]);
+        let mut seen: Vec<f64> = batches.iter().flat_map(|b| b.inputs.iter().map(|x| x[0])).collect();
+        seen.sort_by(f64::total_cmp);
+        assert_eq!(seen, (0..10).map(|i| i as f64).collect::<Vec<_>>());
+        assert_eq!(loader.epochs_served(), 1);
+    }
+
+    #[test]
+    fn curriculum_phases_in_hard_samples() {
+        // difficulty is the distance from 4.5, so the middle samples come first
+        let mut loader = DataLoader::new(line(10), 100).with_curriculum(|x, _| (x[0] - 4.5).abs(), 5);
+
+        let first = loader.next_epoch();
+        assert_eq!(first[0].inputs, vec![vec![4.0], vec![5.0]]);
+        assert_eq!(loader.next_epoch()[0].len(), 4);
+        loader.next_epoch();
+        loader.next_epoch();
+        let full = loader.next_epoch();
+        assert_eq!(full[0].len(), 10);
+        // the two hardest samples come last, ties in dataset order
+        assert_eq!(full[0].inputs[8..], [vec![0.0], vec![9.0]]);
+    }
+
+    fn imbalanced() -> Dataset {
+        // 18 samples of class 0, 2 of class 1
+        let xs: Vec<Vec<f64>> = (0..20).map(|i| vec![i as f64]).collect();
+        let ys = (0..20).map(|i| vec![if i < 18 { 0.0 } else { 1.0 }]).collect();
+        Dataset::new(xs, ys)
+    }
+
+    fn class_counts(batches: &[Batch]) -> [usize; 2] {
+        let mut counts = [0; 2];
+        for y in batches.iter().flat_map(|b| &b.targets) {
+            counts[y[0] as usize] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn oversampling_balances_classes() {
+        let mut loader = DataLoader::new(imbalanced(), 8).shuffle(0).with_sampling(Sampling::Oversample, |y| y[0] as usize);
+        assert_eq!(class_counts(&loader.next_epoch()), [18, 18]);
+    }
+
+    #[test]
+    fn weighted_sampling_draws_classes_evenly() {
+        let mut loader = DataLoader::new(imbalanced(), 8).with_sampling(Sampling::Weighted, |y| y[0] as usize);
+        let mut totals = [0; 2];
+        for _ in 0..50 {
+            let counts = class_counts(&loader.next_epoch());
+            assert_eq!(counts[0] + counts[1], 20);
+            totals[0] += counts[0];
+            totals[1] += counts[1];
+        }
+        let minority_share = totals[1] as f64 / 1000.0;
+        assert!((minority_share - 0.5).abs() < 0.1, "minority share {}", minority_share);
+    }
+
+    #[test]
+    fn transforms_are_reproducible_and_fresh_each_epoch() {
+        use crate::transform::{Jitter, Pipeline, Rotate};
+        let make = || {
+            DataLoader::new(line(6), 6).with_transform(Pipeline::new().then(Jitter { amount: 0.5 }), 42)
+        };
+        let (mut a, mut b) = (make(), make());
+        let first = a.next_epoch();
+        assert_eq!(first, b.next_epoch());
+        assert_ne!(first, a.next_epoch());
+        assert_ne!(first[0].inputs, line(6).inputs);
+        // targets are never augmented
+        assert_eq!(first[0].targets, line(6).targets);
+
+        let points = Dataset::new(vec![vec![1.0, 0.0]], vec![vec![0.0]]);
+        let mut rotated = DataLoader::new(points, 1).with_transform(Rotate { max_angle: 0.5 }, 1);
+        let p = &rotated.next_epoch()[0].inputs[0];
+        assert!((p[0].hypot(p[1]) - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,290 @@
+use crate::data::Dataset;
+use crate::noise::gaussian;
+use crate::tensor::Tensor;
+use rand::Rng;
+use rand::seq::index;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io(e) => write!(f, "i/o error: {}", e),
+            DatasetError::Format(msg) => write!(f, "malformed dataset: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+impl From<std::io::Error> for DatasetError {
+    fn from(e: std::io::Error) -> Self {
+        DatasetError::Io(e)
+    }
+}
+
+/// An array read from an IDX file: its dimensions and the raw unsigned bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdxArray {
+    pub dims: Vec<usize>,
+    pub data: Vec<u8>,
+}
+
+/// Parses the IDX format used by MNIST. Only the unsigned byte element type is supported.
+pub fn parse_idx(bytes: &[u8]) -> Result<IdxArray, DatasetError> {
+    if bytes.len() < 4 || bytes[0] != 0 || bytes[1] != 0 {
+        return Err(DatasetError::Format("missing IDX magic number".into()));
+    }
+    if bytes[2] != 0x08 {
+        return Err(DatasetError::Format(format!("unsupported IDX element type 0x{:02x}", bytes[2])));
+    }
+
+    let ndims = bytes[3] as usize;
+    let header = 4 + 4 * ndims;
+    if bytes.len() < header {
+        return Err(DatasetError::Format("truncated IDX header".into()));
+    }
+    let dims: Vec<usize> = bytes[4..header]
+        .chunks(4)
+        .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]) as usize)
+        .collect();
+
+    let len = dims
+        .iter()
+        .try_fold(1usize, |len, &d| len.checked_mul(d))
+        .ok_or_else(|| DatasetError::Format(format!("IDX dimensions {:?} overflow", dims)))?;
+    if bytes.len() - header != len {
+        return Err(DatasetError::Format(format!(
+            "IDX body has {} bytes, dimensions {:?} need {}", bytes.len() - header, dims, len
+        )));
+    }
+    Ok(IdxArray { dims, data: bytes[header..].to_vec() })
+}
+
+/// MNIST-style images with pixels scaled to `[0, 1]`, one flattened row-major image per entry.
+#[derive(Debug, Clone)]
+pub struct Mnist {
+    pub images: Vec<Vec<f64>>,
+    pub labels: Vec<u8>,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl Mnist {
+    /// Loads a pair of (uncompressed) IDX image and label files.
+    pub fn load(images_path: impl AsRef<Path>, labels_path: impl AsRef<Path>) -> Result<Self, DatasetError> {
+        let images = parse_idx(&fs::read(images_path)?)?;
+        let labels = parse_idx(&fs::read(labels_path)?)?;
+
+        if images.dims.len() != 3 || labels.dims.len() != 1 {
+            return Err(DatasetError::Format("expected 3-D images and 1-D labels".into()));
+        }
+        if images.dims[0] != labels.dims[0] {
+            return Err(DatasetError::Format(format!(
+                "{} images but {} labels", images.dims[0], labels.dims[0]
+            )));
+        }
+
+        let (rows, cols) = (images.dims[1], images.dims[2]);
+        if rows * cols == 0 {
+            return Err(DatasetError::Format(format!("empty {}x{} images", rows, cols)));
+        }
+        let images = images
+            .data
+            .chunks(rows * cols)
+            .map(|img| img.iter().map(|&p| p as f64 / 255.0).collect())
+            .collect();
+        Ok(Mnist { images, labels: labels.data, rows, cols })
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Standardizes every pixel with the given mean and standard deviation
+    /// (0.1307 and 0.3081 for the MNIST training set).
+    pub fn normalize(&mut self, mean: f64, std: f64) {
+        for img in &mut self.images {
+            img.iter_mut().for_each(|p| *p = (*p - mean) / std);
+        }
+    }
+
+    /// A random subset of `n` samples (all of them if `n` is larger than the set), in random order.
+    pub fn subset<R: Rng>(&self, n: usize, rng: &mut R) -> Mnist {
+        let picked = index::sample(rng, self.len(), n.min(self.len()));
+        Mnist {
+            images: picked.iter().map(|i| self.images[i].clone()).collect(),
+            labels: picked.iter().map(|i| self.labels[i]).collect(),
+            rows: self.rows,
+            cols: self.cols,
+        }
+    }
+
+    /// All images as a `[n, rows * cols]` tensor.
+    pub fn images_tensor(&self) -> Tensor {
+        let data: Vec<f64> = self.images.iter().flatten().copied().collect();
+        Tensor::new(data, &[self.len(), self.rows * self.cols])
+    }
+
+    /// One-hot encoded labels as a `[n, 10]` tensor. Fails on a label that is not a digit.
+    pub fn labels_one_hot(&self) -> Result<Tensor, DatasetError> {
+        let mut data = vec![0.0; self.len() * 10];
+        for (i, &l) in self.labels.iter().enumerate() {
+            if l >= 10 {
+                return Err(DatasetError::Format(format!("label {} of sample {} is not a digit", l, i)));
+            }
+            data[i * 10 + l as usize] = 1.0;
+        }
+        Ok(Tensor::new(data, &[self.len(), 10]))
+    }
+}
+
+/// Loads the MNIST training set from `dir`, which must hold the uncompressed
+/// `train-images-idx3-ubyte` and `train-labels-idx1-ubyte` files. Nothing is downloaded.
+pub fn mnist(dir: impl AsRef<Path>) -> Result<Mnist, DatasetError> {
+    let dir = dir.as_ref();
+    Mnist::load(dir.join("train-images-idx3-ubyte"), dir.join("train-labels-idx1-ubyte"))
+}
+
+/// Same as `mnist`, but for the `t10k-*` test files.
+pub fn mnist_test(dir: impl AsRef<Path>) -> Result<Mnist, DatasetError> {
+    let dir = dir.as_ref();
+    Mnist::load(dir.join("t10k-images-idx3-ubyte"), dir.join("t10k-labels-idx1-ubyte"))
+}
+
+/// Gaussian blobs, one per class, with centers spaced evenly on a circle of radius 3.
+/// `counts[c]` samples are drawn for class `c`, so unequal counts give an imbalanced set.
+/// Inputs are 2-D points, targets the class index as a single float.
+pub fn blobs<R: Rng>(counts: &[usize], std: f64, rng: &mut R) -> Dataset {
+    let mut inputs = Vec::new();
+    let mut targets = Vec::new();
+    for (class, &count) in counts.iter().enumerate() {
+        let angle = 2.0 * std::f64::consts::PI * class as f64 / counts.len() as f64;
+        let center = [3.0 * angle.cos(), 3.0 * angle.sin()];
+        for _ in 0..count {
+            inputs.push(vec![center[0] + std * gaussian(rng), center[1] + std * gaussian(rng)]);
+            targets.push(vec![class as f64]);
+        }
+    }
+    Dataset::new(inputs, targets)
+}
+
+/// Two interleaving half circles ("moons"), `n` points each, with gaussian noise of standard
+/// deviation `noise`. Targets are `1.0` for the upper moon and `-1.0` for the lower one.
+pub fn moons<R: Rng>(n: usize, noise: f64, rng: &mut R) -> Dataset {
+    let mut inputs = Vec::with_capacity(2 * n);
+    let mut targets = Vec::with_capacity(2 * n);
+    for i in 0..n {
+        let t = std::f64::consts::PI * i as f64 / (n.max(2) - 1) as f64;
+        inputs.push(vec![t.cos() + noise * gaussian(rng), t.sin() + noise * gaussian(rng)]);
+        targets.push(vec![1.0]);
+        inputs.push(vec![1.0 - t.cos() + noise * gaussian(rng), 0.5 - t.sin() + noise * gaussian(rng)]);
+        targets.push(vec![-1.0]);
+    }
+    Dataset::new(inputs, targets)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn idx(dims: &[u32], body: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0, 0, 0x08, dims.len() as u8];
+        for d in dims {
+            bytes.extend_from_slice(&d.to_be_bytes());
+        }
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn parses_idx_and_rejects_garbage() {
+        let arr = parse_idx(&idx(&[2, 3], &[1, 2, 3, 4, 5, 6])).unwrap();
+        assert_eq!(arr.dims, vec![2, 3]);
+        assert_eq!(arr.data, vec![1, 2, 3, 4, 5, 6]);
+
+        assert!(matches!(parse_idx(&[1, 2, 3]), Err(DatasetError::Format(_))));
+        assert!(matches!(parse_idx(&idx(&[4], &[1, 2])), Err(DatasetError::Format(_))));
+        let huge = [u32::MAX; 4];
+        assert!(matches!(parse_idx(&idx(&huge, &[])), Err(DatasetError::Format(_))));
+    }
+
+    #[test]
+    fn rejects_empty_images_and_non_digit_labels() {
+        let dir = std::env::temp_dir().join(format!("micrograd-mnist-bad-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (images, labels) = (dir.join("images"), dir.join("labels"));
+        fs::write(&labels, idx(&[2], &[3, 10])).unwrap();
+
+        fs::write(&images, idx(&[2, 0, 4], &[])).unwrap();
+        assert!(matches!(Mnist::load(&images, &labels), Err(DatasetError::Format(_))));
+
+        fs::write(&images, idx(&[2, 1, 1], &[0, 255])).unwrap();
+        let data = Mnist::load(&images, &labels).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(data.labels_one_hot(), Err(DatasetError::Format(_))));
+    }
+
+    #[test]
+    fn loads_a_tiny_mnist_directory() {
+        let dir = std::env::temp_dir().join(format!("micrograd-mnist-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pixels: Vec<u8> = (0..3 * 2 * 2).map(|i| (i * 20) as u8).collect();
+        fs::write(dir.join("train-images-idx3-ubyte"), idx(&[3, 2, 2], &pixels)).unwrap();
+        fs::write(dir.join("train-labels-idx1-ubyte"), idx(&[3], &[7, 0, 9])).unwrap();
+
+        let mut data = mnist(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(data.len(), 3);
+        assert_eq!((data.rows, data.cols), (2, 2));
+        assert_eq!(data.images[0][1], 20.0 / 255.0);
+        assert_eq!(data.images_tensor().shape(), vec![3, 4]);
+        assert_eq!(data.labels_one_hot().unwrap().data()[7], 1.0);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let sub = data.subset(2, &mut rng);
+        assert_eq!(sub.len(), 2);
+        assert_eq!(sub.images.len(), 2);
+
+        data.normalize(0.5, 0.5);
+        assert_eq!(data.images[0][0], -1.0);
+
+        assert!(matches!(mnist_test(std::env::temp_dir().join("no-such-mnist")), Err(DatasetError::Io(_))));
+    }
+
+    #[test]
+    fn blobs_are_labelled_and_sized() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let data = blobs(&[30, 5], 0.1, &mut rng);
+        assert_eq!(data.len(), 35);
+        assert_eq!(data.targets.iter().filter(|t| t[0] == 1.0).count(), 5);
+        // class 0 sits around (3, 0)
+        assert!((data.inputs[0][0] - 3.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn moons_without_noise_lie_on_the_arcs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let data = moons(10, 0.0, &mut rng);
+        assert_eq!(data.len(), 20);
+        for (x, y) in data.inputs.iter().zip(&data.targets) {
+            let r = if y[0] > 0.0 { x[0].hypot(x[1]) } else { (x[0] - 1.0).hypot(x[1] - 0.5) };
+            assert!((r - 1.0).abs() < 1e-12);
+        }
+    }
+}
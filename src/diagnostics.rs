@@ -0,0 +1,499 @@
+#[cfg(all(feature = "nn", feature = "datasets"))]
+use crate::data::Dataset;
+#[cfg(all(feature = "nn", feature = "datasets"))]
+use crate::nn::{eval_mode, Module};
+#[cfg(all(feature = "nn", feature = "datasets"))]
+use crate::operators::no_grad;
+#[cfg(any(all(feature = "nn", feature = "datasets"), feature = "rand"))]
+use crate::operators::Value;
+#[cfg(feature = "rand")]
+use rand::Rng;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static LIVE_NODES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_LIVE_NODES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Number of graph nodes currently alive in the whole process.
+pub fn live_node_count() -> usize {
+    LIVE_NODES.load(Ordering::Relaxed)
+}
+
+/// Number of graph nodes alive on the current thread. Nodes never leave the thread that
+/// created them, so this is the count to assert on in tests, which the harness runs in parallel.
+pub fn thread_live_node_count() -> usize {
+    THREAD_LIVE_NODES.with(|c| c.get())
+}
+
+// Embedded in every GraphNode; counts itself in on creation and clone, out on drop
+pub(crate) struct LiveToken;
+
+impl LiveToken {
+    pub(crate) fn new() -> Self {
+        LIVE_NODES.fetch_add(1, Ordering::Relaxed);
+        THREAD_LIVE_NODES.with(|c| c.set(c.get() + 1));
+        LiveToken
+    }
+}
+
+impl Clone for LiveToken {
+    fn clone(&self) -> Self {
+        LiveToken::new()
+    }
+}
+
+impl Drop for LiveToken {
+    fn drop(&mut self) {
+        LIVE_NODES.fetch_sub(1, Ordering::Relaxed);
+        // try_with: the thread-local may already be gone if a node outlives it during thread exit
+        let _ = THREAD_LIVE_NODES.try_with(|c| c.set(c.get().saturating_sub(1)));
+    }
+}
+
+/// Losses on a plane through the current weights, for loss-landscape plots.
+#[cfg(all(feature = "nn", feature = "datasets"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossSlice {
+    /// Step taken along each direction, evenly spaced over `[-1, 1]`.
+    pub coords: Vec<f64>,
+    /// `losses[i][j]` is the mean loss over the data at `w + coords[i] * d1 + coords[j] * d2`.
+    pub losses: Vec<Vec<f64>>,
+}
+
+/// Evaluates the mean loss over `data` on a `resolution` x `resolution` grid spanned by two
+/// directions in parameter space (one entry per `Module::parameters`), centred on the current
+/// weights. The directions set the extent of the grid. Runs without recording a graph and in
+/// `eval_mode`, and puts the weights back afterwards.
+#[cfg(all(feature = "nn", feature = "datasets"))]
+pub fn loss_slice<M: Module>(
+    model: &M,
+    loss_fn: impl Fn(&[Value], &[Value]) -> Value,
+    data: &Dataset,
+    direction1: &[f64],
+    direction2: &[f64],
+    resolution: usize,
+) -> LossSlice {
+    assert!(resolution > 0, "resolution must be positive");
+    let params = model.parameters();
+    assert_eq!(direction1.len(), params.len(), "first direction does not match the parameters");
+    assert_eq!(direction2.len(), params.len(), "second direction does not match the parameters");
+
+    let coords: Vec<f64> = match resolution {
+        1 => vec![0.0],
+        n => (0..n).map(|i| -1.0 + 2.0 * i as f64 / (n - 1) as f64).collect(),
+    };
+    let origin: Vec<f64> = params.iter().map(|p| p.data()).collect();
+    let losses = no_grad(|| {
+        eval_mode(|| {
+            coords
+                .iter()
+                .map(|&a| {
+                    coords
+                        .iter()
+                        .map(|&b| {
+                            for (i, p) in params.iter().enumerate() {
+                                p.set_data(origin[i] + a * direction1[i] + b * direction2[i]);
+                            }
+                            mean_loss(model, &loss_fn, data)
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+    });
+    for (p, w) in params.iter().zip(&origin) {
+        p.set_data(*w);
+    }
+    LossSlice { coords, losses }
+}
+
+// Mean loss of `model` over `data`
+#[cfg(all(feature = "nn", feature = "datasets"))]
+fn mean_loss<M: Module>(model: &M, loss_fn: &impl Fn(&[Value], &[Value]) -> Value, data: &Dataset) -> f64 {
+    let total: f64 = (0..data.len())
+        .map(|i| {
+            let (x, y) = data.get(i);
+            let xs: Vec<Value> = x.iter().map(|v| Value::from(*v)).collect();
+            let ys: Vec<Value> = y.iter().map(|v| Value::from(*v)).collect();
+            loss_fn(&model.forward(&xs), &ys).data()
+        })
+        .sum();
+    total / data.len().max(1) as f64
+}
+
+/// How much the mean loss rises when the weights are shaken, see `sharpness`.
+#[cfg(all(feature = "nn", feature = "datasets"))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sharpness {
+    /// Mean loss at the current weights.
+    pub loss: f64,
+    /// Mean over the perturbations of the loss increase.
+    pub mean_increase: f64,
+    /// Largest loss increase seen.
+    pub max_increase: f64,
+}
+
+/// Sharpness of the minimum `model` sits in: the mean loss over `data` at `samples` random
+/// perturbations of the weights, each weight moved independently and uniformly within
+/// `radius`, compared with the loss at the weights themselves. A flat minimum barely notices;
+/// a sharp one, which tends to generalize worse, shows a large increase. Runs without recording
+/// a graph and in `eval_mode`, and puts the weights back afterwards.
+#[cfg(all(feature = "nn", feature = "datasets"))]
+pub fn sharpness<M: Module, R: Rng>(
+    model: &M,
+    loss_fn: impl Fn(&[Value], &[Value]) -> Value,
+    data: &Dataset,
+    radius: f64,
+    samples: usize,
+    rng: &mut R,
+) -> Sharpness {
+    assert!(radius > 0.0, "radius must be positive");
+    assert!(samples > 0, "need at least one sample");
+    let params = model.parameters();
+    let origin: Vec<f64> = params.iter().map(|p| p.data()).collect();
+
+    let (loss, increases) = no_grad(|| {
+        eval_mode(|| {
+            let loss = mean_loss(model, &loss_fn, data);
+            let increases: Vec<f64> = (0..samples)
+                .map(|_| {
+                    for (p, w) in params.iter().zip(&origin) {
+                        p.set_data(w + rng.gen_range(-radius..=radius));
+                    }
+                    mean_loss(model, &loss_fn, data) - loss
+                })
+                .collect();
+            (loss, increases)
+        })
+    });
+    for (p, w) in params.iter().zip(&origin) {
+        p.set_data(*w);
+    }
+    Sharpness {
+        loss,
+        mean_increase: increases.iter().sum::<f64>() / samples as f64,
+        max_increase: increases.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    }
+}
+
+/// L2 norm of the loss gradient of every sample in `data` with respect to `model`'s parameters,
+/// in dataset order. Samples with large norms are the ones pulling hardest on the weights:
+/// outliers, mislabelled points, or whatever the model has not fit yet. The parameters'
+/// gradients are restored afterwards.
+#[cfg(all(feature = "nn", feature = "datasets"))]
+pub fn sample_grad_norms<M: Module>(
+    model: &M,
+    loss_fn: impl Fn(&[Value], &[Value]) -> Value,
+    data: &Dataset,
+) -> Vec<f64> {
+    let params = model.parameters();
+    let saved: Vec<f64> = params.iter().map(|p| p.grad()).collect();
+    let norms = (0..data.len())
+        .map(|i| {
+            let (x, y) = data.get(i);
+            let xs: Vec<Value> = x.iter().map(|v| Value::from(*v)).collect();
+            let ys: Vec<Value> = y.iter().map(|v| Value::from(*v)).collect();
+            for p in &params {
+                p.set_grad(0.0);
+            }
+            loss_fn(&model.forward(&xs), &ys).backward();
+            params.iter().map(|p| p.grad().powi(2)).sum::<f64>().sqrt()
+        })
+        .collect();
+    for (p, g) in params.iter().zip(saved) {
+        p.set_grad(g);
+    }
+    norms
+}
+
+// Half-width of the central difference used for Hessian-vector products
+#[cfg(feature = "rand")]
+const HVP_EPS: f64 = 1e-4;
+
+/// Hutchinson estimate of the Hessian diagonal of a scalar loss with respect to `params`.
+///
+/// `loss` rebuilds the loss graph from the current parameter values on every call. Each of the
+/// `samples` draws a random ±1 vector `v` and adds `v * Hv` to the estimate, with the
+/// Hessian-vector product taken as a central difference of gradients,
+/// `(g(w + εv) - g(w - εv)) / 2ε`, since the graph cannot be differentiated twice. The
+/// estimate is unbiased; its variance comes from the off-diagonal curvature. Parameter values
+/// and gradients are restored afterwards.
+#[cfg(feature = "rand")]
+pub fn hessian_diag<R: Rng>(loss: impl Fn() -> Value, params: &[Value], samples: usize, rng: &mut R) -> Vec<f64> {
+    assert!(samples > 0, "need at least one sample");
+    let saved: Vec<(f64, f64)> = params.iter().map(|p| (p.data(), p.grad())).collect();
+    let grads_at = |v: &[f64], scale: f64| -> Vec<f64> {
+        for (p, (&(w, _), vi)) in params.iter().zip(saved.iter().zip(v)) {
+            p.set_data(w + scale * vi);
+            p.set_grad(0.0);
+        }
+        loss().backward();
+        params.iter().map(|p| p.grad()).collect()
+    };
+
+    let mut diag = vec![0.0; params.len()];
+    for _ in 0..samples {
+        let v: Vec<f64> = (0..params.len()).map(|_| if rng.gen_bool(0.5) { 1.0 } else { -1.0 }).collect();
+        let plus = grads_at(&v, HVP_EPS);
+        let minus = grads_at(&v, -HVP_EPS);
+        for (d, ((vi, gp), gm)) in diag.iter_mut().zip(v.iter().zip(plus).zip(minus)) {
+            *d += vi * (gp - gm) / (2.0 * HVP_EPS);
+        }
+    }
+
+    for (p, (w, g)) in params.iter().zip(saved) {
+        p.set_data(w);
+        p.set_grad(g);
+    }
+    diag.iter().map(|d| d / samples as f64).collect()
+}
+
+/// Counts of values in `counts.len()` equal-width bins spanning `[lo, hi]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub lo: f64,
+    pub hi: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Bins `values` into `bins` bins over `[lo, hi]`. Values outside the range are counted in
+    /// the first or last bin, so every finite value is counted; NaNs are skipped.
+    pub fn new(values: impl IntoIterator<Item = f64>, bins: usize, lo: f64, hi: f64) -> Self {
+        assert!(bins > 0, "need at least one bin");
+        assert!(lo < hi, "histogram range must be non-empty");
+        let mut counts = vec![0; bins];
+        for x in values.into_iter().filter(|x| !x.is_nan()) {
+            let b = ((x - lo) / (hi - lo) * bins as f64).clamp(0.0, (bins - 1) as f64) as usize;
+            counts[b] += 1;
+        }
+        Histogram { lo, hi, counts }
+    }
+
+    /// Lower and upper edge of bin `i`.
+    pub fn edges(&self, i: usize) -> (f64, f64) {
+        let width = (self.hi - self.lo) / self.counts.len() as f64;
+        (self.lo + i as f64 * width, self.lo + (i + 1) as f64 * width)
+    }
+}
+
+/// Records a histogram of every parameter group (every layer of an `MLP`) at the end of each
+/// epoch into `History::weight_histograms`. The bins are fixed up front so epochs compare
+/// directly.
+#[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+#[derive(Debug, Clone)]
+pub struct WeightHistograms {
+    bins: usize,
+    lo: f64,
+    hi: f64,
+}
+
+#[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+impl WeightHistograms {
+    pub fn new(bins: usize, lo: f64, hi: f64) -> Self {
+        assert!(bins > 0 && lo < hi, "need at least one bin over a non-empty range");
+        WeightHistograms { bins, lo, hi }
+    }
+}
+
+#[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+impl crate::trainer::Callback for WeightHistograms {
+    fn on_epoch_end(&mut self, model: &dyn Module, history: &mut crate::trainer::History) {
+        let snapshot = model
+            .parameter_groups()
+            .iter()
+            .map(|(_, params)| Histogram::new(params.iter().map(Value::data), self.bins, self.lo, self.hi))
+            .collect();
+        history.weight_histograms.push(snapshot);
+    }
+}
+
+/// Per-epoch, per-group histograms as CSV with an `epoch,group,bin_lo,bin_hi,count` header, one
+/// row per bin.
+pub fn histograms_csv(epochs: &[Vec<Histogram>]) -> String {
+    let mut csv = String::from("epoch,group,bin_lo,bin_hi,count\n");
+    for (epoch, groups) in epochs.iter().enumerate() {
+        for (group, h) in groups.iter().enumerate() {
+            for (i, count) in h.counts.iter().enumerate() {
+                let (lo, hi) = h.edges(i);
+                csv.push_str(&format!("{},{},{},{},{}\n", epoch, group, lo, hi, count));
+            }
+        }
+    }
+    csv
+}
+
+/// The same as JSON: one array per epoch of `{"lo", "hi", "counts"}` objects, one per group.
+pub fn histograms_json(epochs: &[Vec<Histogram>]) -> String {
+    let epochs: Vec<String> = epochs
+        .iter()
+        .map(|groups| {
+            let groups: Vec<String> = groups
+                .iter()
+                .map(|h| {
+                    let counts: Vec<String> = h.counts.iter().map(|c| c.to_string()).collect();
+                    format!("{{\"lo\":{:?},\"hi\":{:?},\"counts\":[{}]}}", h.lo, h.hi, counts.join(","))
+                })
+                .collect();
+            format!("[{}]", groups.join(","))
+        })
+        .collect();
+    format!("[{}]", epochs.join(","))
+}
+
+#[cfg(all(test, feature = "nn", feature = "datasets"))]
+mod tests {
+    use super::*;
+    use crate::loss::mse;
+    use crate::nn::MLP;
+
+    #[test]
+    fn graphs_are_freed_between_epochs() {
+        let base = thread_live_node_count();
+        let mlp = MLP::new(2, vec![3, 1]);
+        let params = thread_live_node_count();
+        assert!(params > base);
+
+        for _ in 0..3 {
+            let out = mlp.forward(vec![Value::from(1.0), Value::from(-2.0)]);
+            out[0].backward();
+            assert!(thread_live_node_count() > params);
+        }
+        assert_eq!(thread_live_node_count(), params);
+        assert!(live_node_count() >= params - base);
+
+        drop(mlp);
+        assert_eq!(thread_live_node_count(), base);
+    }
+
+    #[test]
+    fn sharp_minima_rise_faster_than_flat_ones() {
+        use crate::nn::{Activation, Layer};
+        use rand::SeedableRng;
+        // y = 2x fit exactly; stretching the inputs makes the same minimum sharper
+        let model = Layer::from_weights(&[vec![2.0]], &[0.0], Activation::Linear);
+        let data = |scale: f64| {
+            let xs: Vec<Vec<f64>> = (-5..=5).map(|i| vec![scale * i as f64 / 5.0]).collect();
+            let ys = xs.iter().map(|x| vec![2.0 * x[0]]).collect();
+            Dataset::new(xs, ys)
+        };
+        let measure = |scale| sharpness(&model, mse, &data(scale), 0.1, 50, &mut rand::rngs::StdRng::seed_from_u64(4));
+
+        let (flat, sharp) = (measure(1.0), measure(10.0));
+        assert_eq!((flat.loss, sharp.loss), (0.0, 0.0));
+        assert!(flat.mean_increase > 0.0 && flat.max_increase >= flat.mean_increase);
+        assert!(sharp.mean_increase > 10.0 * flat.mean_increase);
+        assert_eq!(model.parameters().iter().map(|p| p.data()).collect::<Vec<_>>(), vec![0.0, 2.0]);
+    }
+
+    #[test]
+    fn loss_slice_is_centred_on_current_weights() {
+        use crate::nn::{Layer, StochasticDepth};
+        let model = MLP::new(2, vec![3, 1]);
+        let data = Dataset::new(vec![vec![0.5, -1.0], vec![1.0, 0.25]], vec![vec![0.3], vec![-0.6]]);
+        let before: Vec<f64> = model.parameters().iter().map(|p| p.data()).collect();
+        let d1: Vec<f64> = (0..before.len()).map(|i| if i % 2 == 0 { 0.5 } else { 0.0 }).collect();
+        let d2: Vec<f64> = (0..before.len()).map(|i| if i % 2 == 1 { -0.5 } else { 0.0 }).collect();
+
+        let slice = loss_slice(&model, mse, &data, &d1, &d2, 5);
+        assert_eq!(slice.coords, vec![-1.0, -0.5, 0.0, 0.5, 1.0]);
+        assert_eq!(slice.losses.len(), 5);
+        assert!(slice.losses.iter().all(|row| row.len() == 5));
+
+        let params = model.parameters();
+        assert!(params.iter().zip(&before).all(|(p, w)| p.data() == *w && p.grad() == 0.0));
+        let current: f64 = (0..data.len())
+            .map(|i| {
+                let (x, y) = data.get(i);
+                let out = model.forward(x.iter().map(|v| Value::from(*v)).collect::<Vec<_>>());
+                mse(&out, &[Value::from(y[0])]).data()
+            })
+            .sum::<f64>()
+            / 2.0;
+        assert!((slice.losses[2][2] - current).abs() < 1e-12);
+        assert_ne!(slice.losses[0][0], slice.losses[2][2]);
+
+        // train-only randomness stays out of the surface
+        let block = StochasticDepth::new(Box::new(Layer::new(2, 2)), 0.5);
+        let data = Dataset::new(vec![vec![0.5, -1.0], vec![1.0, 0.25]], vec![vec![0.3, 0.1], vec![-0.6, 0.2]]);
+        let d: Vec<f64> = vec![0.1; block.parameters().len()];
+        assert_eq!(loss_slice(&block, mse, &data, &d, &d, 3), loss_slice(&block, mse, &data, &d, &d, 3));
+    }
+
+    #[test]
+    fn hutchinson_recovers_quadratic_curvature() {
+        use rand::SeedableRng;
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        let w: Vec<Value> = [0.5, -1.0, 2.0].iter().map(|&x| Value::new(x, "w")).collect();
+        w[0].set_grad(7.0);
+
+        // separable: v * Hv is the diagonal exactly, whatever v is
+        let separable = || w[0].clone().powop(2) * 3.0 + w[1].clone().powop(2) + w[2].clone().powop(2) * 0.5;
+        let diag = hessian_diag(separable, &w, 1, &mut rng);
+        for (d, expected) in diag.iter().zip([6.0, 2.0, 1.0]) {
+            assert!((d - expected).abs() < 1e-6, "{diag:?}");
+        }
+        assert_eq!((w[0].data(), w[0].grad(), w[2].data()), (0.5, 7.0, 2.0));
+
+        // a cross term only adds noise that averages out
+        let coupled = || separable() + w[0].clone() * w[1].clone() * 2.0;
+        let diag = hessian_diag(coupled, &w, 2000, &mut rng);
+        for (d, expected) in diag.iter().zip([6.0, 2.0, 1.0]) {
+            assert!((d - expected).abs() < 0.2, "{diag:?}");
+        }
+    }
+
+    #[test]
+    fn outlier_has_the_largest_gradient() {
+        let model = MLP::new(1, vec![3, 1]);
+        let inputs: Vec<Vec<f64>> = (0..6).map(|i| vec![i as f64 / 5.0]).collect();
+        let mut targets: Vec<Vec<f64>> =
+            inputs.iter().map(|x| vec![model.forward(vec![Value::from(x[0])])[0].data()]).collect();
+        targets[4][0] += 5.0;
+        let data = Dataset::new(inputs, targets);
+
+        let norms = sample_grad_norms(&model, mse, &data);
+        assert_eq!(norms.len(), 6);
+        // every other target is already matched exactly
+        assert!(norms.iter().enumerate().all(|(i, n)| (i == 4) == (*n > 1e-9)), "{norms:?}");
+        assert!(model.parameters().iter().all(|p| p.grad() == 0.0));
+    }
+
+    #[test]
+    fn histograms_bin_and_export() {
+        let h = Histogram::new([-5.0, -0.9, -0.1, 0.0, 0.4, 0.99, 7.0, f64::NAN], 4, -1.0, 1.0);
+        assert_eq!(h.counts, vec![2, 1, 2, 2]);
+        assert_eq!(h.edges(1), (-0.5, 0.0));
+
+        let epochs = vec![vec![h.clone()], vec![Histogram::new([0.1], 4, -1.0, 1.0)]];
+        let csv = histograms_csv(&epochs);
+        assert_eq!(csv.lines().count(), 1 + 8);
+        assert_eq!(csv.lines().nth(2), Some("0,0,-0.5,0,1"));
+        assert_eq!(histograms_json(&epochs), "[[{\"lo\":-1.0,\"hi\":1.0,\"counts\":[2,1,2,2]}],[{\"lo\":-1.0,\"hi\":1.0,\"counts\":[0,0,1,0]}]]");
+    }
+
+    #[test]
+    #[cfg(feature = "optim")]
+    fn weight_histograms_are_recorded_every_epoch() {
+        use crate::data::DataLoader;
+        use crate::optim::SGD;
+        use crate::trainer::Trainer;
+
+        let xs: Vec<Vec<f64>> = (0..8).map(|i| vec![i as f64 / 8.0, 1.0]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs.clone(), xs), 4);
+        let model = MLP::new(2, vec![4, 2]);
+        let opt = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(model, opt, mse).with_callback(WeightHistograms::new(10, -2.0, 2.0));
+        let history = trainer.fit(&mut loader, 3);
+
+        assert_eq!(history.weight_histograms.len(), 3);
+        for epoch in &history.weight_histograms {
+            let counts: Vec<usize> = epoch.iter().map(|h| h.counts.iter().sum()).collect();
+            // weights and biases of each layer
+            assert_eq!(counts, vec![4 * 3, 2 * 5]);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! `graph!`, a small notation for writing out expressions step by step, as when working through
+//! backprop by hand. Every node it binds is labelled with its variable name, so debug prints and
+//! graph views read like the code that built them:
+//!
+//! ```
+//! use micrograd_rs::graph;
+//!
+//! graph! {
+//!     x1 = 2.0; x2 = 0.0;
+//!     w1 = -3.0; w2 = 1.0;
+//!     b = 6.881373587019543;
+//!     n = x1*w1 + x2*w2 + b;
+//!     o = tanh(n);
+//! }
+//! o.backward();
+//! assert_eq!(n.borrow().label(), "n");
+//! assert!((w1.grad() - 1.0).abs() < 1e-6);
+//! ```
+//!
+//! A statement `name = expr;` binds `name` to a `Value`: a number becomes a new leaf, and any
+//! other expression is computed with the usual operators and the functions of this module.
+//! Names bound earlier in the block can be used any number of times; Values from outside it are
+//! moved as in ordinary code, so clone those that are needed again.
+
+use crate::operators::Value;
+
+pub fn tanh(x: impl Into<Value>) -> Value {
+    x.into().tanh()
+}
+
+pub fn exp(x: impl Into<Value>) -> Value {
+    x.into().exp()
+}
+
+pub fn log(x: impl Into<Value>) -> Value {
+    x.into().log()
+}
+
+pub fn relu(x: impl Into<Value>) -> Value {
+    x.into().relu()
+}
+
+pub fn sigmoid(x: impl Into<Value>) -> Value {
+    x.into().sigmoid()
+}
+
+pub fn sqrt(x: impl Into<Value>) -> Value {
+    x.into().sqrt()
+}
+
+pub fn abs(x: impl Into<Value>) -> Value {
+    x.into().abs()
+}
+
+pub fn pow(x: impl Into<Value>, exponent: f64) -> Value {
+    x.into().powop(exponent)
+}
+
+#[macro_export]
+macro_rules! graph {
+    // `[...]` holds the names bound so far
+    (@start [$($bound:ident)*]) => {};
+    (@start [$($bound:ident)*] $name:ident = $($rest:tt)*) => {
+        $crate::graph!(@stmt [$($bound)*] [$name] [] $($rest)*);
+    };
+    // collect the tokens of one statement up to its `;`
+    (@stmt [$($bound:ident)*] [$name:ident] [$($e:tt)*] ; $($rest:tt)*) => {
+        let $name = {
+            #[allow(unused_imports)]
+            use $crate::dsl::*;
+            $(
+                #[allow(unused_variables)]
+                let $bound = $bound.clone();
+            )*
+            $crate::operators::Value::from($($e)*)
+        };
+        $name.borrow_mut().set_label(stringify!($name));
+        $crate::graph!(@start [$($bound)* $name] $($rest)*);
+    };
+    (@stmt [$($bound:ident)*] [$name:ident] [$($e:tt)*] $t:tt $($rest:tt)*) => {
+        $crate::graph!(@stmt [$($bound)*] [$name] [$($e)* $t] $($rest)*);
+    };
+    ($($t:tt)*) => {
+        $crate::graph!(@start [] $($t)*);
+    };
+}
+
+/// Defines functions over Values from plain formulas, for porting numeric code:
+///
+/// ```
+/// use micrograd_rs::{def_fn, operators::Value};
+///
+/// def_fn! {
+///     fn softplus(x) { log(1.0 + exp(x)) }
+///     pub fn smooth_max(a, b) {
+///         let d = a - b;
+///         b + softplus(&[d.clone()])
+///     }
+/// }
+/// let (a, b) = (Value::new(0.5, "a"), Value::new(-1.0, "b"));
+/// let m = smooth_max(&[a.clone(), b]);
+/// m.backward();
+/// assert!(a.grad() > 0.5);
+/// ```
+///
+/// Each `fn name(args) { body }` becomes `fn name(&[Value]) -> Value`, taking its arguments in
+/// order and panicking when given a different number. The body is `let` statements followed by
+/// an expression, written with `+ - * /`, unary minus, number literals, the functions of this
+/// module and other Value methods. Arguments and `let` bindings are references, so each can be
+/// used any number of times.
+#[macro_export]
+macro_rules! def_fn {
+    () => {};
+    ($vis:vis fn $name:ident($($arg:ident),* $(,)?) { $($body:tt)* } $($rest:tt)*) => {
+        $vis fn $name(args: &[$crate::operators::Value]) -> $crate::operators::Value {
+            #[allow(unused_imports)]
+            use $crate::dsl::*;
+            let [$($arg),*] = args else {
+                panic!(
+                    "{} takes {} arguments, got {}",
+                    stringify!($name), [$(stringify!($arg)),*].len(), args.len()
+                );
+            };
+            $crate::def_fn!(@body $($body)*)
+        }
+        $crate::def_fn!($($rest)*);
+    };
+    (@body let $var:ident = $e:expr; $($rest:tt)*) => {{
+        let $var = $crate::operators::Value::from($e);
+        let $var = &$var;
+        $crate::def_fn!(@body $($rest)*)
+    }};
+    (@body $e:expr) => {
+        $crate::operators::Value::from($e)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gradcheck::check_gradients;
+    use crate::operators::Value;
+
+    crate::def_fn! {
+        fn gelu(x) { 0.5 * x * (1.0 + tanh(0.7978845608 * (x + 0.044715 * pow(x, 3.0)))) }
+        fn rosenbrock(x, y) {
+            let a = 1.0 - x;
+            let b = y - x * x;
+            a * a + 100.0 * b * b
+        }
+    }
+
+    fn gelu_f64(x: f64) -> f64 {
+        0.5 * x * (1.0 + (0.7978845608 * (x + 0.044715 * x.powi(3))).tanh())
+    }
+
+    #[test]
+    fn def_fn_matches_the_f64_formula() {
+        for x in [-2.0, -0.3, 0.0, 0.8, 3.0] {
+            let v = [Value::new(x, "x")];
+            let out = gelu(&v);
+            assert!((out.data() - gelu_f64(x)).abs() < 1e-12);
+            check_gradients(&out, &v, 1e-6, 1e-6).unwrap();
+        }
+
+        let xy = [Value::new(-1.0, "x"), Value::new(1.0, "y")];
+        let out = rosenbrock(&xy);
+        assert_eq!(out.data(), 4.0);
+        out.backward();
+        assert_eq!((xy[0].grad(), xy[1].grad()), (-4.0, 0.0));
+        check_gradients(&out, &xy, 1e-6, 1e-6).unwrap();
+
+        // usable wherever a closure over Values is expected
+        let mut cache = crate::memo::EvalCache::new(rosenbrock, 0.0);
+        assert_eq!(cache.eval(&[1.0, 1.0]).grad, vec![0.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "rosenbrock takes 2 arguments, got 1")]
+    fn def_fn_checks_arity() {
+        rosenbrock(&[Value::from(1.0)]);
+    }
+
+    #[test]
+    fn labels_nodes_with_variable_names() {
+        crate::graph! {
+            x = 0.5;
+            w = -1.5;
+            h = tanh(x * w + 0.2);
+            y = pow(h, 2.0) + exp(-x) * w;
+            z = (y - h) / x.relu();
+        }
+        let expected = (0.5f64 * -1.5 + 0.2).tanh();
+        assert!((h.data() - expected).abs() < 1e-12);
+        let y_expected = expected.powi(2) + (-0.5f64).exp() * -1.5;
+        assert!((y.data() - y_expected).abs() < 1e-12);
+        assert!((z.data() - (y_expected - expected) / 0.5).abs() < 1e-12);
+
+        let labels: Vec<String> = [&x, &w, &h, &y, &z].iter().map(|v| v.borrow().label().to_string()).collect();
+        assert_eq!(labels, ["x", "w", "h", "y", "z"]);
+        // the variables stay usable and backward flows through all of them
+        z.backward();
+        assert!(x.grad() != 0.0 && w.grad() != 0.0);
+        assert!(format!("{:?}", z).contains("h"));
+    }
+}
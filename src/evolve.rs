@@ -0,0 +1,156 @@
+//! Neuroevolution: training an `MLP` without gradients, as a baseline to compare backprop
+//! against.
+//!
+//! A population of copies of a model is mutated by gaussian perturbation of the weights; each
+//! generation the fittest survive unchanged and the rest of the population is refilled with
+//! mutated copies of them. Fitness is any closure over a model, higher being better, and runs
+//! under `no_grad` since nothing is ever differentiated.
+
+use crate::nn::MLP;
+use crate::noise::gaussian;
+use crate::operators::no_grad;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Settings of an evolutionary run.
+#[derive(Debug, Clone)]
+pub struct Evolution {
+    population: usize,
+    elite: usize,
+    sigma: f64,
+    seed: u64,
+}
+
+/// Outcome of `Evolution::run`.
+#[derive(Debug, Clone)]
+pub struct Evolved {
+    pub best: MLP,
+    pub fitness: f64,
+    /// Best fitness after each generation; never decreases, since the elite survive.
+    pub history: Vec<f64>,
+}
+
+impl Evolution {
+    /// A population of `population` models of which the best fifth (at least one) survive each
+    /// generation, mutated with standard deviation 0.1.
+    pub fn new(population: usize) -> Self {
+        assert!(population > 0, "population must not be empty");
+        Evolution { population, elite: (population / 5).max(1), sigma: 0.1, seed: 0 }
+    }
+
+    /// How many of the fittest models survive each generation and parent the rest.
+    pub fn with_elite(mut self, elite: usize) -> Self {
+        assert!((1..=self.population).contains(&elite), "elite must be between 1 and the population size");
+        self.elite = elite;
+        self
+    }
+
+    /// Standard deviation of the noise added to every weight of a child.
+    pub fn with_sigma(mut self, sigma: f64) -> Self {
+        assert!(sigma > 0.0, "mutation sigma must be positive");
+        self.sigma = sigma;
+        self
+    }
+
+    /// Seed of the mutations and parent picks; the same seed and fitness give the same run.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Adds gaussian noise to every trainable parameter of `model`
+    fn mutate(&self, model: &MLP, rng: &mut StdRng) {
+        for p in model.parameters().iter().filter(|p| p.requires_grad()) {
+            p.set_data(p.data() + self.sigma * gaussian(rng));
+        }
+    }
+
+    /// Evolves `generations` generations starting from mutated copies of `template` (plus one
+    /// unmutated copy), which itself is left alone. A NaN fitness counts as the worst possible.
+    pub fn run(&self, template: &MLP, generations: usize, mut fitness: impl FnMut(&MLP) -> f64) -> Evolved {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut score = |m: &MLP| {
+            let f = no_grad(|| fitness(m));
+            if f.is_nan() { f64::NEG_INFINITY } else { f }
+        };
+        let by_fitness = |a: &(f64, MLP), b: &(f64, MLP)| b.0.total_cmp(&a.0);
+
+        let mut population: Vec<(f64, MLP)> = (0..self.population)
+            .map(|i| {
+                let model = template.deep_copy();
+                if i > 0 {
+                    self.mutate(&model, &mut rng);
+                }
+                (score(&model), model)
+            })
+            .collect();
+        population.sort_by(by_fitness);
+
+        let mut history = Vec::with_capacity(generations);
+        for _ in 0..generations {
+            population.truncate(self.elite);
+            while population.len() < self.population {
+                let child = population[rng.gen_range(0..self.elite)].1.deep_copy();
+                self.mutate(&child, &mut rng);
+                population.push((score(&child), child));
+            }
+            // stable, so an elite keeps its place over an equally fit child
+            population.sort_by(by_fitness);
+            history.push(population[0].0);
+        }
+
+        let (fitness, best) = population.swap_remove(0);
+        Evolved { best, fitness, history }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::Activation;
+    use crate::operators::Value;
+
+    // Negative mean squared error of `model` on y = x^2 - 0.5
+    fn fitness(model: &MLP) -> f64 {
+        let xs = [-1.0, -0.5, 0.0, 0.5, 1.0];
+        -xs.iter()
+            .map(|&x| (model.forward(vec![Value::from(x)])[0].data() - (x * x - 0.5)).powi(2))
+            .sum::<f64>()
+            / xs.len() as f64
+    }
+
+    #[test]
+    fn evolution_improves_fitness_reproducibly() {
+        let template = MLP::with_activations(1, vec![6, 1], Activation::Tanh, Activation::Linear);
+        let mut rng = StdRng::seed_from_u64(2);
+        template.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+        let start: Vec<f64> = template.parameters().iter().map(|p| p.data()).collect();
+
+        let evolution = Evolution::new(20).with_elite(4).with_sigma(0.05).with_seed(7);
+        let run = evolution.run(&template, 40, fitness);
+        assert_eq!(run.history.len(), 40);
+        assert!(run.history.windows(2).all(|w| w[0] <= w[1]));
+        // at least halves the error
+        assert!(run.fitness > fitness(&template) / 2.0, "{} vs {}", run.fitness, fitness(&template));
+        assert_eq!(run.fitness, fitness(&run.best));
+
+        // the template is untouched, and the same seed evolves the same model
+        assert_eq!(template.parameters().iter().map(|p| p.data()).collect::<Vec<_>>(), start);
+        let again = evolution.run(&template, 40, fitness);
+        assert_eq!(again.history, run.history);
+    }
+
+    #[test]
+    fn frozen_weights_do_not_mutate_and_nan_ranks_last() {
+        let template = MLP::new(2, vec![2]);
+        template.parameters()[0].set_requires_grad(false);
+        let frozen = template.parameters()[0].data();
+        // the unmutated template is the only model with a finite fitness
+        let run = Evolution::new(5).with_seed(1).run(&template, 3, |m| {
+            if m.parameters()[1].data() == template.parameters()[1].data() { 1.0 } else { f64::NAN }
+        });
+        assert_eq!(run.fitness, 1.0);
+        assert!(run.best.parameters().iter().zip(template.parameters()).all(|(a, b)| a.data() == b.data()));
+        assert_eq!(run.best.parameters()[0].data(), frozen);
+    }
+}
@@ -0,0 +1,142 @@
+//! Repeating a training run over several seeds and summarizing the spread of its results, since
+//! a single run on a toy problem says little about how a setup behaves.
+
+use std::fmt;
+
+/// Aggregate of one metric over all runs. `std` is the sample standard deviation (0 for a
+/// single run).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stat {
+    pub name: String,
+    pub mean: f64,
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Results of `repeat`, printable as a text table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    /// The values reported by each run, in seed order, named as in `stats`.
+    pub runs: Vec<Vec<f64>>,
+    pub stats: Vec<Stat>,
+}
+
+impl Summary {
+    pub fn get(&self, name: &str) -> Option<&Stat> {
+        self.stats.iter().find(|s| s.name == name)
+    }
+}
+
+/// Calls `train_fn` with seeds `0..n_seeds` and aggregates the named metrics each call returns,
+/// e.g. `[("loss", final_loss), ("accuracy", acc)]`. Every run must report the same metrics in
+/// the same order.
+pub fn repeat<F, R, K>(n_seeds: usize, mut train_fn: F) -> Summary
+where
+    F: FnMut(u64) -> R,
+    R: IntoIterator<Item = (K, f64)>,
+    K: Into<String>,
+{
+    assert!(n_seeds > 0, "need at least one seed");
+    let mut names: Vec<String> = Vec::new();
+    let mut runs = Vec::with_capacity(n_seeds);
+    for seed in 0..n_seeds as u64 {
+        let (run_names, values): (Vec<String>, Vec<f64>) =
+            train_fn(seed).into_iter().map(|(k, v)| (k.into(), v)).unzip();
+        if seed == 0 {
+            names = run_names;
+        } else {
+            assert_eq!(run_names, names, "seed {} reported different metrics", seed);
+        }
+        runs.push(values);
+    }
+
+    let n = runs.len() as f64;
+    let stats = names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let xs: Vec<f64> = runs.iter().map(|r| r[i]).collect();
+            let mean = xs.iter().sum::<f64>() / n;
+            let sq: f64 = xs.iter().map(|x| (x - mean).powi(2)).sum();
+            let var = if runs.len() > 1 { sq / (n - 1.0) } else { 0.0 };
+            Stat {
+                name,
+                mean,
+                std: var.sqrt(),
+                min: xs.iter().cloned().fold(f64::INFINITY, f64::min),
+                max: xs.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            }
+        })
+        .collect();
+    Summary { runs, stats }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seeds = self.runs.len();
+        writeln!(f, "{:>12} {:>10} {:>10} {:>10} {:>10}   ({} seeds)", "metric", "mean", "std", "min", "max", seeds)?;
+        for s in &self.stats {
+            writeln!(f, "{:>12} {:>10.4} {:>10.4} {:>10.4} {:>10.4}", s.name, s.mean, s.std, s.min, s.max)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_over_seeds() {
+        let summary = repeat(4, |seed| [("loss", seed as f64), ("const", 2.0)]);
+        assert_eq!(summary.runs, vec![vec![0.0, 2.0], vec![1.0, 2.0], vec![2.0, 2.0], vec![3.0, 2.0]]);
+        let loss = summary.get("loss").unwrap();
+        assert_eq!((loss.mean, loss.min, loss.max), (1.5, 0.0, 3.0));
+        assert!((loss.std - (5.0f64 / 3.0).sqrt()).abs() < 1e-12);
+        assert_eq!(summary.get("const").unwrap().std, 0.0);
+        assert!(summary.to_string().contains("(4 seeds)"));
+    }
+
+    #[cfg(feature = "nn")]
+    fn train(seed: u64) -> Vec<(&'static str, f64)> {
+        use crate::nn::{Activation, Layer};
+        use crate::operators::Value;
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let w: Vec<Vec<f64>> = vec![(0..2).map(|_| rng.gen_range(-1.0..1.0)).collect()];
+        let model = Layer::from_weights(&w, &[0.0], Activation::Linear);
+        let loss = || -> Value {
+            [([1.0, 2.0], 1.0), ([-1.0, 0.5], -2.0)]
+                .iter()
+                .map(|(x, y)| (model.forward(&[Value::from(x[0]), Value::from(x[1])])[0].clone() - *y).powop(2))
+                .sum()
+        };
+        let initial = loss().data();
+        for _ in 0..50 {
+            let l = loss();
+            model.parameters().iter().for_each(|p| p.set_grad(0.0));
+            l.backward();
+            model.parameters().iter().for_each(|p| p.set_data(p.data() - 0.05 * p.grad()));
+        }
+        vec![("initial", initial), ("final", loss().data())]
+    }
+
+    #[test]
+    #[cfg(feature = "nn")]
+    fn seeded_training_runs() {
+        let summary = repeat(3, train);
+        assert!(summary.get("final").unwrap().max < summary.get("initial").unwrap().min);
+        assert!(summary.get("initial").unwrap().std > 0.0);
+        // same seeds, same results
+        assert_eq!(summary, repeat(3, train));
+    }
+
+    #[test]
+    #[should_panic(expected = "seed 1 reported different metrics")]
+    fn runs_must_agree_on_metrics() {
+        repeat(2, |seed| if seed == 0 { vec![("a", 1.0)] } else { vec![("b", 1.0)] });
+    }
+}
@@ -0,0 +1,133 @@
+//! Attributions: how much each input feature contributes to one output of a model, and
+//! inputs optimized to drive an output up.
+
+use crate::nn::Module;
+use crate::operators::Value;
+#[cfg(feature = "optim")]
+use crate::optim::{Optimizer, SGD};
+
+// Runs `f` and puts the model's parameter gradients back as they were, so explaining a model
+// in the middle of training does not disturb the next optimizer step
+pub(crate) fn keeping_grads<M: Module, R>(model: &M, f: impl FnOnce() -> R) -> R {
+    let params = model.parameters();
+    let saved: Vec<f64> = params.iter().map(|p| p.grad()).collect();
+    let result = f();
+    params.iter().zip(saved).for_each(|(p, g)| p.set_grad(g));
+    result
+}
+
+/// Gradient of output `target` with respect to each input at `input`: which features the
+/// output is locally most sensitive to. Parameter gradients are left untouched.
+pub fn saliency<M: Module>(model: &M, target: usize, input: &[f64]) -> Vec<f64> {
+    keeping_grads(model, || {
+        let xs: Vec<Value> = input.iter().map(|&x| Value::new(x, "x")).collect();
+        let outputs = model.forward(&xs);
+        assert!(target < outputs.len(), "target output {} out of range for {} outputs", target, outputs.len());
+        outputs[target].backward();
+        xs.iter().map(|x| x.grad()).collect()
+    })
+}
+
+/// Integrated gradients of output `target`: `(input - baseline)` times the saliency averaged
+/// over `steps` points on the straight path from `baseline` to `input` (midpoint rule). The
+/// attributions add up to about `f(input) - f(baseline)`, closer with more steps; an all-zero
+/// baseline is the usual choice.
+pub fn integrated_gradients<M: Module>(model: &M, target: usize, input: &[f64], baseline: &[f64], steps: usize) -> Vec<f64> {
+    assert_eq!(input.len(), baseline.len(), "input and baseline differ in length");
+    assert!(steps > 0, "need at least one step");
+    let mut total = vec![0.0; input.len()];
+    for k in 0..steps {
+        let alpha = (k as f64 + 0.5) / steps as f64;
+        let point: Vec<f64> = baseline.iter().zip(input).map(|(b, x)| b + alpha * (x - b)).collect();
+        for (t, g) in total.iter_mut().zip(saliency(model, target, &point)) {
+            *t += g;
+        }
+    }
+    total.iter().zip(input.iter().zip(baseline)).map(|(t, (x, b))| t / steps as f64 * (x - b)).collect()
+}
+
+/// Gradient ascent on the input: starting from `start`, takes `steps` SGD steps of size `lr`
+/// that increase output `target`, and returns the input reached. The model's parameters are
+/// frozen while it runs, so only the input moves; their values, gradients and freeze state
+/// are as before afterwards. The search is unconstrained, so outputs that grow without bound
+/// take the input with them.
+#[cfg(feature = "optim")]
+pub fn optimize_input<M: Module>(model: &M, target: usize, start: &[f64], steps: usize, lr: f64) -> Vec<f64> {
+    keeping_grads(model, || {
+        let params = model.parameters();
+        let trainable: Vec<bool> = params.iter().map(|p| p.requires_grad()).collect();
+        params.iter().for_each(|p| p.set_requires_grad(false));
+
+        let xs: Vec<Value> = start.iter().map(|&x| Value::new(x, "x")).collect();
+        // the optimizer sees the parameters too, and skips them because they are frozen
+        let mut opt = SGD::new(xs.iter().chain(&params).cloned().collect(), lr);
+        for _ in 0..steps {
+            let outputs = model.forward(&xs);
+            assert!(target < outputs.len(), "target output {} out of range for {} outputs", target, outputs.len());
+            opt.zero_grad();
+            (-outputs[target].clone()).backward();
+            opt.step();
+        }
+
+        params.iter().zip(trainable).for_each(|(p, t)| p.set_requires_grad(t));
+        xs.iter().map(|x| x.data()).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::{Activation, Layer, MLP};
+
+    fn output(model: &MLP, target: usize, x: &[f64]) -> f64 {
+        let xs: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+        Module::forward(model, &xs)[target].data()
+    }
+
+    #[test]
+    fn saliency_of_a_linear_model_is_its_weights() {
+        let layer = Layer::from_weights(&[vec![2.0, -1.0, 0.5], vec![0.0, 3.0, 1.0]], &[0.1, 0.0], Activation::Linear);
+        assert_eq!(saliency(&layer, 0, &[1.0, 2.0, 3.0]), vec![2.0, -1.0, 0.5]);
+        assert_eq!(saliency(&layer, 1, &[1.0, 2.0, 3.0]), vec![0.0, 3.0, 1.0]);
+        // for a linear model, integrated gradients are exactly weight * (input - baseline)
+        let ig = integrated_gradients(&layer, 0, &[1.0, 2.0, 3.0], &[0.0, 1.0, 1.0], 1);
+        assert_eq!(ig, vec![2.0, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn integrated_gradients_add_up_to_the_output_change() {
+        let model = MLP::new(3, vec![5, 2]);
+        let params = model.parameters();
+        params[0].set_grad(0.25);
+
+        let (input, baseline) = ([0.8, -0.4, 1.5], [0.0; 3]);
+        for target in 0..2 {
+            let ig = integrated_gradients(&model, target, &input, &baseline, 200);
+            let change = output(&model, target, &input) - output(&model, target, &baseline);
+            assert!((ig.iter().sum::<f64>() - change).abs() < 1e-4);
+        }
+        // the model's own gradients are as they were
+        assert_eq!(params[0].grad(), 0.25);
+        assert!(params[1..].iter().all(|p| p.grad() == 0.0));
+    }
+
+    #[test]
+    #[cfg(feature = "optim")]
+    fn optimized_input_raises_the_output_and_leaves_the_model_alone() {
+        let layer = Layer::from_weights(&[vec![2.0, -1.0]], &[0.0], Activation::Linear);
+        // a linear output is climbed along its weights
+        let climbed = optimize_input(&layer, 0, &[0.0, 0.0], 10, 0.1);
+        assert!((climbed[0] - 2.0).abs() < 1e-12 && (climbed[1] + 1.0).abs() < 1e-12);
+
+        let model = MLP::with_activations(2, vec![6, 3], Activation::Tanh, Activation::Tanh);
+        let params = model.parameters();
+        params[2].set_requires_grad(false);
+        let before: Vec<f64> = params.iter().map(|p| p.data()).collect();
+
+        let start = [0.1, -0.2];
+        let found = optimize_input(&model, 1, &start, 50, 0.05);
+        assert!(output(&model, 1, &found) > output(&model, 1, &start));
+        assert_eq!(params.iter().map(|p| p.data()).collect::<Vec<_>>(), before);
+        assert!(params.iter().enumerate().all(|(i, p)| p.requires_grad() == (i != 2) && p.grad() == 0.0));
+    }
+}
@@ -0,0 +1,221 @@
+//! Feature columns for tabular data: each column of a row of strings becomes one or more model
+//! inputs, so a CSV-like table can feed an `MLP` directly.
+//!
+//! Numeric columns pass through, bucketized ones become one-hot over ranges, and categorical
+//! ones become either a one-hot over their vocabulary or a learnable embedding. Categories
+//! outside the vocabulary share one extra out-of-vocabulary slot.
+
+use crate::operators::Value;
+use rand::Rng;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureError {
+    /// The row has a different number of cells than there are columns.
+    Arity { expected: usize, got: usize },
+    /// A numeric or bucketized cell is not a number.
+    Parse { column: String, cell: String },
+}
+
+impl fmt::Display for FeatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeatureError::Arity { expected, got } => write!(f, "row has {} cells but there are {} columns", got, expected),
+            FeatureError::Parse { column, cell } => write!(f, "column {}: {:?} is not a number", column, cell),
+        }
+    }
+}
+
+impl std::error::Error for FeatureError {}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Numeric,
+    /// Ascending boundaries; a value lands in the bucket of the number of boundaries `<=` it.
+    Buckets(Vec<f64>),
+    OneHot(Vec<String>),
+    /// One row per vocabulary entry, plus the out-of-vocabulary row.
+    Embedding(Vec<String>, Vec<Vec<Value>>),
+}
+
+#[derive(Debug, Clone)]
+struct Column {
+    name: String,
+    kind: Kind,
+}
+
+impl Column {
+    fn width(&self) -> usize {
+        match &self.kind {
+            Kind::Numeric => 1,
+            Kind::Buckets(b) => b.len() + 1,
+            Kind::OneHot(vocab) => vocab.len() + 1,
+            Kind::Embedding(_, table) => table[0].len(),
+        }
+    }
+
+    fn number(&self, cell: &str) -> Result<f64, FeatureError> {
+        cell.trim()
+            .parse()
+            .map_err(|_| FeatureError::Parse { column: self.name.clone(), cell: cell.to_string() })
+    }
+}
+
+// Vocabulary index of `cell`, or `vocab.len()` for the out-of-vocabulary slot
+fn lookup(vocab: &[String], cell: &str) -> usize {
+    vocab.iter().position(|v| v == cell).unwrap_or(vocab.len())
+}
+
+fn one_hot(width: usize, hot: usize) -> impl Iterator<Item = Value> {
+    (0..width).map(move |i| Value::from(if i == hot { 1.0 } else { 0.0 }))
+}
+
+/// The columns of a table, in the order of the cells of its rows.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureColumns {
+    columns: Vec<Column>,
+}
+
+impl FeatureColumns {
+    pub fn new() -> Self {
+        FeatureColumns::default()
+    }
+
+    fn with(mut self, name: &str, kind: Kind) -> Self {
+        self.columns.push(Column { name: name.to_string(), kind });
+        self
+    }
+
+    /// A number used as is.
+    pub fn with_numeric(self, name: &str) -> Self {
+        self.with(name, Kind::Numeric)
+    }
+
+    /// A number one-hot encoded by range: `boundaries` `[a, b]` give the buckets `x < a`,
+    /// `a <= x < b` and `b <= x`.
+    pub fn with_buckets(self, name: &str, boundaries: &[f64]) -> Self {
+        assert!(boundaries.windows(2).all(|w| w[0] < w[1]), "bucket boundaries must be ascending");
+        self.with(name, Kind::Buckets(boundaries.to_vec()))
+    }
+
+    /// A category one-hot encoded over `vocab`.
+    pub fn with_categorical(self, name: &str, vocab: &[&str]) -> Self {
+        self.with(name, Kind::OneHot(vocab.iter().map(|v| v.to_string()).collect()))
+    }
+
+    /// A category looked up in a table of `dim` learnable values per entry of `vocab`, returned
+    /// by `parameters` so it trains along with the model. The table starts uniform in `[-1, 1)`,
+    /// drawn from `rng`.
+    pub fn with_embedding<R: Rng>(self, name: &str, vocab: &[&str], dim: usize, rng: &mut R) -> Self {
+        assert!(dim > 0, "embedding dimension must be positive");
+        let table = (0..=vocab.len())
+            .map(|_| (0..dim).map(|_| Value::new(rng.gen_range(-1.0..1.0), "emb")).collect())
+            .collect();
+        self.with(name, Kind::Embedding(vocab.iter().map(|v| v.to_string()).collect(), table))
+    }
+
+    /// Number of model inputs a row turns into.
+    pub fn width(&self) -> usize {
+        self.columns.iter().map(Column::width).sum()
+    }
+
+    /// The embedding tables, for the optimizer.
+    pub fn parameters(&self) -> Vec<Value> {
+        self.columns
+            .iter()
+            .flat_map(|c| match &c.kind {
+                Kind::Embedding(_, table) => table.iter().flatten().cloned().collect(),
+                _ => vec![],
+            })
+            .collect()
+    }
+
+    /// Model inputs for one row. Embedding outputs are the table's own nodes, so gradients
+    /// reach them; everything else is a constant.
+    pub fn transform(&self, row: &[&str]) -> Result<Vec<Value>, FeatureError> {
+        if row.len() != self.columns.len() {
+            return Err(FeatureError::Arity { expected: self.columns.len(), got: row.len() });
+        }
+        let mut out = Vec::with_capacity(self.width());
+        for (column, cell) in self.columns.iter().zip(row) {
+            match &column.kind {
+                Kind::Numeric => out.push(Value::from(column.number(cell)?)),
+                Kind::Buckets(b) => {
+                    let x = column.number(cell)?;
+                    out.extend(one_hot(b.len() + 1, b.partition_point(|&edge| edge <= x)));
+                }
+                Kind::OneHot(vocab) => out.extend(one_hot(vocab.len() + 1, lookup(vocab, cell))),
+                Kind::Embedding(vocab, table) => out.extend(table[lookup(vocab, cell)].iter().cloned()),
+            }
+        }
+        Ok(out)
+    }
+
+    /// `transform` of every row.
+    pub fn transform_all(&self, rows: &[Vec<&str>]) -> Result<Vec<Vec<Value>>, FeatureError> {
+        rows.iter().map(|r| self.transform(r)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loss::mse;
+    use crate::nn::{Activation, MLP};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn data(xs: &[Value]) -> Vec<f64> {
+        xs.iter().map(|v| v.data()).collect()
+    }
+
+    #[test]
+    fn encodes_each_kind_of_column() {
+        let columns = FeatureColumns::new()
+            .with_numeric("age")
+            .with_buckets("income", &[10.0, 50.0])
+            .with_categorical("color", &["red", "green"]);
+        assert_eq!(columns.width(), 1 + 3 + 3);
+        assert_eq!(data(&columns.transform(&["31", "50", "green"]).unwrap()), [31.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(data(&columns.transform(&["2.5", "-1", "blue"]).unwrap()), [2.5, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+
+        assert_eq!(columns.transform(&["1", "2"]).unwrap_err(), FeatureError::Arity { expected: 3, got: 2 });
+        assert_eq!(
+            columns.transform(&["x", "2", "red"]).unwrap_err(),
+            FeatureError::Parse { column: "age".into(), cell: "x".into() }
+        );
+    }
+
+    #[test]
+    fn embeddings_train_with_the_model() {
+        let embed = |seed: u64| FeatureColumns::new().with_embedding("city", &["paris", "oslo", "lima"], 2, &mut StdRng::seed_from_u64(seed));
+        let data = |ps: &[Value]| ps.iter().map(|p| p.data()).collect::<Vec<_>>();
+        assert_eq!(data(&embed(1).parameters()), data(&embed(1).parameters()));
+        let columns = embed(1).with_numeric("x");
+        assert_eq!((columns.width(), columns.parameters().len()), (3, 4 * 2));
+        let rows: Vec<Vec<&str>> = vec![vec!["paris", "1"], vec!["oslo", "1"], vec!["lima", "-1"], vec!["rome", "0"]];
+        let targets = [0.5, -0.5, 0.2, 0.0];
+
+        let model = MLP::with_activations(3, vec![4, 1], Activation::Tanh, Activation::Linear);
+        let params: Vec<Value> = model.parameters().into_iter().chain(columns.parameters()).collect();
+        let loss = || {
+            let inputs = columns.transform_all(&rows).unwrap();
+            let preds: Vec<Value> = inputs.iter().map(|x| model.forward(x.clone())[0].clone()).collect();
+            let ys: Vec<Value> = targets.iter().map(|&y| Value::from(y)).collect();
+            mse(&preds, &ys)
+        };
+
+        let before = loss().data();
+        let start: Vec<f64> = data(&columns.parameters());
+        for _ in 0..100 {
+            let l = loss();
+            params.iter().for_each(|p| p.set_grad(0.0));
+            l.backward();
+            params.iter().for_each(|p| p.set_data(p.data() - 0.1 * p.grad()));
+        }
+        assert!(loss().data() < before);
+        // every row of the table moved, the out-of-vocabulary one included
+        let moved: Vec<bool> = data(&columns.parameters()).iter().zip(&start).map(|(a, b)| a != b).collect();
+        assert!(moved.chunks(2).all(|row| row.iter().any(|&m| m)));
+    }
+}
@@ -0,0 +1,228 @@
+//! Finite-difference checks of backward rules: nudges each input, replays the graph with
+//! `Value::recompute` and compares the slope against the gradient from `backward`.
+
+use crate::operators::Value;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GradCheckError {
+    /// The gradient of input `index` disagrees with its central difference.
+    Mismatch { index: usize, label: String, analytic: f64, numeric: f64 },
+    /// The graph contains an op with no forward rule, so it cannot be re-evaluated.
+    NotReplayable,
+}
+
+impl fmt::Display for GradCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GradCheckError::Mismatch { index, label, analytic, numeric } => write!(
+                f,
+                "gradient of input {} ({}) is {} but finite differences give {}",
+                index, label, analytic, numeric
+            ),
+            GradCheckError::NotReplayable => write!(f, "graph contains an op that cannot be recomputed"),
+        }
+    }
+}
+
+impl std::error::Error for GradCheckError {}
+
+/// Checks the gradient of `output` with respect to each of `inputs` against the central
+/// difference `(f(x + eps) - f(x - eps)) / 2eps`. A gradient passes when it is within `tol` of
+/// the estimate, relative to the larger of the two once they exceed 1.
+///
+/// The graph's gradients are overwritten, and its data is restored before returning.
+pub fn check_gradients(output: &Value, inputs: &[Value], eps: f64, tol: f64) -> Result<(), GradCheckError> {
+    assert!(eps > 0.0, "eps must be positive");
+    if !output.recompute() {
+        return Err(GradCheckError::NotReplayable);
+    }
+    output.backward_retain(false);
+    let analytic: Vec<f64> = inputs.iter().map(|x| x.grad()).collect();
+
+    let mut result = Ok(());
+    for (index, (x, &analytic)) in inputs.iter().zip(&analytic).enumerate() {
+        let start = x.data();
+        let at = |v: f64| {
+            x.set_data(v);
+            output.recompute();
+            output.data()
+        };
+        let numeric = (at(start + eps) - at(start - eps)) / (2.0 * eps);
+        x.set_data(start);
+
+        if !agrees(analytic, numeric, tol) {
+            result = Err(GradCheckError::Mismatch { index, label: x.borrow().label().to_string(), analytic, numeric });
+            break;
+        }
+    }
+    output.recompute();
+    result
+}
+
+// Within `tol` relative to the larger gradient once past 1; written so that a NaN on either
+// side fails
+fn agrees(analytic: f64, numeric: f64, tol: f64) -> bool {
+    let scale = analytic.abs().max(numeric.abs()).max(1.0);
+    (analytic - numeric).abs() / scale <= tol
+}
+
+// Finite-difference step of `Module::gradcheck`
+#[cfg(feature = "nn")]
+const MODULE_EPS: f64 = 1e-6;
+
+/// A parameter whose gradient disagrees with finite differences, by its path in the module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamMismatch {
+    pub path: String,
+    pub analytic: f64,
+    pub numeric: f64,
+}
+
+/// Outcome of `Module::gradcheck`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradcheckReport {
+    /// Number of parameters checked.
+    pub checked: usize,
+    pub failures: Vec<ParamMismatch>,
+}
+
+impl GradcheckReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for GradcheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} parameters disagree with finite differences", self.failures.len(), self.checked)?;
+        for m in &self.failures {
+            write!(f, "\n  {}: backward gives {}, finite differences {}", m.path, m.analytic, m.numeric)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks the gradient of `loss()` with respect to each named parameter against central
+// differences, rebuilding the loss without recording a graph for every nudge so that no op
+// needs a forward rule
+#[cfg(feature = "nn")]
+pub(crate) fn check_named(params: &[(String, Value)], loss: &dyn Fn() -> Value, tol: f64) -> GradcheckReport {
+    params.iter().for_each(|(_, p)| p.set_grad(0.0));
+    loss().backward_retain(false);
+    let analytic: Vec<f64> = params.iter().map(|(_, p)| p.grad()).collect();
+
+    let failures = params
+        .iter()
+        .zip(analytic)
+        .filter_map(|((path, p), analytic)| {
+            let start = p.data();
+            let at = |v: f64| {
+                p.set_data(v);
+                crate::operators::no_grad(|| loss().data())
+            };
+            let numeric = (at(start + MODULE_EPS) - at(start - MODULE_EPS)) / (2.0 * MODULE_EPS);
+            p.set_data(start);
+            let failed = !agrees(analytic, numeric, tol);
+            failed.then(|| ParamMismatch { path: path.clone(), analytic, numeric })
+        })
+        .collect();
+    GradcheckReport { checked: params.len(), failures }
+}
+
+/// Test helper for shared parameters: asserts that the gradient of `sum(losses)` equals the sum
+/// of the gradients of each loss taken on its own, within `tol`. Per-timestep losses of an
+/// unrolled network must pass, or some step's contribution is dropped or counted twice.
+///
+/// Overwrites the gradients of the graphs involved.
+pub fn assert_grad_is_sum(losses: &[Value], params: &[Value], tol: f64) {
+    let mut parts = vec![0.0; params.len()];
+    for loss in losses {
+        loss.backward_retain(false);
+        parts.iter_mut().zip(params).for_each(|(g, p)| *g += p.grad());
+    }
+    let total: Value = losses.iter().sum();
+    total.backward_retain(false);
+    for (i, (p, part)) in params.iter().zip(parts).enumerate() {
+        assert!(
+            (p.grad() - part).abs() <= tol * part.abs().max(1.0),
+            "gradient of parameter {} is {} but the per-loss gradients sum to {}",
+            i, p.grad(), part
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops;
+
+    const EPS: f64 = 1e-6;
+    const TOL: f64 = 1e-6;
+
+    fn inputs(xs: &[f64]) -> Vec<Value> {
+        xs.iter().enumerate().map(|(i, &x)| Value::new(x, &format!("x{}", i))).collect()
+    }
+
+    fn check(xs: &[f64], f: impl Fn(&[Value]) -> Value) {
+        let x = inputs(xs);
+        let out = f(&x);
+        check_gradients(&out, &x, EPS, TOL).unwrap();
+    }
+
+    #[test]
+    fn elementary_ops() {
+        check(&[0.7, -1.3], |x| x[0].clone() + x[1].clone());
+        check(&[0.7, -1.3], |x| x[0].clone() - x[1].clone());
+        check(&[0.7, -1.3], |x| x[0].clone() * x[1].clone());
+        check(&[0.7, -1.3], |x| x[0].clone() / x[1].clone());
+        check(&[1.7], |x| x[0].clone().powop(3));
+        check(&[1.7], |x| x[0].clone().powop(-0.5));
+        check(&[0.4], |x| x[0].clone().tanh());
+        check(&[0.4], |x| x[0].clone().exp());
+        check(&[0.4], |x| -x[0].clone());
+    }
+
+    #[test]
+    fn other_ops() {
+        check(&[2.5], |x| x[0].clone().log());
+        check(&[-0.8], |x| x[0].clone().abs());
+        check(&[0.8], |x| x[0].clone().relu());
+        check(&[-0.3], |x| x[0].clone().sigmoid());
+        check(&[2.5], |x| x[0].clone().sqrt());
+        check(&[0.2, -0.5, 1.0, 0.3, 0.4, -0.9], |x| ops::cosine_similarity(&x[..3], &x[3..]));
+        check(&[0.2, 0.5, 0.3, 0.4, 0.4, 0.2], |x| ops::kl_div(&x[..3], &x[3..]));
+        check(&[0.2, 0.5, 0.3], ops::entropy);
+    }
+
+    #[test]
+    fn composite_graph_with_shared_nodes() {
+        check(&[0.3, -0.6, 1.1], |x| {
+            let h = (x[0].clone() * x[1].clone() + x[2].clone()).tanh();
+            (h.clone() * h.clone() + h.exp()).powop(2) / (x[2].clone() + 2.0)
+        });
+    }
+
+    #[test]
+    fn reports_a_wrong_backward_rule() {
+        let x = inputs(&[0.5, 2.0]);
+        let wrong = Value::from_op(1.0, "wrong", &[&x[0], &x[1]], |_, g, d| vec![g * d[1], g * 2.0 * d[1]])
+            .with_forward(|d| d[0] * d[1]);
+        match check_gradients(&wrong, &x, EPS, TOL) {
+            Err(GradCheckError::Mismatch { index: 1, analytic, numeric, .. }) => {
+                assert!((analytic - 4.0).abs() < 1e-12 && (numeric - 0.5).abs() < 1e-6);
+            }
+            other => panic!("expected a mismatch on input 1, got {:?}", other),
+        }
+        // the data is put back
+        assert_eq!(x[1].data(), 2.0);
+        assert_eq!(wrong.data(), 1.0);
+    }
+
+    #[test]
+    fn ops_without_a_forward_rule_are_rejected() {
+        let x = inputs(&[0.5]);
+        let opaque = Value::from_op(0.5, "opaque", &[&x[0]], |_, g, _| vec![g]);
+        assert_eq!(check_gradients(&opaque, &x, EPS, TOL), Err(GradCheckError::NotReplayable));
+    }
+}
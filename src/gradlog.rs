@@ -0,0 +1,150 @@
+//! CSV log of parameter values and gradients over training, for post-hoc analysis.
+
+use crate::operators::Value;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// One logged `(step, parameter)` pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradRecord {
+    pub step: usize,
+    /// Position of the parameter in the list given to the logger.
+    pub param: usize,
+    pub data: f64,
+    pub grad: f64,
+}
+
+/// Appends the data and gradient of a fixed set of parameters to a CSV log, one row per
+/// parameter per recorded step.
+///
+/// Call `record` once per optimizer step, while the gradients are still there: after
+/// `Trainer::train_batch`/`partial_fit` (which zero them at the start of the next update) or
+/// between `backward` and `zero_grad` in a hand-written loop.
+pub struct GradLogger<W: Write> {
+    out: W,
+    params: Vec<Value>,
+    every: usize,
+    stride: usize,
+    step: usize,
+}
+
+impl GradLogger<BufWriter<File>> {
+    /// Logs to a new file at `path`, replacing any existing one.
+    pub fn create(path: impl AsRef<Path>, params: Vec<Value>) -> io::Result<Self> {
+        GradLogger::new(BufWriter::new(File::create(path)?), params)
+    }
+}
+
+impl<W: Write> GradLogger<W> {
+    /// Logs to `out`, writing the header right away.
+    pub fn new(mut out: W, params: Vec<Value>) -> io::Result<Self> {
+        writeln!(out, "step,param,data,grad")?;
+        Ok(GradLogger { out, params, every: 1, stride: 1, step: 0 })
+    }
+
+    /// Only writes every `n`th step, starting with the first.
+    pub fn every(mut self, n: usize) -> Self {
+        assert!(n > 0, "step interval must be positive");
+        self.every = n;
+        self
+    }
+
+    /// Only writes every `n`th parameter, starting with the first.
+    pub fn stride(mut self, n: usize) -> Self {
+        assert!(n > 0, "parameter stride must be positive");
+        self.stride = n;
+        self
+    }
+
+    /// Number of `record` calls so far, logged or skipped.
+    pub fn steps(&self) -> usize {
+        self.step
+    }
+
+    /// Marks the end of an optimizer step, writing the parameters if the step is not downsampled away.
+    pub fn record(&mut self) -> io::Result<()> {
+        let step = self.step;
+        self.step += 1;
+        if !step.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        for (i, p) in self.params.iter().enumerate().step_by(self.stride) {
+            writeln!(self.out, "{},{},{:e},{:e}", step, i, p.data(), p.grad())?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Parses a log written by `GradLogger`.
+pub fn parse(text: &str) -> Result<Vec<GradRecord>, String> {
+    let mut lines = text.lines();
+    if lines.next() != Some("step,param,data,grad") {
+        return Err("missing gradient log header".to_string());
+    }
+    lines
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(n, line)| {
+            let bad = |what: &str| format!("line {}: bad {}", n + 2, what);
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != 4 {
+                return Err(bad("field count"));
+            }
+            Ok(GradRecord {
+                step: fields[0].parse().map_err(|_| bad("step"))?,
+                param: fields[1].parse().map_err(|_| bad("parameter index"))?,
+                data: fields[2].parse().map_err(|_| bad("data"))?,
+                grad: fields[3].parse().map_err(|_| bad("grad"))?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(all(test, feature = "nn", feature = "optim", feature = "datasets"))]
+mod tests {
+    use super::*;
+    use crate::data::Batch;
+    use crate::loss::mse;
+    use crate::nn::MLP;
+    use crate::optim::SGD;
+    use crate::trainer::Trainer;
+
+    #[test]
+    fn downsampled_log_round_trips() {
+        let model = MLP::new(1, vec![3, 1]);
+        let params = model.parameters();
+        let opt = SGD::new(params.clone(), 0.1);
+        let mut trainer = Trainer::new(model, opt, mse);
+        let mut logger = GradLogger::new(Vec::new(), params.clone()).unwrap().every(3).stride(2);
+
+        let batch = Batch { inputs: vec![vec![0.5], vec![-0.5]], targets: vec![vec![0.2], vec![-0.2]] };
+        for _ in 0..7 {
+            trainer.train_batch(&batch);
+            logger.record().unwrap();
+        }
+        assert_eq!(logger.steps(), 7);
+
+        let text = String::from_utf8(logger.finish().unwrap()).unwrap();
+        let records = parse(&text).unwrap();
+        let logged_params = params.len().div_ceil(2);
+        assert_eq!(records.len(), 3 * logged_params);
+        assert_eq!(records[0].step, 0);
+        assert_eq!(records.last().unwrap().step, 6);
+        assert!(records.iter().all(|r| r.param % 2 == 0));
+
+        // the last rows are the current state of the model
+        for r in &records[records.len() - logged_params..] {
+            assert_eq!(r.data, params[r.param].data());
+            assert_eq!(r.grad, params[r.param].grad());
+        }
+        assert_eq!(parse("step,param,data,grad\n"), Ok(vec![]));
+        assert!(parse("nope\n").is_err());
+    }
+}
@@ -0,0 +1,636 @@
+//! Arena-backed alternative to `Value`: a `Graph` owns every node in one slab and hands out
+//! `NodeId` handles, so there is no `Rc`/`RefCell` per node and no closure per op.
+//!
+//! Nodes are only ever appended, so a node's parents always sit at lower indices and the slab
+//! is already in topological order. Dropping everything built after a `checkpoint` (an epoch's
+//! forward graph, say) is a truncation; handles into the dropped part go stale and are caught by
+//! their generation. `Graph::scope` does the same around a closure, keeping only the nodes it
+//! promotes.
+
+use crate::math;
+
+/// Handle to a node of a `Graph`. Only meaningful for the graph that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+/// How a node was computed from its parents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Leaf,
+    Add(u32, u32),
+    Sub(u32, u32),
+    Mul(u32, u32),
+    Div(u32, u32),
+    Pow(u32, f64),
+    Tanh(u32),
+    Exp(u32),
+    Log(u32),
+    Relu(u32),
+}
+
+/// One slab entry: plain data, so a whole graph can be copied or written out as is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Node {
+    pub data: f64,
+    pub grad: f64,
+    pub op: Op,
+    generation: u32,
+}
+
+/// Everything built after the `Graph::checkpoint` call that returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+/// Owns the nodes; ops are methods taking and returning `NodeId`s, e.g. `g.add(a, b)`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    // generation of the nodes created now, and the last one handed out
+    generation: u32,
+    generations: u32,
+    scopes: Vec<Scope>,
+    // nodes given a new value by `set_data` since the last `recompute`
+    dirty: Vec<u32>,
+}
+
+// An open `Graph::scope`: where it started, the generation its survivors will carry, and the
+// indices of the nodes promoted so far, `DROPPED` for those rewound since
+#[derive(Debug, Clone, PartialEq)]
+struct Scope {
+    start: usize,
+    generation: u32,
+    kept: Vec<u32>,
+}
+
+// Promoted slot whose node was rewound away; its handle goes stale when the scope ends
+const DROPPED: u32 = u32::MAX;
+
+impl Graph {
+    pub fn new() -> Self {
+        Graph::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// All nodes in creation (and so topological) order.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Whether `id` still refers to a live node of this graph.
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.nodes.get(id.index as usize).is_some_and(|n| n.generation == id.generation)
+    }
+
+    fn index(&self, id: NodeId) -> u32 {
+        assert!(self.contains(id), "stale or foreign NodeId {:?}", id);
+        id.index
+    }
+
+    fn push(&mut self, data: f64, op: Op) -> NodeId {
+        let index = u32::try_from(self.nodes.len()).expect("graph is full");
+        self.nodes.push(Node { data, grad: 0.0, op, generation: self.generation });
+        NodeId { index, generation: self.generation }
+    }
+
+    pub fn leaf(&mut self, data: f64) -> NodeId {
+        self.push(data, Op::Leaf)
+    }
+
+    pub fn data(&self, id: NodeId) -> f64 {
+        self.nodes[self.index(id) as usize].data
+    }
+
+    /// Sets a node's value. Nodes computed from it keep their old values until `recompute`.
+    pub fn set_data(&mut self, id: NodeId, data: f64) {
+        let i = self.index(id);
+        self.nodes[i as usize].data = data;
+        if !self.dirty.contains(&i) {
+            self.dirty.push(i);
+        }
+    }
+
+    /// Re-evaluates only the nodes downstream of those changed by `set_data` since the last call,
+    /// and returns how many that was. The changed nodes themselves keep the values they were set to.
+    pub fn recompute(&mut self) -> usize {
+        let Some(&start) = self.dirty.iter().min() else {
+            return 0;
+        };
+        let start = start as usize;
+        let mut changed = vec![false; self.nodes.len() - start];
+        for i in self.dirty.drain(..) {
+            changed[i as usize - start] = true;
+        }
+
+        let mut count = 0;
+        for i in start..self.nodes.len() {
+            if changed[i - start] {
+                continue;
+            }
+            let op = self.nodes[i].op;
+            if parents(op).any(|p| p as usize >= start && changed[p as usize - start]) {
+                self.nodes[i].data = self.eval(op);
+                changed[i - start] = true;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    // Forward value of `op` from the current values of its parents
+    fn eval(&self, op: Op) -> f64 {
+        let d = |j: u32| self.nodes[j as usize].data;
+        match op {
+            Op::Leaf => unreachable!("leaves have no parents to evaluate"),
+            Op::Add(a, b) => d(a) + d(b),
+            Op::Sub(a, b) => d(a) - d(b),
+            Op::Mul(a, b) => d(a) * d(b),
+            Op::Div(a, b) => d(a) / d(b),
+            Op::Pow(a, e) => math::powf(d(a), e),
+            Op::Tanh(a) => math::tanh(d(a)),
+            Op::Exp(a) => math::exp(d(a)),
+            Op::Log(a) => {
+                assert!(d(a) > 0.0, "log of a non-positive value");
+                math::ln(d(a))
+            }
+            Op::Relu(a) => d(a).max(0.0),
+        }
+    }
+
+    fn push_op(&mut self, op: Op) -> NodeId {
+        self.push(self.eval(op), op)
+    }
+
+    pub fn grad(&self, id: NodeId) -> f64 {
+        self.nodes[self.index(id) as usize].grad
+    }
+
+    pub fn set_grad(&mut self, id: NodeId, grad: f64) {
+        let i = self.index(id) as usize;
+        self.nodes[i].grad = grad;
+    }
+
+    pub fn add(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let (a, b) = (self.index(a), self.index(b));
+        self.push_op(Op::Add(a, b))
+    }
+
+    pub fn sub(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let (a, b) = (self.index(a), self.index(b));
+        self.push_op(Op::Sub(a, b))
+    }
+
+    pub fn mul(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let (a, b) = (self.index(a), self.index(b));
+        self.push_op(Op::Mul(a, b))
+    }
+
+    pub fn div(&mut self, a: NodeId, b: NodeId) -> NodeId {
+        let (a, b) = (self.index(a), self.index(b));
+        self.push_op(Op::Div(a, b))
+    }
+
+    pub fn pow(&mut self, a: NodeId, exponent: f64) -> NodeId {
+        let a = self.index(a);
+        self.push_op(Op::Pow(a, exponent))
+    }
+
+    pub fn tanh(&mut self, a: NodeId) -> NodeId {
+        let a = self.index(a);
+        self.push_op(Op::Tanh(a))
+    }
+
+    pub fn exp(&mut self, a: NodeId) -> NodeId {
+        let a = self.index(a);
+        self.push_op(Op::Exp(a))
+    }
+
+    /// Natural logarithm; panics for non-positive inputs.
+    pub fn log(&mut self, a: NodeId) -> NodeId {
+        let a = self.index(a);
+        self.push_op(Op::Log(a))
+    }
+
+    /// `max(0, x)`, with a subgradient of 0 at 0.
+    pub fn relu(&mut self, a: NodeId) -> NodeId {
+        let a = self.index(a);
+        self.push_op(Op::Relu(a))
+    }
+
+    /// Sum of `ids`; 0 for an empty slice.
+    pub fn sum(&mut self, ids: &[NodeId]) -> NodeId {
+        match ids.split_first() {
+            None => self.leaf(0.0),
+            Some((first, rest)) => rest.iter().fold(*first, |acc, &id| self.add(acc, id)),
+        }
+    }
+
+    /// Backpropagates from `root`, seeding its gradient with 1.0. Gradients accumulate, as with
+    /// `Value::backward`; only nodes `root` depends on are touched.
+    pub fn backward(&mut self, root: NodeId) {
+        let root = self.index(root) as usize;
+        let mut reachable = vec![false; root + 1];
+        reachable[root] = true;
+        for i in (0..=root).rev() {
+            if reachable[i] {
+                for p in parents(self.nodes[i].op) {
+                    reachable[p as usize] = true;
+                }
+            }
+        }
+
+        self.nodes[root].grad = 1.0;
+        for i in (0..=root).rev().filter(|&i| reachable[i]) {
+            let Node { data: out, grad: g, op, .. } = self.nodes[i];
+            let d = |j: u32| self.nodes[j as usize].data;
+            let contributions: [(u32, f64); 2] = match op {
+                Op::Leaf => continue,
+                Op::Add(a, b) => [(a, g), (b, g)],
+                Op::Sub(a, b) => [(a, g), (b, -g)],
+                Op::Mul(a, b) => [(a, g * d(b)), (b, g * d(a))],
+                Op::Div(a, b) => [(a, g / d(b)), (b, -g * d(a) / (d(b) * d(b)))],
+                Op::Pow(a, e) => [(a, g * e * math::powf(d(a), e - 1.0)), (a, 0.0)],
+                Op::Tanh(a) => [(a, g * (1.0 - out * out)), (a, 0.0)],
+                Op::Exp(a) => [(a, g * out), (a, 0.0)],
+                Op::Log(a) => [(a, g / d(a)), (a, 0.0)],
+                Op::Relu(a) => [(a, if d(a) > 0.0 { g } else { 0.0 }), (a, 0.0)],
+            };
+            for (j, c) in contributions {
+                self.nodes[j as usize].grad += c;
+            }
+        }
+    }
+
+    /// Sets every gradient in the graph to zero.
+    pub fn zero_grad(&mut self) {
+        for n in &mut self.nodes {
+            n.grad = 0.0;
+        }
+    }
+
+    /// Marks the current end of the graph, typically right after creating the parameters.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.nodes.len())
+    }
+
+    /// Drops every node created after `checkpoint` at once. Their `NodeId`s become stale, while
+    /// those of older nodes stay valid; so do the handles `promote` returned for them.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        assert!(checkpoint.0 <= self.nodes.len(), "checkpoint is past the end of the graph");
+        if let Some(scope) = self.scopes.last_mut() {
+            assert!(checkpoint.0 >= scope.start, "cannot rewind past the start of the open scope");
+            for k in scope.kept.iter_mut().filter(|k| **k as usize >= checkpoint.0) {
+                *k = DROPPED;
+            }
+        }
+        self.truncate(checkpoint.0);
+        self.generation = self.fresh_generation();
+    }
+
+    /// Dead code elimination: drops every node created after `since` that none of `roots`
+    /// depends on, such as discarded branches of an exploratory session, so `backward` and
+    /// `recompute` no longer step over them. Returns the new handles of `roots`, in order.
+    ///
+    /// Surviving nodes after `since` move, so all other handles to them go stale, as do
+    /// checkpoints taken after `since`. Nodes promoted in the open scope are kept.
+    pub fn eliminate_dead(&mut self, since: Checkpoint, roots: &[NodeId]) -> Vec<NodeId> {
+        let (start, len) = (since.0, self.nodes.len());
+        assert!(start <= len, "checkpoint is past the end of the graph");
+        let kept = match self.scopes.last() {
+            Some(scope) => {
+                assert!(start >= scope.start, "cannot eliminate nodes from before the open scope");
+                scope.kept.clone()
+            }
+            None => vec![],
+        };
+        let roots: Vec<u32> = roots.iter().map(|&id| self.index(id)).collect();
+
+        // Parents sit at lower indices, so one backwards sweep finds everything the roots need
+        let mut live = vec![false; len - start];
+        for &i in roots.iter().chain(kept.iter().filter(|&&k| k != DROPPED)) {
+            if i as usize >= start {
+                live[i as usize - start] = true;
+            }
+        }
+        for i in (start..len).rev() {
+            if live[i - start] {
+                for p in parents(self.nodes[i].op) {
+                    if p as usize >= start {
+                        live[p as usize - start] = true;
+                    }
+                }
+            }
+        }
+
+        // new index of every node after `start`, u32::MAX for the dead
+        let mut next = start as u32;
+        let moved: Vec<u32> = live
+            .iter()
+            .map(|&l| {
+                next += l as u32;
+                if l { next - 1 } else { u32::MAX }
+            })
+            .collect();
+        let remap = |i: u32| if (i as usize) < start || i == DROPPED { i } else { moved[i as usize - start] };
+
+        let generation = self.fresh_generation();
+        let survivors: Vec<Node> = (start..len)
+            .filter(|&i| live[i - start])
+            .map(|i| Node { op: map_parents(self.nodes[i].op, remap), generation, ..self.nodes[i] })
+            .collect();
+        self.nodes.truncate(start);
+        self.nodes.extend(survivors);
+        self.generation = generation;
+        self.dirty = self.dirty.iter().map(|&i| remap(i)).filter(|&i| i != u32::MAX).collect();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.kept.iter_mut().for_each(|k| *k = remap(*k));
+        }
+
+        roots
+            .into_iter()
+            .map(|i| {
+                let index = remap(i);
+                NodeId { index, generation: self.nodes[index as usize].generation }
+            })
+            .collect()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.nodes.truncate(len);
+        self.dirty.retain(|&i| (i as usize) < len);
+    }
+
+    fn fresh_generation(&mut self) -> u32 {
+        self.generations = self.generations.checked_add(1).expect("out of graph generations");
+        self.generations
+    }
+
+    /// Runs `f` and then drops every node it created, except those passed to `promote`, which
+    /// survive as leaves holding their data and grad. Use it around one epoch or one training step
+    /// so retained losses and intermediates cannot pile up. Scopes nest.
+    pub fn scope<R>(&mut self, f: impl FnOnce(&mut Graph) -> R) -> R {
+        let generation = self.fresh_generation();
+        self.scopes.push(Scope { start: self.nodes.len(), generation, kept: vec![] });
+        let result = f(self);
+
+        let scope = self.scopes.pop().expect("scope stack out of balance");
+        // rewound slots keep their place, so the other handles stay right, under a generation
+        // no handle carries
+        let dropped = if scope.kept.contains(&DROPPED) { self.fresh_generation() } else { generation };
+        let survivors: Vec<Node> = scope
+            .kept
+            .iter()
+            .map(|&i| match i {
+                DROPPED => Node { data: 0.0, grad: 0.0, op: Op::Leaf, generation: dropped },
+                i => Node { op: Op::Leaf, generation, ..self.nodes[i as usize] },
+            })
+            .collect();
+        self.truncate(scope.start);
+        self.nodes.extend(survivors);
+        self.generation = generation;
+        result
+    }
+
+    /// Keeps `id` alive past the innermost open `scope`, returning the handle it will have there.
+    /// That handle only becomes valid once the scope ends; nodes from before the scope are
+    /// returned unchanged.
+    pub fn promote(&mut self, id: NodeId) -> NodeId {
+        let index = self.index(id);
+        let scope = self.scopes.last_mut().expect("promote called outside of a scope");
+        if (index as usize) < scope.start {
+            return id;
+        }
+        let slot = match scope.kept.iter().position(|&k| k == index) {
+            Some(slot) => slot,
+            None => {
+                scope.kept.push(index);
+                scope.kept.len() - 1
+            }
+        };
+        NodeId { index: (scope.start + slot) as u32, generation: scope.generation }
+    }
+}
+
+fn parents(op: Op) -> impl Iterator<Item = u32> {
+    let (a, b) = match op {
+        Op::Leaf => (None, None),
+        Op::Add(a, b) | Op::Sub(a, b) | Op::Mul(a, b) | Op::Div(a, b) => (Some(a), Some(b)),
+        Op::Pow(a, _) | Op::Tanh(a) | Op::Exp(a) | Op::Log(a) | Op::Relu(a) => (Some(a), None),
+    };
+    a.into_iter().chain(b)
+}
+
+// `op` with each parent index passed through `f`
+fn map_parents(op: Op, f: impl Fn(u32) -> u32) -> Op {
+    match op {
+        Op::Leaf => Op::Leaf,
+        Op::Add(a, b) => Op::Add(f(a), f(b)),
+        Op::Sub(a, b) => Op::Sub(f(a), f(b)),
+        Op::Mul(a, b) => Op::Mul(f(a), f(b)),
+        Op::Div(a, b) => Op::Div(f(a), f(b)),
+        Op::Pow(a, e) => Op::Pow(f(a), e),
+        Op::Tanh(a) => Op::Tanh(f(a)),
+        Op::Exp(a) => Op::Exp(f(a)),
+        Op::Log(a) => Op::Log(f(a)),
+        Op::Relu(a) => Op::Relu(f(a)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::Value;
+
+    #[test]
+    fn matches_value_graph() {
+        let mut g = Graph::new();
+        let (x, w, b) = (g.leaf(0.5), g.leaf(-1.5), g.leaf(0.2));
+        let xw = g.mul(x, w);
+        let z = g.add(xw, b);
+        let t = g.tanh(z);
+        let e = g.exp(x);
+        let l = g.log(e);
+        let q = g.div(t, l);
+        let r = g.relu(b);
+        let p = g.pow(r, 3.0);
+        let s = g.sub(q, p);
+        let out = g.sum(&[s, x]);
+        g.backward(out);
+
+        let (vx, vw, vb) = (Value::new(0.5, "x"), Value::new(-1.5, "w"), Value::new(0.2, "b"));
+        let vt = (vx.clone() * vw.clone() + vb.clone()).tanh();
+        let vq = vt / vx.clone().exp().log();
+        let vout = vq - vb.clone().relu().powop(3.0) + vx.clone();
+        vout.backward();
+
+        assert!((g.data(out) - vout.data()).abs() < 1e-12);
+        for (id, v) in [(x, &vx), (w, &vw), (b, &vb)] {
+            assert!((g.grad(id) - v.grad()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn rewind_frees_an_epoch_and_keeps_parameters() {
+        let mut g = Graph::new();
+        let w = g.leaf(3.0);
+        let params = g.checkpoint();
+        let mut stale = None;
+
+        for _ in 0..3 {
+            let sq = g.pow(w, 2.0);
+            g.zero_grad();
+            g.backward(sq);
+            let step = g.data(w) - 0.1 * g.grad(w);
+            g.set_data(w, step);
+            stale = Some(sq);
+            g.rewind(params);
+            assert_eq!(g.len(), 1);
+        }
+        assert!((g.data(w) - 3.0 * 0.8f64.powi(3)).abs() < 1e-12);
+
+        // a node created in the new generation reuses the slot but not the handle
+        let fresh = g.leaf(1.0);
+        assert!(g.contains(w) && g.contains(fresh));
+        assert!(!g.contains(stale.unwrap()));
+    }
+
+    #[test]
+    #[should_panic(expected = "stale or foreign NodeId")]
+    fn stale_ids_are_rejected() {
+        let mut g = Graph::new();
+        let start = g.checkpoint();
+        let a = g.leaf(1.0);
+        g.rewind(start);
+        g.leaf(2.0);
+        g.data(a);
+    }
+
+    #[test]
+    fn scope_keeps_only_promoted_nodes() {
+        let mut g = Graph::new();
+        let w = g.leaf(2.0);
+        let params = g.checkpoint();
+        let mut losses = Vec::new();
+        let mut inner_stale = None;
+
+        for _ in 0..4 {
+            let (loss, kept_w) = g.scope(|g| {
+                let sq = g.pow(w, 2.0);
+                let loss = g.mul(sq, w);
+                g.backward(loss);
+                let inner = g.scope(|g| {
+                    let tmp = g.add(loss, w);
+                    inner_stale = Some(tmp);
+                    g.promote(tmp)
+                });
+                assert!(g.contains(inner) && g.data(inner) == g.data(loss) + 2.0);
+                (g.promote(loss), g.promote(w))
+            });
+            assert_eq!(kept_w, w);
+            assert_eq!(g.len(), 2);
+            assert_eq!(g.data(loss), 8.0);
+            assert_eq!(g.grad(loss), 1.0);
+            assert_eq!(g.nodes()[1].op, Op::Leaf);
+            losses.push(loss);
+            g.zero_grad();
+            g.rewind(params);
+        }
+        // memory stays flat across epochs, and old handles are recognised as stale
+        assert_eq!(g.len(), 1);
+        assert!(losses.iter().all(|l| !g.contains(*l)));
+        assert!(!g.contains(inner_stale.unwrap()));
+        assert!(g.contains(w));
+    }
+
+    #[test]
+    fn rewinding_inside_a_scope_forgets_promotions_past_the_checkpoint() {
+        let mut g = Graph::new();
+        let w = g.leaf(2.0);
+        let (kept, dropped) = g.scope(|g| {
+            let early = g.mul(w, w);
+            let mark = g.checkpoint();
+            let late = g.add(early, w);
+            let dropped = g.promote(late);
+            g.rewind(mark);
+            let again = g.tanh(early);
+            assert_eq!(g.data(again), math::tanh(4.0));
+            (g.promote(early), dropped)
+        });
+        assert!(g.contains(kept) && !g.contains(dropped));
+        assert_eq!((g.data(kept), g.len()), (4.0, 3));
+    }
+
+    #[test]
+    fn recompute_only_touches_descendants() {
+        let mut g = Graph::new();
+        let (x, y) = (g.leaf(1.0), g.leaf(2.0));
+        // a long chain on y that does not depend on x
+        let mut chain = y;
+        for _ in 0..50 {
+            chain = g.tanh(chain);
+        }
+        let x2 = g.pow(x, 2.0);
+        let e = g.exp(x2);
+        let out = g.add(e, chain);
+        assert_eq!(g.recompute(), 0);
+
+        g.set_data(x, 3.0);
+        assert_eq!(g.data(out), math::exp(1.0) + g.data(chain));
+        assert_eq!(g.recompute(), 3);
+        assert_eq!(g.data(out), math::exp(9.0) + g.data(chain));
+
+        // same values as building the graph from scratch
+        let mut fresh = Graph::new();
+        let fx = fresh.leaf(3.0);
+        let fx2 = fresh.pow(fx, 2.0);
+        assert_eq!(g.data(e), math::exp(fresh.data(fx2)));
+        assert_eq!(g.recompute(), 0);
+    }
+
+    #[test]
+    fn eliminate_dead_drops_discarded_branches() {
+        let mut g = Graph::new();
+        let (x, w) = (g.leaf(0.5), g.leaf(-1.5));
+        let session = g.checkpoint();
+
+        // abandoned attempts around the expression that is finally used
+        let mut discarded = g.exp(x);
+        for _ in 0..20 {
+            discarded = g.tanh(discarded);
+        }
+        let xw = g.mul(x, w);
+        let _unused = g.relu(xw);
+        let c = g.leaf(0.2);
+        let h = g.add(xw, c);
+        let out = g.tanh(h);
+        let other = g.pow(w, 2.0);
+        assert_eq!(g.len(), 2 + 21 + 6);
+
+        let mut reference = g.clone();
+        reference.backward(out);
+
+        g.set_data(c, 0.3);
+        let new = g.eliminate_dead(session, &[out, other]);
+        let (out, other) = (new[0], new[1]);
+        assert_eq!(g.len(), 2 + 5);
+        assert!(g.contains(x) && g.contains(w) && g.contains(out));
+        assert!(!g.contains(discarded) && !g.contains(h));
+        assert_eq!(g.data(other), 2.25);
+
+        g.backward(out);
+        assert_eq!((g.grad(x), g.grad(w)), (reference.grad(x), reference.grad(w)));
+
+        // the pending set_data moved along with its node
+        assert_eq!(g.recompute(), 2);
+        assert_eq!(g.data(out), math::tanh(-0.75 + 0.3));
+    }
+}
@@ -1,2 +1,73 @@
 pub mod operators;
+#[cfg(feature = "nn")]
+pub mod attack;
+#[cfg(feature = "rand")]
+pub mod baseline;
+pub mod complex;
+pub mod custom;
+#[cfg(feature = "datasets")]
+pub mod data;
+pub mod diagnostics;
+pub mod dsl;
+#[cfg(feature = "nn")]
+pub mod evolve;
+pub mod experiment;
+#[cfg(feature = "nn")]
+pub mod explain;
+#[cfg(feature = "nn")]
+pub mod features;
+pub mod gradcheck;
+pub mod gradlog;
+pub mod graph;
+#[cfg(feature = "datasets")]
+pub mod datasets;
+pub mod loss;
+pub mod math;
+pub mod memo;
+pub mod metrics;
+#[cfg(feature = "nn")]
 pub mod nn;
+#[cfg(feature = "rand")]
+pub mod noise;
+#[cfg(feature = "evcxr")]
+pub mod notebook;
+pub mod ops;
+#[cfg(feature = "optim")]
+pub mod optim;
+pub mod profile;
+#[cfg(feature = "nn")]
+pub mod prune;
+#[cfg(feature = "optim")]
+pub mod scheduler;
+#[cfg(feature = "rand")]
+pub mod search;
+#[cfg(feature = "nn")]
+pub mod store;
+pub mod tensor;
+#[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+pub mod trainer;
+#[cfg(feature = "datasets")]
+pub mod transform;
+pub mod uncertainty;
+pub mod units;
+pub mod vector;
+#[cfg(feature = "viz-server")]
+pub mod viz;
+
+/// Commonly used types, `use micrograd_rs::prelude::*;` to get started.
+pub mod prelude {
+    pub use crate::operators::{deterministic_backward, lazy, no_grad, GraphNode, NodeView, Value};
+    #[cfg(feature = "datasets")]
+    pub use crate::data::{DataLoader, Dataset};
+    #[cfg(feature = "nn")]
+    pub use crate::nn::{eval_mode, Activation, Backend, Layer, MLPBuilder, Module, Neuron, Residual, StochasticDepth, MLP};
+    pub use crate::loss::{
+        binary_cross_entropy, contrastive, focal, hinge, l1_penalty, l2_penalty, mse, mse_batch, multi_task, triplet, TaskWeights,
+    };
+    #[cfg(feature = "optim")]
+    pub use crate::optim::{Adam, Optimizer, SGD};
+    pub use crate::tensor::Tensor;
+    #[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+    pub use crate::trainer::{Callback, GradFlow, History, StopReason, Trainer, TrainingSession};
+    pub use crate::vector::Vector;
+}
@@ -0,0 +1,312 @@
+use crate::operators::*;
+
+/// Mean squared error between predictions and targets of the same length. Like every mean loss
+/// here, it is 0 for no predictions rather than NaN.
+pub fn mse(preds: &[Value], targets: &[Value]) -> Value {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    let n = preds.len().max(1) as f64;
+    preds
+        .iter()
+        .zip(targets)
+        .fold(Value::from(0.0), |acc, (p, t)| acc + (p.clone() - t.clone()).powop(2))
+        / n
+}
+
+/// Mean squared error over a batch of multi-output predictions, averaged over every sample and output.
+pub fn mse_batch(preds: &[Vec<Value>], targets: &[Vec<f64>]) -> Value {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    let count: usize = preds.iter().map(|p| p.len()).sum();
+    let total = preds.iter().zip(targets).fold(Value::from(0.0), |acc, (p, t)| {
+        assert_eq!(p.len(), t.len(), "prediction and target widths differ");
+        p.iter().zip(t).fold(acc, |acc, (p, &t)| acc + (p.clone() - t).powop(2))
+    });
+    total / count.max(1) as f64
+}
+
+/// Mean squared error of each output column over a batch, for reporting. Not differentiable.
+pub fn per_output_mse(preds: &[Vec<f64>], targets: &[Vec<f64>]) -> Vec<f64> {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    let width = targets.first().map_or(0, |t| t.len());
+    let mut sums = vec![0.0; width];
+    for (p, t) in preds.iter().zip(targets) {
+        assert!(p.len() == width && t.len() == width, "prediction and target widths differ");
+        for (s, (p, t)) in sums.iter_mut().zip(p.iter().zip(t)) {
+            *s += (p - t) * (p - t);
+        }
+    }
+    sums.iter().map(|s| s / preds.len().max(1) as f64).collect()
+}
+
+// Added inside logs so probabilities of exactly 0 or 1 give finite losses
+const LOG_EPS: f64 = 1e-12;
+
+/// Mean binary cross-entropy `-(t ln p + (1 - t) ln(1 - p))` of predicted probabilities against
+/// targets in `[0, 1]`.
+pub fn binary_cross_entropy(preds: &[Value], targets: &[Value]) -> Value {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    let n = preds.len().max(1) as f64;
+    preds
+        .iter()
+        .zip(targets)
+        .map(|(p, t)| -(t.clone() * (p + LOG_EPS).log() + (-t + 1.0) * (-p + 1.0 + LOG_EPS).log()))
+        .sum::<Value>()
+        / n
+}
+
+/// Mean hinge loss `max(0, 1 - t * p)` of raw scores against targets of `-1` or `1`.
+pub fn hinge(preds: &[Value], targets: &[Value]) -> Value {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    let n = preds.len().max(1) as f64;
+    preds.iter().zip(targets).map(|(p, t)| (-(p.clone() * t.clone()) + 1.0).relu()).sum::<Value>() / n
+}
+
+/// How `multi_task` weighs the individual task losses.
+#[derive(Debug, Clone, Copy)]
+pub enum TaskWeights<'a> {
+    /// Constant weight per task.
+    Fixed(&'a [f64]),
+    /// Learnable log-variance `s` per task, giving `0.5 * exp(-s) * loss + 0.5 * s` (Kendall et al.).
+    /// Pass the log-variances to the optimizer alongside the model parameters.
+    Uncertainty(&'a [Value]),
+}
+
+/// Combines several task losses into one scalar to backpropagate.
+pub fn multi_task(losses: &[Value], weights: TaskWeights) -> Value {
+    let n = match weights {
+        TaskWeights::Fixed(w) => w.len(),
+        TaskWeights::Uncertainty(s) => s.len(),
+    };
+    assert_eq!(losses.len(), n, "need one weight per task loss");
+    losses.iter().enumerate().fold(Value::from(0.0), |acc, (i, loss)| match weights {
+        TaskWeights::Fixed(w) => acc + loss.clone() * w[i],
+        TaskWeights::Uncertainty(s) => {
+            let precision = (s[i].clone() * -1.0).exp();
+            acc + precision * loss.clone() * 0.5 + s[i].clone() * 0.5
+        }
+    })
+}
+
+/// Log-probabilities of `logits` under a softmax, shifted by the max logit so `exp` never overflows.
+pub fn log_softmax(logits: &[Value]) -> Vec<Value> {
+    assert!(!logits.is_empty(), "log_softmax of no logits");
+    let max = logits.iter().map(|v| v.data()).fold(f64::NEG_INFINITY, f64::max);
+    let sum = logits.iter().fold(Value::from(0.0), |acc, x| acc + (x.clone() - max).exp());
+    // sum >= 1 since the max term is exp(0)
+    let log_sum = sum.log() + max;
+    logits.iter().map(|x| x.clone() - log_sum.clone()).collect()
+}
+
+/// Focal loss `-alpha[t] * (1 - p_t)^gamma * log(p_t)` for one sample of class `target`.
+///
+/// `gamma` down-weights examples the model already gets right; with `gamma = 0` and no `alpha`
+/// this is plain cross-entropy. `alpha` optionally weighs each class, e.g. up for rare ones.
+pub fn focal(logits: &[Value], target: usize, gamma: f64, alpha: Option<&[f64]>) -> Value {
+    assert!(target < logits.len(), "target class {target} out of range for {} logits", logits.len());
+    let log_p = log_softmax(logits)[target].clone();
+    let mut loss = log_p.clone() * -1.0;
+    if gamma != 0.0 {
+        let p = log_p.exp();
+        loss = (p * -1.0 + 1.0).powop(gamma) * loss;
+    }
+    match alpha {
+        Some(alpha) => {
+            assert_eq!(alpha.len(), logits.len(), "need one alpha per class");
+            loss * alpha[target]
+        }
+        None => loss,
+    }
+}
+
+// Squared euclidean distance between two embeddings
+fn squared_distance(a: &[Value], b: &[Value]) -> Value {
+    assert_eq!(a.len(), b.len(), "embeddings differ in length");
+    a.iter().zip(b).fold(Value::from(0.0), |acc, (a, b)| acc + (a.clone() - b.clone()).powop(2))
+}
+
+/// Contrastive loss for a pair of embeddings: `d^2` when `similar`, otherwise `max(0, margin - d)^2`,
+/// with `d` the euclidean distance.
+pub fn contrastive(a: &[Value], b: &[Value], similar: bool, margin: f64) -> Value {
+    let d2 = squared_distance(a, b);
+    if similar {
+        return d2;
+    }
+    // the epsilon keeps sqrt differentiable when the pair collapses onto one point
+    let d = (d2 + 1e-12).powop(0.5);
+    (d * -1.0 + margin).relu().powop(2)
+}
+
+/// Triplet loss `max(0, |anchor - pos|^2 - |anchor - neg|^2 + margin)`.
+pub fn triplet(anchor: &[Value], pos: &[Value], neg: &[Value], margin: f64) -> Value {
+    (squared_distance(anchor, pos) - squared_distance(anchor, neg) + margin).relu()
+}
+
+/// L1 penalty `sum |p|` to add to a loss. Its subgradient makes weights hover around zero rather
+/// than land on it; see `SGD::with_elastic_net` for the proximal alternative.
+pub fn l1_penalty(params: &[Value]) -> Value {
+    params.iter().fold(Value::from(0.0), |acc, p| acc + p.clone().abs())
+}
+
+/// L2 penalty `sum p^2` to add to a loss.
+pub fn l2_penalty(params: &[Value]) -> Value {
+    params.iter().fold(Value::from(0.0), |acc, p| acc + p.clone().powop(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mse_value_and_gradient() {
+        let preds = [Value::new(1.0, "p0"), Value::new(-1.0, "p1")];
+        let targets = [Value::from(0.0), Value::from(1.0)];
+        let loss = mse(&preds, &targets);
+        assert_eq!(loss.data(), 2.5);
+        loss.backward();
+        // d/dp (p - t)^2 / n = (p - t)
+        assert_eq!(preds[0].grad(), 1.0);
+        assert_eq!(preds[1].grad(), -2.0);
+    }
+
+    #[test]
+    fn binary_cross_entropy_value_and_gradient() {
+        let preds = [Value::new(0.8, "p0"), Value::new(0.25, "p1")];
+        let targets = [Value::from(1.0), Value::from(0.0)];
+        let loss = binary_cross_entropy(&preds, &targets);
+        assert!((loss.data() - (-(0.8f64.ln()) - 0.75f64.ln()) / 2.0).abs() < 1e-9);
+        loss.backward();
+        // d/dp = -t / p + (1 - t) / (1 - p), over n
+        assert!((preds[0].grad() - -1.0 / 0.8 / 2.0).abs() < 1e-9);
+        assert!((preds[1].grad() - 1.0 / 0.75 / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hinge_value_and_gradient() {
+        let preds = [Value::new(0.5, "p0"), Value::new(-2.0, "p1"), Value::new(0.3, "p2")];
+        let targets = [Value::from(1.0), Value::from(-1.0), Value::from(-1.0)];
+        let loss = hinge(&preds, &targets);
+        // margins: 0.5 short, satisfied, 1.3 short
+        assert!((loss.data() - (0.5 + 0.0 + 1.3) / 3.0).abs() < 1e-12);
+        loss.backward();
+        let grads: Vec<f64> = preds.iter().map(|p| p.grad()).collect();
+        assert!((grads[0] + 1.0 / 3.0).abs() < 1e-12);
+        assert_eq!(grads[1], 0.0);
+        assert!((grads[2] - 1.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn batch_and_per_output_mse() {
+        let preds = vec![
+            vec![Value::new(1.0, ""), Value::new(0.0, "")],
+            vec![Value::new(3.0, ""), Value::new(2.0, "")],
+        ];
+        let targets = vec![vec![0.0, 0.0], vec![1.0, 0.0]];
+        let loss = mse_batch(&preds, &targets);
+        assert_eq!(loss.data(), (1.0 + 0.0 + 4.0 + 4.0) / 4.0);
+        loss.backward();
+        assert_eq!(preds[1][0].grad(), 2.0 * 2.0 / 4.0);
+
+        let floats: Vec<Vec<f64>> = preds.iter().map(|p| p.iter().map(|v| v.data()).collect()).collect();
+        assert_eq!(per_output_mse(&floats, &targets), vec![2.5, 2.0]);
+
+        // no samples: zero rather than 0 / 0
+        assert_eq!(mse(&[], &[]).data(), 0.0);
+        assert_eq!(binary_cross_entropy(&[], &[]).data(), 0.0);
+        assert_eq!(hinge(&[], &[]).data(), 0.0);
+        assert_eq!(mse_batch(&[], &[]).data(), 0.0);
+        assert!(per_output_mse(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn fixed_task_weights() {
+        let a = Value::new(2.0, "a");
+        let b = Value::new(3.0, "b");
+        let total = multi_task(&[a.clone(), b.clone()], TaskWeights::Fixed(&[1.0, 0.5]));
+        assert_eq!(total.data(), 3.5);
+        total.backward();
+        assert_eq!((a.grad(), b.grad()), (1.0, 0.5));
+    }
+
+    #[test]
+    #[cfg(feature = "optim")]
+    fn uncertainty_weights_learn_task_scale() {
+        use crate::optim::{Optimizer, SGD};
+        let log_vars = vec![Value::new(0.0, "s0"), Value::new(0.0, "s1")];
+        let mut opt = SGD::new(log_vars.clone(), 0.2);
+        for _ in 0..300 {
+            opt.zero_grad();
+            // constant task losses: the optimum is s = ln(loss)
+            let losses = [Value::from(4.0), Value::from(1.0)];
+            multi_task(&losses, TaskWeights::Uncertainty(&log_vars)).backward();
+            opt.step();
+        }
+        assert!((log_vars[0].data() - 4f64.ln()).abs() < 1e-3);
+        assert!(log_vars[1].data().abs() < 1e-3);
+    }
+
+    #[test]
+    fn log_softmax_is_stable() {
+        let logits = [Value::from(1000.0), Value::from(1000.0)];
+        let lp = log_softmax(&logits);
+        assert!((lp[0].data() - 0.5f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn focal_reduces_to_cross_entropy_and_downweights_easy_examples() {
+        let logits = [Value::new(2.0, "a"), Value::new(0.0, "b"), Value::new(-1.0, "c")];
+        let p0 = 2f64.exp() / (2f64.exp() + 1.0 + (-1f64).exp());
+        let ce = focal(&logits, 0, 0.0, None);
+        assert!((ce.data() + p0.ln()).abs() < 1e-12);
+
+        // an easy, correct example contributes much less with gamma > 0
+        let easy = focal(&logits, 0, 2.0, None).data();
+        assert!((easy - (1.0 - p0).powi(2) * -p0.ln()).abs() < 1e-12);
+        let hard = focal(&logits, 2, 2.0, None).data();
+        assert!(easy / ce.data() < hard / focal(&logits, 2, 0.0, None).data());
+
+        let weighted = focal(&logits, 2, 2.0, Some(&[0.25, 0.25, 0.75]));
+        assert!((weighted.data() - 0.75 * hard).abs() < 1e-12);
+        weighted.backward();
+        // pushing the target logit up lowers the loss
+        assert!(logits[2].grad() < 0.0);
+    }
+
+    #[test]
+    fn contrastive_pulls_similar_and_pushes_dissimilar_pairs() {
+        let a = [Value::new(0.0, "a0"), Value::new(0.0, "a1")];
+        let b = [Value::new(3.0, "b0"), Value::new(4.0, "b1")];
+        assert_eq!(contrastive(&a, &b, true, 1.0).data(), 25.0);
+        // already further apart than the margin: no loss and no gradient
+        let far = contrastive(&a, &b, false, 2.0);
+        assert_eq!(far.data(), 0.0);
+        far.backward();
+        assert_eq!(b[0].grad(), 0.0);
+
+        let near = contrastive(&a, &b, false, 6.0);
+        assert!((near.data() - 1.0).abs() < 1e-9);
+        near.backward();
+        // d(margin - d)^2 / db0 = -2 (margin - d) * b0 / d
+        assert!((b[0].grad() + 2.0 * 3.0 / 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn triplet_is_hinged_at_the_margin() {
+        let anchor = [Value::new(0.0, "a")];
+        let pos = [Value::new(1.0, "p")];
+        let neg = [Value::new(2.0, "n")];
+        assert_eq!(triplet(&anchor, &pos, &neg, 1.0).data(), 0.0);
+        let loss = triplet(&anchor, &pos, &neg, 4.0);
+        assert_eq!(loss.data(), 1.0);
+        loss.backward();
+        assert_eq!((pos[0].grad(), neg[0].grad()), (2.0, -4.0));
+    }
+
+    #[test]
+    fn penalties() {
+        let params = [Value::new(-2.0, "a"), Value::new(0.0, "b"), Value::new(1.0, "c")];
+        let l1 = l1_penalty(&params);
+        assert_eq!(l1.data(), 3.0);
+        l1.backward();
+        assert_eq!([params[0].grad(), params[1].grad(), params[2].grad()], [-1.0, 0.0, 1.0]);
+        assert_eq!(l2_penalty(&params).data(), 5.0);
+    }
+}
@@ -0,0 +1,227 @@
+//! Transcendental functions used by the ops.
+//!
+//! By default these are the platform's `f64` methods, whose last bits may differ between libms
+//! (glibc, musl, macOS, the WASM host). With the `deterministic-math` feature they are computed by
+//! the `soft_*` versions below instead, which only use `+ - * /`, rounding and bit manipulation,
+//! so a training run gives bit-identical results everywhere. The soft versions are accurate to a
+//! few ulps and always available.
+
+#[cfg(not(feature = "deterministic-math"))]
+pub fn exp(x: f64) -> f64 {
+    x.exp()
+}
+
+#[cfg(feature = "deterministic-math")]
+pub fn exp(x: f64) -> f64 {
+    soft_exp(x)
+}
+
+#[cfg(not(feature = "deterministic-math"))]
+pub fn ln(x: f64) -> f64 {
+    x.ln()
+}
+
+#[cfg(feature = "deterministic-math")]
+pub fn ln(x: f64) -> f64 {
+    soft_ln(x)
+}
+
+#[cfg(not(feature = "deterministic-math"))]
+pub fn tanh(x: f64) -> f64 {
+    x.tanh()
+}
+
+#[cfg(feature = "deterministic-math")]
+pub fn tanh(x: f64) -> f64 {
+    soft_tanh(x)
+}
+
+#[cfg(not(feature = "deterministic-math"))]
+pub fn powf(x: f64, e: f64) -> f64 {
+    x.powf(e)
+}
+
+#[cfg(feature = "deterministic-math")]
+pub fn powf(x: f64, e: f64) -> f64 {
+    soft_powf(x, e)
+}
+
+// ln 2 split so that `k * LN2_HI` is exact for the exponents that occur
+const LN2_HI: f64 = f64::from_bits(0x3fe6_2e42_fee0_0000);
+const LN2_LO: f64 = f64::from_bits(0x3dea_39ef_3579_3c76);
+
+// Taylor terms needed for `exp(r) - 1` to converge to below an ulp for |r| <= 1.1
+const EXP_TERMS: u32 = 20;
+
+// `q` with `exp(r) = 1 + r * q`, by Horner on the Taylor series; no cancellation for small r
+fn exp_tail(r: f64) -> f64 {
+    let mut q = 1.0;
+    for n in (2..=EXP_TERMS).rev() {
+        q = 1.0 + q * r / n as f64;
+    }
+    q
+}
+
+// x * 2^k, in steps so that neither the factor nor an intermediate leaves the normal range early
+fn scale2(mut x: f64, mut k: i64) -> f64 {
+    while k > 1000 {
+        x *= f64::from_bits(((1023 + 1000) as u64) << 52);
+        k -= 1000;
+    }
+    while k < -1000 {
+        x *= f64::from_bits(((1023 - 1000) as u64) << 52);
+        k += 1000;
+    }
+    x * f64::from_bits(((1023 + k) as u64) << 52)
+}
+
+/// `e^x` from basic operations: `x = k ln 2 + r` with `|r| <= ln 2 / 2`, then a series for `e^r`.
+pub fn soft_exp(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    if x > 709.8 {
+        return f64::INFINITY;
+    }
+    if x < -745.2 {
+        return 0.0;
+    }
+    let k = (x / std::f64::consts::LN_2).round();
+    let r = (x - k * LN2_HI) - k * LN2_LO;
+    scale2(1.0 + r * exp_tail(r), k as i64)
+}
+
+/// Natural logarithm from basic operations: `x = m 2^e` with `m` in `[√½, √2)`, then
+/// `ln m = 2 atanh((m - 1) / (m + 1))` as a series.
+pub fn soft_ln(x: f64) -> f64 {
+    if x.is_nan() || x < 0.0 {
+        return f64::NAN;
+    }
+    if x == 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if x.is_infinite() {
+        return x;
+    }
+    // bring subnormals into the normal range first
+    let (x, bias) = if x < f64::MIN_POSITIVE { (x * 2f64.powi(54), -54) } else { (x, 0) };
+    let bits = x.to_bits();
+    let mut e = ((bits >> 52) & 0x7ff) as i64 - 1023 + bias;
+    let mut m = f64::from_bits((bits & ((1u64 << 52) - 1)) | (1023u64 << 52));
+    if m > std::f64::consts::SQRT_2 {
+        m /= 2.0;
+        e += 1;
+    }
+
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    // 2 (s + s^3/3 + s^5/5 + ...), |s| <= 0.172 so 11 odd terms are plenty
+    let mut series = 0.0;
+    for n in (0..11).rev() {
+        series = 1.0 / (2 * n + 1) as f64 + s2 * series;
+    }
+    let e = e as f64;
+    e * LN2_HI + (e * LN2_LO + 2.0 * s * series)
+}
+
+/// Hyperbolic tangent from basic operations, via `e^{2|x|} - 1` so small inputs keep full precision.
+pub fn soft_tanh(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    let a = x.abs();
+    let t = if a > 22.0 {
+        1.0
+    } else if a < 0.55 {
+        let u = 2.0 * a * exp_tail(2.0 * a);
+        u / (u + 2.0)
+    } else {
+        1.0 - 2.0 / (soft_exp(2.0 * a) + 1.0)
+    };
+    t.copysign(x)
+}
+
+// Integer exponents up to this size go by repeated squaring, whose error grows with the exponent
+const POW_SQUARING_MAX: f64 = 64.0;
+
+/// `x^e` from basic operations: repeated squaring for small integer exponents, `e^(e ln x)`
+/// otherwise. The relative error grows with `|e ln x|`, to about 1e-13 at results near the ends
+/// of the `f64` range. Negative `x` only has real powers for integer `e`; others give NaN.
+pub fn soft_powf(x: f64, e: f64) -> f64 {
+    if e == 0.0 {
+        return 1.0;
+    }
+    if x.is_nan() || e.is_nan() {
+        return f64::NAN;
+    }
+    let integer = e.fract() == 0.0;
+    if integer && e.abs() <= POW_SQUARING_MAX {
+        let (mut base, mut n, mut acc) = (x, e.abs() as u32, 1.0);
+        while n > 0 {
+            if n & 1 == 1 {
+                acc *= base;
+            }
+            base *= base;
+            n >>= 1;
+        }
+        return if e < 0.0 { 1.0 / acc } else { acc };
+    }
+    if x < 0.0 {
+        if !integer {
+            return f64::NAN;
+        }
+        let odd = (e / 2.0).fract() != 0.0;
+        let magnitude = soft_powf(-x, e);
+        return if odd { -magnitude } else { magnitude };
+    }
+    if x == 0.0 {
+        return if e > 0.0 { 0.0 } else { f64::INFINITY };
+    }
+    soft_exp(e * soft_ln(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        a == b || (a - b).abs() <= 4.0 * f64::EPSILON * b.abs().max(f64::MIN_POSITIVE)
+    }
+
+    #[test]
+    fn soft_functions_match_libm_to_a_few_ulps() {
+        for i in -2000..=2000 {
+            let x = i as f64 / 97.0;
+            assert!(close(soft_exp(x), x.exp()), "exp({x})");
+            assert!(close(soft_tanh(x), x.tanh()), "tanh({x})");
+            let y = (x / 3.0).exp();
+            assert!(close(soft_ln(y), y.ln()) || (soft_ln(y) - y.ln()).abs() < 1e-15, "ln({y})");
+        }
+        for x in [1e-300, 5e-324, 1e300, f64::MAX] {
+            assert!(close(soft_ln(x), x.ln()), "ln({x})");
+        }
+        assert_eq!(soft_exp(-740.0), (-740f64).exp());
+        assert_eq!(soft_exp(710.0), f64::INFINITY);
+        assert_eq!(soft_ln(0.0), f64::NEG_INFINITY);
+        assert!(soft_ln(-1.0).is_nan());
+        assert_eq!(soft_tanh(1e-10), 1e-10);
+        assert_eq!(soft_tanh(-30.0), -1.0);
+        assert!(soft_tanh(0.0).is_sign_positive() && soft_tanh(-0.0).is_sign_negative());
+    }
+
+    #[test]
+    fn soft_powf_matches_libm() {
+        let near = |a: f64, b: f64| a == b || (a - b).abs() <= 1e-13 * b.abs();
+        for x in [0.1, 0.5, 1.0, 1.7, 3.0, 123.4] {
+            for e in [-3.0, -1.0, -0.5, 0.5, 1.0, 1.5, 2.0, 3.0, 7.25, 100.0] {
+                assert!(near(soft_powf(x, e), x.powf(e)), "{x}^{e}");
+                assert!(near(soft_powf(-x, e), (-x).powf(e)) || e.fract() != 0.0, "-{x}^{e}");
+            }
+        }
+        assert_eq!(soft_powf(3.0, 2.0), 9.0);
+        assert_eq!(soft_powf(-2.0, -1.0), -0.5);
+        assert!(near(soft_powf(-2.0, 101.0), -(2f64.powi(101))));
+        assert!(soft_powf(-2.0, 0.5).is_nan());
+        assert_eq!((soft_powf(0.0, -1.0), soft_powf(0.0, 2.5), soft_powf(f64::NAN, 0.0)), (f64::INFINITY, 0.0, 1.0));
+    }
+}
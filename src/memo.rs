@@ -0,0 +1,129 @@
+//! Memoized evaluation of a scalar function and its gradient, for line searches and sweeps
+//! that keep coming back to the same or nearby points.
+
+use crate::operators::Value;
+use std::collections::HashMap;
+
+/// Output and gradient of a function at one point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub value: f64,
+    pub grad: Vec<f64>,
+}
+
+/// Wraps `f`, which builds a scalar graph from its input leaves, and caches its value and
+/// gradient by input.
+///
+/// With a positive `tolerance` inputs are bucketed on a grid of that spacing and every point in a
+/// bucket gets the result of the first one evaluated there, so keep it well below the scale on
+/// which `f` changes. A tolerance of 0 only reuses results for bit-identical inputs.
+pub struct EvalCache<F: Fn(&[Value]) -> Value> {
+    f: F,
+    tolerance: f64,
+    entries: HashMap<Vec<i64>, Evaluation>,
+    hits: usize,
+    misses: usize,
+}
+
+impl<F: Fn(&[Value]) -> Value> EvalCache<F> {
+    pub fn new(f: F, tolerance: f64) -> Self {
+        assert!(tolerance >= 0.0, "tolerance must not be negative");
+        EvalCache { f, tolerance, entries: HashMap::new(), hits: 0, misses: 0 }
+    }
+
+    fn key(&self, x: &[f64]) -> Vec<i64> {
+        if self.tolerance == 0.0 {
+            x.iter().map(|v| v.to_bits() as i64).collect()
+        } else {
+            x.iter().map(|v| (v / self.tolerance).round() as i64).collect()
+        }
+    }
+
+    /// Value and gradient of `f` at `x`, from the cache when a close enough point was seen before.
+    pub fn eval(&mut self, x: &[f64]) -> Evaluation {
+        let key = self.key(x);
+        if let Some(hit) = self.entries.get(&key) {
+            self.hits += 1;
+            return hit.clone();
+        }
+        self.misses += 1;
+        let leaves: Vec<Value> = x.iter().map(|v| Value::new(*v, "x")).collect();
+        let out = (self.f)(&leaves);
+        out.backward();
+        let result = Evaluation { value: out.data(), grad: leaves.iter().map(|l| l.grad()).collect() };
+        self.entries.insert(key, result.clone());
+        result
+    }
+
+    /// Just the value at `x`; see `eval`.
+    pub fn value(&mut self, x: &[f64]) -> f64 {
+        self.eval(x).value
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Forgets every cached result, e.g. after the parameters `f` closes over have changed.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn backtracking_line_search_reuses_points() {
+        let calls = Cell::new(0);
+        let rosenbrock = |x: &[Value]| {
+            calls.set(calls.get() + 1);
+            (-x[0].clone() + 1.0).powop(2) + (x[1].clone() - x[0].clone().powop(2)).powop(2) * 100.0
+        };
+        let mut cache = EvalCache::new(rosenbrock, 0.0);
+
+        let start = [-1.0, 1.0];
+        let here = cache.eval(&start);
+        assert_eq!(here.value, 4.0);
+        assert_eq!(here.grad, vec![-4.0, 0.0]);
+
+        // two searches from the same point try the same step sizes
+        for _ in 0..2 {
+            let mut t = 1.0;
+            while cache.value(&[start[0] - t * here.grad[0], start[1]]) > here.value {
+                t /= 2.0;
+            }
+            cache.eval(&start);
+        }
+        assert_eq!(calls.get(), cache.misses());
+        assert!(cache.hits() > cache.misses());
+        assert_eq!(cache.len(), cache.misses());
+    }
+
+    #[test]
+    fn tolerance_buckets_nearby_inputs() {
+        let mut cache = EvalCache::new(|x: &[Value]| x[0].clone() * 3.0, 1e-6);
+        let a = cache.eval(&[0.5]);
+        let b = cache.eval(&[0.5 + 1e-8]);
+        assert_eq!(a, b);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        cache.eval(&[0.6]);
+        assert_eq!(cache.misses(), 2);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
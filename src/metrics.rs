@@ -0,0 +1,274 @@
+use crate::operators::Value;
+use std::fmt;
+
+/// Fraction of predictions equal to their target.
+pub fn accuracy(preds: &[usize], targets: &[usize]) -> f64 {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    if preds.is_empty() {
+        return 0.0;
+    }
+    preds.iter().zip(targets).filter(|(p, t)| p == t).count() as f64 / preds.len() as f64
+}
+
+/// `matrix[target][pred]` counts how often class `target` was predicted as `pred`.
+pub fn confusion_matrix(preds: &[usize], targets: &[usize], num_classes: usize) -> Vec<Vec<usize>> {
+    assert_eq!(preds.len(), targets.len(), "preds and targets differ in length");
+    let mut matrix = vec![vec![0; num_classes]; num_classes];
+    for (&p, &t) in preds.iter().zip(targets) {
+        assert!(p < num_classes && t < num_classes, "class index out of range for {} classes", num_classes);
+        matrix[t][p] += 1;
+    }
+    matrix
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub support: usize,
+}
+
+/// Per-class precision/recall/F1, printable as a text table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassificationReport {
+    pub classes: Vec<ClassMetrics>,
+    pub accuracy: f64,
+}
+
+impl ClassificationReport {
+    /// Unweighted mean of the per-class metrics.
+    pub fn macro_avg(&self) -> ClassMetrics {
+        let n = self.classes.len().max(1) as f64;
+        ClassMetrics {
+            precision: self.classes.iter().map(|c| c.precision).sum::<f64>() / n,
+            recall: self.classes.iter().map(|c| c.recall).sum::<f64>() / n,
+            f1: self.classes.iter().map(|c| c.f1).sum::<f64>() / n,
+            support: self.classes.iter().map(|c| c.support).sum(),
+        }
+    }
+}
+
+// Ratio that is 0 rather than NaN when nothing was counted
+fn ratio(num: usize, den: usize) -> f64 {
+    if den == 0 { 0.0 } else { num as f64 / den as f64 }
+}
+
+pub fn classification_report(preds: &[usize], targets: &[usize], num_classes: usize) -> ClassificationReport {
+    let matrix = confusion_matrix(preds, targets, num_classes);
+    let classes = (0..num_classes)
+        .map(|c| {
+            let tp = matrix[c][c];
+            let predicted: usize = matrix.iter().map(|row| row[c]).sum();
+            let support: usize = matrix[c].iter().sum();
+            let precision = ratio(tp, predicted);
+            let recall = ratio(tp, support);
+            let f1 = if precision + recall == 0.0 { 0.0 } else { 2.0 * precision * recall / (precision + recall) };
+            ClassMetrics { precision, recall, f1, support }
+        })
+        .collect();
+    ClassificationReport { classes, accuracy: accuracy(preds, targets) }
+}
+
+impl fmt::Display for ClassificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>10} {:>9} {:>9} {:>9} {:>9}", "class", "precision", "recall", "f1", "support")?;
+        for (i, c) in self.classes.iter().enumerate() {
+            writeln!(f, "{:>10} {:>9.3} {:>9.3} {:>9.3} {:>9}", i, c.precision, c.recall, c.f1, c.support)?;
+        }
+        let avg = self.macro_avg();
+        writeln!(f, "{:>10} {:>9.3} {:>9.3} {:>9.3} {:>9}", "macro avg", avg.precision, avg.recall, avg.f1, avg.support)?;
+        writeln!(f, "{:>10} {:>29.3} {:>9}", "accuracy", self.accuracy, avg.support)
+    }
+}
+
+/// One point of a ROC curve: predicting positive for every score `>= threshold` gives these rates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RocPoint {
+    pub threshold: f64,
+    pub fpr: f64,
+    pub tpr: f64,
+}
+
+/// ROC curve from `(0, 0)` to `(1, 1)`, one point per distinct score.
+pub fn roc_curve(scores: &[f64], labels: &[bool]) -> Vec<RocPoint> {
+    assert_eq!(scores.len(), labels.len(), "scores and labels differ in length");
+    let positives = labels.iter().filter(|&&l| l).count();
+    let negatives = labels.len() - positives;
+
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+    let mut points = vec![RocPoint { threshold: f64::INFINITY, fpr: 0.0, tpr: 0.0 }];
+    let (mut tp, mut fp) = (0, 0);
+    for (k, &i) in order.iter().enumerate() {
+        if labels[i] { tp += 1 } else { fp += 1 }
+        // tied scores share a single threshold, so only emit after the last of them
+        if k + 1 == order.len() || scores[order[k + 1]] != scores[i] {
+            points.push(RocPoint { threshold: scores[i], fpr: ratio(fp, negatives), tpr: ratio(tp, positives) });
+        }
+    }
+    points
+}
+
+/// Area under the ROC curve (trapezoidal rule). NaN if `labels` lacks either class.
+pub fn roc_auc(scores: &[f64], labels: &[bool]) -> f64 {
+    let positives = labels.iter().filter(|&&l| l).count();
+    if positives == 0 || positives == labels.len() {
+        return f64::NAN;
+    }
+    roc_curve(scores, labels)
+        .windows(2)
+        .map(|w| (w[1].fpr - w[0].fpr) * (w[1].tpr + w[0].tpr) / 2.0)
+        .sum()
+}
+
+/// Curve points as CSV with a `threshold,fpr,tpr` header, for plotting elsewhere.
+pub fn roc_curve_csv(points: &[RocPoint]) -> String {
+    let mut csv = String::from("threshold,fpr,tpr\n");
+    for p in points {
+        csv.push_str(&format!("{},{},{}\n", p.threshold, p.fpr, p.tpr));
+    }
+    csv
+}
+
+/// Expected calibration error of positive-class probabilities: the support-weighted gap between
+/// mean predicted probability and observed positive rate over `bins` equal-width bins.
+pub fn ece(scores: &[f64], labels: &[bool], bins: usize) -> f64 {
+    assert_eq!(scores.len(), labels.len(), "scores and labels differ in length");
+    assert!(bins > 0, "ece needs at least one bin");
+    let mut sums = vec![(0usize, 0.0, 0usize); bins];
+    for (&s, &l) in scores.iter().zip(labels) {
+        let b = ((s.clamp(0.0, 1.0) * bins as f64) as usize).min(bins - 1);
+        sums[b].0 += 1;
+        sums[b].1 += s;
+        sums[b].2 += l as usize;
+    }
+    sums.iter()
+        .filter(|(count, _, _)| *count > 0)
+        .map(|&(count, score, pos)| {
+            count as f64 / scores.len() as f64 * (score / count as f64 - pos as f64 / count as f64).abs()
+        })
+        .sum()
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + crate::math::exp(-x))
+}
+
+/// Positive-class probabilities of `logits` after dividing by `temperature`.
+pub fn apply_temperature(logits: &[f64], temperature: f64) -> Vec<f64> {
+    logits.iter().map(|z| sigmoid(z / temperature)).collect()
+}
+
+/// Fits the temperature that minimizes the binary cross-entropy of `sigmoid(logit / T)`, by
+/// gradient descent on a single `Value` holding `ln T` (which keeps T positive).
+pub fn fit_temperature(logits: &[f64], labels: &[bool], steps: usize, lr: f64) -> f64 {
+    assert_eq!(logits.len(), labels.len(), "logits and labels differ in length");
+    let log_t = Value::new(0.0, "log_t");
+    let n = logits.len() as f64;
+
+    for _ in 0..steps {
+        let inv_t = (log_t.clone() * -1.0).exp();
+        // BCE(sigmoid(u), y) = softplus(u) - y * u, with softplus written to avoid overflow
+        let loss = logits.iter().zip(labels).fold(Value::from(0.0), |acc, (&z, &y)| {
+            let u = &inv_t * z;
+            let softplus = if u.data() > 0.0 {
+                u.clone() + ((&u * -1.0).exp() + 1.0).log()
+            } else {
+                (u.clone().exp() + 1.0).log()
+            };
+            let target = if y { u } else { Value::from(0.0) };
+            acc + softplus - target
+        }) / n;
+
+        log_t.set_grad(0.0);
+        loss.backward();
+        log_t.set_data(log_t.data() - lr * log_t.grad());
+    }
+    crate::math::exp(log_t.data())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusion_and_report() {
+        let targets = [0, 0, 1, 1, 2, 2];
+        let preds = [0, 1, 1, 1, 2, 0];
+        let m = confusion_matrix(&preds, &targets, 3);
+        assert_eq!(m, vec![vec![1, 1, 0], vec![0, 2, 0], vec![1, 0, 1]]);
+
+        let report = classification_report(&preds, &targets, 3);
+        assert_eq!(report.accuracy, 4.0 / 6.0);
+        assert_eq!(report.classes[1].precision, 2.0 / 3.0);
+        assert_eq!(report.classes[1].recall, 1.0);
+        assert!((report.classes[1].f1 - 0.8).abs() < 1e-12);
+        assert_eq!(report.classes[2].precision, 1.0);
+        assert_eq!(report.classes[2].support, 2);
+
+        let text = report.to_string();
+        assert!(text.contains("macro avg"));
+        assert_eq!(text.lines().count(), 6);
+    }
+
+    #[test]
+    fn empty_classes_do_not_produce_nan() {
+        let report = classification_report(&[0, 0], &[0, 0], 2);
+        assert_eq!(report.classes[1], ClassMetrics { precision: 0.0, recall: 0.0, f1: 0.0, support: 0 });
+    }
+
+    #[test]
+    fn roc_auc_handles_ties_and_ordering() {
+        let labels = [true, true, false, false];
+        assert_eq!(roc_auc(&[0.9, 0.8, 0.3, 0.1], &labels), 1.0);
+        assert_eq!(roc_auc(&[0.1, 0.2, 0.8, 0.9], &labels), 0.0);
+        // every score tied: the curve is the diagonal
+        assert_eq!(roc_auc(&[0.5; 4], &labels), 0.5);
+
+        // one positive ranked below one negative: 3 of 4 pairs ordered correctly
+        let scores = [0.9, 0.4, 0.6, 0.1];
+        assert_eq!(roc_auc(&scores, &labels), 0.75);
+
+        let curve = roc_curve(&scores, &labels);
+        assert_eq!(curve.len(), 5);
+        assert_eq!((curve[0].fpr, curve[0].tpr), (0.0, 0.0));
+        assert_eq!((curve[4].fpr, curve[4].tpr), (1.0, 1.0));
+        let csv = roc_curve_csv(&curve);
+        assert!(csv.starts_with("threshold,fpr,tpr\n"));
+        assert_eq!(csv.lines().count(), 6);
+
+        assert!(roc_auc(&[0.1, 0.2], &[true, true]).is_nan());
+    }
+
+    #[test]
+    fn temperature_scaling_undoes_overconfidence() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(5);
+
+        // labels drawn from sigmoid(z), logits reported three times too confident
+        let mut logits = Vec::new();
+        let mut labels = Vec::new();
+        for _ in 0..400 {
+            let z: f64 = rng.gen_range(-3.0..3.0);
+            labels.push(rng.gen_bool(sigmoid(z)));
+            logits.push(3.0 * z);
+        }
+
+        let t = fit_temperature(&logits, &labels, 60, 1.0);
+        assert!(t > 2.0 && t < 4.0, "fitted temperature {}", t);
+
+        let before = ece(&apply_temperature(&logits, 1.0), &labels, 10);
+        let after = ece(&apply_temperature(&logits, t), &labels, 10);
+        assert!(after < before);
+    }
+
+    #[test]
+    fn ece_of_perfect_calibration_is_zero() {
+        let scores = [0.25, 0.25, 0.25, 0.25, 0.75, 0.75, 0.75, 0.75];
+        let labels = [true, false, false, false, true, true, true, false];
+        assert!(ece(&scores, &labels, 4).abs() < 1e-12);
+        assert!((ece(&[1.0, 1.0], &[false, false], 5) - 1.0).abs() < 1e-12);
+    }
+}
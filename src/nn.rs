@@ -1,22 +1,198 @@
-use crate::operators::operators::*;
-use rand::Rng;
+use crate::gradcheck::{self, GradcheckReport};
+use crate::operators::*;
+use crate::tensor::Tensor;
+use crate::vector::Vector;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(std::io::Error),
+    Format(String),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io(e) => write!(f, "i/o error: {}", e),
+            ModelError::Format(msg) => write!(f, "malformed model: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
+impl From<std::io::Error> for ModelError {
+    fn from(e: std::io::Error) -> Self {
+        ModelError::Io(e)
+    }
+}
+
+/// Anything with trainable parameters that maps a vector of inputs to a vector of outputs.
+pub trait Module {
+    fn forward(&self, xs: &[Value]) -> Vec<Value>;
+
+    fn parameters(&self) -> Vec<Value>;
+
+    /// Enforces any weight constraints; `Trainer` calls it after every optimizer step.
+    fn constrain(&self) {}
+
+    /// The parameters split into named groups, e.g. one per layer, for per-group diagnostics.
+    /// A single group by default.
+    fn parameter_groups(&self) -> Vec<(String, Vec<Value>)> {
+        vec![("parameters".to_string(), self.parameters())]
+    }
+
+    /// Prefixes the labels of the parameters with `namespace`, so graph exports of a whole network
+    /// say where each node comes from. By default they become `{namespace}.p0`, `{namespace}.p1`,
+    /// ...; `Layer` and `MLP` name theirs after their structure instead.
+    fn set_namespace(&self, namespace: &str) {
+        for (i, p) in self.parameters().iter().enumerate() {
+            p.borrow_mut().set_label(&qualify(namespace, &format!("p{}", i)));
+        }
+    }
+
+    /// Checks the gradient of `loss_fn(forward(input))` with respect to every parameter against
+    /// central differences, within `tol` as in `check_gradients`, and reports the failures by
+    /// parameter path (the label, or `{group} #{i}` for unlabelled ones). Overwrites the
+    /// parameters' gradients; their data is left as it was.
+    fn gradcheck(&self, input: &[Value], loss_fn: &dyn Fn(&[Value]) -> Value, tol: f64) -> GradcheckReport {
+        let named: Vec<(String, Value)> = self
+            .parameter_groups()
+            .into_iter()
+            .flat_map(|(group, params)| {
+                params.into_iter().enumerate().map(move |(i, p)| {
+                    let label = p.borrow().label().to_string();
+                    (if label.is_empty() { format!("{} #{}", group, i) } else { label }, p)
+                })
+            })
+            .collect();
+        gradcheck::check_named(&named, &|| loss_fn(&self.forward(input)), tol)
+    }
+}
+
+thread_local! {
+    // Cleared inside `eval_mode`
+    static TRAINING: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Runs `f` in evaluation mode: modules that behave differently while training, such as
+/// `StochasticDepth`, switch to their deterministic inference behaviour. Applies to the current
+/// thread only; nests.
+pub fn eval_mode<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TRAINING.with(|t| t.set(self.0));
+        }
+    }
+    let _restore = Restore(TRAINING.with(|t| t.replace(false)));
+    f()
+}
+
+/// Whether modules on this thread are in training mode (false inside `eval_mode`).
+pub fn is_training() -> bool {
+    TRAINING.with(|t| t.get())
+}
+
+// `name` inside `namespace`, e.g. `l0.n3` inside `net` is `net.l0.n3`
+fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() { name.to_string() } else { format!("{}.{}", namespace, name) }
+}
+
+// New leaf with the same value, label and freeze state as `p`
+fn copy_param(p: &Value) -> Value {
+    let copy = Value::new(p.data(), p.borrow().label());
+    copy.set_requires_grad(p.requires_grad());
+    copy
+}
+
+/// Nonlinearity applied to a neuron's weighted sum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Activation {
+    #[default]
+    Tanh,
+    ReLU,
+    Sigmoid,
+    /// No nonlinearity, for regression outputs.
+    Linear,
+}
+
+impl Activation {
+    fn name(self) -> &'static str {
+        match self {
+            Activation::Tanh => "tanh",
+            Activation::ReLU => "relu",
+            Activation::Sigmoid => "sigmoid",
+            Activation::Linear => "linear",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Activation::Tanh, Activation::ReLU, Activation::Sigmoid, Activation::Linear]
+            .into_iter()
+            .find(|a| a.name() == name)
+    }
+
+    pub fn apply(self, x: Value) -> Value {
+        match self {
+            Activation::Tanh => x.tanh(),
+            Activation::ReLU => x.relu(),
+            Activation::Sigmoid => x.sigmoid(),
+            Activation::Linear => x,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Neuron {
     weights: Vec<Value>,
     bias: Value,
+    activation: Activation,
 }
 
 impl Neuron {
     pub fn new(nin: usize) -> Self {
+        Neuron::with_activation(nin, Activation::Tanh)
+    }
+
+    pub fn with_activation(nin: usize, activation: Activation) -> Self {
         let mut rng = rand::thread_rng();
         let w = (0..nin)
             .map(|_| Value::new(rng.gen_range(-1.0..1.0), "w"))
             .collect::<Vec<Value>>();
-        Neuron {
+        let neuron = Neuron {
             bias: Value::new(0.0, "b"),
             weights: w, 
+            activation,
+        };
+        neuron.set_namespace("");
+        neuron
+    }
+
+    /// Neuron with the given parameters instead of random ones.
+    pub fn from_weights(weights: &[f64], bias: f64, activation: Activation) -> Self {
+        let neuron = Neuron {
+            weights: weights.iter().map(|w| Value::new(*w, "w")).collect(),
+            bias: Value::new(bias, "b"),
+            activation,
+        };
+        neuron.set_namespace("");
+        neuron
+    }
+
+    /// Labels the parameters `{namespace}.w0`, `{namespace}.w1`, ... and `{namespace}.b`, or
+    /// just `w0`, ..., `b` for an empty namespace.
+    pub fn set_namespace(&self, namespace: &str) {
+        for (i, w) in self.weights.iter().enumerate() {
+            w.borrow_mut().set_label(&qualify(namespace, &format!("w{}", i)));
         }
+        self.bias.borrow_mut().set_label(&qualify(namespace, "b"));
     }
 
     pub fn forward(&self, xs: &[Value]) -> Value {
@@ -27,9 +203,19 @@ impl Neuron {
         let sum = prods
             .into_iter()
             .fold(self.bias.clone(), |acc, v| acc + v);
-        sum.tanh()
+        self.activation.apply(sum)
     }
     
+    /// Copy with fresh parameter nodes holding the same values, so training one leaves the other
+    /// alone (`clone` shares the nodes).
+    pub fn deep_copy(&self) -> Self {
+        Neuron {
+            weights: self.weights.iter().map(copy_param).collect(),
+            bias: copy_param(&self.bias),
+            activation: self.activation,
+        }
+    }
+
     pub fn parameters(&self) -> Vec<Value> {
         [self.bias.clone()]
             .into_iter()
@@ -38,22 +224,304 @@ impl Neuron {
     }
 }
 
+// Power iteration steps per spectral norm estimate; warm-started from the previous estimate
+const POWER_ITERATIONS: usize = 10;
+
+// Upper bound on a layer's spectral norm, with the power iteration's current guess at the top
+// right singular vector
+#[derive(Debug, Clone)]
+struct SpectralNorm {
+    bound: f64,
+    v: RefCell<Vec<f64>>,
+}
+
+fn normalize(x: &mut [f64]) -> f64 {
+    let norm = x.iter().map(|a| a * a).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        x.iter_mut().for_each(|a| *a /= norm);
+    }
+    norm
+}
+
+// Gram-Schmidt on the rows of `m`, or on its columns when there are more rows than columns, so
+// that whichever side fits ends up orthonormal. Vectors that vanish against the earlier ones
+// are left at zero.
+fn orthonormalize(m: &mut [Vec<f64>]) {
+    let cols = m.first().map_or(0, |r| r.len());
+    if m.len() > cols {
+        let mut t: Vec<Vec<f64>> = (0..cols).map(|j| m.iter().map(|r| r[j]).collect()).collect();
+        orthonormalize(&mut t);
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = t[j][i];
+            }
+        }
+        return;
+    }
+    for i in 0..m.len() {
+        let (done, rest) = m.split_at_mut(i);
+        let row = &mut rest[0];
+        for prev in done.iter() {
+            let dot: f64 = prev.iter().zip(row.iter()).map(|(a, b)| a * b).sum();
+            row.iter_mut().zip(prev).for_each(|(x, p)| *x -= dot * p);
+        }
+        if normalize(row) < 1e-12 {
+            row.iter_mut().for_each(|x| *x = 0.0);
+        }
+    }
+}
+
+/// How a layer computes its weighted sums. Both give the same results and gradients into the same
+/// parameter Values; `Scalar` builds one node per multiply-add, which is what you want to look at,
+/// while `Tensor` does the whole layer as a single matrix product.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    #[default]
+    Scalar,
+    Tensor,
+}
+
+// `x W^T + b` for `data` holding the inputs followed by the layer's parameters (each neuron's
+// bias, then its weights). Returns the input, weight and bias leaves along with the output.
+fn linear(data: &[f64], nin: usize, nout: usize) -> (Tensor, Tensor, Tensor, Tensor) {
+    let (x, params) = data.split_at(nin);
+    let rows: Vec<&[f64]> = params.chunks(nin + 1).collect();
+    let xt = Tensor::new(x.to_vec(), &[1, nin]);
+    let wt = Tensor::new(rows.iter().flat_map(|r| r[1..].to_vec()).collect(), &[nout, nin]);
+    let bt = Tensor::new(rows.iter().map(|r| r[0]).collect(), &[nout]);
+    let z = xt.matmul(&wt.transpose(0, 1)) + bt.clone();
+    (xt, wt, bt, z)
+}
+
 #[derive(Debug, Clone)]
 pub struct Layer {
     neurons: Vec<Neuron>,
+    spectral_norm: Option<SpectralNorm>,
+    backend: Backend,
+    namespace: RefCell<String>,
 }
 
 impl Layer {
     pub fn new(nin:usize, nout:usize) -> Self {
+        Layer::with_activation(nin, nout, Activation::Tanh)
+    }
+
+    pub fn with_activation(nin: usize, nout: usize, activation: Activation) -> Self {
+        Layer::from_neurons(
+            (0..nout)
+                .map(|_| Neuron::with_activation(nin, activation))
+                .collect(),
+        )
+    }
+
+    fn from_neurons(neurons: Vec<Neuron>) -> Self {
+        let layer = Layer { neurons, spectral_norm: None, backend: Backend::Scalar, namespace: RefCell::default() };
+        layer.relabel();
+        layer
+    }
+
+    /// Layer with one neuron per row of `weights`, each row holding that neuron's input weights.
+    pub fn from_weights(weights: &[Vec<f64>], biases: &[f64], activation: Activation) -> Self {
+        assert_eq!(weights.len(), biases.len(), "need one bias per row of weights");
+        let nin = weights.first().map_or(0, |w| w.len());
+        assert!(weights.iter().all(|w| w.len() == nin), "weight rows differ in length");
+        Layer::from_neurons(weights.iter().zip(biases).map(|(w, b)| Neuron::from_weights(w, *b, activation)).collect())
+    }
+
+    /// Layer whose weight matrix has orthonormal rows (or columns, when `nout > nin`) and zero
+    /// biases, which keeps signals from shrinking or blowing up through deep tanh stacks.
+    pub fn orthogonal(nin: usize, nout: usize, activation: Activation) -> Self {
+        let mut rng = rand::thread_rng();
+        let mut w: Vec<Vec<f64>> = (0..nout).map(|_| (0..nin).map(|_| rng.gen_range(-1.0..1.0)).collect()).collect();
+        orthonormalize(&mut w);
+        Layer::from_weights(&w, &vec![0.0; nout], activation)
+    }
+
+    /// Replaces the weights with their Gram-Schmidt orthonormalization, pulling a layer that has
+    /// drifted during training back to an orthogonal matrix. Biases are left alone.
+    pub fn reorthogonalize(&self) {
+        let mut w = self.weight_matrix();
+        orthonormalize(&mut w);
+        for (n, row) in self.neurons.iter().zip(w) {
+            n.weights.iter().zip(row).for_each(|(p, x)| p.set_data(x));
+        }
+    }
+
+    // Weight data, one row per neuron
+    fn weight_matrix(&self) -> Vec<Vec<f64>> {
+        self.neurons.iter().map(|n| n.weights.iter().map(|w| w.data()).collect()).collect()
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Labels the parameters of neuron `j` as in `Neuron::set_namespace` under `{namespace}.n{j}`,
+    /// e.g. `n3.w2` for an empty namespace. Labels follow the layer as it grows or is pruned.
+    pub fn set_namespace(&self, namespace: &str) {
+        *self.namespace.borrow_mut() = namespace.to_string();
+        self.relabel();
+    }
+
+    pub fn namespace(&self) -> String {
+        self.namespace.borrow().clone()
+    }
+
+    fn relabel(&self) {
+        let namespace = self.namespace.borrow();
+        for (j, n) in self.neurons.iter().enumerate() {
+            n.set_namespace(&qualify(&namespace, &format!("n{}", j)));
+        }
+    }
+
+    pub fn forward(&self, x: &[Value]) -> Vec<Value> {
+        match self.backend {
+            Backend::Scalar => self.neurons.iter().map(|n| n.forward(x)).collect(),
+            Backend::Tensor => self.tensor_forward(x),
+        }
+    }
+
+    // The weighted sums as one tensor matmul inside a single graph node, whose outputs then go
+    // through the neurons' activations as usual
+    fn tensor_forward(&self, x: &[Value]) -> Vec<Value> {
+        let (nin, nout) = (self.nin(), self.nout());
+        assert_eq!(x.len(), nin, "layer expects {} inputs, got {}", nin, x.len());
+        let params = self.parameters();
+        let parents: Vec<&Value> = x.iter().chain(&params).collect();
+        let data: Vec<f64> = parents.iter().map(|v| v.data()).collect();
+        let (xt, wt, bt, z) = linear(&data, nin, nout);
+        let sums = z.data();
+
+        let pending = Rc::new(RefCell::new(vec![0.0; nout]));
+        let collected = pending.clone();
+        // runs after every output has its gradient, since they all hang off it
+        let hub = Value::from_op(0.0, "linear", &parents, move |_, _, _| {
+            let seed = std::mem::replace(&mut *collected.borrow_mut(), vec![0.0; nout]);
+            z.backward_with(&seed);
+            let (gw, gb) = (wt.grad(), bt.grad());
+            // back to parameter order: each neuron's bias, then its weights
+            let gp = (0..nout).flat_map(|j| std::iter::once(gb[j]).chain(gw[j * nin..(j + 1) * nin].to_vec()));
+            xt.grad().into_iter().chain(gp).collect()
+        });
+
+        self.neurons
+            .iter()
+            .enumerate()
+            .map(|(j, n)| {
+                let pending = pending.clone();
+                let sum = Value::from_op(sums[j], "element", &[&hub], move |_, g, _| {
+                    pending.borrow_mut()[j] += g;
+                    vec![0.0]
+                });
+                n.activation.apply(sum)
+            })
+            .collect()
+    }
+
+    pub fn nin(&self) -> usize {
+        self.neurons.first().map_or(0, |n| n.weights.len())
+    }
+
+    pub fn nout(&self) -> usize {
+        self.neurons.len()
+    }
+
+    // Drops the neurons at `indices`, i.e. those outputs of the layer
+    pub(crate) fn remove_neurons(&mut self, indices: &[usize]) {
+        let mut i = 0;
+        self.neurons.retain(|_| {
+            i += 1;
+            !indices.contains(&(i - 1))
+        });
+        self.relabel();
+    }
+
+    // Drops input `indices` from every neuron; a spectral norm estimate restarts at the new size
+    pub(crate) fn remove_inputs(&mut self, indices: &[usize]) {
+        for n in &mut self.neurons {
+            let mut i = 0;
+            n.weights.retain(|_| {
+                i += 1;
+                !indices.contains(&(i - 1))
+            });
+        }
+        let nin = self.nin();
+        if let Some(sn) = &self.spectral_norm {
+            *sn.v.borrow_mut() = vec![1.0; nin];
+        }
+        self.relabel();
+    }
+
+    /// Adds `n_new` neurons with the activation of the existing ones, their input weights drawn
+    /// from `init` and their biases at zero. Returns the new parameters, to hand to the optimizer
+    /// with `Optimizer::add_params`.
+    pub fn grow(&mut self, n_new: usize, mut init: impl FnMut() -> f64) -> Vec<Value> {
+        let (nin, activation) = (self.nin(), self.neurons.last().map_or(Activation::Tanh, |n| n.activation));
+        let new: Vec<Neuron> = (0..n_new)
+            .map(|_| Neuron::from_weights(&(0..nin).map(|_| init()).collect::<Vec<_>>(), 0.0, activation))
+            .collect();
+        let params = new.iter().flat_map(Neuron::parameters).collect();
+        self.neurons.extend(new);
+        self.relabel();
+        params
+    }
+
+    // Appends `n` inputs with zero weights, so the outputs do not change; returns the new weights
+    pub(crate) fn add_inputs(&mut self, n: usize) -> Vec<Value> {
+        let mut added = vec![];
+        for neuron in &mut self.neurons {
+            let new: Vec<Value> = (0..n).map(|_| Value::new(0.0, "w")).collect();
+            added.extend(new.iter().cloned());
+            neuron.weights.extend(new);
+        }
+        let nin = self.nin();
+        if let Some(sn) = &self.spectral_norm {
+            *sn.v.borrow_mut() = vec![1.0; nin];
+        }
+        self.relabel();
+        added
+    }
+
+    /// Copy with fresh parameter nodes, see `Neuron::deep_copy`.
+    pub fn deep_copy(&self) -> Self {
         Layer {
-            neurons: (0..nout)
-                .map(|_| Neuron::new(nin))
-                .collect()
+            neurons: self.neurons.iter().map(Neuron::deep_copy).collect(),
+            spectral_norm: self.spectral_norm.clone(),
+            backend: self.backend,
+            namespace: self.namespace.clone(),
         }
     }
 
-    pub fn forward(&self, x: &Vec<Value>) -> Vec<Value> {
-        self.neurons.iter().map(|n| n.forward(x)).collect()
+    /// Keeps the spectral norm of the weight matrix (biases excluded) at most `bound`: whenever
+    /// `constrain` finds it larger, the weights are scaled down to it. With a bound of 1 the layer
+    /// is 1-Lipschitz, as wanted for e.g. a GAN discriminator.
+    pub fn with_spectral_norm(mut self, bound: f64) -> Self {
+        assert!(bound > 0.0, "spectral norm bound must be positive");
+        self.spectral_norm = Some(SpectralNorm { bound, v: RefCell::new(vec![1.0; self.nin()]) });
+        self
+    }
+
+    /// Estimate of the largest singular value of the weight matrix, by power iteration.
+    pub fn spectral_norm(&self) -> f64 {
+        let w = self.weight_matrix();
+        let fresh = RefCell::new(vec![1.0; self.nin()]);
+        let v = self.spectral_norm.as_ref().map_or(&fresh, |s| &s.v);
+        let mut v = v.borrow_mut();
+        let mut sigma = 0.0;
+        for _ in 0..POWER_ITERATIONS {
+            normalize(&mut v);
+            let mut u: Vec<f64> = w.iter().map(|row| row.iter().zip(v.iter()).map(|(a, b)| a * b).sum()).collect();
+            sigma = normalize(&mut u);
+            for (j, vj) in v.iter_mut().enumerate() {
+                *vj = w.iter().zip(&u).map(|(row, ui)| row[j] * ui).sum();
+            }
+        }
+        sigma
     }
 
     pub fn parameters(&self) -> Vec<Value> {
@@ -61,21 +529,83 @@ impl Layer {
     }
 }
 
+impl Module for Layer {
+    fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        Layer::forward(self, xs)
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        Layer::parameters(self)
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        Layer::set_namespace(self, namespace);
+    }
+
+    fn constrain(&self) {
+        let Some(sn) = &self.spectral_norm else { return };
+        let sigma = self.spectral_norm();
+        if sigma > sn.bound {
+            let scale = sn.bound / sigma;
+            for w in self.neurons.iter().flat_map(|n| &n.weights) {
+                w.set_data(w.data() * scale);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MLP {
     layers: Vec<Layer>,
+    namespace: RefCell<String>,
 }
 
 impl MLP {
+    /// Every layer uses tanh.
     pub fn new(nin: usize, nout: Vec<usize>) -> Self {
+        MLP::with_activations(nin, nout, Activation::Tanh, Activation::Tanh)
+    }
+
+    pub fn builder(nin: usize, nout: Vec<usize>) -> MLPBuilder {
+        MLPBuilder::new(nin, nout)
+    }
+
+    /// `hidden` on every layer but the last, which uses `output`; e.g. ReLU hidden layers with a
+    /// linear output as in the original micrograd.
+    pub fn with_activations(nin: usize, nout: Vec<usize>, hidden: Activation, output: Activation) -> Self {
         let out_cnt = nout.len();
         let layer_size: Vec<usize> = [nin].into_iter().chain(nout).collect();
 
-        MLP {
-            layers: (0..out_cnt)
-                .map(|i| Layer::new(layer_size[i], layer_size[i + 1]))
-                .collect()
-        }
+        MLP::from_layers(
+            (0..out_cnt)
+                .map(|i| {
+                    let activation = if i + 1 == out_cnt { output } else { hidden };
+                    Layer::with_activation(layer_size[i], layer_size[i + 1], activation)
+                })
+                .collect(),
+        )
+    }
+
+    fn from_layers(layers: Vec<Layer>) -> Self {
+        let mlp = MLP { layers, namespace: RefCell::default() };
+        mlp.relabel();
+        mlp
+    }
+
+    /// Like `with_activations`, with every layer initialized by `Layer::orthogonal`.
+    pub fn orthogonal(nin: usize, nout: Vec<usize>, hidden: Activation, output: Activation) -> Self {
+        let sizes: Vec<usize> = [nin].into_iter().chain(nout).collect();
+        let last = sizes.len() - 1;
+        MLP::from_layers(
+            (1..sizes.len())
+                .map(|i| Layer::orthogonal(sizes[i - 1], sizes[i], if i == last { output } else { hidden }))
+                .collect(),
+        )
+    }
+
+    /// Re-orthogonalizes every layer, see `Layer::reorthogonalize`.
+    pub fn reorthogonalize(&self) {
+        self.layers.iter().for_each(Layer::reorthogonalize);
     }
 
     pub fn forward(&self, mut xs: Vec<Value>) -> Vec<Value> {
@@ -85,15 +615,429 @@ impl MLP {
         xs
     }
 
-    
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub(crate) fn layers_mut(&mut self) -> &mut [Layer] {
+        &mut self.layers
+    }
+
+    /// Labels the parameters of layer `i` as in `Layer::set_namespace` under `{namespace}.l{i}`,
+    /// e.g. `l1.n3.w2` for the default empty namespace.
+    pub fn set_namespace(&self, namespace: &str) {
+        *self.namespace.borrow_mut() = namespace.to_string();
+        self.relabel();
+    }
+
+    pub fn namespace(&self) -> String {
+        self.namespace.borrow().clone()
+    }
+
+    fn relabel(&self) {
+        let namespace = self.namespace.borrow();
+        for (i, l) in self.layers.iter().enumerate() {
+            l.set_namespace(&qualify(&namespace, &format!("l{}", i)));
+        }
+    }
+
+    /// Adds `n_new` neurons to layer `layer` (see `Layer::grow`) and matching zero-weight inputs
+    /// to the layer after it, so the model computes the same function until training moves the
+    /// new weights. Returns every new parameter, for `Optimizer::add_params`.
+    pub fn grow(&mut self, layer: usize, n_new: usize, init: impl FnMut() -> f64) -> Vec<Value> {
+        assert!(layer < self.layers.len(), "layer {} out of range for {} layers", layer, self.layers.len());
+        let mut params = self.layers[layer].grow(n_new, init);
+        if let Some(next) = self.layers.get_mut(layer + 1) {
+            params.extend(next.add_inputs(n_new));
+        }
+        params
+    }
+
+    /// Inserts `layer` before layer `idx` (at the end for `idx == layers().len()`). Its sizes must
+    /// fit between its neighbours; a linear layer with identity weights keeps the model's
+    /// function unchanged. Returns its parameters, for `Optimizer::add_params`.
+    pub fn insert_layer(&mut self, idx: usize, layer: Layer) -> Vec<Value> {
+        assert!(idx <= self.layers.len(), "cannot insert at {} into {} layers", idx, self.layers.len());
+        let nin = if idx == 0 { self.layers.first().map(Layer::nin) } else { Some(self.layers[idx - 1].nout()) };
+        if let Some(nin) = nin {
+            assert_eq!(layer.nin(), nin, "inserted layer takes {} inputs but receives {}", layer.nin(), nin);
+        }
+        if let Some(next) = self.layers.get(idx) {
+            assert_eq!(layer.nout(), next.nin(), "inserted layer gives {} outputs but the next takes {}", layer.nout(), next.nin());
+        }
+        let params = layer.parameters();
+        self.layers.insert(idx, layer);
+        self.relabel();
+        params
+    }
+
+    /// Bounds the spectral norm of every layer, see `Layer::with_spectral_norm`.
+    pub fn with_spectral_norm(mut self, bound: f64) -> Self {
+        self.layers = self.layers.into_iter().map(|l| l.with_spectral_norm(bound)).collect();
+        self
+    }
+
+    /// Writes the topology and parameters to `path` in the text format of `to_text`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    /// Reads a model written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ModelError> {
+        MLP::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// A header line, then per layer a `layer <activation> <nin> <nout>` line followed by one
+    /// `<bias> <weights...>` line per neuron. Values are written so they read back bit for bit.
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("micrograd-mlp 1\n");
+        for layer in &self.layers {
+            let activation = layer.neurons.first().map_or(Activation::Tanh, |n| n.activation);
+            out += &format!("layer {} {} {}\n", activation.name(), layer.nin(), layer.nout());
+            for n in &layer.neurons {
+                let params: Vec<String> = n.parameters().iter().map(|p| format!("{:?}", p.data())).collect();
+                out += &params.join(" ");
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Parses the format written by `to_text`.
+    pub fn from_text(text: &str) -> Result<Self, ModelError> {
+        let bad = |n: usize, msg: String| ModelError::Format(format!("line {}: {}", n + 1, msg));
+        let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+        match lines.next() {
+            Some((_, "micrograd-mlp 1")) => {}
+            _ => return Err(ModelError::Format("missing `micrograd-mlp 1` header".to_string())),
+        }
+
+        let mut layers: Vec<Layer> = Vec::new();
+        while let Some((n, line)) = lines.next() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (activation, nin, nout) = match fields[..] {
+                ["layer", a, nin, nout] => (
+                    Activation::from_name(a).ok_or_else(|| bad(n, format!("unknown activation {}", a)))?,
+                    nin.parse::<usize>().map_err(|_| bad(n, "bad input count".to_string()))?,
+                    nout.parse::<usize>().map_err(|_| bad(n, "bad output count".to_string()))?,
+                ),
+                _ => return Err(bad(n, "expected a layer header".to_string())),
+            };
+            if let Some(prev) = layers.last().filter(|l| l.nout() != nin) {
+                return Err(bad(n, format!("layer takes {} inputs after one with {} outputs", nin, prev.nout())));
+            }
+
+            let mut weights = Vec::with_capacity(nout);
+            let mut biases = Vec::with_capacity(nout);
+            for _ in 0..nout {
+                let (n, line) = lines.next().ok_or_else(|| ModelError::Format("truncated layer".to_string()))?;
+                let values = line
+                    .split_whitespace()
+                    .map(|v| v.parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .map_err(|_| bad(n, "bad number".to_string()))?;
+                if values.len() != nin + 1 {
+                    return Err(bad(n, format!("expected {} values, found {}", nin + 1, values.len())));
+                }
+                biases.push(values[0]);
+                weights.push(values[1..].to_vec());
+            }
+            layers.push(Layer::from_weights(&weights, &biases, activation));
+        }
+        if layers.is_empty() {
+            return Err(ModelError::Format("no layers".to_string()));
+        }
+        Ok(MLP::from_layers(layers))
+    }
+
+    /// Copy with fresh parameter nodes, see `Neuron::deep_copy`.
+    pub fn deep_copy(&self) -> Self {
+        MLP { layers: self.layers.iter().map(Layer::deep_copy).collect(), namespace: self.namespace.clone() }
+    }
+
     pub fn parameters(&self) -> Vec<Value> {
         self.layers.iter().flat_map(|l| l.parameters()).collect()
     }
 }
 
+/// Layers with at least this many weights get the tensor backend when `MLPBuilder` chooses.
+pub const DEFAULT_TENSOR_THRESHOLD: usize = 64;
+
+/// Builds an `MLP`, choosing each layer's `Backend` by size unless told otherwise: small layers
+/// stay scalar so their graphs can be inspected, larger ones run as tensor products.
+#[derive(Debug, Clone)]
+pub struct MLPBuilder {
+    nin: usize,
+    nout: Vec<usize>,
+    hidden: Activation,
+    output: Activation,
+    backend: Option<Backend>,
+    tensor_threshold: usize,
+}
+
+impl MLPBuilder {
+    pub fn new(nin: usize, nout: Vec<usize>) -> Self {
+        MLPBuilder {
+            nin,
+            nout,
+            hidden: Activation::Tanh,
+            output: Activation::Tanh,
+            backend: None,
+            tensor_threshold: DEFAULT_TENSOR_THRESHOLD,
+        }
+    }
+
+    pub fn with_hidden(mut self, activation: Activation) -> Self {
+        self.hidden = activation;
+        self
+    }
+
+    pub fn with_output(mut self, activation: Activation) -> Self {
+        self.output = activation;
+        self
+    }
+
+    /// Uses `backend` for every layer instead of choosing by size.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Weight count from which a layer gets the tensor backend.
+    pub fn with_tensor_threshold(mut self, weights: usize) -> Self {
+        self.tensor_threshold = weights;
+        self
+    }
+
+    pub fn build(self) -> MLP {
+        let mut mlp = MLP::with_activations(self.nin, self.nout, self.hidden, self.output);
+        mlp.layers = mlp
+            .layers
+            .into_iter()
+            .map(|l| {
+                let auto = if l.nin() * l.nout() >= self.tensor_threshold { Backend::Tensor } else { Backend::Scalar };
+                l.with_backend(self.backend.unwrap_or(auto))
+            })
+            .collect();
+        mlp
+    }
+}
+
+impl Module for MLP {
+    fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        MLP::forward(self, xs.to_vec())
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        MLP::parameters(self)
+    }
+
+    fn constrain(&self) {
+        self.layers.iter().for_each(Module::constrain);
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        MLP::set_namespace(self, namespace);
+    }
+
+    /// One group per layer, named `layer 0`, `layer 1`, ... from the input.
+    fn parameter_groups(&self) -> Vec<(String, Vec<Value>)> {
+        self.layers.iter().enumerate().map(|(i, l)| (format!("layer {}", i), l.parameters())).collect()
+    }
+}
+
+/// Skip connection around a module: `x + inner(x)`. The inner module must map its input to an
+/// output of the same length.
+pub struct Residual {
+    inner: Box<dyn Module>,
+}
+
+impl Residual {
+    pub fn new(inner: Box<dyn Module>) -> Self {
+        Residual { inner }
+    }
+}
+
+impl Module for Residual {
+    fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        let out = self.inner.forward(xs);
+        assert_eq!(out.len(), xs.len(), "residual block maps {} inputs to {} outputs", xs.len(), out.len());
+        (Vector::new(xs.to_vec()) + Vector::new(out)).into_inner()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.inner.parameters()
+    }
+
+    fn constrain(&self) {
+        self.inner.constrain();
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        self.inner.set_namespace(namespace);
+    }
+}
+
+/// Stochastic depth around a module whose output is as long as its input: while training the
+/// inner module is skipped with probability `p`, passing the input straight through, and in
+/// `eval_mode` the output is the expectation `p * x + (1 - p) * inner(x)`.
+pub struct StochasticDepth {
+    inner: Box<dyn Module>,
+    p: f64,
+    rng: RefCell<StdRng>,
+}
+
+impl StochasticDepth {
+    /// Seeds the block from `thread_rng`, so blocks stacked in one model skip independently.
+    pub fn new(inner: Box<dyn Module>, p: f64) -> Self {
+        assert!((0.0..1.0).contains(&p), "skip probability must be in [0, 1)");
+        let rng = StdRng::from_rng(rand::thread_rng()).expect("thread_rng never fails");
+        StochasticDepth { inner, p, rng: RefCell::new(rng) }
+    }
+
+    /// Makes the skips reproducible.
+    pub fn with_seed(self, seed: u64) -> Self {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Module for StochasticDepth {
+    fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        let training = is_training();
+        if training && self.rng.borrow_mut().gen_bool(self.p) {
+            return xs.to_vec();
+        }
+        let out = self.inner.forward(xs);
+        assert_eq!(out.len(), xs.len(), "stochastic depth block maps {} inputs to {} outputs", xs.len(), out.len());
+        if training {
+            return out;
+        }
+        (Vector::new(xs.to_vec()) * self.p + Vector::new(out) * (1.0 - self.p)).into_inner()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.inner.parameters()
+    }
+
+    fn constrain(&self) {
+        self.inner.constrain();
+    }
+
+    fn parameter_groups(&self) -> Vec<(String, Vec<Value>)> {
+        self.inner.parameter_groups()
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        self.inner.set_namespace(namespace);
+    }
+}
+
+/// Gradient checkpointing around a module: the forward pass keeps only the outputs, and backward
+/// runs the inner module again from the stored inputs to get the activations it needs. Trades a
+/// second forward pass for not holding the inner graph in memory; the inner module must compute
+/// the same function both times (no dropout or other randomness).
+pub struct Checkpoint {
+    inner: Rc<dyn Module>,
+}
+
+impl Checkpoint {
+    pub fn new(inner: Box<dyn Module>) -> Self {
+        Checkpoint { inner: Rc::from(inner) }
+    }
+}
+
+impl Module for Checkpoint {
+    fn forward(&self, xs: &[Value]) -> Vec<Value> {
+        let outs: Vec<f64> = no_grad(|| self.inner.forward(xs).iter().map(|v| v.data()).collect());
+        let n = outs.len();
+        let pending = Rc::new(RefCell::new(vec![0.0; n]));
+
+        // Every output hangs off this node, so it runs after all of them have their gradients
+        let inner = self.inner.clone();
+        let collected = pending.clone();
+        let inputs: Vec<&Value> = xs.iter().collect();
+        let hub = Value::from_op(0.0, "checkpoint", &inputs, move |_, _, data| {
+            let seeds = std::mem::replace(&mut *collected.borrow_mut(), vec![0.0; n]);
+            let leaves: Vec<Value> = data.iter().map(|&x| Value::from(x)).collect();
+            let outs = inner.forward(&leaves);
+            outs.iter().zip(seeds).for_each(|(o, g)| o.set_grad(g));
+            Value::propagate(&outs);
+            leaves.iter().map(|l| l.grad()).collect()
+        });
+
+        outs.into_iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let pending = pending.clone();
+                Value::from_op(x, "element", &[&hub], move |_, g, _| {
+                    pending.borrow_mut()[i] += g;
+                    vec![0.0]
+                })
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.inner.parameters()
+    }
+
+    fn constrain(&self) {
+        self.inner.constrain();
+    }
+
+    fn set_namespace(&self, namespace: &str) {
+        self.inner.set_namespace(namespace);
+    }
+}
+
+/// Elman recurrent cell, `h' = tanh(W [x; h] + b)`, with one set of weights shared by every
+/// timestep it is unrolled over.
+#[derive(Debug, Clone)]
+pub struct RnnCell {
+    layer: Layer,
+}
+
+impl RnnCell {
+    pub fn new(nin: usize, hidden: usize) -> Self {
+        RnnCell { layer: Layer::new(nin + hidden, hidden) }
+    }
+
+    pub fn hidden(&self) -> usize {
+        self.layer.nout()
+    }
+
+    /// The next hidden state from input `x` and hidden state `h`.
+    pub fn step(&self, x: &[Value], h: &[Value]) -> Vec<Value> {
+        assert_eq!(x.len() + h.len(), self.layer.nin(), "RnnCell input or hidden state of the wrong size");
+        let xh: Vec<Value> = x.iter().chain(h).cloned().collect();
+        self.layer.forward(&xh)
+    }
+
+    /// Runs the cell over `xs` from a zero hidden state and returns the hidden state after every
+    /// step. Every step uses the same parameter nodes, so `backward` sums their gradients over time.
+    pub fn unroll(&self, xs: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        let mut h: Vec<Value> = (0..self.hidden()).map(|_| Value::from(0.0)).collect();
+        xs.iter()
+            .map(|x| {
+                h = self.step(x, &h);
+                h.clone()
+            })
+            .collect()
+    }
+
+    pub fn parameters(&self) -> Vec<Value> {
+        self.layer.parameters()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn init() {
@@ -112,33 +1056,378 @@ mod tests {
         println!("out = {:?}", out);
     }
 
+    #[test]
+    fn linear_output_layer() {
+        let mlp = MLP::with_activations(2, vec![8, 1], Activation::ReLU, Activation::Linear);
+        let params = mlp.parameters();
+        // bias of the single output neuron, ahead of its 8 weights
+        params[params.len() - 9].set_data(5.0);
+        // tanh would squash this into (-1, 1)
+        let xs: Vec<Value> = vec![Value::from(0.0), Value::from(0.0)];
+        assert_eq!(mlp.forward(xs)[0].data(), 5.0);
+
+        let n = Neuron::with_activation(1, Activation::Sigmoid);
+        assert_eq!(n.forward(&[Value::from(0.0)]).data(), 0.5);
+        assert_eq!(Activation::default(), Activation::Tanh);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mlp = MLP::with_activations(3, vec![4, 2], Activation::ReLU, Activation::Linear);
+        let path = std::env::temp_dir().join(format!("micrograd-mlp-{}.txt", std::process::id()));
+        mlp.save(&path).unwrap();
+        let loaded = MLP::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let x = || vec![Value::from(0.3), Value::from(-1.2), Value::from(2.0)];
+        let before: Vec<f64> = mlp.forward(x()).iter().map(|v| v.data()).collect();
+        let after: Vec<f64> = loaded.forward(x()).iter().map(|v| v.data()).collect();
+        assert_eq!(before, after);
+        assert_eq!(loaded.to_text(), mlp.to_text());
+
+        let layer = Layer::from_weights(&[vec![1.0, 2.0]], &[0.5], Activation::Linear);
+        assert_eq!(layer.forward(&[Value::from(1.0), Value::from(1.0)])[0].data(), 3.5);
+
+        assert!(matches!(MLP::from_text("nope"), Err(ModelError::Format(_))));
+        let text = mlp.to_text().replacen("layer linear 4 2", "layer linear 5 2", 1);
+        assert!(matches!(MLP::from_text(&text), Err(ModelError::Format(_))));
+        assert!(matches!(MLP::load(std::env::temp_dir().join("no-such-mlp")), Err(ModelError::Io(_))));
+    }
+
+    #[test]
+    fn deep_copy_is_independent() {
+        let mlp = MLP::new(2, vec![3, 1]);
+        let copy = mlp.deep_copy();
+        let (a, b) = (mlp.parameters(), copy.parameters());
+        assert!(a.iter().zip(&b).all(|(a, b)| a.data() == b.data() && a.id() != b.id()));
+        b[0].set_data(b[0].data() + 1.0);
+        assert_ne!(a[0].data(), b[0].data());
+    }
+
+    // Largest deviation of `m m^T` (or `m^T m` when tall) from the identity
+    fn orthogonality_error(layer: &Layer) -> f64 {
+        let mut m = layer.weight_matrix();
+        if m.len() > layer.nin() {
+            m = (0..layer.nin()).map(|j| m.iter().map(|r| r[j]).collect()).collect();
+        }
+        let mut worst: f64 = 0.0;
+        for (i, a) in m.iter().enumerate() {
+            for (j, b) in m.iter().enumerate() {
+                let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+                worst = worst.max((dot - if i == j { 1.0 } else { 0.0 }).abs());
+            }
+        }
+        worst
+    }
+
+    #[test]
+    fn orthogonal_init_and_reorthogonalize() {
+        let mlp = MLP::orthogonal(5, vec![3, 8, 1], Activation::Tanh, Activation::Linear);
+        assert_eq!(mlp.layers()[1].nin(), 3);
+        assert!(mlp.layers().iter().all(|l| orthogonality_error(l) < 1e-12));
+        assert!(mlp.layers().iter().all(|l| (l.spectral_norm() - 1.0).abs() < 1e-9));
+
+        for p in mlp.parameters() {
+            p.set_data(p.data() * 1.1 + 0.01);
+        }
+        assert!(orthogonality_error(&mlp.layers()[0]) > 0.1);
+        let biases: Vec<f64> = mlp.layers()[0].neurons.iter().map(|n| n.bias.data()).collect();
+        mlp.reorthogonalize();
+        assert!(mlp.layers().iter().all(|l| orthogonality_error(l) < 1e-12));
+        assert!(mlp.layers()[0].neurons.iter().zip(biases).all(|(n, b)| n.bias.data() == b));
+    }
+
+    #[test]
+    fn residual_stack_trains() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let blocks: Vec<Residual> = (0..12)
+            .map(|_| {
+                let w: Vec<Vec<f64>> = (0..2).map(|_| vec![rng.gen_range(-0.5..0.5), rng.gen_range(-0.5..0.5)]).collect();
+                Residual::new(Box::new(Layer::from_weights(&w, &[0.0, 0.0], Activation::Tanh)))
+            })
+            .collect();
+        let params: Vec<Value> = blocks.iter().flat_map(|b| b.parameters()).collect();
+        assert_eq!(params.len(), 12 * 6);
+        let data = [([0.5, -0.2], [0.9, -0.6]), ([-0.3, 0.8], [-0.7, 1.2]), ([0.1, 0.1], [0.3, 0.0])];
+
+        let loss = || -> Value {
+            data.iter()
+                .flat_map(|(x, y)| {
+                    let mut h: Vec<Value> = x.iter().map(|&v| Value::from(v)).collect();
+                    for b in &blocks {
+                        h = b.forward(&h);
+                    }
+                    h.into_iter().zip(y).map(|(p, &t)| (p - t).powop(2))
+                })
+                .sum()
+        };
+        let first = loss();
+        first.backward();
+        // the identity path carries gradient all the way to the first block
+        assert!(blocks[0].parameters().iter().any(|p| p.grad().abs() > 1e-3));
+
+        let mut last = first.data();
+        for _ in 0..100 {
+            let l = loss();
+            params.iter().for_each(|p| p.set_grad(0.0));
+            l.backward();
+            params.iter().for_each(|p| p.set_data(p.data() - 0.02 * p.grad()));
+            last = l.data();
+        }
+        assert!(last < first.data() / 4.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "residual block maps 2 inputs to 3 outputs")]
+    fn residual_checks_shapes() {
+        Residual::new(Box::new(Layer::new(2, 3))).forward(&[Value::from(1.0), Value::from(2.0)]);
+    }
+
+    #[test]
+    fn rnn_shares_weights_across_time() {
+        let cell = RnnCell::new(2, 3);
+        let xs: Vec<Vec<Value>> = (0..5).map(|t| vec![Value::from(t as f64 * 0.2), Value::from(-0.5)]).collect();
+        let hs = cell.unroll(&xs);
+        assert_eq!(hs.len(), 5);
+
+        // the last state depends on exactly the cell's parameter nodes, with no per-step copies
+        let params: HashSet<usize> = cell.parameters().iter().map(|p| p.id()).collect();
+        assert_eq!(params.len(), 3 * (2 + 3 + 1));
+        let views = hs[4][0].graph_view();
+        let leaves: HashSet<usize> = views.iter().filter(|v| v.label.starts_with('n')).map(|v| v.id).collect();
+        assert_eq!(leaves, params);
+
+        let step_losses: Vec<Value> = hs.iter().map(|h| h.iter().map(|v| v.clone().powop(2)).sum()).collect();
+        crate::gradcheck::assert_grad_is_sum(&step_losses, &cell.parameters(), 1e-12);
+    }
+
+    #[test]
+    fn checkpoint_matches_plain_backward() {
+        let mlp = MLP::new(3, vec![8, 8, 8, 2]);
+        let xs = || vec![Value::new(0.4, "x"), Value::new(-1.2, "x"), Value::new(0.7, "x")];
+        let loss = |out: Vec<Value>| (out[0].clone() - 1.0).powop(2) + out[1].clone() * 3.0;
+
+        let plain_x = xs();
+        let plain = loss(Module::forward(&mlp, &plain_x));
+        plain.backward();
+        let plain_grads: Vec<f64> = mlp.parameters().iter().chain(&plain_x).map(|p| p.grad()).collect();
+        mlp.parameters().iter().for_each(|p| p.set_grad(0.0));
+
+        let wrapped = Checkpoint::new(Box::new(mlp.clone()));
+        let ck_x = xs();
+        let ck = loss(wrapped.forward(&ck_x));
+        assert_eq!(ck.data(), plain.data());
+        // inputs, the checkpoint node, its two outputs and the loss ops; none of the MLP
+        assert!(ck.graph_view().len() < 15);
+        ck.backward();
+        let ck_grads: Vec<f64> = wrapped.parameters().iter().chain(&ck_x).map(|p| p.grad()).collect();
+        assert_eq!(ck_grads.len(), plain_grads.len());
+        assert!(ck_grads.iter().zip(&plain_grads).all(|(a, b)| (a - b).abs() < 1e-12));
+    }
+
+    #[test]
+    fn tensor_backend_matches_scalar() {
+        let scalar = MLP::with_activations(4, vec![5, 3, 2], Activation::ReLU, Activation::Sigmoid);
+        let tensor = MLP::from_layers(scalar.layers.iter().map(|l| l.clone().with_backend(Backend::Tensor)).collect());
+        let run = |mlp: &MLP| {
+            mlp.parameters().iter().for_each(|p| p.set_grad(0.0));
+            let x: Vec<Value> = [0.3, -0.7, 1.1, 0.2].iter().map(|&v| Value::new(v, "x")).collect();
+            let out = mlp.forward(x.clone());
+            let loss = out[0].clone() * 2.0 - out[1].clone().powop(2);
+            loss.backward();
+            let grads: Vec<f64> = mlp.parameters().iter().chain(&x).map(|p| p.grad()).collect();
+            (out.iter().map(|v| v.data()).collect::<Vec<f64>>(), grads, loss.graph_view().len())
+        };
+        let (out_s, grads_s, nodes_s) = run(&scalar);
+        let (out_t, grads_t, nodes_t) = run(&tensor);
+        assert!(out_s.iter().zip(&out_t).all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(grads_s.iter().zip(&grads_t).all(|(a, b)| (a - b).abs() < 1e-12));
+        // no per-weight product or sum nodes in the tensor layers
+        assert!(nodes_t < nodes_s * 2 / 3);
+    }
+
+    #[test]
+    fn builder_picks_backend_by_size() {
+        let mlp = MLP::builder(2, vec![16, 16, 1]).with_output(Activation::Linear).build();
+        let backends: Vec<Backend> = mlp.layers().iter().map(Layer::backend).collect();
+        assert_eq!(backends, vec![Backend::Scalar, Backend::Tensor, Backend::Scalar]);
+
+        let forced = MLP::builder(2, vec![16, 1]).with_backend(Backend::Tensor).build();
+        assert!(forced.layers().iter().all(|l| l.backend() == Backend::Tensor));
+        let low = MLP::builder(2, vec![3]).with_tensor_threshold(6).build();
+        assert_eq!(low.layers()[0].backend(), Backend::Tensor);
+    }
+
+    #[test]
+    fn spectral_norm_caps_the_weights() {
+        let layer = Layer::from_weights(&[vec![3.0, 0.0], vec![0.0, 1.0], vec![0.0, 0.0]], &[0.5; 3], Activation::Linear)
+            .with_spectral_norm(1.5);
+        assert!((layer.spectral_norm() - 3.0).abs() < 1e-9);
+        layer.constrain();
+        let w: Vec<f64> = layer.parameters().iter().map(|p| p.data()).collect();
+        assert_eq!(w, vec![0.5, 1.5, 0.0, 0.5, 0.0, 0.5, 0.5, 0.0, 0.0]);
+        // already within the bound: untouched
+        layer.constrain();
+        assert_eq!(layer.parameters()[1].data(), 1.5);
+        assert!((layer.spectral_norm() - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn growing_keeps_the_function_and_trains_the_new_weights() {
+        let mut mlp = MLP::with_activations(2, vec![3, 1], Activation::Tanh, Activation::Linear);
+        let x = || vec![Value::from(0.4), Value::from(-0.9)];
+        let before = mlp.forward(x())[0].data();
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let new = mlp.grow(0, 2, || rng.gen_range(-1.0..1.0));
+        // two neurons of 1 + 2 parameters, and a zero weight from each into the output neuron
+        assert_eq!((new.len(), mlp.layers()[0].nout(), mlp.layers()[1].nin()), (2 * 3 + 2, 5, 5));
+        assert_eq!(mlp.forward(x())[0].data(), before);
+
+        let identity = Layer::from_weights(&[vec![1.0, 0.0], vec![0.0, 1.0]], &[0.0; 2], Activation::Linear);
+        let new: Vec<Value> = new.into_iter().chain(mlp.insert_layer(0, identity)).collect();
+        assert_eq!((mlp.layers().len(), mlp.parameters().len(), new.len()), (3, 6 + 5 * 3 + 6, 8 + 6));
+        assert_eq!(mlp.forward(x())[0].data(), before);
+
+        #[cfg(feature = "optim")]
+        {
+            use crate::optim::{Optimizer, SGD};
+            let mut opt = SGD::new(vec![], 0.1);
+            opt.add_params(new.clone());
+            opt.zero_grad();
+            (mlp.forward(x())[0].clone() - 5.0).powop(2).backward();
+            let start: Vec<f64> = new.iter().map(|p| p.data()).collect();
+            opt.step();
+            assert!(new.iter().zip(start).any(|(p, s)| p.data() != s));
+        }
+    }
+
+    #[test]
+    fn parameter_labels_follow_the_module_path() {
+        let labels = |m: &dyn Module| m.parameters().iter().map(|p| p.borrow().label().to_string()).collect::<Vec<_>>();
+        let mut mlp = MLP::new(2, vec![2, 1]);
+        assert_eq!(labels(&mlp)[..4], ["l0.n0.b", "l0.n0.w0", "l0.n0.w1", "l0.n1.b"]);
+
+        mlp.set_namespace("net");
+        let out = mlp.forward(vec![Value::new(1.0, "x0"), Value::new(2.0, "x1")]);
+        assert!(out[0].graph_view().iter().any(|v| v.label == "net.l1.n0.w1"));
+
+        // growing and inserting layers renumbers under the same namespace
+        mlp.grow(0, 1, || 0.5);
+        mlp.insert_layer(0, Layer::from_weights(&[vec![1.0, 0.0], vec![0.0, 1.0]], &[0.0, 0.0], Activation::Linear));
+        assert_eq!(labels(&mlp).last().unwrap(), "net.l2.n0.w2");
+        assert_eq!(labels(&mlp.layers()[1]).last().unwrap(), "net.l1.n2.w1");
+        assert_eq!(labels(&mlp.deep_copy())[0], "net.l0.n0.b");
+
+        let residual = Residual::new(Box::new(Layer::new(1, 1)));
+        residual.set_namespace("res");
+        assert_eq!(labels(&residual), ["res.n0.b", "res.n0.w0"]);
+    }
+
+    // y = w x through an op whose backward gets the weight's gradient wrong by a factor of 2
+    struct BrokenScale {
+        w: Value,
+    }
+
+    impl Module for BrokenScale {
+        fn forward(&self, xs: &[Value]) -> Vec<Value> {
+            let w = &self.w;
+            xs.iter().map(|x| Value::from_op(w.data() * x.data(), "scale", &[w, x], |_, g, d| vec![2.0 * g * d[1], g * d[0]])).collect()
+        }
+
+        fn parameters(&self) -> Vec<Value> {
+            vec![self.w.clone()]
+        }
+    }
+
+    #[test]
+    fn gradcheck_reports_failures_by_parameter_path() {
+        let mse = |out: &[Value]| -> Value { out.iter().map(|y| (y.clone() - 0.5).powop(2)).sum() };
+        let input = vec![Value::from(0.3), Value::from(-0.8)];
+        let mlp = MLP::with_activations(2, vec![3, 1], Activation::Tanh, Activation::Sigmoid);
+        let report = mlp.gradcheck(&input, &mse, 1e-6);
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.checked, mlp.parameters().len());
+
+        let broken = BrokenScale { w: Value::new(1.5, "") };
+        broken.set_namespace("scale");
+        let report = broken.gradcheck(&input, &mse, 1e-6);
+        assert_eq!(report.failures.len(), 1);
+        let m = &report.failures[0];
+        assert_eq!(m.path, "scale.p0");
+        assert!((m.analytic - 2.0 * m.numeric).abs() < 1e-6);
+        assert_eq!(broken.w.data(), 1.5);
+        assert!(report.to_string().starts_with("1 of 1 parameters disagree"));
+    }
+
+    #[test]
+    fn stochastic_depth_skips_while_training_and_averages_in_eval() {
+        let inner = Layer::from_weights(&[vec![2.0, 0.0], vec![0.0, 2.0]], &[1.0, 1.0], Activation::Linear);
+        let block = StochasticDepth::new(Box::new(inner), 0.25).with_seed(3);
+        let x = || vec![Value::from(1.0), Value::from(-2.0)];
+        let data = |out: Vec<Value>| out.iter().map(|v| v.data()).collect::<Vec<_>>();
+
+        let runs: Vec<Vec<f64>> = (0..400).map(|_| data(block.forward(&x()))).collect();
+        let skipped = runs.iter().filter(|r| *r == &vec![1.0, -2.0]).count();
+        assert!(runs.iter().all(|r| r == &vec![1.0, -2.0] || r == &vec![3.0, -3.0]));
+        assert!((70..130).contains(&skipped), "{} of 400 skipped", skipped);
+
+        // a skipped pass sends no gradient into the block
+        let out: Value = block.forward(&x()).into_iter().sum();
+        out.backward();
+        let reached = block.parameters().iter().any(|p| p.grad() != 0.0);
+        let skipped_now = out.data() == -1.0;
+        assert_ne!(reached, skipped_now);
+
+        // blocks built alike still draw their skips independently
+        let blocks: Vec<StochasticDepth> = (0..2).map(|_| StochasticDepth::new(Box::new(Layer::new(2, 2)), 0.5)).collect();
+        let skips = |b: &StochasticDepth| (0..64).map(|_| data(b.forward(&x())) == vec![1.0, -2.0]).collect::<Vec<_>>();
+        assert_ne!(skips(&blocks[0]), skips(&blocks[1]));
+
+        assert!(is_training());
+        let eval = eval_mode(|| {
+            assert!(!is_training());
+            data(block.forward(&x()))
+        });
+        assert!(is_training());
+        assert_eq!(eval, vec![0.25 * 1.0 + 0.75 * 3.0, 0.25 * -2.0 + 0.75 * -3.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "inserted layer gives 2 outputs but the next takes 3")]
+    fn inserted_layer_must_fit() {
+        let mut mlp = MLP::new(2, vec![3, 1]);
+        mlp.insert_layer(1, Layer::new(3, 2));
+    }
+
     #[test]
     fn simple_model() {
-        let x = vec![2.0, 3.0, -1.0];
         let mlp = MLP::new(3, vec![4, 4, 1]);
 
-        let xs = vec![
-            vec![2.0, 3.0, -1.0],
-            vec![3.0, -1.0, 0.5],
-            vec![0.5, 1.0, 1.0],
-            vec![1.0, 1.0, -1.0],
+        let xs = [
+            [2.0, 3.0, -1.0],
+            [3.0, -1.0, 0.5],
+            [0.5, 1.0, 1.0],
+            [1.0, 1.0, -1.0],
         ];
 
-        let ys = vec![Value::new(1.0, ""), Value::new(-1.0, ""), Value::new(-1.0, ""), Value::new(1.0, "")];
+        let ys = [Value::new(1.0, ""), Value::new(-1.0, ""), Value::new(-1.0, ""), Value::new(1.0, "")];
         let ypred: Vec<Value> = xs
             .iter()
             .map(|x| mlp.forward(x.iter().map(|x| Value::from(*x)).collect())[0].clone())
             .collect();
 
-        let ypred_floats: Vec<f64> = ypred.iter().map(|v| v.borrow().data).collect();
+        let ypred_floats: Vec<f64> = ypred.iter().map(|v| v.data()).collect();
+        assert_eq!(ypred_floats.len(), ys.len());
 
-        let ygt = ys.iter().map(|y| Value::from(y.clone()));
-
-        // Loss function
-        // let loss: Value = ypred
-        //     .into_iter()
-        //     .zip(ygt)
-        //     .map(|(yp, yg)| (yp - yg).powop(2.0))
-        //     .sum();
+        let loss: Value = ypred
+            .iter()
+            .zip(&ys)
+            .map(|(yp, yg)| (yp.clone() - yg.clone()).powop(2.0))
+            .sum();
+        let expected: f64 = ypred_floats.iter().zip(&ys).map(|(p, y)| (p - y.data()).powi(2)).sum();
+        assert!((loss.data() - expected).abs() < 1e-12);
+        loss.backward();
+        assert!(mlp.parameters().iter().any(|p| p.grad() != 0.0));
     }
 }
@@ -0,0 +1,212 @@
+use crate::operators::*;
+use rand::Rng;
+
+/// Inverted dropout: each input is zeroed with probability `p` and the survivors are scaled by
+/// `1 / (1 - p)` so the expected activation is unchanged.
+pub fn dropout<R: Rng>(xs: &[Value], p: f64, rng: &mut R) -> Vec<Value> {
+    assert!((0.0..1.0).contains(&p), "dropout probability must be in [0, 1)");
+    let mask = draw(xs.len(), || {
+        (0..xs.len())
+            .map(|_| if rng.gen_bool(p) { 0.0 } else { 1.0 / (1.0 - p) })
+            .collect()
+    });
+    xs.iter().zip(mask).map(|(x, m)| x * m).collect()
+}
+
+/// Adds zero-mean gaussian noise with standard deviation `std` to each input.
+pub fn gaussian_noise<R: Rng>(xs: &[Value], std: f64, rng: &mut R) -> Vec<Value> {
+    let noise = draw(xs.len(), || {
+        (0..xs.len()).map(|_| std * gaussian(rng)).collect()
+    });
+    xs.iter().zip(noise).map(|(x, n)| x + n).collect()
+}
+
+// Standard normal sample via Box-Muller, so we don't need rand_distr just for this
+pub(crate) fn gaussian<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen_range(0.0..1.0);
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(feature = "mask-replay")]
+fn draw(len: usize, sample: impl FnOnce() -> Vec<f64>) -> Vec<f64> {
+    replay::next_mask(len, sample)
+}
+
+#[cfg(not(feature = "mask-replay"))]
+fn draw(_len: usize, sample: impl FnOnce() -> Vec<f64>) -> Vec<f64> {
+    sample()
+}
+
+/// Recording and replaying of the masks drawn by the ops in this module, so that two runs
+/// (say native and WASM) see exactly the same randomness.
+#[cfg(feature = "mask-replay")]
+pub mod replay {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Masks drawn inside a `record` call, in draw order.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct MaskLog {
+        pub masks: Vec<Vec<f64>>,
+    }
+
+    enum Mode {
+        Off,
+        Record(Vec<Vec<f64>>),
+        Replay(VecDeque<Vec<f64>>),
+    }
+
+    thread_local! {
+        static MODE: RefCell<Mode> = const { RefCell::new(Mode::Off) };
+    }
+
+    // Puts the previous mode back when dropped, even when unwinding
+    struct Restore(Option<Mode>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(mode) = self.0.take() {
+                MODE.with(|m| *m.borrow_mut() = mode);
+            }
+        }
+    }
+
+    fn enter(mode: Mode) -> Restore {
+        Restore(Some(MODE.with(|m| m.replace(mode))))
+    }
+
+    impl MaskLog {
+        /// One newline-terminated line per mask, empty masks included, each entry written as the
+        /// hex bits of the f64 so the round trip is exact.
+        pub fn to_text(&self) -> String {
+            self.masks
+                .iter()
+                .map(|m| m.iter().map(|x| format!("{:016x}", x.to_bits())).collect::<Vec<_>>().join(" ") + "\n")
+                .collect()
+        }
+
+        /// Reads what `to_text` wrote: one mask per line, so an empty line is an empty mask.
+        pub fn from_text(text: &str) -> Result<Self, String> {
+            let masks = text
+                .lines()
+                .map(|line| {
+                    line.split_whitespace()
+                        .map(|w| u64::from_str_radix(w, 16).map(f64::from_bits).map_err(|e| format!("bad mask entry {:?}: {}", w, e)))
+                        .collect::<Result<Vec<f64>, String>>()
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(MaskLog { masks })
+        }
+    }
+
+    /// Runs `f`, recording every mask drawn by dropout/noise ops.
+    pub fn record<T>(f: impl FnOnce() -> T) -> (T, MaskLog) {
+        let _restore = enter(Mode::Record(Vec::new()));
+        let out = f();
+        let masks = MODE.with(|m| match m.replace(Mode::Off) {
+            Mode::Record(masks) => masks,
+            _ => Vec::new(),
+        });
+        (out, MaskLog { masks })
+    }
+
+    /// Runs `f`, feeding dropout/noise ops the masks from `log` instead of sampling.
+    ///
+    /// Panics if `f` draws more masks than were recorded or a mask has the wrong length.
+    pub fn replay<T>(log: &MaskLog, f: impl FnOnce() -> T) -> T {
+        let _restore = enter(Mode::Replay(log.masks.iter().cloned().collect()));
+        f()
+    }
+
+    pub(crate) fn next_mask(len: usize, sample: impl FnOnce() -> Vec<f64>) -> Vec<f64> {
+        let replayed = MODE.with(|m| match &mut *m.borrow_mut() {
+            Mode::Replay(queue) => Some(queue.pop_front().expect("mask log exhausted during replay")),
+            _ => None,
+        });
+        if let Some(mask) = replayed {
+            assert_eq!(mask.len(), len, "replayed mask has the wrong length");
+            return mask;
+        }
+
+        let mask = sample();
+        MODE.with(|m| {
+            if let Mode::Record(masks) = &mut *m.borrow_mut() {
+                masks.push(mask.clone());
+            }
+        });
+        mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn dropout_scales_survivors() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let xs: Vec<Value> = (0..100).map(|i| Value::new(i as f64 + 1.0, "x")).collect();
+        let out = dropout(&xs, 0.5, &mut rng);
+        for (x, o) in xs.iter().zip(&out) {
+            assert!(o.data() == 0.0 || (o.data() - 2.0 * x.data()).abs() < 1e-12);
+        }
+        assert!(out.iter().any(|o| o.data() == 0.0));
+
+        out[0].backward();
+        assert!(xs[0].grad() == 0.0 || xs[0].grad() == 2.0);
+    }
+
+    #[cfg(feature = "mask-replay")]
+    #[test]
+    fn replay_reproduces_recorded_masks() {
+        let xs: Vec<Value> = (0..16).map(|i| Value::new(i as f64, "x")).collect();
+
+        let (first, log) = replay::record(|| {
+            let mut rng = StdRng::seed_from_u64(1);
+            let h = dropout(&xs, 0.3, &mut rng);
+            gaussian_noise(&h, 0.1, &mut rng)
+        });
+        assert_eq!(log.masks.len(), 2);
+
+        let log = replay::MaskLog::from_text(&log.to_text()).unwrap();
+        let second = replay::replay(&log, || {
+            // a different seed, the replayed masks must win
+            let mut rng = StdRng::seed_from_u64(99);
+            let h = dropout(&xs, 0.3, &mut rng);
+            gaussian_noise(&h, 0.1, &mut rng)
+        });
+        let a: Vec<u64> = first.iter().map(|v| v.data().to_bits()).collect();
+        let b: Vec<u64> = second.iter().map(|v| v.data().to_bits()).collect();
+        assert_eq!(a, b);
+    }
+
+    #[cfg(feature = "mask-replay")]
+    #[test]
+    fn empty_masks_survive_the_text_round_trip() {
+        let log = replay::MaskLog { masks: vec![vec![0.5, 2.0], vec![], vec![1.0], vec![]] };
+        assert_eq!(replay::MaskLog::from_text(&log.to_text()).unwrap(), log);
+        assert_eq!(replay::MaskLog::from_text("").unwrap(), replay::MaskLog::default());
+
+        // a zero-length draw is recorded, and replayed rather than running the log dry
+        let (_, log) = replay::record(|| dropout(&[], 0.5, &mut StdRng::seed_from_u64(1)));
+        let log = replay::MaskLog::from_text(&log.to_text()).unwrap();
+        assert_eq!(log.masks, vec![Vec::<f64>::new()]);
+        assert!(replay::replay(&log, || dropout(&[], 0.5, &mut StdRng::seed_from_u64(2))).is_empty());
+    }
+
+    #[cfg(feature = "mask-replay")]
+    #[test]
+    fn a_panic_inside_replay_leaves_sampling_on() {
+        let xs: Vec<Value> = (0..16).map(|i| Value::new(i as f64, "x")).collect();
+        let log = replay::MaskLog { masks: vec![vec![1.0]] };
+        let result = std::panic::catch_unwind(|| replay::replay(&log, || panic!("training step failed")));
+        assert!(result.is_err());
+
+        // still replaying, this would pop the one-entry mask and fail its length check
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(dropout(&xs, 0.3, &mut rng).len(), 16);
+    }
+}
@@ -0,0 +1,136 @@
+//! Rich output for Rust notebooks: evcxr calls `evcxr_display` on the value of a cell, which
+//! here prints a graph as inline SVG and a model as an HTML table.
+
+#[cfg(feature = "nn")]
+use crate::nn::MLP;
+use crate::operators::Value;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const NODE_W: usize = 150;
+const NODE_H: usize = 34;
+const COL_GAP: usize = 50;
+const ROW_GAP: usize = 14;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn print_html(html: &str) {
+    println!("EVCXR_BEGIN_CONTENT text/html\n{}\nEVCXR_END_CONTENT", html);
+}
+
+/// The graph reachable from `value` as an SVG, one column per depth from the leaves.
+pub fn graph_svg(value: &Value) -> String {
+    let views = value.graph_view();
+    let index: HashMap<usize, usize> = views.iter().enumerate().map(|(i, v)| (v.id, i)).collect();
+    // parents come first, so every parent's depth is known when its children are reached
+    let mut depth = vec![0; views.len()];
+    for (i, v) in views.iter().enumerate() {
+        depth[i] = v.parents.iter().map(|p| depth[index[p]] + 1).max().unwrap_or(0);
+    }
+    let mut rows = vec![0; depth.iter().max().map_or(0, |d| d + 1)];
+    let pos: Vec<(usize, usize)> = depth
+        .iter()
+        .map(|&d| {
+            rows[d] += 1;
+            (d * (NODE_W + COL_GAP), (rows[d] - 1) * (NODE_H + ROW_GAP))
+        })
+        .collect();
+    let width = rows.len() * (NODE_W + COL_GAP);
+    let height = rows.iter().max().unwrap_or(&0) * (NODE_H + ROW_GAP);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"11\">",
+        width, height
+    );
+    for (i, v) in views.iter().enumerate() {
+        for p in &v.parents {
+            let (px, py) = pos[index[p]];
+            let (x, y) = pos[i];
+            write!(
+                svg,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999\"/>",
+                px + NODE_W, py + NODE_H / 2, x, y + NODE_H / 2
+            )
+            .unwrap();
+        }
+    }
+    for (v, (x, y)) in views.iter().zip(&pos) {
+        let fill = if v.op.is_some() { "#eef4ff" } else { "#fff" };
+        let title = v.op.as_deref().unwrap_or(if v.label.is_empty() { "leaf" } else { &v.label });
+        write!(
+            svg,
+            "<g transform=\"translate({},{})\"><rect width=\"{}\" height=\"{}\" rx=\"4\" fill=\"{}\" stroke=\"#555\"/>\
+             <text x=\"6\" y=\"14\">{}</text><text x=\"6\" y=\"28\">d={:.4} g={:.4}</text></g>",
+            x, y, NODE_W, NODE_H, fill, escape(title), v.data, v.grad
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// One row per layer: its shape, parameter count and the range of its parameters.
+#[cfg(feature = "nn")]
+pub fn model_table(mlp: &MLP) -> String {
+    let mut html = String::from(
+        "<table><tr><th>layer</th><th>inputs</th><th>outputs</th><th>params</th><th>min</th><th>max</th></tr>",
+    );
+    for (i, layer) in mlp.layers().iter().enumerate() {
+        let params: Vec<f64> = layer.parameters().iter().map(|p| p.data()).collect();
+        let min = params.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = params.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.4}</td><td>{:.4}</td></tr>",
+            i, layer.nin(), layer.nout(), params.len(), min, max
+        )
+        .unwrap();
+    }
+    write!(html, "<tr><td colspan=\"3\">total</td><td>{}</td><td></td><td></td></tr></table>", mlp.parameters().len())
+        .unwrap();
+    html
+}
+
+impl Value {
+    /// evcxr display hook: renders the graph inline.
+    pub fn evcxr_display(&self) {
+        print_html(&graph_svg(self));
+    }
+}
+
+#[cfg(feature = "nn")]
+impl MLP {
+    /// evcxr display hook: renders the layers as a table.
+    pub fn evcxr_display(&self) {
+        print_html(&model_table(self));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_as_svg() {
+        let x = Value::new(0.5, "<x>");
+        let out = (x.clone() * 2.0).tanh();
+        out.backward();
+        let svg = graph_svg(&out);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect").count(), 4);
+        assert_eq!(svg.matches("<line").count(), 3);
+        assert!(svg.contains("&lt;x&gt;") && svg.contains(">tanh<"));
+    }
+
+    #[test]
+    #[cfg(feature = "nn")]
+    fn model_as_table() {
+        let mlp = MLP::new(3, vec![4, 2]);
+        let table = model_table(&mlp);
+        assert_eq!(table.matches("<tr>").count(), 4);
+        assert!(table.contains("<td>0</td><td>3</td><td>4</td><td>16</td>"));
+        assert!(table.contains("<td>26</td>"));
+    }
+}
@@ -1,359 +1,1313 @@
-use std::cell::RefCell;
-use std::rc::Rc;
+// The graph internals still use the deprecated public fields directly.
+#![allow(deprecated)]
+
+use crate::diagnostics::LiveToken;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::fmt;
+use std::collections::{HashMap, HashSet};
+use std::iter::Sum;
+use std::ops::{Add, Mul, Div, Neg, Sub};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_AUTO_LABEL: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static AUTO_LABELS: Cell<bool> = const { Cell::new(false) };
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+    static DETERMINISTIC: Cell<bool> = const { Cell::new(false) };
+    static LAZY: Cell<bool> = const { Cell::new(false) };
+    // Gradient contributions held back in deterministic mode, by target node address, each
+    // tagged with the creation number of the node that sent it
+    static PENDING: RefCell<HashMap<usize, Vec<(usize, f64)>>> = RefCell::new(HashMap::new());
+    // Creation number of the node whose backward closure is running
+    static SOURCE: Cell<usize> = const { Cell::new(0) };
+}
 
-pub mod operators {
-    use super::*;
-    use std::fmt;
-    use std::collections::HashSet;
-    use std::ops::{Add, Mul, Div, Sub};
-    
-    #[derive(Clone)]
-    pub struct GraphNode {
-        pub data: f64,
-        pub grad: f64,
-        pub label: String,
-        pub prev: Vec<Rc<RefCell<GraphNode>>>,
-        pub op: Option<String>,
-        pub backward: Option<Rc<dyn Fn()>>,
-    }
-
-    #[derive(Debug, Clone)]
-    pub struct Value(Rc<RefCell<GraphNode>>);
-
-    impl GraphNode {
-        fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
-            let indent_str = " ".repeat(indent);
-            writeln!(
-                f,
-                "{}{} (data={:.6}, grad={:.6}, op={:?})",
-                indent_str,
-                if self.label.is_empty() { "GraphNode" } else { &self.label },
-                self.data,
-                self.grad,
-                self.op
-            )?;
-
-            for parent_rc in &self.prev {
-                parent_rc.borrow().fmt_indented(f, indent + 4)?;
-            }
+static NEXT_SEQ: AtomicUsize = AtomicUsize::new(0);
 
-            Ok(())
+/// Runs `f` with automatic labelling: op outputs built inside are labelled `add_17`, `mul_42`,
+/// ... and unlabelled leaves `value_3`, instead of `+`, `*` and the empty string. Applies to the
+/// current thread only; nests.
+pub fn auto_labels<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            AUTO_LABELS.with(|a| a.set(self.0));
         }
+    }
+    let _restore = Restore(AUTO_LABELS.with(|a| a.replace(true)));
+    f()
+}
 
-        fn topological_sort(root : &Value) -> Vec<Value> {
-            let mut topo: Vec<Value> = Vec::new();
-            let mut visited: HashSet<usize> = HashSet::new();
+/// Whether values built on this thread are labelled automatically (inside `auto_labels`).
+pub fn is_auto_labelling() -> bool {
+    AUTO_LABELS.with(|a| a.get())
+}
 
-            fn dfs(node_rc: Rc<RefCell<GraphNode>>, visited: &mut HashSet<usize>, topo: &mut Vec<Value>) {
-                let id = Rc::as_ptr(&node_rc) as usize;
-                if visited.contains(&id) { return; }
-                visited.insert(id);
+/// Runs `f` without recording the graph: ops inside compute their values as usual but keep no
+/// parents or backward closures, so nothing flows back through them and intermediates are freed
+/// right away. Applies to the current thread only; nests.
+pub fn no_grad<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            GRAD_ENABLED.with(|g| g.set(self.0));
+        }
+    }
+    let _restore = Restore(GRAD_ENABLED.with(|g| g.replace(false)));
+    f()
+}
 
-                let parents: Vec<Rc<RefCell<GraphNode>>> = node_rc.borrow().prev.clone();
+/// Whether ops on this thread currently record the graph (false inside `no_grad`).
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(|g| g.get())
+}
 
-                for w in parents {
-                    dfs(w, visited, topo);
-                }
+/// Runs `f` with deterministic backward passes: the gradient contributions a shared node gets
+/// from its consumers are summed in the order those consumers were created rather than the order
+/// the traversal reaches them, so the result is bit-identical whatever the traversal does.
+/// Applies to the current thread only; nests.
+pub fn deterministic_backward<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DETERMINISTIC.with(|d| d.set(self.0));
+        }
+    }
+    let _restore = Restore(DETERMINISTIC.with(|d| d.replace(true)));
+    f()
+}
 
-                topo.push(Value(node_rc.clone()));
-            }
+/// Whether backward passes on this thread sort gradient contributions (inside `deterministic_backward`).
+pub fn is_deterministic_backward() -> bool {
+    DETERMINISTIC.with(|d| d.get())
+}
 
-            dfs(root.rc(), &mut visited, &mut topo);
-            topo
+/// Runs `f` in lazy mode: ops inside record the graph as usual but leave the data of their
+/// outputs as NaN until `Value::materialize` computes the whole graph in one pass, so passes
+/// such as `fuse_mul_add` can rewrite it before any of its arithmetic matters. Ops that cannot
+/// be replayed (see `Value::recompute`) keep what they computed from their pending inputs, so
+/// stay NaN. Checks on data, such as division by zero, cannot see pending values. Applies to the
+/// current thread only; nests.
+pub fn lazy<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            LAZY.with(|l| l.set(self.0));
         }
+    }
+    let _restore = Restore(LAZY.with(|l| l.replace(true)));
+    f()
+}
 
-        pub fn backward(root: &Value)  {
-            let topo = GraphNode::topological_sort(root);
-            root.borrow_mut().grad = 1.0;
-            
-            for node in topo.into_iter().rev() {
-                if let Some(cb) = node.borrow().backward.as_ref() {
-                    (cb)();
-                }
-            }
-        }
+/// Whether ops on this thread currently defer their data (inside `lazy`).
+pub fn is_lazy() -> bool {
+    LAZY.with(|l| l.get())
+}
+
+// Adds `g` to the gradient of `target`, or queues it for `flush` in deterministic mode
+fn accumulate(target: &Rc<RefCell<GraphNode>>, g: f64) {
+    if is_deterministic_backward() {
+        let source = SOURCE.with(|s| s.get());
+        PENDING.with(|p| p.borrow_mut().entry(Rc::as_ptr(target) as usize).or_default().push((source, g)));
+    } else {
+        target.borrow_mut().grad += g;
     }
+}
 
-    impl fmt::Debug for GraphNode {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            writeln!(f, "Computation Graph:")?;
-            self.fmt_indented(f, 0)
+// Adds the queued contributions to `node`'s gradient, ordered by sender
+fn flush(node: &Rc<RefCell<GraphNode>>) {
+    if let Some(mut parts) = PENDING.with(|p| p.borrow_mut().remove(&(Rc::as_ptr(node) as usize))) {
+        parts.sort_by_key(|&(source, _)| source);
+        let mut n = node.borrow_mut();
+        for (_, g) in parts {
+            n.grad += g;
         }
     }
+}
 
-    impl Value {
-        fn rc(&self) -> Rc<RefCell<GraphNode>> { self.0.clone() }
+// Like f64::signum but 0 at 0, the subgradient used for |x|
+fn sign(x: f64) -> f64 {
+    if x > 0.0 { 1.0 } else if x < 0.0 { -1.0 } else { 0.0 }
+}
 
-        pub fn new(data: f64, label: &str) -> Self {
-            Value(Rc::new(RefCell::new(GraphNode {
-                data,
-                grad: 0.0,
-                label: label.to_string(),
-                prev: vec![],
-                op: None,
-                backward: None,
-            })))
-        }
+fn sigmoid(x: f64) -> f64 {
+    // the two forms keep exp from overflowing on either side
+    if x >= 0.0 {
+        1.0 / (1.0 + crate::math::exp(-x))
+    } else {
+        let e = crate::math::exp(x);
+        e / (1.0 + e)
+    }
+}
+
+// d(ab + c) = b da + a db + dc
+fn mul_add_backward(_: f64, out_grad: f64, x: &[f64]) -> Vec<f64> {
+    vec![out_grad * x[1], out_grad * x[0], out_grad]
+}
+
+fn next_auto_label(kind: &str) -> String {
+    format!("{}_{}", kind, NEXT_AUTO_LABEL.fetch_add(1, Ordering::Relaxed))
+}
+
+type ForwardFn = Rc<dyn Fn(&[f64]) -> f64>;
+
+#[derive(Clone)]
+pub struct GraphNode {
+    #[deprecated(note = "use `GraphNode::data`/`set_data` or `Value::data`/`set_data`")]
+    pub data: f64,
+    #[deprecated(note = "use `GraphNode::grad`/`set_grad` or `Value::grad`/`set_grad`")]
+    pub grad: f64,
+    #[deprecated(note = "use `GraphNode::label`/`set_label`")]
+    pub label: String,
+    #[deprecated(note = "use `GraphNode::prev`")]
+    pub prev: Vec<Rc<RefCell<GraphNode>>>,
+    #[deprecated(note = "use `GraphNode::op`")]
+    pub op: Option<String>,
+    #[deprecated(note = "use `GraphNode::has_backward`")]
+    pub backward: Option<Rc<dyn Fn()>>,
+    // Weak handles to the parents captured by `backward`, kept so `Value::validate` can check them
+    pub(crate) backward_refs: Vec<Weak<RefCell<GraphNode>>>,
+    // Recomputes the data from the parents' data, for `Value::recompute`
+    pub(crate) forward: Option<ForwardFn>,
+    // Optimizers leave nodes with this cleared untouched
+    pub(crate) requires_grad: bool,
+    // A fixed number rather than a parameter or input, see `Value::constant`
+    pub(crate) constant: bool,
+    // Spread of the data, carried along inside `uncertainty::track`
+    pub(crate) variance: f64,
+    // Creation order, which fixes the summation order of deterministic backward passes
+    pub(crate) seq: usize,
+    pub(crate) _live: LiveToken,
+}
+
+/// A broken graph invariant reported by `Value::validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvariantViolation {
+    /// The node is reachable from itself through `prev`; `path` lists the labels around the cycle.
+    Cycle { path: Vec<String> },
+    /// The backward closure of `label` points at a parent that has already been dropped.
+    DeadParent { label: String },
+}
 
-        // Reference borrowing of the inner object
-        pub fn borrow(&self) -> std::cell::Ref<GraphNode> {
-            self.0.borrow()
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantViolation::Cycle { path } => write!(f, "cycle in prev: {}", path.join(" -> ")),
+            InvariantViolation::DeadParent { label } => {
+                write!(f, "backward closure of {} refers to a dropped parent", label)
+            }
         }
+    }
+}
 
-        // Create a mutable borrow of the inner object
-        pub fn borrow_mut(&self) -> std::cell::RefMut<GraphNode> {
-            self.0.borrow_mut()
+/// Why a fallible `Value` operation refused to run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueError {
+    DivideByZero,
+    LogOfNonPositive(f64),
+    SqrtOfNegative(f64),
+    /// The node is mutably borrowed elsewhere, e.g. from inside a backward closure.
+    Borrowed,
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::DivideByZero => write!(f, "Divide by zero"),
+            ValueError::LogOfNonPositive(x) => write!(f, "log of a non-positive value ({})", x),
+            ValueError::SqrtOfNegative(x) => write!(f, "sqrt of a negative value ({})", x),
+            ValueError::Borrowed => write!(f, "value is already mutably borrowed"),
         }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+/// Read-only snapshot of a single node, for inspection tools that should not hold borrows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeView {
+    pub id: usize,
+    pub data: f64,
+    pub grad: f64,
+    pub label: String,
+    pub op: Option<String>,
+    pub parents: Vec<usize>,
+}
 
-        pub fn label(&mut self, label: &str) {
-            self.borrow_mut().label = label.to_string();
+#[derive(Debug, Clone)]
+pub struct Value(Rc<RefCell<GraphNode>>);
+
+/// Reverse edges of a graph: for every node, the nodes that use it as a parent. Nodes only
+/// link to their parents, so this is built by walking the graph from its roots, and goes stale
+/// as soon as new ops are recorded on top of them.
+#[derive(Debug, Clone, Default)]
+pub struct Consumers {
+    // consumers of every node by id, in topological order and without repeats
+    edges: HashMap<usize, Vec<Value>>,
+}
+
+impl Consumers {
+    /// The reverse edges of everything reachable from `roots`.
+    pub fn build(roots: &[Value]) -> Self {
+        let mut edges: HashMap<usize, Vec<Value>> = HashMap::new();
+        for node in GraphNode::topological_sort_many(roots) {
+            let mut parents: Vec<usize> = node.borrow().prev.iter().map(|p| Rc::as_ptr(p) as usize).collect();
+            parents.sort_unstable();
+            parents.dedup();
+            for p in parents {
+                edges.entry(p).or_default().push(node.clone());
+            }
         }
+        Consumers { edges }
+    }
 
-        pub fn tanh(self) -> Value {
-            let x = self.borrow().data;
+    /// The nodes that use `value` directly.
+    pub fn of(&self, value: &Value) -> &[Value] {
+        self.edges.get(&value.id()).map_or(&[], Vec::as_slice)
+    }
 
-            let out = Self::new(x.tanh(), "tanh");
-            {
-                let mut out_mut = out.borrow_mut();
-                out_mut.op = Some("tanh".to_string());
-                out_mut.prev = vec![Rc::clone(&self.0), ];
+    /// Everything downstream of `value`: its consumers, their consumers and so on, each once.
+    pub fn downstream(&self, value: &Value) -> Vec<Value> {
+        let mut seen: HashSet<usize> = HashSet::new();
+        let mut out = Vec::new();
+        let mut stack = vec![value.clone()];
+        while let Some(v) = stack.pop() {
+            for c in self.of(&v) {
+                if seen.insert(c.id()) {
+                    out.push(c.clone());
+                    stack.push(c.clone());
+                }
             }
+        }
+        out
+    }
+}
+
+impl GraphNode {
+    pub fn data(&self) -> f64 { self.data }
 
-            let weak_out = Rc::downgrade(&out.0);
-            let weak_a = Rc::downgrade(&self.0);
+    pub fn set_data(&mut self, data: f64) { self.data = data; }
 
-            out.borrow_mut().backward = Some(Rc::new(move || {
-                if let Some(out_rc) = weak_out.upgrade() {
-                    let out_grad = out_rc.borrow().grad;
-                    let out_val = out_rc.borrow().data;
+    pub fn grad(&self) -> f64 { self.grad }
 
-                    if let Some(a_rc) = weak_a.upgrade() {
-                        a_rc.borrow_mut().grad += (1.0 - out_val.powf(2.0)) * out_grad;
+    pub fn set_grad(&mut self, grad: f64) { self.grad = grad; }
+
+    pub fn label(&self) -> &str { &self.label }
+
+    pub fn set_label(&mut self, label: &str) { self.label = label.to_string(); }
+
+    pub fn op(&self) -> Option<&str> { self.op.as_deref() }
+
+    pub fn prev(&self) -> &[Rc<RefCell<GraphNode>>] { &self.prev }
+
+    pub fn has_backward(&self) -> bool { self.backward.is_some() }
+
+    fn display_label(&self) -> String {
+        if self.label.is_empty() { "GraphNode".to_string() } else { self.label.clone() }
+    }
+
+    fn fmt_line(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{}{} (data={:.6}, grad={:.6}, op={:?})",
+            " ".repeat(indent),
+            if self.label.is_empty() { "GraphNode" } else { &self.label },
+            self.data,
+            self.grad,
+            self.op
+        )
+    }
+
+    // The node and its ancestors as an indented tree, parents under their child. Uses an explicit
+    // stack so deep graphs cannot overflow the call stack.
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        self.fmt_line(f, indent)?;
+        let mut stack: Vec<(Rc<RefCell<GraphNode>>, usize)> =
+            self.prev.iter().rev().map(|p| (p.clone(), indent + 4)).collect();
+        while let Some((node_rc, indent)) = stack.pop() {
+            let node = node_rc.borrow();
+            node.fmt_line(f, indent)?;
+            stack.extend(node.prev.iter().rev().map(|p| (p.clone(), indent + 4)));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn topological_sort(root : &Value) -> Vec<Value> {
+        GraphNode::topological_sort_many(std::slice::from_ref(root))
+    }
+
+    // Joint topological order of everything reachable from any of `roots`: a post-order DFS,
+    // run with an explicit stack of (node, next parent to visit) so depth is not limited
+    pub(crate) fn topological_sort_many(roots: &[Value]) -> Vec<Value> {
+        let mut topo: Vec<Value> = Vec::new();
+        let mut visited: HashSet<usize> = HashSet::new();
+        let mut stack: Vec<(Rc<RefCell<GraphNode>>, usize)> = Vec::new();
+
+        for root in roots {
+            if visited.insert(root.id()) {
+                stack.push((root.rc(), 0));
+            }
+            while let Some((node_rc, next)) = stack.last_mut() {
+                let parent = node_rc.borrow().prev.get(*next).cloned();
+                *next += 1;
+                match parent {
+                    Some(p) => {
+                        if visited.insert(Rc::as_ptr(&p) as usize) {
+                            stack.push((p, 0));
+                        }
+                    }
+                    None => {
+                        let (node_rc, _) = stack.pop().unwrap();
+                        topo.push(Value(node_rc));
                     }
                 }
-            }));
-            out
+            }
         }
+        topo
+    }
 
-        pub fn powop<T: Into<f64>>(self, other: T) -> Value {
-            let exponent = other.into();
-            let val = self.borrow().data.powf(exponent);
-            let out = Self::new(val, "pow");
-            {
-                let mut out_mut = out.borrow_mut();
-                out_mut.op = Some("pow".to_string());
-                out_mut.prev = vec![Rc::clone(&self.0), ];
+    // Kept for compatibility, prefer `Value::backward`
+    pub fn backward(root: &Value)  {
+        root.backward();
+    }
+}
+
+impl Drop for GraphNode {
+    // Dropping the last handle to a long chain would otherwise free it recursively, one stack
+    // frame per node; unlink the ancestors this node solely owns one at a time instead
+    fn drop(&mut self) {
+        let mut orphans = std::mem::take(&mut self.prev);
+        while let Some(rc) = orphans.pop() {
+            if let Ok(cell) = Rc::try_unwrap(rc) {
+                orphans.append(&mut cell.into_inner().prev);
             }
+        }
+    }
+}
 
-            // Prepare references for gradient calculation
-            let weak_out = Rc::downgrade(&out.0);
-            let weak_a = Rc::downgrade(&self.0);
+impl fmt::Debug for GraphNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Computation Graph:")?;
+        self.fmt_indented(f, 0)
+    }
+}
 
-            out.borrow_mut().backward = Some(Rc::new(move || {
-                if let Some(out_rc) = weak_out.upgrade() {
-                    let out_grad = out_rc.borrow().grad;
+impl Value {
+    pub(crate) fn rc(&self) -> Rc<RefCell<GraphNode>> { self.0.clone() }
+
+    pub fn new(data: f64, label: &str) -> Self {
+        let label = if label.is_empty() && is_auto_labelling() { next_auto_label("value") } else { label.to_string() };
+        Value(Rc::new(RefCell::new(GraphNode {
+            data,
+            grad: 0.0,
+            label,
+            prev: vec![],
+            op: None,
+            backward: None,
+            backward_refs: vec![],
+            forward: None,
+            requires_grad: true,
+            constant: false,
+            variance: 0.0,
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+            _live: LiveToken::new(),
+        })))
+    }
 
-                    // read current values of parents (they should exist)
-                    if let Some(a_rc) = weak_a.upgrade() {
-                        let a_val = a_rc.borrow().data;
-                        a_rc.borrow_mut().grad += exponent * (a_val.powf((exponent - 1.0))) * out_grad;
-                    }
-                }
-            }));
-            out
+    /// A leaf holding a fixed number, such as the `2.0` in `x * 2.0`: frozen for optimizers, and
+    /// never mistaken for a parameter by passes like `prune_zero_grad`. `Value::from` builds these;
+    /// parameters and inputs come from `new`.
+    pub fn constant(data: f64) -> Self {
+        let value = Value::new(data, "");
+        {
+            let mut node = value.borrow_mut();
+            node.requires_grad = false;
+            node.constant = true;
         }
-        
-        pub fn exp(self) -> Value {
-            let x = self.borrow().data;
-            let out = Self::new(x.exp(), "exp");
-            {
-                let mut out_mut = out.borrow_mut();
-                out_mut.op = Some("exp".to_string());
-                out_mut.prev = vec![Rc::clone(&self.0), ];
-            }
+        value
+    }
 
-            let weak_out = Rc::downgrade(&out.0);
-            let weak_a = Rc::downgrade(&self.0);
+    /// Whether this is a leaf built by `constant` (or an op output computed under `no_grad`).
+    pub fn is_constant(&self) -> bool { self.borrow().constant }
 
-            out.borrow_mut().backward = Some(Rc::new(move || {
-                if let Some(out_rc) = weak_out.upgrade() {
-                    let out_grad = out_rc.borrow().grad;
-                    let out_val = out_rc.borrow().data;
+    // Drops the graph links of a freshly built op output when inside `no_grad`, after passing
+    // the parents' uncertainty on to it when that is being tracked
+    fn recorded(self) -> Self {
+        if crate::uncertainty::is_tracking() {
+            crate::uncertainty::propagate_variance(&self);
+        }
+        if !is_grad_enabled() {
+            let mut node = self.borrow_mut();
+            node.prev.clear();
+            node.backward_refs.clear();
+            node.backward = None;
+            node.forward = None;
+            node.constant = true;
+        }
+        self
+    }
 
-                    if let Some(a_rc) = weak_a.upgrade() {
-                        a_rc.borrow_mut().grad += out_val * out_grad;
-                    }
+    // Derivative of this op output with respect to each of its distinct parents, found by
+    // running its backward closure once with a unit gradient; the gradients are left as they were
+    pub(crate) fn local_partials(&self) -> Vec<(Value, f64)> {
+        let (parents, backward) = {
+            let node = self.borrow();
+            let mut parents: Vec<Value> = Vec::new();
+            for p in &node.prev {
+                if !parents.iter().any(|q| Rc::ptr_eq(&q.0, p)) {
+                    parents.push(Value(Rc::clone(p)));
                 }
-            }));
-            out
+            }
+            (parents, node.backward.clone())
+        };
+        let Some(backward) = backward else {
+            return Vec::new();
+        };
+
+        struct Restore(bool);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                DETERMINISTIC.with(|d| d.set(self.0));
+            }
+        }
+        let saved: Vec<f64> = parents.iter().map(|p| p.grad()).collect();
+        let out_grad = self.grad();
+        parents.iter().for_each(|p| p.set_grad(0.0));
+        self.set_grad(1.0);
+        {
+            // contributions must land right away rather than wait for a flush
+            let _restore = Restore(DETERMINISTIC.with(|d| d.replace(false)));
+            (backward)();
         }
+        self.set_grad(out_grad);
+        parents
+            .into_iter()
+            .zip(saved)
+            .map(|(p, g)| {
+                let partial = p.grad();
+                p.set_grad(g);
+                (p, partial)
+            })
+            .collect()
     }
 
-    impl From<f64> for Value {
-        fn from(x: f64) -> Self {
-            Value::new(x, "")
+    // Records how to recompute this op output from its parents' data
+    pub(crate) fn with_forward(self, forward: impl Fn(&[f64]) -> f64 + 'static) -> Self {
+        if !self.borrow().prev.is_empty() {
+            let mut node = self.borrow_mut();
+            node.forward = Some(Rc::new(forward));
+            if is_lazy() {
+                node.data = f64::NAN;
+            }
         }
+        self
     }
 
-    impl Add for Value {
-        type Output = Value;
+    // Output node of an op, labelled with the op symbol or a generated `add_17` style label
+    fn op_output(data: f64, op: &str) -> Self {
+        if !is_auto_labelling() {
+            return Self::new(data, op);
+        }
+        let kind = match op {
+            "+" => "add",
+            "*" => "mul",
+            other => other,
+        };
+        Self::new(data, &next_auto_label(kind))
+    }
 
-        fn add (self, other: Value) -> Value {
-            let sum = self.borrow().data + other.borrow().data;
-            let out = Self::new(sum, "+");
-            {
-                let mut out_mut = out.borrow_mut();
-                out_mut.op = Some("+".to_string());
-                out_mut.prev = vec![Rc::clone(&self.0), Rc::clone(&other.0)];
-            }
+    /// Creates the output node of an op from its forward value and a backward rule. `backward`
+    /// gets the output's data and grad plus the parents' data, and returns each parent's gradient
+    /// contribution in order.
+    pub(crate) fn from_op(
+        data: f64,
+        op: &str,
+        parents: &[&Value],
+        backward: impl Fn(f64, f64, &[f64]) -> Vec<f64> + 'static,
+    ) -> Value {
+        let out = Self::op_output(data, op);
+        out.set_op(op, parents, backward);
+        out.recorded()
+    }
 
-            // Capture weak refs for closure
-            let weak_out = Rc::downgrade(&out.0);
-            let weak_a = Rc::downgrade(&self.0);
-            let weak_b = Rc::downgrade(&other.0);
+    // Makes `self` the output of `op` on `parents`, replacing whatever links and backward rule
+    // it had; see `from_op` for `backward`
+    fn set_op(&self, op: &str, parents: &[&Value], backward: impl Fn(f64, f64, &[f64]) -> Vec<f64> + 'static) {
+        {
+            let mut out_mut = self.borrow_mut();
+            out_mut.op = Some(op.to_string());
+            out_mut.prev = parents.iter().map(|p| Rc::clone(&p.0)).collect();
+            out_mut.backward_refs = parents.iter().map(|p| Rc::downgrade(&p.0)).collect();
+            out_mut.forward = None;
+        }
 
-            out.borrow_mut().backward = Some(Rc::new(move || {
-                if let Some(out_rc) = weak_out.upgrade() {
-                    let out_grad = out_rc.borrow().grad;
-                    if let Some(a_rc) = weak_a.upgrade() {
-                        a_rc.borrow_mut().grad += out_grad;
+        let weak_out = Rc::downgrade(&self.0);
+        let weak_parents: Vec<_> = parents.iter().map(|p| Rc::downgrade(&p.0)).collect();
+
+        self.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let (out_data, out_grad) = {
+                    let out_ref = out_rc.borrow();
+                    (out_ref.data, out_ref.grad)
+                };
+                let parents: Option<Vec<_>> = weak_parents.iter().map(|w| w.upgrade()).collect();
+                if let Some(parents) = parents {
+                    let datas: Vec<f64> = parents.iter().map(|p| p.borrow().data).collect();
+                    let grads = backward(out_data, out_grad, &datas);
+                    for (p, g) in parents.iter().zip(grads) {
+                        accumulate(p, g);
                     }
+                }
+            }
+        }));
+    }
 
-                    if let Some(b_rc) = weak_b.upgrade() {
-                        b_rc.borrow_mut().grad += out_grad;
-                    }
+    /// Walks the graph below `self` and checks that no node is its own (transitive) parent
+    /// and that every backward closure can still reach the parents it captured.
+    pub fn validate(&self) -> Result<(), Vec<InvariantViolation>> {
+        // false while a node is on the DFS path, true once all its parents are done
+        let mut state: HashMap<usize, bool> = HashMap::new();
+        let mut violations: Vec<InvariantViolation> = Vec::new();
+        // the DFS path, each node with the index of the next parent to visit
+        type Path = Vec<(Rc<RefCell<GraphNode>>, usize)>;
+        let mut path: Path = Vec::new();
+
+        fn enter(
+            node_rc: Rc<RefCell<GraphNode>>,
+            state: &mut HashMap<usize, bool>,
+            path: &mut Path,
+            violations: &mut Vec<InvariantViolation>,
+        ) {
+            let id = Rc::as_ptr(&node_rc) as usize;
+            match state.get(&id) {
+                Some(true) => return,
+                Some(false) => {
+                    let start = path.iter().position(|(n, _)| Rc::as_ptr(n) as usize == id).unwrap_or(0);
+                    let mut labels: Vec<String> = path[start..].iter().map(|(n, _)| n.borrow().display_label()).collect();
+                    labels.push(node_rc.borrow().display_label());
+                    violations.push(InvariantViolation::Cycle { path: labels });
+                    return;
+                }
+                None => {}
+            }
+            state.insert(id, false);
+            {
+                let node = node_rc.borrow();
+                if node.backward.is_some() && node.backward_refs.iter().any(|w| w.upgrade().is_none()) {
+                    violations.push(InvariantViolation::DeadParent { label: node.display_label() });
+                }
+            }
+            path.push((node_rc, 0));
+        }
+
+        enter(self.rc(), &mut state, &mut path, &mut violations);
+        while let Some((node_rc, next)) = path.last_mut() {
+            let parent = node_rc.borrow().prev.get(*next).cloned();
+            *next += 1;
+            match parent {
+                Some(p) => enter(p, &mut state, &mut path, &mut violations),
+                None => {
+                    let (node_rc, _) = path.pop().unwrap();
+                    state.insert(Rc::as_ptr(&node_rc) as usize, true);
                 }
-            }));
-            out
+            }
+        }
+        if violations.is_empty() { Ok(()) } else { Err(violations) }
+    }
+
+    // Reference borrowing of the inner object
+    pub fn borrow(&self) -> std::cell::Ref<'_, GraphNode> {
+        self.0.borrow()
+    }
+
+    // Create a mutable borrow of the inner object
+    pub fn borrow_mut(&self) -> std::cell::RefMut<'_, GraphNode> {
+        self.0.borrow_mut()
+    }
+
+    pub fn label(&mut self, label: &str) {
+        self.borrow_mut().label = label.to_string();
+    }
+
+    // In debug builds, refuse to run backward over a graph with broken invariants
+    pub(crate) fn debug_validate(&self) {
+        #[cfg(debug_assertions)]
+        if let Err(violations) = self.validate() {
+            let report: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+            panic!("graph invariants violated: {}", report.join("; "));
         }
     }
 
-    impl Add<f64> for Value {
-        type Output = Value;
+    /// Backpropagates from `self`, seeding its gradient with 1.0.
+    pub fn backward(&self) {
+        self.backward_with(1.0);
+    }
 
-        fn add(self, rhs: f64) -> Value {
-            self + Value::from(rhs)
+    /// Backpropagates from `self`. With `retain` the gradients already in the graph are kept and
+    /// added to, as `backward` does; without it every gradient in the graph is zeroed first, so
+    /// repeated forward/backward cycles over shared nodes do not pile up.
+    pub fn backward_retain(&self, retain: bool) {
+        if !retain {
+            self.zero_grad_graph();
         }
+        self.backward();
     }
 
-    impl<'a> Add<f64> for &'a Value {
-        type Output = Value;
+    /// Zeroes the gradient of every node reachable from `self`, parameters included.
+    pub fn zero_grad_graph(&self) {
+        for node in GraphNode::topological_sort(self) {
+            node.set_grad(0.0);
+        }
+    }
 
-        fn add(self, rhs: f64) -> Value {
-            self.clone() + Value::from(rhs)
+    /// Replays the forward pass of the graph behind `self` from the current data of its leaves,
+    /// e.g. after changing an input with `set_data`. Returns `false`, leaving the data of the
+    /// nodes in question as they were, when some op in the graph cannot be replayed.
+    pub fn recompute(&self) -> bool {
+        let mut complete = true;
+        for node in GraphNode::topological_sort(self) {
+            let mut n = node.borrow_mut();
+            if n.prev.is_empty() {
+                continue;
+            }
+            match n.forward.clone() {
+                Some(forward) => {
+                    let inputs: Vec<f64> = n.prev.iter().map(|p| p.borrow().data).collect();
+                    n.data = forward(&inputs);
+                }
+                None => complete = false,
+            }
         }
+        complete
     }
 
-    impl Mul for Value {
-        type Output = Value;
+    /// Computes the data of every node behind `self` from its leaves, parents first: the second
+    /// half of `lazy` evaluation. Returns `false` when some op cannot be replayed.
+    pub fn materialize(&self) -> bool {
+        self.recompute()
+    }
 
-        fn mul(self, other: Value) -> Value {
-            let prod = self.borrow().data * other.borrow().data;
+    /// Backpropagates from `self` using `seed` as the output cotangent.
+    pub fn backward_with(&self, seed: f64) {
+        self.debug_validate();
 
-            let out = Self::new(prod, "*");
-            {
-                let mut out_mut = out.borrow_mut();
-                out_mut.op = Some("*".to_string());
-                out_mut.prev = vec![Rc::clone(&self.0), Rc::clone(&other.0)];
+        self.borrow_mut().grad = seed;
+        Value::propagate(std::slice::from_ref(self));
+    }
+
+    // Runs the backward closures of everything reachable from `roots`, children first, using
+    // whatever gradients the roots already hold
+    pub(crate) fn propagate(roots: &[Value]) {
+        Value::propagate_with(roots, |_, run| run());
+    }
+
+    // Same as `propagate`, handing every node with a backward closure to `visit` along with the
+    // closure; `visit` must run it exactly once, e.g. to time it
+    pub(crate) fn propagate_with(roots: &[Value], mut visit: impl FnMut(&Value, &dyn Fn())) {
+        let topo = GraphNode::topological_sort_many(roots);
+        let deterministic = is_deterministic_backward();
+        // closures may propagate through subgraphs of their own, which changes the source
+        let outer = SOURCE.with(|s| s.get());
+        for node in topo.into_iter().rev() {
+            if deterministic {
+                // every consumer of `node` has run by now
+                flush(&node.0);
+                SOURCE.with(|s| s.set(node.borrow().seq));
             }
+            let cb = node.borrow().backward.clone();
+            if let Some(cb) = cb {
+                visit(&node, &*cb);
+            }
+        }
+        SOURCE.with(|s| s.set(outer));
+    }
 
-            // backward closure for multiplication: d(a*b)/da = b, d(a*b)/db = a
-            let weak_out = Rc::downgrade(&out.0);
-            let weak_a = Rc::downgrade(&self.0);
-            let weak_b = Rc::downgrade(&other.0);
+    // Backpropagates the extra gradients `deltas` from `roots`, adding what they contribute to
+    // the gradients already in the graph rather than running its closures over the totals again
+    pub(crate) fn propagate_delta(roots: &[Value], deltas: &[f64]) {
+        let topo = GraphNode::topological_sort_many(roots);
+        let saved: Vec<f64> = topo.iter().map(|n| n.grad()).collect();
+        topo.iter().for_each(|n| n.set_grad(0.0));
+        roots.iter().zip(deltas).for_each(|(r, d)| r.set_grad(r.grad() + d));
+        Value::propagate(roots);
+        topo.iter().zip(saved).for_each(|(n, s)| n.set_grad(n.grad() + s));
+    }
 
-            out.borrow_mut().backward = Some(Rc::new(move || {
-                if let Some(out_rc) = weak_out.upgrade() {
-                    let out_grad = out_rc.borrow().grad;
+    pub fn data(&self) -> f64 { self.borrow().data }
 
-                    // read current values of parents (they should exist)
-                    if let (Some(a_rc), Some(b_rc)) = (weak_a.upgrade(), weak_b.upgrade()) {
-                        let a_val = a_rc.borrow().data;
-                        let b_val = b_rc.borrow().data;
+    /// Same as `data`, for call sites that read better as a conversion.
+    pub fn to_f64(&self) -> f64 { self.data() }
 
-                        // accumulate gradients using product rule
-                        a_rc.borrow_mut().grad += b_val * out_grad;
-                        b_rc.borrow_mut().grad += a_val * out_grad;
-                    }
-                }
-            }));
+    pub fn set_data(&self, data: f64) { self.borrow_mut().data = data; }
 
-            out
+    pub fn grad(&self) -> f64 { self.borrow().grad }
+
+    pub fn set_grad(&self, grad: f64) { self.borrow_mut().grad = grad; }
+
+    /// Whether optimizers should update this value. Gradients still flow through it either way.
+    pub fn requires_grad(&self) -> bool { self.borrow().requires_grad }
+
+    /// Freezes (`false`) or unfreezes (`true`) this value for optimizers.
+    pub fn set_requires_grad(&self, requires_grad: bool) { self.borrow_mut().requires_grad = requires_grad; }
+
+    // Identity of the underlying node, stable for as long as the node is alive
+    pub fn id(&self) -> usize { Rc::as_ptr(&self.0) as usize }
+
+    pub fn view(&self) -> NodeView {
+        let node = self.borrow();
+        NodeView {
+            id: self.id(),
+            data: node.data,
+            grad: node.grad,
+            label: node.label.clone(),
+            op: node.op.clone(),
+            parents: node.prev.iter().map(|p| Rc::as_ptr(p) as usize).collect(),
         }
     }
 
-    impl Mul<f64> for Value {
-        type Output = Value;
+    /// The nodes in the graph behind `root` that use `self` as a parent. Builds a `Consumers`
+    /// index each call; build one directly for repeated queries.
+    pub fn consumers(&self, root: &Value) -> Vec<Value> {
+        Consumers::build(std::slice::from_ref(root)).of(self).to_vec()
+    }
+
+    /// Views of every node reachable from `self`, parents before children.
+    pub fn graph_view(&self) -> Vec<NodeView> {
+        GraphNode::topological_sort(self).iter().map(|v| v.view()).collect()
+    }
 
-        fn mul(self, rhs: f64) -> Value {
-            self * Value::from(rhs)
+    /// Fuses every `a * b + c` in the graph behind `self` into one `fma` node with a single
+    /// backward rule, as `mul_add` builds directly. A product is only folded into its sum when
+    /// nothing else holds on to it: no other node uses it and no handle to it is kept outside
+    /// the graph. Data and gradients stay the same; returns the number of products removed.
+    ///
+    /// `Neuron::forward` sums its weighted inputs as such a chain, so fusing a neuron's output
+    /// halves the number of op nodes in it.
+    pub fn fuse_mul_add(&self) -> usize {
+        let topo = GraphNode::topological_sort(self);
+        let mut fused = 0;
+        for node in &topo {
+            let (addend, product) = {
+                let n = node.borrow();
+                if n.op.as_deref() != Some("+") || n.backward.is_none() || n.prev.len() != 2 {
+                    continue;
+                }
+                // held by the sum and by `topo`, and nothing else
+                let foldable = |p: &Rc<RefCell<GraphNode>>| {
+                    let p_ref = p.borrow();
+                    p_ref.op.as_deref() == Some("*") && p_ref.backward.is_some() && p_ref.prev.len() == 2 && Rc::strong_count(p) == 2
+                };
+                match n.prev.iter().position(foldable) {
+                    Some(j) => (Value(n.prev[1 - j].clone()), Value(n.prev[j].clone())),
+                    None => continue,
+                }
+            };
+            let (a, b) = {
+                let p = product.borrow();
+                (Value(p.prev[0].clone()), Value(p.prev[1].clone()))
+            };
+            if node.borrow().label == "+" {
+                node.borrow_mut().label = "fma".to_string();
+            }
+            node.set_op("fma", &[&a, &b, &addend], mul_add_backward);
+            node.clone().with_forward(|x| x[0] * x[1] + x[2]);
+            fused += 1;
         }
+        fused
+    }
+
+    /// `self * b + c` as a single node, the fused form of `self * b + c` that `fuse_mul_add`
+    /// produces. Rounds like the unfused expression, not like `f64::mul_add`.
+    pub fn mul_add(self, b: Value, c: Value) -> Value {
+        let data = self.data() * b.data() + c.data();
+        Self::from_op(data, "fma", &[&self, &b, &c], mul_add_backward).with_forward(|x| x[0] * x[1] + x[2])
     }
 
-    impl<'a> Mul<f64> for &'a Value {
-        type Output = Value;
+    /// Collapses every parent subgraph in which all gradients are exactly zero and no leaf is a
+    /// parameter into a constant leaf holding its already-computed data, freeing the nodes behind
+    /// it. Parameters are leaves that are neither `constant` nor frozen; one whose gradient just
+    /// happens to be zero, such as the weight of a dead ReLU, stays attached with everything
+    /// downstream of it. The children keep their backward rules, so gradients still reach the
+    /// parents that were kept. Meant to be called after `backward` to trim a graph before
+    /// exporting it; returns the number of nodes removed.
+    pub fn prune_zero_grad(&self) -> usize {
+        let topo = GraphNode::topological_sort(self);
+        let before = topo.len();
+
+        // Parents come first in topo order, so their flags are ready when a child is visited
+        let mut all_zero: HashMap<usize, bool> = HashMap::new();
+        for node in &topo {
+            let n = node.borrow();
+            let parameter = n.prev.is_empty() && n.requires_grad && !n.constant;
+            let zero = n.grad == 0.0 && !parameter && n.prev.iter().all(|p| all_zero[&(Rc::as_ptr(p) as usize)]);
+            all_zero.insert(node.id(), zero);
+        }
 
-        fn mul(self, rhs: f64) -> Value {
-            self.clone() * Value::from(rhs)
+        // the topmost prunable nodes: the root, or parents of nodes that stay
+        let mut cut: HashSet<usize> = topo
+            .iter()
+            .filter(|n| !all_zero[&n.id()])
+            .flat_map(|n| n.borrow().prev.iter().map(|p| Rc::as_ptr(p) as usize).collect::<Vec<_>>())
+            .filter(|id| all_zero[id])
+            .collect();
+        if all_zero[&self.id()] {
+            cut.insert(self.id());
+        }
+        for node in topo.iter().filter(|n| cut.contains(&n.id())) {
+            let mut n = node.borrow_mut();
+            n.prev.clear();
+            n.backward_refs.clear();
+            n.backward = None;
+            n.forward = None;
+            n.op = None;
+            n.constant = true;
         }
+
+        before - GraphNode::topological_sort(self).len()
     }
 
-    impl Div for Value {
-        type Output = Value;
+    pub fn tanh(self) -> Value {
+        let x = self.borrow().data;
+
+        let out = Self::op_output(crate::math::tanh(x), "tanh");
+        {
+            let mut out_mut = out.borrow_mut();
+            out_mut.op = Some("tanh".to_string());
+            out_mut.prev = vec![Rc::clone(&self.0), ];
+            out_mut.backward_refs = vec![Rc::downgrade(&self.0)];
+        }
 
-        fn div (self, other: Value) -> Value {
-            if other.borrow().data == 0.0 {
-                panic!("Divide by zero")
+        let weak_out = Rc::downgrade(&out.0);
+        let weak_a = Rc::downgrade(&self.0);
+
+        out.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let out_grad = out_rc.borrow().grad;
+                let out_val = out_rc.borrow().data;
+
+                if let Some(a_rc) = weak_a.upgrade() {
+                    accumulate(&a_rc, (1.0 - out_val * out_val) * out_grad);
+                }
             }
-            self.clone() * other.powop(-1)
+        }));
+        out.recorded().with_forward(|x| crate::math::tanh(x[0]))
+    }
+
+    pub fn powop<T: Into<f64>>(self, other: T) -> Value {
+        let exponent = other.into();
+        let val = crate::math::powf(self.borrow().data, exponent);
+        let out = Self::op_output(val, "pow");
+        {
+            let mut out_mut = out.borrow_mut();
+            out_mut.op = Some("pow".to_string());
+            out_mut.prev = vec![Rc::clone(&self.0), ];
+            out_mut.backward_refs = vec![Rc::downgrade(&self.0)];
         }
+
+        // Prepare references for gradient calculation
+        let weak_out = Rc::downgrade(&out.0);
+        let weak_a = Rc::downgrade(&self.0);
+
+        out.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let out_grad = out_rc.borrow().grad;
+
+                // read current values of parents (they should exist)
+                if let Some(a_rc) = weak_a.upgrade() {
+                    let a_val = a_rc.borrow().data;
+                    accumulate(&a_rc, exponent * crate::math::powf(a_val, exponent - 1.0) * out_grad);
+                }
+            }
+        }));
+        out.recorded().with_forward(move |x| crate::math::powf(x[0], exponent))
+    }
+    
+    pub fn exp(self) -> Value {
+        let x = self.borrow().data;
+        let out = Self::op_output(crate::math::exp(x), "exp");
+        {
+            let mut out_mut = out.borrow_mut();
+            out_mut.op = Some("exp".to_string());
+            out_mut.prev = vec![Rc::clone(&self.0), ];
+            out_mut.backward_refs = vec![Rc::downgrade(&self.0)];
+        }
+
+        let weak_out = Rc::downgrade(&out.0);
+        let weak_a = Rc::downgrade(&self.0);
+
+        out.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let out_grad = out_rc.borrow().grad;
+                let out_val = out_rc.borrow().data;
+
+                if let Some(a_rc) = weak_a.upgrade() {
+                    accumulate(&a_rc, out_val * out_grad);
+                }
+            }
+        }));
+        out.recorded().with_forward(|x| crate::math::exp(x[0]))
+    }
+
+    /// Natural logarithm; panics for non-positive inputs, see `try_log`.
+    pub fn log(self) -> Value {
+        self.try_log().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// `|x|`, with a subgradient of 0 at 0.
+    pub fn abs(self) -> Value {
+        let x = self.borrow().data;
+        Self::from_op(x.abs(), "abs", &[&self], |_, out_grad, parents| vec![out_grad * sign(parents[0])])
+            .with_forward(|x| x[0].abs())
+    }
+
+    /// `max(0, x)`, with a subgradient of 0 at 0.
+    pub fn relu(self) -> Value {
+        let x = self.borrow().data;
+        Self::from_op(x.max(0.0), "relu", &[&self], |_, out_grad, parents| {
+            vec![if parents[0] > 0.0 { out_grad } else { 0.0 }]
+        })
+        .with_forward(|x| x[0].max(0.0))
+    }
+
+    /// Logistic sigmoid `1 / (1 + e^-x)`.
+    pub fn sigmoid(self) -> Value {
+        let s = sigmoid(self.borrow().data);
+        Self::from_op(s, "sigmoid", &[&self], |out, out_grad, _| vec![out_grad * out * (1.0 - out)])
+            .with_forward(|x| sigmoid(x[0]))
+    }
+
+    /// Square root; panics for negative inputs, see `try_sqrt`.
+    pub fn sqrt(self) -> Value {
+        self.try_sqrt().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+/// Fallible versions of the operations that can panic, for embedding the engine where a bad
+/// input must not take the process down. Nothing in this block may panic.
+#[deny(clippy::panic, clippy::unwrap_used, clippy::expect_used, clippy::indexing_slicing)]
+impl Value {
+    /// Like `borrow`, but fails instead of panicking while the node is mutably borrowed.
+    pub fn try_borrow(&self) -> Result<std::cell::Ref<'_, GraphNode>, ValueError> {
+        self.0.try_borrow().map_err(|_| ValueError::Borrowed)
+    }
+
+    /// Like `borrow_mut`, but fails instead of panicking while the node is borrowed.
+    pub fn try_borrow_mut(&self) -> Result<std::cell::RefMut<'_, GraphNode>, ValueError> {
+        self.0.try_borrow_mut().map_err(|_| ValueError::Borrowed)
     }
 
-    impl Div<f64> for Value {
-        type Output = Value;
+    pub fn try_data(&self) -> Result<f64, ValueError> {
+        Ok(self.try_borrow()?.data)
+    }
 
-        fn div(self, rhs: f64) -> Value {
-            self * Value::from(rhs).powop(-1)
+    /// `self / other`, failing when `other` is exactly zero.
+    pub fn try_div(self, other: Value) -> Result<Value, ValueError> {
+        if other.try_data()? == 0.0 {
+            return Err(ValueError::DivideByZero);
         }
+        Ok(self * other.powop(-1))
     }
 
-    impl<'a> Div<f64> for &'a Value {
-        type Output = Value;
+    /// Natural logarithm, failing for non-positive inputs.
+    pub fn try_log(self) -> Result<Value, ValueError> {
+        let x = self.try_data()?;
+        if x <= 0.0 {
+            return Err(ValueError::LogOfNonPositive(x));
+        }
+        let out = Self::from_op(crate::math::ln(x), "log", &[&self], |_, out_grad, parents| {
+            parents.iter().map(|x| out_grad / x).collect()
+        });
+        Ok(out.with_forward(|x| x.first().map_or(f64::NAN, |&x| crate::math::ln(x))))
+    }
 
-        fn div(self, rhs: f64) -> Value {
-            self.clone() * Value::from(rhs).powop(-1)
+    /// Square root, failing for negative inputs.
+    pub fn try_sqrt(self) -> Result<Value, ValueError> {
+        let x = self.try_data()?;
+        if x < 0.0 {
+            return Err(ValueError::SqrtOfNegative(x));
         }
+        let out = Self::from_op(x.sqrt(), "sqrt", &[&self], |out, out_grad, _| vec![out_grad / (2.0 * out)]);
+        Ok(out.with_forward(|x| x.first().map_or(f64::NAN, |x| x.sqrt())))
     }
+}
 
-    impl Sub for Value {
-        type Output = Value;
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::constant(x)
+    }
+}
 
-        fn sub(self, other: Value) -> Value {
-            self.clone() + (other * -1.0)
+impl Add for Value {
+    type Output = Value;
+
+    fn add (self, other: Value) -> Value {
+        let sum = self.borrow().data + other.borrow().data;
+        let out = Self::op_output(sum, "+");
+        {
+            let mut out_mut = out.borrow_mut();
+            out_mut.op = Some("+".to_string());
+            out_mut.prev = vec![Rc::clone(&self.0), Rc::clone(&other.0)];
+            out_mut.backward_refs = vec![Rc::downgrade(&self.0), Rc::downgrade(&other.0)];
         }
+
+        // Capture weak refs for closure
+        let weak_out = Rc::downgrade(&out.0);
+        let weak_a = Rc::downgrade(&self.0);
+        let weak_b = Rc::downgrade(&other.0);
+
+        out.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let out_grad = out_rc.borrow().grad;
+                if let Some(a_rc) = weak_a.upgrade() {
+                    accumulate(&a_rc, out_grad);
+                }
+
+                if let Some(b_rc) = weak_b.upgrade() {
+                    accumulate(&b_rc, out_grad);
+                }
+            }
+        }));
+        out.recorded().with_forward(|x| x[0] + x[1])
+    }
+}
+
+impl Add<f64> for Value {
+    type Output = Value;
+
+    fn add(self, rhs: f64) -> Value {
+        self + Value::from(rhs)
+    }
+}
+
+impl Add<f64> for &Value {
+    type Output = Value;
+
+    fn add(self, rhs: f64) -> Value {
+        self.clone() + Value::from(rhs)
     }
+}
+
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Mul for Value {
+    type Output = Value;
 
-    impl Sub<f64> for Value {
-        type Output = Value;
+    fn mul(self, other: Value) -> Value {
+        let prod = self.borrow().data * other.borrow().data;
 
-        fn sub(self, rhs: f64) -> Value {
-            self + (Value::from(rhs) * -1.0)
+        let out = Self::op_output(prod, "*");
+        {
+            let mut out_mut = out.borrow_mut();
+            out_mut.op = Some("*".to_string());
+            out_mut.prev = vec![Rc::clone(&self.0), Rc::clone(&other.0)];
+            out_mut.backward_refs = vec![Rc::downgrade(&self.0), Rc::downgrade(&other.0)];
         }
+
+        // backward closure for multiplication: d(a*b)/da = b, d(a*b)/db = a
+        let weak_out = Rc::downgrade(&out.0);
+        let weak_a = Rc::downgrade(&self.0);
+        let weak_b = Rc::downgrade(&other.0);
+
+        out.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let out_grad = out_rc.borrow().grad;
+
+                // read current values of parents (they should exist)
+                if let (Some(a_rc), Some(b_rc)) = (weak_a.upgrade(), weak_b.upgrade()) {
+                    let a_val = a_rc.borrow().data;
+                    let b_val = b_rc.borrow().data;
+
+                    // accumulate gradients using product rule
+                    accumulate(&a_rc, b_val * out_grad);
+                    accumulate(&b_rc, a_val * out_grad);
+                }
+            }
+        }));
+
+        out.recorded().with_forward(|x| x[0] * x[1])
+    }
+}
+
+impl Mul<f64> for Value {
+    type Output = Value;
+
+    fn mul(self, rhs: f64) -> Value {
+        self * Value::from(rhs)
+    }
+}
+
+impl Mul<f64> for &Value {
+    type Output = Value;
+
+    fn mul(self, rhs: f64) -> Value {
+        self.clone() * Value::from(rhs)
     }
+}
+
+impl Div for Value {
+    type Output = Value;
+
+    /// Panics when `other` is exactly zero, see `Value::try_div`.
+    fn div (self, other: Value) -> Value {
+        self.try_div(other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Div<f64> for Value {
+    type Output = Value;
+
+    fn div(self, rhs: f64) -> Value {
+        self * Value::from(rhs).powop(-1)
+    }
+}
+
+impl Div<f64> for &Value {
+    type Output = Value;
+
+    fn div(self, rhs: f64) -> Value {
+        self.clone() * Value::from(rhs).powop(-1)
+    }
+}
+
+impl Sub for Value {
+    type Output = Value;
+
+    fn sub(self, other: Value) -> Value {
+        self.clone() + (other * -1.0)
+    }
+}
+
+impl Sub<f64> for Value {
+    type Output = Value;
+
+    fn sub(self, rhs: f64) -> Value {
+        self + (Value::from(rhs) * -1.0)
+    }
+}
+
+impl Sub<f64> for &Value {
+    type Output = Value;
+
+    fn sub(self, rhs: f64) -> Value {
+        self.clone() + (Value::from(rhs) * -1.0 )
+    }
+}
+
+impl Neg for Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        self * -1.0
+    }
+}
+
+impl Neg for &Value {
+    type Output = Value;
+
+    fn neg(self) -> Value {
+        self.clone() * -1.0
+    }
+}
+
+impl From<&Value> for f64 {
+    fn from(v: &Value) -> Self {
+        v.data()
+    }
+}
+
+impl From<Value> for f64 {
+    fn from(v: Value) -> Self {
+        v.data()
+    }
+}
+
+/// Prints the data, honouring width and precision: `format!("{:.4}", v)`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.data(), f)
+    }
+}
+
+impl From<&Value> for Value {
+    fn from(v: &Value) -> Self {
+        v.clone()
+    }
+}
+
+// Borrowed operands and f64 on the left, forwarding to the owned impls above, so formulas can
+// reuse a Value without cloning it at every use
+macro_rules! forward_binary_op {
+    ($trait:ident, $method:ident) => {
+        impl $trait<&Value> for Value {
+            type Output = Value;
+
+            fn $method(self, rhs: &Value) -> Value {
+                self.$method(rhs.clone())
+            }
+        }
+
+        impl $trait<Value> for &Value {
+            type Output = Value;
+
+            fn $method(self, rhs: Value) -> Value {
+                self.clone().$method(rhs)
+            }
+        }
+
+        impl $trait<&Value> for &Value {
+            type Output = Value;
+
+            fn $method(self, rhs: &Value) -> Value {
+                self.clone().$method(rhs.clone())
+            }
+        }
+
+        impl $trait<Value> for f64 {
+            type Output = Value;
+
+            fn $method(self, rhs: Value) -> Value {
+                Value::from(self).$method(rhs)
+            }
+        }
 
-    impl<'a> Sub<f64> for &'a Value {
-        type Output = Value;
+        impl $trait<&Value> for f64 {
+            type Output = Value;
 
-        fn sub(self, rhs: f64) -> Value {
-            self.clone() + (Value::from(rhs) * -1.0 )
+            fn $method(self, rhs: &Value) -> Value {
+                Value::from(self).$method(rhs.clone())
+            }
         }
+    };
+}
+
+forward_binary_op!(Add, add);
+forward_binary_op!(Sub, sub);
+forward_binary_op!(Mul, mul);
+forward_binary_op!(Div, div);
+
+impl Sum for Value {
+    fn sum<I: Iterator<Item = Value>>(iter: I) -> Value {
+        iter.fold(Value::from(0.0), |acc, v| acc + v)
+    }
+}
+
+impl<'a> Sum<&'a Value> for Value {
+    fn sum<I: Iterator<Item = &'a Value>>(iter: I) -> Value {
+        iter.cloned().sum()
     }
 }
 
+// Old double-module path, kept so `crate::operators::operators::*` keeps compiling
+#[deprecated(note = "use `micrograd_rs::operators` or `micrograd_rs::prelude` instead")]
+#[allow(clippy::module_inception)]
+pub mod operators {
+    pub use super::{GraphNode, InvariantViolation, NodeView, Value};
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::operators::operators::*;
     
     #[test]
     fn chaining() {
@@ -385,11 +1339,11 @@ mod tests {
         x1w1x2w2.label("x1w1 + x2w2");
 
         // bias
-        let b = Value::new(6.8813735870195432, "b");
+        let b = Value::new(6.881_373_587_019_543, "b");
         let mut n = x1w1x2w2.clone() + b.clone();
         n.label("n");
 
-        let mut o = n.tanh();
+        let o = n.tanh();
 
         // Backward propagate
         GraphNode::backward(&o);
@@ -415,7 +1369,7 @@ mod tests {
         x1w1x2w2.label("x1w1 + x2w2");
 
         // bias
-        let b = Value::new(6.8813735870195432, "b");
+        let b = Value::new(6.881_373_587_019_543, "b");
         let mut n = x1w1x2w2.clone() + b.clone();
         n.label("n");
 
@@ -480,6 +1434,328 @@ mod tests {
         println!("{:#?}", d.borrow());
     }
 
+    #[test]
+    fn deep_chains_do_not_overflow_the_stack() {
+        let x = Value::new(1.0, "x");
+        let mut y = x.clone();
+        for _ in 0..200_000 {
+            y = y * 1.0;
+        }
+        y.backward();
+        assert_eq!(x.grad(), 1.0);
+        assert!(y.validate().is_ok());
+        drop(y);
+
+        let mut z = x.clone();
+        for _ in 0..3000 {
+            z = z.tanh();
+        }
+        let text = format!("{:?}", z.borrow());
+        assert_eq!(text.lines().count(), 3002);
+        assert!(text.lines().last().unwrap().trim_start().starts_with("x "));
+    }
+
+    #[test]
+    fn display_and_conversions() {
+        let v = Value::new(0.123456, "v");
+        assert_eq!(format!("{:.4}", v), "0.1235");
+        assert_eq!(format!("{:>8.2}|{}", v, Value::from(2.5)), "    0.12|2.5");
+        let x: f64 = (&v).into();
+        assert_eq!((x, v.to_f64(), f64::from(v.clone() * 2.0)), (0.123456, 0.123456, 0.246912));
+    }
+
+    #[test]
+    fn deterministic_backward_ignores_traversal_order() {
+        // contributions of 1e16, 1 and -1e16 sum to 0 or 1 depending on the order
+        let x = Value::new(1.0, "x");
+        let parts: Vec<Value> = [1e16, 1.0, -1e16].iter().map(|&k| x.clone() * k).collect();
+        let orders = [[0, 1, 2], [2, 1, 0], [1, 0, 2], [0, 2, 1]];
+        let grad = |order: &[usize; 3]| {
+            let loss = parts[order[0]].clone() + parts[order[1]].clone() + parts[order[2]].clone();
+            loss.backward_retain(false);
+            x.grad()
+        };
+        let plain: Vec<f64> = orders.iter().map(grad).collect();
+        assert!(plain.iter().any(|&g| g != plain[0]));
+
+        let sorted: Vec<f64> = deterministic_backward(|| orders.iter().map(grad).collect());
+        assert!(sorted.iter().all(|&g| g == sorted[0]));
+        // summed in creation order: 1e16 + 1 - 1e16
+        assert_eq!(sorted[0], 0.0);
+        assert!(!is_deterministic_backward());
+    }
+
+    #[test]
+    fn backward_without_retain_starts_from_zero() {
+        let w = Value::new(2.0, "w");
+        let loss = w.clone() * w.clone();
+        loss.backward();
+        loss.backward();
+        assert_eq!(w.grad(), 8.0);
+
+        loss.backward_retain(false);
+        assert_eq!(w.grad(), 4.0);
+        loss.backward_retain(true);
+        assert_eq!(w.grad(), 8.0);
+
+        loss.zero_grad_graph();
+        assert_eq!((w.grad(), loss.grad()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn sigmoid_and_sqrt() {
+        let x = Value::new(0.5, "x");
+        let s = x.clone().sigmoid();
+        let expected = 1.0 / (1.0 + (-0.5f64).exp());
+        assert!((s.data() - expected).abs() < 1e-12);
+        s.backward();
+        assert!((x.grad() - expected * (1.0 - expected)).abs() < 1e-12);
+        assert_eq!(Value::from(-800.0).sigmoid().data(), 0.0);
+
+        let y = Value::new(4.0, "y");
+        let r = y.clone().sqrt();
+        assert_eq!(r.data(), 2.0);
+        r.backward();
+        assert_eq!(y.grad(), 0.25);
+    }
+
+    #[test]
+    fn sum_and_neg() {
+        let xs = [Value::new(1.0, "x0"), Value::new(2.0, "x1"), Value::new(-4.0, "x2")];
+        let total: Value = xs.iter().sum();
+        let out = -total + xs.iter().cloned().map(|x| x.powop(2)).sum::<Value>();
+        assert_eq!(out.data(), 1.0 + 21.0);
+        out.backward();
+        // d/dx (-x + x^2) = 2x - 1
+        assert_eq!(xs.iter().map(|x| x.grad()).collect::<Vec<_>>(), vec![1.0, 3.0, -9.0]);
+        assert_eq!((-&xs[0]).data(), -1.0);
+        assert_eq!(std::iter::empty::<Value>().sum::<Value>().data(), 0.0);
+    }
+
+    #[test]
+    fn validate_detects_cycles_and_dead_parents() {
+        let a = Value::new(2.0, "a");
+        let b = Value::new(3.0, "b");
+        let mut c = a.clone() * b.clone();
+        c.label("c");
+        assert!(c.validate().is_ok());
+
+        // a -> c -> a
+        a.borrow_mut().prev.push(c.rc());
+        let violations = c.validate().unwrap_err();
+        assert_eq!(violations, vec![InvariantViolation::Cycle { path: vec!["c".into(), "a".into(), "c".into()] }]);
+        a.borrow_mut().prev.clear();
+
+        c.borrow_mut().prev.clear();
+        drop(a);
+        drop(b);
+        let violations = c.validate().unwrap_err();
+        assert_eq!(violations, vec![InvariantViolation::DeadParent { label: "c".into() }]);
+    }
+
+    #[test]
+    fn accessors_and_views() {
+        let a = Value::new(2.0, "a");
+        let b = Value::new(-3.0, "b");
+        let mut c = a.clone() * b.clone();
+        c.label("c");
+        GraphNode::backward(&c);
+
+        assert_eq!(c.data(), -6.0);
+        assert_eq!(a.grad(), -3.0);
+        assert_eq!(c.borrow().op(), Some("*"));
+        assert_eq!(c.borrow().prev().len(), 2);
+        assert!(c.borrow().has_backward());
+
+        let view = c.view();
+        assert_eq!(view.label, "c");
+        assert_eq!(view.parents, vec![a.id(), b.id()]);
+
+        let ids: Vec<usize> = c.graph_view().iter().map(|v| v.id).collect();
+        assert_eq!(ids, vec![a.id(), b.id(), c.id()]);
+
+        a.set_data(4.0);
+        b.borrow_mut().set_grad(1.5);
+        assert_eq!(a.data(), 4.0);
+        assert_eq!(b.grad(), 1.5);
+    }
+
+    #[test]
+    fn backward_method_and_seed() {
+        let a = Value::new(2.0, "a");
+        let b = Value::new(-3.0, "b");
+        let c = a.clone() * b.clone();
+        c.backward();
+        assert_eq!(c.grad(), 1.0);
+        assert_eq!(a.grad(), -3.0);
+        assert_eq!(b.grad(), 2.0);
+
+        let x = Value::new(3.0, "x");
+        let y = x.clone().powop(2);
+        y.backward_with(0.5);
+        assert_eq!(y.grad(), 0.5);
+        assert_eq!(x.grad(), 3.0);
+    }
+
+    #[test]
+    fn prune_zero_grad_drops_dead_branches() {
+        let (a, b) = (Value::from(2.0), Value::from(3.0));
+        let e = Value::new(0.0, "e");
+        let mut ab = a * b;
+        ab.label("ab");
+        let mut d = ab.clone() * e.clone() + Value::new(1.0, "k");
+        d.label("d");
+        d.backward();
+
+        // ab and the constants behind it all have zero gradient because e == 0
+        assert_eq!(d.prune_zero_grad(), 2);
+        drop(ab);
+        let view = d.graph_view();
+        let ab = view.iter().find(|v| v.label == "ab").unwrap();
+        assert!(ab.parents.is_empty() && ab.op.is_none());
+        assert_eq!(d.data(), 1.0);
+        assert!(d.validate().is_ok());
+
+        // the product still sends e its gradient through the collapsed branch
+        d.backward_retain(false);
+        assert_eq!(e.grad(), 6.0);
+    }
+
+    #[test]
+    fn prune_zero_grad_keeps_parameters_with_zero_gradient() {
+        // a dead ReLU: its weight gets no gradient but is still being trained
+        let w = Value::new(-2.0, "w");
+        let x = Value::new(1.0, "x");
+        let out = (w.clone() * x.clone()).relu() + Value::new(1.0, "k");
+        out.backward();
+        assert_eq!(w.grad(), 0.0);
+
+        assert_eq!(out.prune_zero_grad(), 0);
+        let labels: Vec<String> = out.graph_view().into_iter().map(|v| v.label).collect();
+        assert!(labels.contains(&"w".to_string()));
+        assert!(!x.is_constant() && Value::from(1.0).is_constant());
+    }
+
+    #[test]
+    fn fusing_mul_add_chains_keeps_data_and_gradients() {
+        // a neuron's weighted sum: ((b + w0 x0) + w1 x1) + ...
+        let build = || {
+            let w: Vec<Value> = [0.5, -1.0, 2.0, 0.25].iter().map(|&v| Value::new(v, "w")).collect();
+            let x: Vec<Value> = [1.5, 0.5, -0.75, 4.0].iter().map(|&v| Value::new(v, "x")).collect();
+            let out = w.iter().zip(&x).fold(Value::new(0.1, "b"), |acc, (w, x)| acc + w.clone() * x.clone()).tanh();
+            (w, x, out)
+        };
+        let (w, x, plain) = build();
+        plain.backward();
+        let (fw, fx, fused) = build();
+        let before = fused.graph_view().len();
+        assert_eq!(fused.fuse_mul_add(), 4);
+        assert_eq!(fused.graph_view().len(), before - 4);
+        assert!(fused.validate().is_ok());
+        fused.backward();
+        assert_eq!(fused.data(), plain.data());
+        let grads = |vs: &[Value]| vs.iter().map(|v| v.grad()).collect::<Vec<_>>();
+        assert_eq!((grads(&fw), grads(&fx)), (grads(&w), grads(&x)));
+        fx[0].set_data(0.0);
+        assert!(fused.recompute());
+        let expected = fw.iter().zip(&fx).fold(0.1, |acc, (w, x)| acc + w.data() * x.data());
+        assert_eq!(fused.data(), crate::math::tanh(expected));
+
+        // a product someone still holds is left alone
+        let (a, b) = (Value::new(2.0, "a"), Value::new(3.0, "b"));
+        let ab = a.clone() * b.clone();
+        let kept = ab.clone() + Value::from(1.0);
+        assert_eq!(kept.fuse_mul_add(), 0);
+        drop(ab);
+        assert_eq!(kept.fuse_mul_add(), 1);
+        let direct = a.clone().mul_add(b, Value::from(1.0));
+        assert_eq!((kept.data(), direct.data(), kept.borrow().op()), (7.0, 7.0, Some("fma")));
+    }
+
+    #[test]
+    fn lazy_graphs_compute_on_materialize() {
+        let f = |x: &Value, y: &Value| (x.clone() * y.clone() + x.clone()).tanh() / (y.clone() * y.clone() + 1.0);
+        let leaves = || (Value::new(0.5, "x"), Value::new(-2.0, "y"));
+        let (ex, ey) = leaves();
+        let eager = f(&ex, &ey);
+        let (x, y) = leaves();
+        let out = lazy(|| {
+            assert!(is_lazy());
+            f(&x, &y)
+        });
+        assert!(!is_lazy());
+        assert!(out.data().is_nan());
+        // rewrite before computing anything
+        assert_eq!(out.fuse_mul_add(), 2);
+        assert!(out.materialize());
+        assert_eq!(out.data(), eager.data());
+
+        out.backward();
+        eager.backward();
+        assert_eq!((x.grad(), y.grad()), (ex.grad(), ey.grad()));
+        // ops outside `lazy` are eager again, and leaves never wait
+        assert_eq!(lazy(|| Value::new(3.0, "c")).data(), 3.0);
+        assert_eq!((x.clone() + 1.0).data(), 1.5);
+    }
+
+    #[test]
+    fn consumers_answer_who_uses_a_node() {
+        let (w, x) = (Value::new(2.0, "w"), Value::new(3.0, "x"));
+        let mut a = w.clone() * x.clone();
+        a.label("a");
+        let mut b = w.clone() * w.clone();
+        b.label("b");
+        let mut out = a.clone() + b.clone();
+        out.label("out");
+        let labels = |vs: &[Value]| vs.iter().map(|v| v.borrow().label().to_string()).collect::<Vec<_>>();
+
+        // `w * w` is one consumer, not two
+        assert_eq!(labels(&w.consumers(&out)), vec!["a", "b"]);
+        assert_eq!(labels(&x.consumers(&out)), vec!["a"]);
+        assert!(out.consumers(&out).is_empty());
+        // only the graph behind the root counts
+        assert_eq!(labels(&w.consumers(&a)), vec!["a"]);
+
+        let index = Consumers::build(&[out.clone()]);
+        let mut down = labels(&index.downstream(&x));
+        down.sort();
+        assert_eq!(down, vec!["a", "out"]);
+        assert!(index.of(&Value::from(1.0)).is_empty());
+    }
+
+    #[test]
+    fn auto_labels_are_unique() {
+        let (a, b, c) = auto_labels(|| {
+            let a = Value::new(2.0, "a");
+            let b = Value::from(3.0);
+            let c = a.clone() * b.clone() + 1.0;
+            (a, b, c)
+        });
+        assert!(!is_auto_labelling());
+
+        let view = c.view();
+        assert!(view.label.starts_with("add_"));
+        assert_eq!(view.op.as_deref(), Some("+"));
+        assert!(b.view().label.starts_with("value_"));
+        assert_eq!(a.view().label, "a");
+
+        let labels: Vec<String> = c.graph_view().into_iter().map(|v| v.label).collect();
+        let mut unique = labels.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), labels.len());
+        assert!(labels.iter().any(|l| l.starts_with("mul_")));
+    }
+
+    #[test]
+    fn log() {
+        let a = Value::new(2.0, "a");
+        let b = (a.clone() * 3.0).log();
+        assert!((b.data() - 6.0_f64.ln()).abs() < 1e-12);
+        b.backward();
+        assert!((a.grad() - 0.5).abs() < 1e-12);
+    }
+
     #[test]
     fn scalar() {
         let a = Value::new(2.0, "a");
@@ -490,4 +1766,37 @@ mod tests {
         println!("{:#?}", d.borrow());
     }
 
+
+    #[test]
+    fn no_grad_skips_graph_recording() {
+        let w = Value::new(2.0, "w");
+        let y = no_grad(|| {
+            assert!(!is_grad_enabled());
+            (w.clone() * 3.0 + 1.0).tanh()
+        });
+        assert!(is_grad_enabled());
+        assert_eq!(y.data(), crate::math::tanh(7.0));
+        assert!(y.borrow().prev().is_empty());
+        y.backward();
+        assert_eq!(w.grad(), 0.0);
+    }
+
+    #[test]
+    fn fallible_ops_report_instead_of_panicking() {
+        let x = Value::new(4.0, "x");
+        assert_eq!(x.clone().try_div(Value::from(0.0)).unwrap_err(), ValueError::DivideByZero);
+        assert_eq!(Value::from(-1.0).try_log().unwrap_err(), ValueError::LogOfNonPositive(-1.0));
+        assert_eq!(Value::from(-4.0).try_sqrt().unwrap_err(), ValueError::SqrtOfNegative(-4.0));
+
+        let y = x.clone().try_sqrt().unwrap().try_log().unwrap();
+        assert!((y.data() - 2f64.ln()).abs() < 1e-12);
+        y.backward();
+        assert!((x.grad() - 0.125).abs() < 1e-12);
+
+        let held = x.borrow_mut();
+        assert_eq!(x.try_data().unwrap_err(), ValueError::Borrowed);
+        assert!(x.try_borrow_mut().is_err());
+        drop(held);
+        assert_eq!(x.try_data(), Ok(4.0));
+    }
 }
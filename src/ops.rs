@@ -0,0 +1,364 @@
+use crate::operators::*;
+
+/// `xs[i] + s` for every element, e.g. a shared bias on a layer's outputs. `s` is one node
+/// feeding every output, so its gradient is the sum over all of them.
+pub fn add_scalar(xs: &[Value], s: &Value) -> Vec<Value> {
+    xs.iter().map(|x| x.clone() + s.clone()).collect()
+}
+
+/// `xs[i] - s` for every element.
+pub fn sub_scalar(xs: &[Value], s: &Value) -> Vec<Value> {
+    xs.iter().map(|x| x.clone() - s.clone()).collect()
+}
+
+/// `xs[i] * s` for every element, e.g. a learnable gain.
+pub fn mul_scalar(xs: &[Value], s: &Value) -> Vec<Value> {
+    xs.iter().map(|x| x.clone() * s.clone()).collect()
+}
+
+/// `xs[i] / s` for every element; panics if `s` is zero.
+pub fn div_scalar(xs: &[Value], s: &Value) -> Vec<Value> {
+    xs.iter().map(|x| x.clone() / s.clone()).collect()
+}
+
+/// Cosine of the angle between `a` and `b`, as a single node with a fused backward rather than a
+/// graph of products and square roots. Zero vectors have a similarity (and gradient) of 0.
+pub fn cosine_similarity(a: &[Value], b: &[Value]) -> Value {
+    assert_eq!(a.len(), b.len(), "cosine_similarity needs vectors of the same length");
+    let n = a.len();
+    let parents: Vec<&Value> = a.iter().chain(b).collect();
+    let data: Vec<f64> = parents.iter().map(|v| v.data()).collect();
+    let cos = cosine(&data, n);
+
+    Value::from_op(cos, "cosine", &parents, move |cos, out_grad, data| {
+        let (xa, xb) = data.split_at(n);
+        let (_, na, nb) = norms(xa, xb);
+        if na == 0.0 || nb == 0.0 {
+            return vec![0.0; 2 * n];
+        }
+        // d cos / da = b / (|a||b|) - cos * a / |a|^2, and symmetrically for b
+        let ga = xa.iter().zip(xb).map(|(a, b)| out_grad * (b / (na * nb) - cos * a / (na * na)));
+        let gb = xb.iter().zip(xa).map(|(b, a)| out_grad * (a / (na * nb) - cos * b / (nb * nb)));
+        ga.chain(gb).collect()
+    })
+    .with_forward(move |data| cosine(data, n))
+}
+
+/// Rescales `xs` to an L2 norm of at most `max_norm`, inside the graph: when the norm is over the
+/// limit the outputs are `xs * max_norm / |xs|` and gradients flow through the norm as well.
+/// Vectors already within the limit come back as the same nodes.
+pub fn clip_by_norm(xs: &[Value], max_norm: f64) -> Vec<Value> {
+    assert!(max_norm > 0.0, "max_norm must be positive");
+    let norm = xs.iter().map(|x| x.clone().powop(2)).sum::<Value>().sqrt();
+    if norm.data() <= max_norm {
+        return xs.to_vec();
+    }
+    let scale = norm.powop(-1) * max_norm;
+    xs.iter().map(|x| x.clone() * scale.clone()).collect()
+}
+
+/// Index of the largest value, e.g. the predicted class of a row of logits. Not differentiable:
+/// it only reads the data. Ties go to the lowest index, and values are compared with
+/// `f64::total_cmp`, so a positive NaN ranks above every number and a negative one (such as
+/// `-f64::NAN`) below every number. Panics on an empty slice.
+pub fn argmax(values: &[Value]) -> usize {
+    assert!(!values.is_empty(), "argmax of no values");
+    top_k(values, 1)[0]
+}
+
+/// Indices of the `k` largest values, largest first; all of them when `k` exceeds the length.
+/// Not differentiable, and ordered like `argmax`.
+pub fn top_k(values: &[Value], k: usize) -> Vec<usize> {
+    let data: Vec<f64> = values.iter().map(|v| v.data()).collect();
+    let mut order: Vec<usize> = (0..data.len()).collect();
+    // stable, so equal values keep their index order
+    order.sort_by(|&a, &b| data[b].total_cmp(&data[a]));
+    order.truncate(k);
+    order
+}
+
+/// `argmax` of every row, e.g. the predicted classes of a batch.
+pub fn argmax_batch(rows: &[Vec<Value>]) -> Vec<usize> {
+    rows.iter().map(|r| argmax(r)).collect()
+}
+
+/// `top_k` of every row.
+pub fn top_k_batch(rows: &[Vec<Value>], k: usize) -> Vec<Vec<usize>> {
+    rows.iter().map(|r| top_k(r, k)).collect()
+}
+
+// Cosine of the first `n` entries of `data` with the rest
+fn cosine(data: &[f64], n: usize) -> f64 {
+    let (dot, na, nb) = norms(&data[..n], &data[n..]);
+    if na == 0.0 || nb == 0.0 { 0.0 } else { dot / (na * nb) }
+}
+
+// Dot product and the two euclidean norms
+fn norms(a: &[f64], b: &[f64]) -> (f64, f64, f64) {
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let na = a.iter().map(|a| a * a).sum::<f64>().sqrt();
+    let nb = b.iter().map(|b| b * b).sum::<f64>().sqrt();
+    (dot, na, nb)
+}
+
+// Probabilities are clamped to this before taking logs, so zeros give finite values and gradients
+const PROB_EPS: f64 = 1e-12;
+
+fn safe_ln(p: f64) -> f64 {
+    crate::math::ln(p.max(PROB_EPS))
+}
+
+/// KL divergence `sum p * (ln p - ln q)` between two probability vectors. Terms with `p = 0`
+/// contribute nothing, and `q` is clamped away from 0.
+pub fn kl_div(p: &[Value], q: &[Value]) -> Value {
+    assert_eq!(p.len(), q.len(), "kl_div needs distributions of the same length");
+    let n = p.len();
+    let parents: Vec<&Value> = p.iter().chain(q).collect();
+    let data: Vec<f64> = parents.iter().map(|v| v.data()).collect();
+    let kl = kl_value(&data, n);
+
+    Value::from_op(kl, "kl_div", &parents, move |_, out_grad, data| {
+        let (p, q) = data.split_at(n);
+        let gp = p.iter().zip(q).map(|(&p, &q)| out_grad * (safe_ln(p) - safe_ln(q) + 1.0));
+        let gq = p.iter().zip(q).map(|(&p, &q)| -out_grad * p / q.max(PROB_EPS));
+        gp.chain(gq).collect()
+    })
+    .with_forward(move |data| kl_value(data, n))
+}
+
+// KL divergence of the first `n` entries of `data` from the rest
+fn kl_value(data: &[f64], n: usize) -> f64 {
+    let (p, q) = data.split_at(n);
+    p.iter().zip(q).filter(|&(&p, _)| p > 0.0).map(|(&p, &q)| p * (safe_ln(p) - safe_ln(q))).sum()
+}
+
+/// Shannon entropy `-sum p * ln p` of a probability vector, in nats.
+pub fn entropy(p: &[Value]) -> Value {
+    let parents: Vec<&Value> = p.iter().collect();
+    let data: Vec<f64> = p.iter().map(|v| v.data()).collect();
+    Value::from_op(entropy_value(&data), "entropy", &parents, |_, out_grad, data| {
+        data.iter().map(|&p| -out_grad * (safe_ln(p) + 1.0)).collect()
+    })
+    .with_forward(entropy_value)
+}
+
+fn entropy_value(p: &[f64]) -> f64 {
+    -p.iter().filter(|&&p| p > 0.0).map(|&p| p * crate::math::ln(p)).sum::<f64>()
+}
+
+/// Piecewise-linear function through the points `(knots[i], values[i])`, as one node: linear
+/// between neighbouring knots and constant beyond the first and last. The knots are fixed and
+/// must be strictly ascending; the values are ordinary nodes, so they can be learned, e.g. as
+/// a learnable activation or a calibration curve. Gradients reach `x` (the slope of its
+/// segment, taking the segment to the right at a knot) and the two values around it. A NaN `x`
+/// gives NaN, with NaN gradients for `x` and every value.
+pub fn pwl(x: &Value, knots: &[f64], values: &[Value]) -> Value {
+    assert!(!knots.is_empty(), "pwl needs at least one knot");
+    assert_eq!(knots.len(), values.len(), "pwl needs one value per knot");
+    assert!(knots.windows(2).all(|w| w[0] < w[1]), "pwl knots must be strictly ascending");
+    let knots = knots.to_vec();
+    let parents: Vec<&Value> = std::iter::once(x).chain(values).collect();
+    let data: Vec<f64> = parents.iter().map(|v| v.data()).collect();
+    let k = knots.clone();
+
+    Value::from_op(pwl_value(&data, &knots), "pwl", &parents, move |_, out_grad, data| {
+        let mut grads = vec![0.0; data.len()];
+        let (x, values) = (data[0], &data[1..]);
+        match segment(x, &k) {
+            Segment::Nan => grads.fill(f64::NAN),
+            Segment::Below => grads[1] = out_grad,
+            Segment::Above => grads[values.len()] = out_grad,
+            Segment::Inside(i, t) => {
+                grads[0] = out_grad * (values[i + 1] - values[i]) / (k[i + 1] - k[i]);
+                grads[i + 1] = out_grad * (1.0 - t);
+                grads[i + 2] = out_grad * t;
+            }
+        }
+        grads
+    })
+    .with_forward(move |data| pwl_value(data, &knots))
+}
+
+enum Segment {
+    Nan,
+    Below,
+    Above,
+    /// Between knots `i` and `i + 1`, at fraction `t` of the way.
+    Inside(usize, f64),
+}
+
+fn segment(x: f64, knots: &[f64]) -> Segment {
+    let last = knots.len() - 1;
+    if x.is_nan() {
+        return Segment::Nan;
+    }
+    if x < knots[0] {
+        return Segment::Below;
+    }
+    if x >= knots[last] {
+        return Segment::Above;
+    }
+    let i = knots.partition_point(|&k| k <= x) - 1;
+    Segment::Inside(i, (x - knots[i]) / (knots[i + 1] - knots[i]))
+}
+
+// `pwl` of `data[0]` through `data[1..]`
+fn pwl_value(data: &[f64], knots: &[f64]) -> f64 {
+    let values = &data[1..];
+    match segment(data[0], knots) {
+        Segment::Nan => f64::NAN,
+        Segment::Below => values[0],
+        Segment::Above => values[values.len() - 1],
+        Segment::Inside(i, t) => (1.0 - t) * values[i] + t * values[i + 1],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pwl_interpolates_and_learns_its_values() {
+        let knots = [-1.0, 0.0, 2.0];
+        let values: Vec<Value> = [1.0, 0.0, 4.0].iter().map(|&v| Value::new(v, "v")).collect();
+        let at = |x: f64| pwl(&Value::from(x), &knots, &values).data();
+        assert_eq!((at(-3.0), at(-0.5), at(0.0), at(1.5), at(2.0), at(9.0)), (1.0, 0.5, 0.0, 3.0, 4.0, 4.0));
+
+        let x = Value::new(0.5, "x");
+        let y = pwl(&x, &knots, &values);
+        y.backward();
+        let grads: Vec<f64> = values.iter().map(|v| v.grad()).collect();
+        assert_eq!((x.grad(), grads), (2.0, vec![0.0, 0.75, 0.25]));
+        x.set_data(-2.0);
+        assert!(y.recompute());
+        assert_eq!(y.data(), 1.0);
+
+        // NaN in, NaN out, rather than a segment index below zero
+        x.set_data(f64::NAN);
+        assert!(y.recompute() && y.data().is_nan());
+        y.backward_retain(false);
+        assert!(x.grad().is_nan() && values.iter().all(|v| v.grad().is_nan()));
+
+        // fit the values to |x| on a grid
+        let values: Vec<Value> = knots.iter().map(|_| Value::new(0.0, "v")).collect();
+        for _ in 0..200 {
+            values.iter().for_each(|v| v.set_grad(0.0));
+            let loss: Value = (-4..=8)
+                .map(|i| (pwl(&Value::from(i as f64 / 4.0), &knots, &values) - (i as f64 / 4.0).abs()).powop(2))
+                .sum();
+            loss.backward();
+            values.iter().for_each(|v| v.set_data(v.data() - 0.05 * v.grad()));
+        }
+        let fitted: Vec<f64> = values.iter().map(|v| v.data()).collect();
+        assert!(fitted.iter().zip([1.0, 0.0, 2.0]).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", fitted);
+    }
+
+    #[test]
+    fn scalar_broadcasts_collect_gradients_from_every_element() {
+        let xs: Vec<Value> = [1.0, -2.0, 4.0].iter().map(|&x| Value::new(x, "x")).collect();
+        let (bias, gain) = (Value::new(0.5, "b"), Value::new(2.0, "g"));
+        let out = div_scalar(&sub_scalar(&mul_scalar(&add_scalar(&xs, &bias), &gain), &bias), &gain);
+        let data: Vec<f64> = out.iter().map(|v| v.data()).collect();
+        assert_eq!(data, vec![1.25, -1.75, 4.25]);
+
+        out.iter().cloned().sum::<Value>().backward();
+        // sum_i ((x_i + b) g - b) / g = sum_i x_i + 3b - 3b / g
+        assert_eq!(bias.grad(), 3.0 - 3.0 / 2.0);
+        assert_eq!(gain.grad(), 3.0 * 0.5 / 4.0);
+        assert!(xs.iter().all(|x| x.grad() == 1.0));
+    }
+
+    #[test]
+    fn cosine_similarity_matches_unfused_graph() {
+        let a = vec![Value::new(1.0, "a0"), Value::new(2.0, "a1"), Value::new(-0.5, "a2")];
+        let b = vec![Value::new(0.3, "b0"), Value::new(-1.0, "b1"), Value::new(2.0, "b2")];
+        let fused = cosine_similarity(&a, &b);
+        fused.backward();
+        let fused_grads: Vec<f64> = a.iter().chain(&b).map(|v| v.grad()).collect();
+
+        let (a2, b2): (Vec<Value>, Vec<Value>) = (
+            a.iter().map(|v| Value::from(v.data())).collect(),
+            b.iter().map(|v| Value::from(v.data())).collect(),
+        );
+        let sum_sq = |v: &[Value]| v.iter().fold(Value::from(0.0), |acc, x| acc + x.clone() * x.clone());
+        let dot = a2.iter().zip(&b2).fold(Value::from(0.0), |acc, (x, y)| acc + x.clone() * y.clone());
+        let plain = dot / (sum_sq(&a2) * sum_sq(&b2)).powop(0.5);
+        plain.backward();
+
+        assert!((fused.data() - plain.data()).abs() < 1e-12);
+        for (f, p) in fused_grads.iter().zip(a2.iter().chain(&b2)) {
+            assert!((f - p.grad()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn cosine_similarity_of_zero_vector_is_zero() {
+        let a = vec![Value::from(0.0), Value::from(0.0)];
+        let b = vec![Value::new(1.0, "b0"), Value::new(1.0, "b1")];
+        let cos = cosine_similarity(&a, &b);
+        cos.backward();
+        assert_eq!((cos.data(), b[0].grad()), (0.0, 0.0));
+    }
+
+    #[test]
+    fn kl_div_values_and_gradients() {
+        let p = vec![Value::new(0.5, "p0"), Value::new(0.5, "p1"), Value::new(0.0, "p2")];
+        let q = vec![Value::new(0.25, "q0"), Value::new(0.25, "q1"), Value::new(0.5, "q2")];
+        let kl = kl_div(&p, &q);
+        assert!((kl.data() - 2f64.ln()).abs() < 1e-12);
+        kl.backward();
+        assert!((p[0].grad() - (2f64.ln() + 1.0)).abs() < 1e-12);
+        assert_eq!(q[0].grad(), -2.0);
+        assert_eq!(q[2].grad(), 0.0);
+        assert!(p[2].grad().is_finite());
+
+        let same = kl_div(&q, &q);
+        assert!(same.data().abs() < 1e-12);
+    }
+
+    #[test]
+    fn entropy_of_uniform_and_one_hot() {
+        let uniform: Vec<Value> = (0..4).map(|_| Value::from(0.25)).collect();
+        let h = entropy(&uniform);
+        assert!((h.data() - 4f64.ln()).abs() < 1e-12);
+        h.backward();
+        assert!((uniform[0].grad() - (4f64.ln() - 1.0)).abs() < 1e-12);
+
+        let one_hot = vec![Value::from(1.0), Value::from(0.0)];
+        let h = entropy(&one_hot);
+        h.backward();
+        assert_eq!(h.data(), 0.0);
+        assert!(one_hot[1].grad().is_finite());
+    }
+
+    #[test]
+    fn clip_by_norm_rescales_long_vectors() {
+        let xs = vec![Value::new(3.0, "x0"), Value::new(4.0, "x1")];
+        let clipped = clip_by_norm(&xs, 1.0);
+        assert!((clipped[0].data() - 0.6).abs() < 1e-12 && (clipped[1].data() - 0.8).abs() < 1e-12);
+
+        // d(x0 / |x|)/dx = (|x|^2 - x0^2) / |x|^3 and -x0 x1 / |x|^3
+        clipped[0].backward();
+        assert!((xs[0].grad() - 16.0 / 125.0).abs() < 1e-12);
+        assert!((xs[1].grad() + 12.0 / 125.0).abs() < 1e-12);
+
+        let short = vec![Value::new(0.1, "s")];
+        assert_eq!(clip_by_norm(&short, 1.0)[0].id(), short[0].id());
+    }
+
+    #[test]
+    fn argmax_and_top_k() {
+        let v = |xs: &[f64]| xs.iter().map(|&x| Value::from(x)).collect::<Vec<_>>();
+        let logits = v(&[0.1, 2.0, -1.0, 2.0, 0.5]);
+        assert_eq!(argmax(&logits), 1);
+        assert_eq!(top_k(&logits, 3), vec![1, 3, 4]);
+        assert_eq!(top_k(&logits, 9).len(), 5);
+        assert!(top_k(&logits, 0).is_empty());
+        assert_eq!(argmax(&v(&[-3.0, f64::NAN, 1.0])), 1);
+        assert_eq!(argmax(&v(&[-3.0, -f64::NAN, 1.0])), 2);
+
+        let batch = vec![v(&[1.0, 0.0]), v(&[0.0, 1.0]), v(&[-1.0, -2.0])];
+        assert_eq!(argmax_batch(&batch), vec![0, 1, 0]);
+        assert_eq!(top_k_batch(&batch, 2)[1], vec![1, 0]);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::operators::*;
+use std::collections::HashMap;
+
+/// Updates a fixed set of parameters from their accumulated gradients. Parameters with
+/// `requires_grad` cleared are skipped.
+pub trait Optimizer {
+    fn step(&mut self);
+
+    /// Resets the gradient of every parameter to zero, ready for the next backward pass.
+    fn zero_grad(&mut self);
+
+    fn lr(&self) -> f64;
+
+    fn set_lr(&mut self, lr: f64);
+
+    /// Starts updating `params` as well, e.g. after growing a model mid-training. Their optimizer
+    /// state (momentum, moments) starts from zero.
+    fn add_params(&mut self, params: Vec<Value>);
+}
+
+/// Stochastic gradient descent: `p -= lr * grad`, optionally with momentum.
+#[derive(Debug, Clone)]
+pub struct SGD {
+    params: Vec<Value>,
+    lr: f64,
+    l1: f64,
+    l2: f64,
+    momentum: f64,
+    // velocity of every parameter, keyed by node id
+    velocity: HashMap<usize, f64>,
+}
+
+impl SGD {
+    pub fn new(params: Vec<Value>, lr: f64) -> Self {
+        SGD { params, lr, l1: 0.0, l2: 0.0, momentum: 0.0, velocity: HashMap::new() }
+    }
+
+    /// Heavy-ball momentum: `v = momentum * v + grad`, then `p -= lr * v`.
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        assert!((0.0..1.0).contains(&momentum), "momentum must be in [0, 1)");
+        self.momentum = momentum;
+        self
+    }
+
+    /// Elastic net regularization of every parameter, applied inside the update rather than
+    /// through the loss. The L2 part is plain weight decay; the L1 part is a proximal
+    /// soft-threshold step, which sets weights to exactly zero once the gradient stops
+    /// pushing them away from it.
+    pub fn with_elastic_net(mut self, l1: f64, l2: f64) -> Self {
+        self.l1 = l1;
+        self.l2 = l2;
+        self
+    }
+}
+
+// Proximal operator of `t * |x|`
+fn soft_threshold(x: f64, t: f64) -> f64 {
+    x.signum() * (x.abs() - t).max(0.0)
+}
+
+impl Optimizer for SGD {
+    fn step(&mut self) {
+        for p in self.params.iter().filter(|p| p.requires_grad()) {
+            let mut step = p.grad() + self.l2 * p.data();
+            if self.momentum > 0.0 {
+                let v = self.velocity.entry(p.id()).or_insert(0.0);
+                *v = self.momentum * *v + step;
+                step = *v;
+            }
+            let updated = p.data() - self.lr * step;
+            p.set_data(if self.l1 > 0.0 { soft_threshold(updated, self.lr * self.l1) } else { updated });
+        }
+    }
+
+    fn zero_grad(&mut self) {
+        for p in &self.params {
+            p.set_grad(0.0);
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn add_params(&mut self, params: Vec<Value>) {
+        self.params.extend(params);
+    }
+}
+
+/// Adam (Kingma & Ba): per-parameter step sizes from bias-corrected running averages of the
+/// gradient and its square.
+#[derive(Debug, Clone)]
+pub struct Adam {
+    params: Vec<Value>,
+    lr: f64,
+    beta1: f64,
+    beta2: f64,
+    eps: f64,
+    t: i32,
+    // first and second moment of every parameter, keyed by node id
+    moments: HashMap<usize, (f64, f64)>,
+}
+
+impl Adam {
+    /// Adam with the usual defaults `beta1 = 0.9`, `beta2 = 0.999`, `eps = 1e-8`.
+    pub fn new(params: Vec<Value>, lr: f64) -> Self {
+        Adam { params, lr, beta1: 0.9, beta2: 0.999, eps: 1e-8, t: 0, moments: HashMap::new() }
+    }
+
+    pub fn with_betas(mut self, beta1: f64, beta2: f64) -> Self {
+        assert!((0.0..1.0).contains(&beta1) && (0.0..1.0).contains(&beta2), "betas must be in [0, 1)");
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn with_eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self) {
+        self.t += 1;
+        let (c1, c2) = (1.0 - self.beta1.powi(self.t), 1.0 - self.beta2.powi(self.t));
+        for p in self.params.iter().filter(|p| p.requires_grad()) {
+            let g = p.grad();
+            let (m, v) = self.moments.entry(p.id()).or_insert((0.0, 0.0));
+            *m = self.beta1 * *m + (1.0 - self.beta1) * g;
+            *v = self.beta2 * *v + (1.0 - self.beta2) * g * g;
+            p.set_data(p.data() - self.lr * (*m / c1) / ((*v / c2).sqrt() + self.eps));
+        }
+    }
+
+    fn zero_grad(&mut self) {
+        for p in &self.params {
+            p.set_grad(0.0);
+        }
+    }
+
+    fn lr(&self) -> f64 {
+        self.lr
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lr = lr;
+    }
+
+    fn add_params(&mut self, params: Vec<Value>) {
+        self.params.extend(params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sgd_steps_against_the_gradient() {
+        let w = Value::new(3.0, "w");
+        let mut opt = SGD::new(vec![w.clone()], 0.1);
+        for _ in 0..100 {
+            opt.zero_grad();
+            w.clone().powop(2).backward();
+            opt.step();
+        }
+        assert!(w.data().abs() < 1e-6);
+        opt.zero_grad();
+        assert_eq!(w.grad(), 0.0);
+    }
+
+    // Linear regression on 4 features where only the first matters
+    fn sparse_regression(opt_for: impl Fn(Vec<Value>) -> SGD, penalty: f64) -> Vec<f64> {
+        let w: Vec<Value> = (0..4).map(|i| Value::new(0.5 - 0.2 * i as f64, "w")).collect();
+        let mut opt = opt_for(w.clone());
+        let xs: Vec<[f64; 4]> = (0..16)
+            .map(|i| [(i % 4) as f64 - 1.5, (i / 4) as f64 - 1.5, ((i * 3) % 5) as f64 - 2.0, (i % 3) as f64 - 1.0])
+            .collect();
+        for _ in 0..400 {
+            opt.zero_grad();
+            let preds: Vec<Value> = xs
+                .iter()
+                .map(|x| w.iter().zip(x).fold(Value::from(0.0), |acc, (w, x)| acc + w * *x))
+                .collect();
+            let targets: Vec<Value> = xs.iter().map(|x| Value::from(2.0 * x[0])).collect();
+            let loss = crate::loss::mse(&preds, &targets) + crate::loss::l1_penalty(&w) * penalty;
+            loss.backward();
+            opt.step();
+        }
+        w.iter().map(|w| w.data()).collect()
+    }
+
+    #[test]
+    fn proximal_l1_reaches_exact_zeros() {
+        let w = sparse_regression(|w| SGD::new(w, 0.05).with_elastic_net(0.1, 0.01), 0.0);
+        assert!((w[0] - 2.0).abs() < 0.1);
+        assert!(w[1..].iter().all(|&w| w == 0.0), "{w:?}");
+
+        // the same penalty as a subgradient only gets close to zero
+        let w = sparse_regression(|w| SGD::new(w, 0.05), 0.1);
+        assert!(w[1..].iter().all(|&w| w.abs() < 0.05));
+    }
+
+    // Steps of `opt` on the badly conditioned quadratic 10 x^2 + y^2 / 10
+    fn minimize(mut opt: impl Optimizer, x: &Value, y: &Value, steps: usize) -> f64 {
+        for _ in 0..steps {
+            opt.zero_grad();
+            (x.clone().powop(2) * 10.0 + y.clone().powop(2) * 0.1).backward();
+            opt.step();
+        }
+        10.0 * x.data().powi(2) + 0.1 * y.data().powi(2)
+    }
+
+    #[test]
+    fn momentum_and_adam_beat_plain_sgd() {
+        let start = || (Value::new(1.0, "x"), Value::new(1.0, "y"));
+        let (x, y) = start();
+        let plain = minimize(SGD::new(vec![x.clone(), y.clone()], 0.04), &x, &y, 100);
+        let (x, y) = start();
+        let heavy = minimize(SGD::new(vec![x.clone(), y.clone()], 0.04).with_momentum(0.9), &x, &y, 100);
+        let (x, y) = start();
+        let adam = minimize(Adam::new(vec![x.clone(), y.clone()], 0.05), &x, &y, 100);
+        assert!(heavy < plain / 10.0, "{heavy} vs {plain}");
+        assert!(adam < plain / 10.0, "{adam} vs {plain}");
+    }
+
+    #[test]
+    fn adam_first_step_is_lr_times_sign() {
+        let w = Value::new(0.0, "w");
+        let frozen = Value::new(1.0, "frozen");
+        frozen.set_requires_grad(false);
+        let mut opt = Adam::new(vec![w.clone(), frozen.clone()], 0.01);
+        ((w.clone() - 3.0) * (frozen.clone() * 5.0)).backward();
+        opt.step();
+        // bias correction makes the first update exactly lr in the gradient's direction
+        assert!((w.data() + 0.01).abs() < 1e-9);
+        assert_eq!(frozen.data(), 1.0);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::operators::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Time spent in the backward closures of one op category.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpTiming {
+    pub op: String,
+    pub count: usize,
+    pub total_us: f64,
+}
+
+/// Per-op timings of a single backward pass, slowest op first.
+#[derive(Debug, Clone, Default)]
+pub struct BackwardProfile {
+    pub rows: Vec<OpTiming>,
+}
+
+impl BackwardProfile {
+    pub fn get(&self, op: &str) -> Option<&OpTiming> {
+        self.rows.iter().find(|r| r.op == op)
+    }
+
+    pub fn total_us(&self) -> f64 {
+        self.rows.iter().map(|r| r.total_us).sum()
+    }
+}
+
+impl fmt::Display for BackwardProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<12} {:>8} {:>12}", "op", "count", "total µs")?;
+        for row in &self.rows {
+            writeln!(f, "{:<12} {:>8} {:>12.1}", row.op, row.count, row.total_us)?;
+        }
+        Ok(())
+    }
+}
+
+impl Value {
+    /// Same as `backward`, but times every backward closure and groups the timings by op.
+    pub fn backward_profiled(&self) -> BackwardProfile {
+        self.debug_validate();
+
+        self.set_grad(1.0);
+
+        let mut totals: HashMap<String, (usize, Duration)> = HashMap::new();
+        Value::propagate_with(std::slice::from_ref(self), |node, run| {
+            let op = node.borrow().op().unwrap_or("?").to_string();
+            let start = Instant::now();
+            run();
+            let entry = totals.entry(op).or_insert((0, Duration::ZERO));
+            entry.0 += 1;
+            entry.1 += start.elapsed();
+        });
+
+        let mut rows: Vec<OpTiming> = totals
+            .into_iter()
+            .map(|(op, (count, total))| OpTiming { op, count, total_us: total.as_secs_f64() * 1e6 })
+            .collect();
+        rows.sort_by(|a, b| b.total_us.total_cmp(&a.total_us).then_with(|| a.op.cmp(&b.op)));
+        BackwardProfile { rows }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_ops_and_matches_backward() {
+        let x = Value::new(0.5, "x");
+        let w = Value::new(-1.5, "w");
+        let b = Value::new(0.1, "b");
+        let out = (x.clone() * w.clone() + b.clone()).tanh();
+
+        let profile = out.backward_profiled();
+        assert_eq!(profile.get("*").unwrap().count, 1);
+        assert_eq!(profile.get("+").unwrap().count, 1);
+        assert_eq!(profile.get("tanh").unwrap().count, 1);
+        assert!(profile.total_us() >= 0.0);
+
+        let expected = (1.0 - out.data().powi(2)) * x.data();
+        assert!((w.grad() - expected).abs() < 1e-12);
+        assert!(profile.to_string().starts_with("op"));
+    }
+
+    #[test]
+    fn deterministic_mode_matches_backward() {
+        let build = || {
+            let x = Value::new(0.3, "x");
+            let h = x.clone() * x.clone();
+            let out = h.clone().tanh() + h.exp() + x.clone();
+            (x, out)
+        };
+        let (x, out) = build();
+        deterministic_backward(|| out.backward());
+        let (y, profiled) = build();
+        deterministic_backward(|| profiled.backward_profiled());
+        assert_eq!(y.grad().to_bits(), x.grad().to_bits());
+    }
+}
@@ -0,0 +1,164 @@
+//! Magnitude pruning of a module's parameters, and structured pruning of whole neurons.
+
+use crate::nn::{Module, MLP};
+
+/// Which parameters survived pruning, in the order of `Module::parameters`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mask {
+    pub keep: Vec<bool>,
+}
+
+impl Mask {
+    /// Number of pruned parameters.
+    pub fn pruned(&self) -> usize {
+        self.keep.iter().filter(|k| !**k).count()
+    }
+
+    /// Fraction of parameters pruned.
+    pub fn sparsity(&self) -> f64 {
+        self.pruned() as f64 / self.keep.len().max(1) as f64
+    }
+
+    /// Zeroes the pruned parameters of `module` again, e.g. after updates that ignored the mask.
+    pub fn apply<M: Module>(&self, module: &M) {
+        for (p, _) in self.paired(module).filter(|(_, keep)| !keep) {
+            p.set_data(0.0);
+        }
+    }
+
+    /// Freezes the pruned parameters so optimizers leave them at zero while the rest fine-tune.
+    pub fn freeze<M: Module>(&self, module: &M) {
+        for (p, keep) in self.paired(module) {
+            p.set_requires_grad(keep);
+        }
+    }
+
+    fn paired<M: Module>(&self, module: &M) -> impl Iterator<Item = (crate::operators::Value, bool)> {
+        let params = module.parameters();
+        assert_eq!(params.len(), self.keep.len(), "mask does not match the module's parameters");
+        params.into_iter().zip(self.keep.clone())
+    }
+}
+
+/// Zeroes the `fraction` of `module`'s parameters with the smallest magnitude (biases included)
+/// and returns the mask of survivors. Call `Mask::freeze` to keep them at zero while fine-tuning.
+pub fn magnitude<M: Module>(module: &M, fraction: f64) -> Mask {
+    assert!((0.0..=1.0).contains(&fraction), "fraction must be in [0, 1]");
+    let params = module.parameters();
+    let mut order: Vec<usize> = (0..params.len()).collect();
+    order.sort_by(|&a, &b| params[a].data().abs().total_cmp(&params[b].data().abs()));
+
+    let count = (fraction * params.len() as f64).round() as usize;
+    let mut keep = vec![true; params.len()];
+    for &i in &order[..count] {
+        keep[i] = false;
+        params[i].set_data(0.0);
+    }
+    Mask { keep }
+}
+
+/// Lottery-ticket rewind: resets the surviving parameters of `module` to their values in
+/// `initial` (typically a `deep_copy` taken right after construction) and zeroes the pruned ones.
+pub fn rewind<M: Module>(module: &M, initial: &M, mask: &Mask) {
+    let start = initial.parameters();
+    assert_eq!(start.len(), mask.keep.len(), "initial snapshot does not match the mask");
+    for ((p, keep), s) in mask.paired(module).zip(start) {
+        p.set_data(if keep { s.data() } else { 0.0 });
+    }
+}
+
+/// Structured pruning: removes the neurons at `indices` from layer `layer` of `mlp`, together
+/// with the input weights of the next layer that read their outputs. Unlike `magnitude`, which
+/// only zeroes parameters, the model really gets smaller. Optimizers and masks built from the
+/// old parameter list no longer match and must be rebuilt.
+pub fn neurons(mlp: &mut MLP, layer: usize, indices: &[usize]) {
+    let layers = mlp.layers_mut();
+    assert!(layer < layers.len(), "layer {} out of range for {} layers", layer, layers.len());
+    let nout = layers[layer].nout();
+    assert!(indices.iter().all(|&i| i < nout), "neuron index out of range for {} neurons", nout);
+    let mut removed = indices.to_vec();
+    removed.sort_unstable();
+    removed.dedup();
+    assert!(removed.len() < nout, "cannot remove every neuron of a layer");
+
+    layers[layer].remove_neurons(&removed);
+    if let Some(next) = layers.get_mut(layer + 1) {
+        next.remove_inputs(&removed);
+    }
+}
+
+#[cfg(all(test, feature = "optim", feature = "datasets"))]
+mod tests {
+    use super::*;
+    use crate::data::{DataLoader, Dataset};
+    use crate::loss::mse;
+    use crate::nn::MLP;
+    use crate::optim::SGD;
+    use crate::trainer::Trainer;
+
+    #[test]
+    fn prune_then_fine_tune_keeps_pruned_weights_at_zero() {
+        let xs: Vec<Vec<f64>> = (-10..=10).map(|i| vec![i as f64 / 10.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![0.6 * x[0]]).collect();
+        let data = Dataset::new(xs, ys);
+        let mut loader = DataLoader::new(data.clone(), 7).shuffle(3);
+
+        let model = MLP::new(1, vec![8, 1]);
+        let opt = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(model, opt, mse);
+        trainer.fit(&mut loader, 60);
+
+        // 8 hidden neurons with 2 parameters each, plus 9 in the output neuron
+        let mask = magnitude(&trainer.model, 0.4);
+        assert_eq!(mask.pruned(), 10);
+        assert_eq!(mask.sparsity(), 0.4);
+        mask.freeze(&trainer.model);
+        let pruned_loss = trainer.evaluate(&data)[0];
+
+        trainer.fit(&mut loader, 30);
+        let params = trainer.model.parameters();
+        assert!(params.iter().zip(&mask.keep).all(|(p, keep)| *keep || p.data() == 0.0));
+        assert!(trainer.evaluate(&data)[0] <= pruned_loss);
+    }
+
+    #[test]
+    fn rewind_restores_initial_survivors() {
+        let model = MLP::new(2, vec![4, 1]);
+        let initial = model.deep_copy();
+        for p in model.parameters() {
+            p.set_data(p.data() * 3.0 + 0.1);
+        }
+
+        let mask = magnitude(&model, 0.5);
+        rewind(&model, &initial, &mask);
+        for ((p, s), keep) in model.parameters().iter().zip(initial.parameters()).zip(&mask.keep) {
+            assert_eq!(p.data(), if *keep { s.data() } else { 0.0 });
+        }
+    }
+
+    #[test]
+    fn removing_neurons_shrinks_the_model() {
+        use crate::operators::Value;
+        let mut model = MLP::new(2, vec![5, 3, 1]);
+        // silence neurons 1 and 3 of the first layer, so removing them changes nothing
+        // each neuron's parameters are its bias, then one weight per input
+        for row in model.layers()[1].parameters().chunks(1 + 5) {
+            row[1 + 1].set_data(0.0);
+            row[1 + 3].set_data(0.0);
+        }
+        let x = [Value::from(0.3), Value::from(-0.8)];
+        let before = Module::forward(&model, &x)[0].data();
+        let count = model.parameters().len();
+
+        neurons(&mut model, 0, &[3, 1, 3]);
+        let sizes: Vec<(usize, usize)> = model.layers().iter().map(|l| (l.nin(), l.nout())).collect();
+        assert_eq!(sizes, vec![(2, 3), (3, 3), (3, 1)]);
+        assert_eq!(model.parameters().len(), count - 2 * 3 - 3 * 2);
+        assert!((Module::forward(&model, &x)[0].data() - before).abs() < 1e-12);
+
+        // the output layer has no next layer to fix up
+        neurons(&mut model, 2, &[]);
+        neurons(&mut model, 1, &[0]);
+        assert_eq!(model.layers()[2].nin(), 2);
+    }
+}
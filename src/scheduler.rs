@@ -0,0 +1,112 @@
+//! Learning rate schedules: the learning rate as a function of how far training has got, counted
+//! in optimizer steps or in epochs. `Trainer::with_schedule` applies one after every step or
+//! every epoch; any `Fn(usize) -> f64` is a schedule too.
+
+use std::f64::consts::PI;
+
+pub trait Schedule {
+    /// Learning rate for step (or epoch) `t`, counting from 0.
+    fn lr(&self, t: usize) -> f64;
+}
+
+impl<F: Fn(usize) -> f64> Schedule for F {
+    fn lr(&self, t: usize) -> f64 {
+        self(t)
+    }
+}
+
+/// Cosine annealing with warm restarts (SGDR): the rate falls from `max_lr` to `min_lr` along
+/// half a cosine over `period` steps, then jumps back to `max_lr` for the next cycle, which is
+/// `period_mult` times as long.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CosineWarmRestarts {
+    max_lr: f64,
+    min_lr: f64,
+    period: usize,
+    period_mult: usize,
+}
+
+impl CosineWarmRestarts {
+    pub fn new(max_lr: f64, min_lr: f64, period: usize) -> Self {
+        assert!(period > 0, "period must be positive");
+        CosineWarmRestarts { max_lr, min_lr, period, period_mult: 1 }
+    }
+
+    /// Makes every cycle `mult` times longer than the one before.
+    pub fn with_period_mult(mut self, mult: usize) -> Self {
+        assert!(mult > 0, "period multiplier must be positive");
+        self.period_mult = mult;
+        self
+    }
+}
+
+impl Schedule for CosineWarmRestarts {
+    fn lr(&self, t: usize) -> f64 {
+        let (mut t, mut period) = (t, self.period);
+        if self.period_mult == 1 {
+            t %= period;
+        }
+        while t >= period {
+            t -= period;
+            period *= self.period_mult;
+        }
+        let progress = t as f64 / period as f64;
+        self.min_lr + (self.max_lr - self.min_lr) * (1.0 + (PI * progress).cos()) / 2.0
+    }
+}
+
+/// Cyclical learning rate with the triangular policy: the rate climbs linearly from `min_lr`
+/// to `max_lr` over `half_cycle` steps, falls back over as many, and repeats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triangular {
+    min_lr: f64,
+    max_lr: f64,
+    half_cycle: usize,
+}
+
+impl Triangular {
+    pub fn new(min_lr: f64, max_lr: f64, half_cycle: usize) -> Self {
+        assert!(half_cycle > 0, "half cycle must be positive");
+        Triangular { min_lr, max_lr, half_cycle }
+    }
+}
+
+impl Schedule for Triangular {
+    fn lr(&self, t: usize) -> f64 {
+        let phase = t % (2 * self.half_cycle);
+        let rise = phase.min(2 * self.half_cycle - phase) as f64 / self.half_cycle as f64;
+        self.min_lr + (self.max_lr - self.min_lr) * rise
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn cosine_restarts_at_the_end_of_every_cycle() {
+        let s = CosineWarmRestarts::new(1.0, 0.1, 4);
+        assert!(close(s.lr(0), 1.0) && close(s.lr(2), 0.55) && close(s.lr(4), 1.0) && close(s.lr(6), 0.55));
+        assert!(s.lr(3) > 0.1 && s.lr(3) < s.lr(2));
+
+        // cycles of 4, 8, 16, ... starting at 0, 4, 12, 28
+        let s = s.with_period_mult(2);
+        for start in [0, 4, 12, 28] {
+            assert!(close(s.lr(start), 1.0));
+        }
+        assert!(close(s.lr(8), 0.55) && close(s.lr(20), 0.55));
+    }
+
+    #[test]
+    fn triangular_climbs_and_falls_linearly() {
+        let s = Triangular::new(0.1, 0.5, 4);
+        let lrs: Vec<f64> = (0..10).map(|t| s.lr(t)).collect();
+        let expected = [0.1, 0.2, 0.3, 0.4, 0.5, 0.4, 0.3, 0.2, 0.1, 0.2];
+        assert!(lrs.iter().zip(expected).all(|(a, b)| close(*a, b)), "{:?}", lrs);
+        assert!(close((|t: usize| 0.5 / (t + 1) as f64).lr(4), 0.1));
+    }
+}
@@ -0,0 +1,172 @@
+//! Gradient-free optimizers, for objectives with no useful gradient (step functions, counts,
+//! anything computed outside the graph) and for comparing against backprop when teaching.
+//!
+//! They move the data of a set of parameter Values around and only ever look at the loss, so
+//! the loss closure can read the parameters any way it likes, e.g. by running an `MLP` they
+//! belong to. Losses are evaluated under `no_grad`.
+
+use crate::operators::{no_grad, Value};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// An optimizer that minimizes a loss by evaluating it at different parameter values.
+pub trait Search {
+    /// Runs `iterations` loss evaluations on top of the one at the starting point and leaves
+    /// `params` at the best point seen. Returns the best loss so far after each iteration.
+    /// Parameters with `requires_grad` cleared are not moved.
+    fn fit(&mut self, params: &[Value], loss: &mut dyn FnMut() -> f64, iterations: usize) -> Vec<f64>;
+}
+
+fn evaluate(loss: &mut dyn FnMut() -> f64) -> f64 {
+    let l = no_grad(loss);
+    if l.is_nan() { f64::INFINITY } else { l }
+}
+
+fn set_all(params: &[&Value], data: &[f64]) {
+    params.iter().zip(data).for_each(|(p, &x)| p.set_data(x));
+}
+
+/// Pure random search: every iteration tries a fresh point drawn uniformly from
+/// `[-bound, bound]` per parameter, and keeps it if it is the best so far.
+#[derive(Debug, Clone)]
+pub struct RandomSearch {
+    bound: f64,
+    rng: StdRng,
+}
+
+impl RandomSearch {
+    pub fn new(bound: f64) -> Self {
+        assert!(bound > 0.0, "search bound must be positive");
+        RandomSearch { bound, rng: StdRng::seed_from_u64(0) }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Search for RandomSearch {
+    fn fit(&mut self, params: &[Value], loss: &mut dyn FnMut() -> f64, iterations: usize) -> Vec<f64> {
+        let params: Vec<&Value> = params.iter().filter(|p| p.requires_grad()).collect();
+        let mut best: Vec<f64> = params.iter().map(|p| p.data()).collect();
+        let mut best_loss = evaluate(loss);
+        let mut history = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let point: Vec<f64> = (0..params.len()).map(|_| self.rng.gen_range(-self.bound..=self.bound)).collect();
+            set_all(&params, &point);
+            let l = evaluate(loss);
+            if l < best_loss {
+                (best, best_loss) = (point, l);
+            }
+            history.push(best_loss);
+        }
+        set_all(&params, &best);
+        history
+    }
+}
+
+/// Simulated annealing: every iteration moves each parameter by gaussian noise of standard
+/// deviation `step`, and accepts the move if it lowers the loss or, with probability
+/// `exp(-increase / temperature)`, if it does not. The temperature starts at `temperature` and
+/// is multiplied by `cooling` after every iteration, so uphill moves get rarer over time.
+#[derive(Debug, Clone)]
+pub struct SimulatedAnnealing {
+    step: f64,
+    temperature: f64,
+    cooling: f64,
+    rng: StdRng,
+}
+
+impl SimulatedAnnealing {
+    /// Starts at temperature 1 and cools by 1% per iteration.
+    pub fn new(step: f64) -> Self {
+        assert!(step > 0.0, "annealing step must be positive");
+        SimulatedAnnealing { step, temperature: 1.0, cooling: 0.99, rng: StdRng::seed_from_u64(0) }
+    }
+
+    pub fn with_schedule(mut self, temperature: f64, cooling: f64) -> Self {
+        assert!(temperature > 0.0, "temperature must be positive");
+        assert!(cooling > 0.0 && cooling <= 1.0, "cooling must be in (0, 1]");
+        self.temperature = temperature;
+        self.cooling = cooling;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    fn gaussian(&mut self) -> f64 {
+        crate::noise::gaussian(&mut self.rng)
+    }
+}
+
+impl Search for SimulatedAnnealing {
+    fn fit(&mut self, params: &[Value], loss: &mut dyn FnMut() -> f64, iterations: usize) -> Vec<f64> {
+        let params: Vec<&Value> = params.iter().filter(|p| p.requires_grad()).collect();
+        let mut current: Vec<f64> = params.iter().map(|p| p.data()).collect();
+        let mut current_loss = evaluate(loss);
+        let (mut best, mut best_loss) = (current.clone(), current_loss);
+        let mut temperature = self.temperature;
+        let mut history = Vec::with_capacity(iterations);
+        for _ in 0..iterations {
+            let point: Vec<f64> = current.iter().map(|x| x + self.step * self.gaussian()).collect();
+            set_all(&params, &point);
+            let l = evaluate(loss);
+            if l <= current_loss || self.rng.gen_bool((-(l - current_loss) / temperature).exp()) {
+                (current, current_loss) = (point, l);
+                if l < best_loss {
+                    (best, best_loss) = (current.clone(), l);
+                }
+            }
+            temperature *= self.cooling;
+            history.push(best_loss);
+        }
+        set_all(&params, &best);
+        history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A bowl with a unit step over its left half, which no gradient can see
+    fn stepped_bowl(x: &Value, y: &Value) -> f64 {
+        let (x, y) = (x.data(), y.data());
+        (x - 1.0).powi(2) + (y + 0.5).powi(2) + if x > 0.0 { 0.0 } else { 1.0 }
+    }
+
+    fn run(mut search: impl Search, iterations: usize) -> (Vec<f64>, Vec<f64>) {
+        let (x, y) = (Value::new(-2.0, "x"), Value::new(2.0, "y"));
+        let history = search.fit(&[x.clone(), y.clone()], &mut || stepped_bowl(&x, &y), iterations);
+        assert_eq!(history.len(), iterations);
+        assert!(history.windows(2).all(|w| w[1] <= w[0]));
+        // the parameters are left at the best point
+        assert_eq!(stepped_bowl(&x, &y), history[iterations - 1]);
+        (history, vec![x.data(), y.data()])
+    }
+
+    #[test]
+    fn both_searches_find_the_minimum() {
+        let (history, at) = run(RandomSearch::new(2.0).with_seed(3), 2000);
+        assert!(history[1999] < 0.05, "{:?} at {:?}", history[1999], at);
+
+        let (history, at) = run(SimulatedAnnealing::new(0.1).with_schedule(1.0, 0.99).with_seed(3), 2000);
+        assert!(history[1999] < 1e-3, "{:?} at {:?}", history[1999], at);
+        // seeded, so repeatable
+        assert_eq!(run(SimulatedAnnealing::new(0.1).with_seed(3), 50).0, run(SimulatedAnnealing::new(0.1).with_seed(3), 50).0);
+    }
+
+    #[test]
+    fn frozen_parameters_stay_put() {
+        let (x, y) = (Value::new(3.0, "x"), Value::new(3.0, "y"));
+        y.set_requires_grad(false);
+        let mut loss = || x.data().powi(2) + y.data().powi(2);
+        SimulatedAnnealing::new(0.5).fit(&[x.clone(), y.clone()], &mut loss, 200);
+        assert!(x.data().abs() < 0.5);
+        assert_eq!(y.data(), 3.0);
+    }
+}
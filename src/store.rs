@@ -0,0 +1,235 @@
+//! A parameter store: named groups of parameter values, kept apart from any model so they can be
+//! saved, edited and loaded into whichever model has groups of the same names and sizes.
+//!
+//! Names are those of `Module::parameter_groups`, e.g. `layer 0`, `layer 1` for an `MLP`.
+//! `HotReload` watches a store file and swaps its values into a live model whenever the file
+//! changes, for tweaking weights by hand while a model runs.
+
+use crate::nn::Module;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),
+    Format(String),
+    /// The store's group `name` has a different number of values than the model's.
+    Shape { name: String, expected: usize, found: usize },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "i/o error: {}", e),
+            StoreError::Format(msg) => write!(f, "malformed parameter store: {}", msg),
+            StoreError::Shape { name, expected, found } => {
+                write!(f, "{}: model has {} parameters, store has {}", name, expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+/// Parameter values by group name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParamStore {
+    groups: BTreeMap<String, Vec<f64>>,
+}
+
+impl ParamStore {
+    pub fn new() -> Self {
+        ParamStore::default()
+    }
+
+    /// The current values of every parameter group of `model`.
+    pub fn from_module(model: &dyn Module) -> Self {
+        let groups = model
+            .parameter_groups()
+            .into_iter()
+            .map(|(name, params)| (name, params.iter().map(|p| p.data()).collect()))
+            .collect();
+        ParamStore { groups }
+    }
+
+    /// Adds or replaces group `name`.
+    pub fn insert(&mut self, name: &str, values: Vec<f64>) {
+        self.groups.insert(name.to_string(), values);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[f64]> {
+        self.groups.get(name).map(Vec::as_slice)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.groups.keys().map(String::as_str)
+    }
+
+    /// Copies the values of every group that `model` also has into its parameters, and returns
+    /// the names of those groups. Groups only one side has are skipped. If any shared group
+    /// differs in size nothing is copied.
+    pub fn apply(&self, model: &dyn Module) -> Result<Vec<String>, StoreError> {
+        let shared: Vec<_> = model
+            .parameter_groups()
+            .into_iter()
+            .filter_map(|(name, params)| self.groups.get(&name).map(|values| (name, params, values)))
+            .collect();
+        if let Some((name, params, values)) = shared.iter().find(|(_, p, v)| p.len() != v.len()) {
+            return Err(StoreError::Shape { name: name.clone(), expected: params.len(), found: values.len() });
+        }
+        for (_, params, values) in &shared {
+            params.iter().zip(values.iter()).for_each(|(p, &v)| p.set_data(v));
+        }
+        Ok(shared.into_iter().map(|(name, _, _)| name).collect())
+    }
+
+    /// A `micrograd-params 1` header, then per group a `group <count> <name>` line followed by a
+    /// line of its values.
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("micrograd-params 1\n");
+        for (name, values) in &self.groups {
+            out += &format!("group {} {}\n", values.len(), name);
+            let values: Vec<String> = values.iter().map(|v| format!("{:?}", v)).collect();
+            out += &values.join(" ");
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Parses the format written by `to_text`.
+    pub fn from_text(text: &str) -> Result<Self, StoreError> {
+        let bad = |n: usize, msg: &str| StoreError::Format(format!("line {}: {}", n + 1, msg));
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, "micrograd-params 1")) => {}
+            _ => return Err(StoreError::Format("missing `micrograd-params 1` header".to_string())),
+        }
+
+        let mut store = ParamStore::new();
+        while let Some((n, line)) = lines.next() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (count, name) = match line.splitn(3, ' ').collect::<Vec<_>>()[..] {
+                ["group", count, name] if !name.is_empty() => {
+                    (count.parse::<usize>().map_err(|_| bad(n, "bad value count"))?, name)
+                }
+                _ => return Err(bad(n, "expected a group header")),
+            };
+            // an empty group still has its (empty) values line
+            let (n, line) = lines.next().ok_or_else(|| StoreError::Format(format!("truncated group {}", name)))?;
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| bad(n, "bad number"))?;
+            if values.len() != count {
+                return Err(bad(n, &format!("expected {} values, found {}", count, values.len())));
+            }
+            store.insert(name, values);
+        }
+        Ok(store)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StoreError> {
+        fs::write(path, self.to_text())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StoreError> {
+        ParamStore::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+/// Watches a store file and applies it to a model each time its contents change.
+#[derive(Debug, Clone)]
+pub struct HotReload {
+    path: PathBuf,
+    last: Option<String>,
+}
+
+impl HotReload {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        HotReload { path: path.as_ref().to_path_buf(), last: None }
+    }
+
+    /// Reads the file and, if it differs from what the last successful poll applied, applies it
+    /// to `model`; returns whether it did. Call it e.g. once per epoch. A file that fails to
+    /// parse or apply leaves the model alone and is tried again on the next poll.
+    pub fn poll(&mut self, model: &dyn Module) -> Result<bool, StoreError> {
+        let text = fs::read_to_string(&self.path)?;
+        if self.last.as_deref() == Some(text.as_str()) {
+            return Ok(false);
+        }
+        ParamStore::from_text(&text)?.apply(model)?;
+        self.last = Some(text);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::MLP;
+
+    fn data(model: &MLP) -> Vec<f64> {
+        model.parameters().iter().map(|p| p.data()).collect()
+    }
+
+    #[test]
+    fn stores_round_trip_and_apply_by_name() {
+        let model = MLP::new(2, vec![3, 1]);
+        let store = ParamStore::from_module(&model);
+        assert_eq!(store.names().collect::<Vec<_>>(), vec!["layer 0", "layer 1"]);
+        assert_eq!(ParamStore::from_text(&store.to_text()).unwrap(), store);
+
+        // a model with the same first layer but a wider output takes only the first
+        let other = MLP::new(2, vec![3, 2]);
+        let mut partial = store.clone();
+        assert!(matches!(
+            partial.apply(&other),
+            Err(StoreError::Shape { ref name, expected: 8, found: 4 }) if name == "layer 1"
+        ));
+        let before = data(&other);
+        partial.insert("layer 1", before[9..].to_vec());
+        partial.insert("unused", vec![]);
+        assert_eq!(partial.apply(&other).unwrap(), vec!["layer 0", "layer 1"]);
+        assert_eq!(data(&other)[..9], data(&model)[..9]);
+        assert_eq!(data(&other)[9..], before[9..]);
+
+        assert!(matches!(ParamStore::from_text("micrograd-params 1\ngroup 2 a\n1.0\n"), Err(StoreError::Format(_))));
+        assert!(matches!(ParamStore::from_text("group 1 a\n1.0\n"), Err(StoreError::Format(_))));
+    }
+
+    #[test]
+    fn hot_reload_applies_each_change_once() {
+        let path = std::env::temp_dir().join(format!("micrograd-params-{}.txt", std::process::id()));
+        let model = MLP::new(1, vec![2]);
+        let mut store = ParamStore::from_module(&model);
+        store.insert("layer 0", vec![0.5, 1.0, -0.5, 2.0]);
+        store.save(&path).unwrap();
+
+        let mut reload = HotReload::new(&path);
+        assert!(reload.poll(&model).unwrap());
+        assert_eq!(data(&model), vec![0.5, 1.0, -0.5, 2.0]);
+        model.parameters()[0].set_data(9.0);
+        assert!(!reload.poll(&model).unwrap());
+        assert_eq!(model.parameters()[0].data(), 9.0);
+
+        fs::write(&path, "micrograd-params 1\ngroup 1 layer 0\n3.0\n").unwrap();
+        assert!(matches!(reload.poll(&model), Err(StoreError::Shape { .. })));
+        store.insert("layer 0", vec![0.0; 4]);
+        store.save(&path).unwrap();
+        assert!(reload.poll(&model).unwrap());
+        assert_eq!(data(&model), vec![0.0; 4]);
+        fs::remove_file(&path).unwrap();
+    }
+}
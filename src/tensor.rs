@@ -0,0 +1,1046 @@
+use crate::operators::Value;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::{Add, Mul};
+use std::rc::Rc;
+
+/// A node in the tensor graph. Same idea as `GraphNode`, but every node holds a whole
+/// row-major array, so one backward closure covers what would be thousands of scalar nodes.
+pub struct TensorNode {
+    // Shared so reshapes can reuse the buffer without copying
+    data: Rc<Vec<f64>>,
+    grad: Vec<f64>,
+    shape: Vec<usize>,
+    label: String,
+    prev: Vec<Rc<RefCell<TensorNode>>>,
+    op: Option<String>,
+    backward: Option<Rc<dyn Fn()>>,
+    // The scalar Values packed into this node by `from_values`, which its gradient belongs to
+    values: Vec<Value>,
+}
+
+impl Drop for TensorNode {
+    // Same as for `GraphNode`: free a long chain one node at a time rather than recursively
+    fn drop(&mut self) {
+        let mut orphans = std::mem::take(&mut self.prev);
+        while let Some(rc) = orphans.pop() {
+            if let Ok(cell) = Rc::try_unwrap(rc) {
+                orphans.append(&mut cell.into_inner().prev);
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Tensor(Rc<RefCell<TensorNode>>);
+
+type NodeRef = Rc<RefCell<TensorNode>>;
+
+/// Shapes that an operation cannot combine, returned by the `try_` methods of `Tensor` and
+/// `Vector`. Vector lengths are reported as one-dimensional shapes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapeError {
+    pub op: &'static str,
+    pub left: Vec<usize>,
+    pub right: Vec<usize>,
+}
+
+impl fmt::Display for ShapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cannot {} shapes {:?} and {:?}", self.op, self.left, self.right)
+    }
+}
+
+impl std::error::Error for ShapeError {}
+
+impl Tensor {
+    /// Panics when `data` does not have exactly the number of elements of `shape`, see `try_new`.
+    pub fn new(data: Vec<f64>, shape: &[usize]) -> Self {
+        Tensor::try_new(data, shape).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn zeros(shape: &[usize]) -> Self {
+        Tensor::new(vec![0.0; shape.iter().product()], shape)
+    }
+
+    #[cfg(feature = "rand")]
+    pub fn rand_uniform<R: rand::Rng>(shape: &[usize], low: f64, high: f64, rng: &mut R) -> Self {
+        let n = shape.iter().product();
+        Tensor::new((0..n).map(|_| rng.gen_range(low..high)).collect(), shape)
+    }
+
+    fn from_shared(data: Rc<Vec<f64>>, shape: Vec<usize>, label: &str) -> Self {
+        let grad = vec![0.0; data.len()];
+        Tensor(Rc::new(RefCell::new(TensorNode {
+            data,
+            grad,
+            shape,
+            label: label.to_string(),
+            prev: vec![],
+            op: None,
+            backward: None,
+            values: vec![],
+        })))
+    }
+
+    pub fn label(&mut self, label: &str) {
+        self.0.borrow_mut().label = label.to_string();
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        self.0.borrow().shape.clone()
+    }
+
+    pub fn numel(&self) -> usize {
+        self.0.borrow().data.len()
+    }
+
+    pub fn data(&self) -> Vec<f64> {
+        self.0.borrow().data.to_vec()
+    }
+
+    pub fn set_data(&self, data: Vec<f64>) {
+        let mut node = self.0.borrow_mut();
+        assert_eq!(data.len(), node.data.len(), "set_data with the wrong number of elements");
+        node.data = Rc::new(data);
+    }
+
+    pub fn grad(&self) -> Vec<f64> {
+        self.0.borrow().grad.clone()
+    }
+
+    pub fn zero_grad(&self) {
+        self.0.borrow_mut().grad.iter_mut().for_each(|g| *g = 0.0);
+    }
+
+    pub fn op(&self) -> Option<String> {
+        self.0.borrow().op.clone()
+    }
+
+    // Cheap handle on the data buffer, for use inside backward closures
+    fn shared_data(&self) -> Rc<Vec<f64>> {
+        self.0.borrow().data.clone()
+    }
+
+    /// Creates the output node of an op and installs its backward closure. `backward` receives the
+    /// output gradient and the parent nodes, in the order given, and accumulates into their grads.
+    fn from_op(
+        data: Rc<Vec<f64>>,
+        shape: Vec<usize>,
+        op: &str,
+        parents: &[&Tensor],
+        backward: impl Fn(&[f64], &[NodeRef]) + 'static,
+    ) -> Tensor {
+        let out = Tensor::from_shared(data, shape, op);
+        {
+            let mut out_mut = out.0.borrow_mut();
+            out_mut.op = Some(op.to_string());
+            out_mut.prev = parents.iter().map(|p| Rc::clone(&p.0)).collect();
+        }
+
+        let weak_out = Rc::downgrade(&out.0);
+        let weak_parents: Vec<_> = parents.iter().map(|p| Rc::downgrade(&p.0)).collect();
+
+        out.0.borrow_mut().backward = Some(Rc::new(move || {
+            if let Some(out_rc) = weak_out.upgrade() {
+                let out_grad = out_rc.borrow().grad.clone();
+                let parents: Option<Vec<NodeRef>> = weak_parents.iter().map(|w| w.upgrade()).collect();
+                if let Some(parents) = parents {
+                    backward(&out_grad, &parents);
+                }
+            }
+        }));
+        out
+    }
+
+    // Parents before children, with an explicit stack like `GraphNode::topological_sort_many`,
+    // so deep graphs cannot overflow the call stack
+    fn topological_sort(&self) -> Vec<NodeRef> {
+        let mut topo: Vec<NodeRef> = Vec::new();
+        let mut visited: HashSet<usize> = HashSet::new();
+        let mut stack: Vec<(NodeRef, usize)> = vec![(self.0.clone(), 0)];
+        visited.insert(Rc::as_ptr(&self.0) as usize);
+
+        while let Some((node_rc, next)) = stack.last_mut() {
+            let parent = node_rc.borrow().prev.get(*next).cloned();
+            *next += 1;
+            match parent {
+                Some(p) => {
+                    if visited.insert(Rc::as_ptr(&p) as usize) {
+                        stack.push((p, 0));
+                    }
+                }
+                None => {
+                    let (node_rc, _) = stack.pop().unwrap();
+                    topo.push(node_rc);
+                }
+            }
+        }
+        topo
+    }
+
+    /// Backpropagates from `self`, seeding every element's gradient with 1.0
+    /// (i.e. differentiating the sum of the elements).
+    pub fn backward(&self) {
+        self.0.borrow_mut().grad.iter_mut().for_each(|g| *g = 0.0);
+        self.backward_delta(&vec![1.0; self.numel()], true);
+    }
+
+    /// Backpropagates from `self` seeded with `seed`, after zeroing every gradient in its graph,
+    /// so the same graph can be differentiated again.
+    #[cfg(feature = "nn")]
+    pub(crate) fn backward_with(&self, seed: &[f64]) {
+        for node in self.topological_sort() {
+            node.borrow_mut().grad.iter_mut().for_each(|g| *g = 0.0);
+        }
+        self.backward_delta(seed, true);
+    }
+
+    // Backpropagates the extra gradient `seed` from `self`, adding what this pass contributes to
+    // the gradients already in the graph instead of running the closures over the totals again,
+    // so a graph reached through several bridges is counted once per path. The share reaching
+    // `from_values` nodes is handed on to their Values with `into_values`; otherwise it is
+    // returned, in the order of `packed_values`, for the caller to pass on.
+    fn backward_delta(&self, seed: &[f64], into_values: bool) -> Vec<f64> {
+        let topo = self.topological_sort();
+        let saved: Vec<Vec<f64>> = topo
+            .iter()
+            .map(|n| {
+                let mut n = n.borrow_mut();
+                let zeros = vec![0.0; n.grad.len()];
+                std::mem::replace(&mut n.grad, zeros)
+            })
+            .collect();
+        accumulate(&self.0, seed);
+        for node in topo.iter().rev() {
+            let cb = node.borrow().backward.clone();
+            if let Some(cb) = cb {
+                (cb)();
+            }
+            let (values, grad) = {
+                let n = node.borrow();
+                (n.values.clone(), n.grad.clone())
+            };
+            if into_values && !values.is_empty() {
+                Value::propagate_delta(&values, &grad);
+            }
+        }
+        let bridged = topo.iter().flat_map(|n| {
+            let n = n.borrow();
+            if n.values.is_empty() { vec![] } else { n.grad.clone() }
+        });
+        let bridged = if into_values { vec![] } else { bridged.collect() };
+        for (node, saved) in topo.iter().zip(saved) {
+            node.borrow_mut().grad.iter_mut().zip(saved).for_each(|(g, s)| *g += s);
+        }
+        bridged
+    }
+
+    // The Values packed by every `from_values` node in the graph behind `self`, in topo order
+    fn packed_values(&self) -> Vec<Value> {
+        self.topological_sort().iter().flat_map(|n| n.borrow().values.clone()).collect()
+    }
+
+    /// Packs scalar Values into a 1-D tensor. Gradients reaching the tensor are handed back to the
+    /// Values and carried on through their own graph.
+    pub fn from_values(values: &[Value]) -> Tensor {
+        let data: Vec<f64> = values.iter().map(|v| v.data()).collect();
+        let out = Tensor::from_shared(Rc::new(data), vec![values.len()], "from_values");
+        {
+            let mut node = out.0.borrow_mut();
+            node.op = Some("from_values".to_string());
+            node.values = values.to_vec();
+        }
+        out
+    }
+
+    /// Splits the tensor into one scalar Value per element, in row-major order. Once backward over
+    /// the Values has collected all of their gradients, it continues through the tensor graph and
+    /// on to any Values packed into it with `from_values`.
+    pub fn unbind(&self) -> Vec<Value> {
+        let numel = self.numel();
+        let pending = Rc::new(RefCell::new(vec![0.0; numel]));
+
+        // Every element Value hangs off this node, so topo order runs it after all of them; its
+        // parents are the Values feeding the tensor graph, which get their gradients through it
+        let tensor = self.clone();
+        let collected = pending.clone();
+        let packed = self.packed_values();
+        let hub = Value::from_op(0.0, "unbind", &packed.iter().collect::<Vec<_>>(), move |_, _, _| {
+            let seed = std::mem::replace(&mut *collected.borrow_mut(), vec![0.0; numel]);
+            tensor.backward_delta(&seed, false)
+        });
+
+        self.data()
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let pending = pending.clone();
+                Value::from_op(x, "element", &[&hub], move |_, g, _| {
+                    pending.borrow_mut()[i] += g;
+                    vec![0.0]
+                })
+            })
+            .collect()
+    }
+
+    /// Matrix product of a `[m, k]` and a `[k, n]` tensor; panics on other shapes, see
+    /// `try_matmul`.
+    pub fn matmul(&self, other: &Tensor) -> Tensor {
+        self.try_matmul(other).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Shapes already checked by `try_matmul`
+    fn matmul_unchecked(&self, other: &Tensor, m: usize, k: usize, n: usize) -> Tensor {
+
+        let mut c = vec![0.0; m * n];
+        gemm(&self.shared_data(), &other.shared_data(), &mut c, m, k, n);
+
+        Tensor::from_op(Rc::new(c), vec![m, n], "matmul", &[self, other], move |dc, parents| {
+            let a = parents[0].borrow().data.clone();
+            let b = parents[1].borrow().data.clone();
+
+            // dA = dC·Bᵀ, dB = Aᵀ·dC
+            let mut da = vec![0.0; m * k];
+            gemm(dc, &transpose(&b, k, n), &mut da, m, n, k);
+            let mut db = vec![0.0; k * n];
+            gemm(&transpose(&a, m, k), dc, &mut db, k, m, n);
+
+            accumulate(&parents[0], &da);
+            accumulate(&parents[1], &db);
+        })
+    }
+
+    /// Same elements in a new shape. Shares the data buffer with `self` instead of copying it.
+    pub fn reshape(&self, shape: &[usize]) -> Tensor {
+        self.try_reshape(shape).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn reshape_unchecked(&self, shape: &[usize]) -> Tensor {
+        Tensor::from_op(self.shared_data(), shape.to_vec(), "reshape", &[self], |dout, parents| {
+            accumulate(&parents[0], dout);
+        })
+    }
+
+    /// Reshapes to a single dimension, sharing the data buffer.
+    pub fn flatten(&self) -> Tensor {
+        self.reshape(&[self.numel()])
+    }
+
+    /// Swaps dimensions `dim0` and `dim1`. Storage is always contiguous row-major, so unlike
+    /// `reshape` this copies the data (unless the swap is a no-op).
+    pub fn transpose(&self, dim0: usize, dim1: usize) -> Tensor {
+        let shape = self.shape();
+        assert!(dim0 < shape.len() && dim1 < shape.len(), "transpose dims out of range for shape {:?}", shape);
+        if dim0 == dim1 {
+            return self.reshape(&shape);
+        }
+
+        let mut out_shape = shape.clone();
+        out_shape.swap(dim0, dim1);
+        let perm = transpose_index(&shape, dim0, dim1);
+
+        let src = self.shared_data();
+        let out: Vec<f64> = perm.iter().map(|&i| src[i]).collect();
+        Tensor::from_op(Rc::new(out), out_shape, "transpose", &[self], move |dout, parents| {
+            let mut dx = vec![0.0; dout.len()];
+            for (g, &i) in dout.iter().zip(&perm) {
+                dx[i] += g;
+            }
+            accumulate(&parents[0], &dx);
+        })
+    }
+
+    /// Copy of the elements `start..end` along `dim`.
+    pub fn slice(&self, dim: usize, start: usize, end: usize) -> Tensor {
+        let size = self.dim_size(dim);
+        assert!(start <= end && end <= size, "slice {}..{} out of bounds for dim of size {}", start, end, size);
+        self.gather(dim, (start..end).collect(), true, "slice")
+    }
+
+    /// Element `index` along `dim`, with that dimension removed.
+    pub fn select(&self, dim: usize, index: usize) -> Tensor {
+        let size = self.dim_size(dim);
+        assert!(index < size, "select index {} out of bounds for dim of size {}", index, size);
+        self.gather(dim, vec![index], false, "select")
+    }
+
+    /// Rows (entries along the first dimension) picked by `indices`, which may repeat.
+    pub fn index(&self, indices: &[usize]) -> Tensor {
+        let size = self.dim_size(0);
+        assert!(indices.iter().all(|&i| i < size), "index out of bounds for dim of size {}", size);
+        self.gather(0, indices.to_vec(), true, "index")
+    }
+
+    fn dim_size(&self, dim: usize) -> usize {
+        let shape = self.shape();
+        assert!(dim < shape.len(), "dim {} out of range for shape {:?}", dim, shape);
+        shape[dim]
+    }
+
+    // Picks `indices` along `dim`; the backward scatters the gradient back to those positions
+    fn gather(&self, dim: usize, indices: Vec<usize>, keep_dim: bool, op: &str) -> Tensor {
+        let shape = self.shape();
+        let outer: usize = shape[..dim].iter().product();
+        let size = shape[dim];
+        let inner: usize = shape[dim + 1..].iter().product();
+
+        let src = self.shared_data();
+        let mut out = Vec::with_capacity(outer * indices.len() * inner);
+        for o in 0..outer {
+            for &i in &indices {
+                let start = (o * size + i) * inner;
+                out.extend_from_slice(&src[start..start + inner]);
+            }
+        }
+
+        let mut out_shape = shape.clone();
+        if keep_dim {
+            out_shape[dim] = indices.len();
+        } else {
+            out_shape.remove(dim);
+        }
+
+        let numel = src.len();
+        Tensor::from_op(Rc::new(out), out_shape, op, &[self], move |dout, parents| {
+            let mut dx = vec![0.0; numel];
+            let mut chunks = dout.chunks(inner.max(1));
+            for o in 0..outer {
+                for &i in &indices {
+                    let start = (o * size + i) * inner;
+                    if let Some(chunk) = chunks.next() {
+                        for (d, g) in dx[start..start + inner].iter_mut().zip(chunk) {
+                            *d += g;
+                        }
+                    }
+                }
+            }
+            accumulate(&parents[0], &dx);
+        })
+    }
+
+    /// Concatenates `tensors` along `dim`; all other dimensions must match.
+    pub fn cat(tensors: &[Tensor], dim: usize) -> Tensor {
+        assert!(!tensors.is_empty(), "cat of an empty list of tensors");
+        let first = tensors[0].shape();
+        assert!(dim < first.len(), "dim {} out of range for shape {:?}", dim, first);
+        for t in tensors {
+            let shape = t.shape();
+            assert!(
+                shape.len() == first.len() && shape.iter().zip(&first).enumerate().all(|(d, (a, b))| d == dim || a == b),
+                "cannot cat shapes {:?} and {:?} along dim {}", first, shape, dim
+            );
+        }
+
+        let sizes: Vec<usize> = tensors.iter().map(|t| t.shape()[dim]).collect();
+        let mut out_shape = first.clone();
+        out_shape[dim] = sizes.iter().sum();
+        let outer = first[..dim].iter().product();
+        let inner = first[dim + 1..].iter().product();
+        Tensor::join(tensors, outer, sizes, inner, out_shape, "cat")
+    }
+
+    /// Stacks same-shaped `tensors` along a new dimension inserted at `dim`.
+    pub fn stack(tensors: &[Tensor], dim: usize) -> Tensor {
+        assert!(!tensors.is_empty(), "stack of an empty list of tensors");
+        let first = tensors[0].shape();
+        assert!(dim <= first.len(), "dim {} out of range for stacking shape {:?}", dim, first);
+        assert!(tensors.iter().all(|t| t.shape() == first), "stack needs tensors of the same shape");
+
+        let mut out_shape = first.clone();
+        out_shape.insert(dim, tensors.len());
+        let outer = first[..dim].iter().product();
+        let inner = first[dim..].iter().product();
+        Tensor::join(tensors, outer, vec![1; tensors.len()], inner, out_shape, "stack")
+    }
+
+    // Interleaves blocks of `sizes[t] * inner` elements from each tensor, `outer` times over;
+    // the backward splits the gradient back along the same blocks
+    fn join(tensors: &[Tensor], outer: usize, sizes: Vec<usize>, inner: usize, out_shape: Vec<usize>, op: &str) -> Tensor {
+        let datas: Vec<Rc<Vec<f64>>> = tensors.iter().map(|t| t.shared_data()).collect();
+        let mut out = Vec::with_capacity(out_shape.iter().product());
+        for o in 0..outer {
+            for (data, size) in datas.iter().zip(&sizes) {
+                let block = size * inner;
+                out.extend_from_slice(&data[o * block..(o + 1) * block]);
+            }
+        }
+
+        let parents: Vec<&Tensor> = tensors.iter().collect();
+        Tensor::from_op(Rc::new(out), out_shape, op, &parents, move |dout, parents| {
+            let mut grads: Vec<Vec<f64>> = sizes.iter().map(|s| Vec::with_capacity(outer * s * inner)).collect();
+            let mut offset = 0;
+            for _ in 0..outer {
+                for (grad, size) in grads.iter_mut().zip(&sizes) {
+                    let block = size * inner;
+                    grad.extend_from_slice(&dout[offset..offset + block]);
+                    offset += block;
+                }
+            }
+            for (parent, grad) in parents.iter().zip(&grads) {
+                accumulate(parent, grad);
+            }
+        })
+    }
+
+    /// Sum along `dim`, which is removed from the shape.
+    pub fn sum(&self, dim: usize) -> Tensor {
+        self.reduce(dim, "sum", 1.0, |xs| (xs.iter().sum(), None))
+    }
+
+    /// Mean along `dim`, which is removed from the shape.
+    pub fn mean(&self, dim: usize) -> Tensor {
+        self.reduce(dim, "mean", 1.0 / self.dim_size(dim) as f64, |xs| (xs.iter().sum::<f64>() / xs.len() as f64, None))
+    }
+
+    /// Maximum along `dim`, which is removed from the shape. The gradient goes to the first
+    /// maximal element only.
+    pub fn max(&self, dim: usize) -> Tensor {
+        self.reduce(dim, "max", 1.0, |xs| {
+            let (i, m) = xs.iter().enumerate().fold((0, f64::NEG_INFINITY), |(bi, bm), (i, &x)| {
+                if x > bm { (i, x) } else { (bi, bm) }
+            });
+            (m, Some(i))
+        })
+    }
+
+    /// Sum of every element, as a 0-dimensional tensor.
+    pub fn sum_all(&self) -> Tensor {
+        self.flatten().sum(0)
+    }
+
+    /// Mean of every element, as a 0-dimensional tensor.
+    pub fn mean_all(&self) -> Tensor {
+        self.flatten().mean(0)
+    }
+
+    // Reduces each fibre along `dim` with `f`, which returns the result and, for selecting
+    // reductions like max, the position inside the fibre that gets the whole gradient. Otherwise
+    // the gradient is spread over the fibre, scaled by `grad_scale`
+    fn reduce(&self, dim: usize, op: &str, grad_scale: f64, f: impl Fn(&[f64]) -> (f64, Option<usize>)) -> Tensor {
+        let shape = self.shape();
+        let size = self.dim_size(dim);
+        let outer: usize = shape[..dim].iter().product();
+        let inner: usize = shape[dim + 1..].iter().product();
+
+        let src = self.shared_data();
+        let mut out = Vec::with_capacity(outer * inner);
+        let mut picks = Vec::with_capacity(outer * inner);
+        let mut fibre = vec![0.0; size];
+        for o in 0..outer {
+            for i in 0..inner {
+                for (d, x) in fibre.iter_mut().enumerate() {
+                    *x = src[(o * size + d) * inner + i];
+                }
+                let (value, pick) = f(&fibre);
+                out.push(value);
+                picks.push(pick);
+            }
+        }
+
+        let mut out_shape = shape;
+        out_shape.remove(dim);
+        let numel = src.len();
+        Tensor::from_op(Rc::new(out), out_shape, op, &[self], move |dout, parents| {
+            let mut dx = vec![0.0; numel];
+            for o in 0..outer {
+                for i in 0..inner {
+                    let g = dout[o * inner + i];
+                    match picks[o * inner + i] {
+                        Some(d) => dx[(o * size + d) * inner + i] += g,
+                        None => {
+                            let g = g * grad_scale;
+                            for d in 0..size {
+                                dx[(o * size + d) * inner + i] += g;
+                            }
+                        }
+                    }
+                }
+            }
+            accumulate(&parents[0], &dx);
+        })
+    }
+
+    pub fn tanh(&self) -> Tensor {
+        let out: Vec<f64> = self.shared_data().iter().map(|&x| crate::math::tanh(x)).collect();
+        let out = Rc::new(out);
+        let y = out.clone();
+        Tensor::from_op(out, self.shape(), "tanh", &[self], move |dout, parents| {
+            let dx: Vec<f64> = y.iter().zip(dout).map(|(y, g)| (1.0 - y * y) * g).collect();
+            accumulate(&parents[0], &dx);
+        })
+    }
+}
+
+fn accumulate(node: &NodeRef, grad: &[f64]) {
+    let mut n = node.borrow_mut();
+    for (g, d) in n.grad.iter_mut().zip(grad) {
+        *g += d;
+    }
+}
+
+// For each element of the transposed tensor, the flat index it comes from in the original
+fn transpose_index(shape: &[usize], dim0: usize, dim1: usize) -> Vec<usize> {
+    let strides: Vec<usize> = (0..shape.len()).map(|d| shape[d + 1..].iter().product()).collect();
+    let mut out_shape = shape.to_vec();
+    out_shape.swap(dim0, dim1);
+    let mut src_strides = strides.clone();
+    src_strides.swap(dim0, dim1);
+
+    let numel: usize = shape.iter().product();
+    let mut index = vec![0; out_shape.len()];
+    let mut perm = Vec::with_capacity(numel);
+    for _ in 0..numel {
+        perm.push(index.iter().zip(&src_strides).map(|(i, s)| i * s).sum());
+        // odometer increment over out_shape
+        for d in (0..out_shape.len()).rev() {
+            index[d] += 1;
+            if index[d] < out_shape[d] { break; }
+            index[d] = 0;
+        }
+    }
+    perm
+}
+
+fn transpose(x: &[f64], rows: usize, cols: usize) -> Vec<f64> {
+    let mut t = vec![0.0; x.len()];
+    for i in 0..rows {
+        for j in 0..cols {
+            t[j * rows + i] = x[i * cols + j];
+        }
+    }
+    t
+}
+
+// Edge length of the square tiles the kernel works on, sized so three tiles fit in L1/L2
+const BLOCK: usize = 64;
+
+/// `c += a·b` for row-major `a: [m, k]`, `b: [k, n]`, `c: [m, n]`.
+#[cfg(not(feature = "blas"))]
+fn gemm(a: &[f64], b: &[f64], c: &mut [f64], m: usize, k: usize, n: usize) {
+    gemm_blocked(a, b, c, m, k, n);
+}
+
+#[cfg(feature = "blas")]
+fn gemm(a: &[f64], b: &[f64], c: &mut [f64], m: usize, k: usize, n: usize) {
+    assert!(a.len() >= m * k && b.len() >= k * n && c.len() >= m * n);
+    // SAFETY: the asserts above keep every strided access of dgemm inside the three slices
+    unsafe {
+        matrixmultiply::dgemm(
+            m, k, n,
+            1.0,
+            a.as_ptr(), k as isize, 1,
+            b.as_ptr(), n as isize, 1,
+            1.0,
+            c.as_mut_ptr(), n as isize, 1,
+        );
+    }
+}
+
+/// Pure-Rust fallback for `gemm`, tiled for cache reuse.
+#[cfg_attr(feature = "blas", allow(dead_code))]
+fn gemm_blocked(a: &[f64], b: &[f64], c: &mut [f64], m: usize, k: usize, n: usize) {
+    for ii in (0..m).step_by(BLOCK) {
+        for pp in (0..k).step_by(BLOCK) {
+            for jj in (0..n).step_by(BLOCK) {
+                for i in ii..(ii + BLOCK).min(m) {
+                    let c_row = &mut c[i * n..(i + 1) * n];
+                    for p in pp..(pp + BLOCK).min(k) {
+                        let a_ip = a[i * k + p];
+                        let b_row = &b[p * n..(p + 1) * n];
+                        for j in jj..(jj + BLOCK).min(n) {
+                            c_row[j] += a_ip * b_row[j];
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Elementwise sum. The right-hand side may also be a single row (`[n]` or `[1, n]`) that is
+/// broadcast over every row of a `[m, n]` left-hand side, e.g. a bias.
+impl Add for Tensor {
+    type Output = Tensor;
+
+    fn add(self, other: Tensor) -> Tensor {
+        self.try_add(&other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Tensor {
+    fn add_unchecked(&self, other: &Tensor) -> Tensor {
+        let a_shape = self.shape();
+        let (a, b) = (self.shared_data(), other.shared_data());
+        let row = b.len();
+        let out: Vec<f64> = a.iter().enumerate().map(|(i, x)| x + b[i % row]).collect();
+        Tensor::from_op(Rc::new(out), a_shape, "+", &[self, other], move |dout, parents| {
+            accumulate(&parents[0], dout);
+            let mut db = vec![0.0; row];
+            for (i, g) in dout.iter().enumerate() {
+                db[i % row] += g;
+            }
+            accumulate(&parents[1], &db);
+        })
+    }
+}
+
+/// Elementwise product of two tensors of the same shape.
+impl Mul for Tensor {
+    type Output = Tensor;
+
+    fn mul(self, other: Tensor) -> Tensor {
+        self.try_mul(&other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Tensor {
+    fn mul_unchecked(&self, other: &Tensor) -> Tensor {
+        let (a, b) = (self.shared_data(), other.shared_data());
+        let out: Vec<f64> = a.iter().zip(b.iter()).map(|(x, y)| x * y).collect();
+        Tensor::from_op(Rc::new(out), self.shape(), "*", &[self, other], move |dout, parents| {
+            let da: Vec<f64> = b.iter().zip(dout).map(|(y, g)| y * g).collect();
+            let db: Vec<f64> = a.iter().zip(dout).map(|(x, g)| x * g).collect();
+            accumulate(&parents[0], &da);
+            accumulate(&parents[1], &db);
+        })
+    }
+}
+
+impl fmt::Debug for Tensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let node = self.0.borrow();
+        f.debug_struct("Tensor")
+            .field("label", &node.label)
+            .field("shape", &node.shape)
+            .field("op", &node.op)
+            .field("data", &node.data)
+            .field("grad", &node.grad)
+            .finish()
+    }
+}
+
+/// Fallible versions of the shape-checked operations, for callers that get shapes from
+/// untrusted input. Nothing in this block may panic.
+#[deny(clippy::panic, clippy::unwrap_used, clippy::expect_used, clippy::indexing_slicing)]
+impl Tensor {
+    pub fn try_new(data: Vec<f64>, shape: &[usize]) -> Result<Self, ShapeError> {
+        if data.len() != shape.iter().product::<usize>() {
+            return Err(ShapeError { op: "fit data into", left: vec![data.len()], right: shape.to_vec() });
+        }
+        Ok(Tensor::from_shared(Rc::new(data), shape.to_vec(), ""))
+    }
+
+    pub fn try_matmul(&self, other: &Tensor) -> Result<Tensor, ShapeError> {
+        match (self.shape().as_slice(), other.shape().as_slice()) {
+            (&[m, k], &[k2, n]) if k == k2 => Ok(self.matmul_unchecked(other, m, k, n)),
+            (a, b) => Err(ShapeError { op: "matmul", left: a.to_vec(), right: b.to_vec() }),
+        }
+    }
+
+    pub fn try_reshape(&self, shape: &[usize]) -> Result<Tensor, ShapeError> {
+        if shape.iter().product::<usize>() != self.numel() {
+            return Err(ShapeError { op: "reshape", left: self.shape(), right: shape.to_vec() });
+        }
+        Ok(self.reshape_unchecked(shape))
+    }
+
+    /// Elementwise sum, with the row broadcasting of `+`.
+    pub fn try_add(&self, other: &Tensor) -> Result<Tensor, ShapeError> {
+        let (a_shape, b_shape) = (self.shape(), other.shape());
+        let (a, row) = (self.numel(), other.numel());
+        let broadcast = row > 0
+            && a % row == 0
+            && a_shape.last() == b_shape.last()
+            && b_shape.iter().rev().skip(1).all(|&d| d == 1);
+        if a_shape != b_shape && !broadcast {
+            return Err(ShapeError { op: "add", left: a_shape, right: b_shape });
+        }
+        Ok(self.add_unchecked(other))
+    }
+
+    /// Elementwise product of two tensors of the same shape.
+    pub fn try_mul(&self, other: &Tensor) -> Result<Tensor, ShapeError> {
+        if self.shape() != other.shape() {
+            return Err(ShapeError { op: "multiply", left: self.shape(), right: other.shape() });
+        }
+        Ok(self.mul_unchecked(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::Value;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    fn naive(a: &[f64], b: &[f64], m: usize, k: usize, n: usize) -> Vec<f64> {
+        let mut c = vec![0.0; m * n];
+        for i in 0..m {
+            for j in 0..n {
+                c[i * n + j] = (0..k).map(|p| a[i * k + p] * b[p * n + j]).sum();
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn blocked_kernel_matches_naive() {
+        let mut rng = StdRng::seed_from_u64(3);
+        // sizes that straddle the tile boundaries
+        let (m, k, n) = (70, 130, 65);
+        let mut uniform = |len: usize| (0..len).map(|_| rng.gen_range(-1.0..1.0)).collect::<Vec<f64>>();
+        let a = Tensor::new(uniform(m * k), &[m, k]);
+        let b = Tensor::new(uniform(k * n), &[k, n]);
+        let c = a.matmul(&b);
+        assert_eq!(c.shape(), vec![m, n]);
+        let expected = naive(&a.data(), &b.data(), m, k, n);
+        for (x, y) in c.data().iter().zip(&expected) {
+            assert!((x - y).abs() < 1e-9);
+        }
+
+        // whichever backend `gemm` uses must agree with the fallback kernel
+        let mut blocked = vec![0.0; m * n];
+        gemm_blocked(&a.data(), &b.data(), &mut blocked, m, k, n);
+        for (x, y) in c.data().iter().zip(&blocked) {
+            assert!((x - y).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn matmul_backward_matches_scalar_graph() {
+        let a = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        let b = Tensor::new(vec![0.5, -1.0, 2.0, 0.0, -0.5, 1.5], &[3, 2]);
+        let bias = Tensor::new(vec![0.1, -0.2], &[2]);
+        let out = (a.matmul(&b) + bias.clone()).tanh();
+        out.backward();
+
+        // Same computation with scalar Values
+        let av: Vec<Value> = a.data().into_iter().map(Value::from).collect();
+        let bv: Vec<Value> = b.data().into_iter().map(Value::from).collect();
+        let biasv: Vec<Value> = bias.data().into_iter().map(Value::from).collect();
+        let mut total = Value::from(0.0);
+        for i in 0..2 {
+            for j in 0..2 {
+                let dot = (0..3).fold(biasv[j].clone(), |acc, p| acc + av[i * 3 + p].clone() * bv[p * 2 + j].clone());
+                total = total + dot.tanh();
+            }
+        }
+        total.backward();
+
+        let close = |t: Vec<f64>, v: &[Value]| t.iter().zip(v).all(|(x, y)| (x - y.grad()).abs() < 1e-12);
+        assert!(close(a.grad(), &av));
+        assert!(close(b.grad(), &bv));
+        assert!(close(bias.grad(), &biasv));
+    }
+
+    #[test]
+    fn slice_select_index_scatter_gradients() {
+        // [[0, 1, 2], [3, 4, 5]]
+        let x = Tensor::new((0..6).map(|i| i as f64).collect(), &[2, 3]);
+
+        let s = x.slice(1, 1, 3);
+        assert_eq!(s.shape(), vec![2, 2]);
+        assert_eq!(s.data(), vec![1.0, 2.0, 4.0, 5.0]);
+        s.backward();
+        assert_eq!(x.grad(), vec![0.0, 1.0, 1.0, 0.0, 1.0, 1.0]);
+        x.zero_grad();
+
+        let col = x.select(1, 2);
+        assert_eq!(col.shape(), vec![2]);
+        assert_eq!(col.data(), vec![2.0, 5.0]);
+        col.backward();
+        assert_eq!(x.grad(), vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+        x.zero_grad();
+
+        let rows = x.index(&[1, 1, 0]);
+        assert_eq!(rows.shape(), vec![3, 3]);
+        assert_eq!(rows.data()[..3], [3.0, 4.0, 5.0]);
+        rows.backward();
+        assert_eq!(x.grad(), vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn select_out_of_bounds_panics() {
+        Tensor::zeros(&[2, 2]).select(0, 2);
+    }
+
+    #[test]
+    fn deep_chains_do_not_overflow_the_stack() {
+        let x = Tensor::new(vec![1.0, 2.0], &[2]);
+        let one = Tensor::new(vec![1.0, 1.0], &[2]);
+        let mut y = x.clone();
+        for _ in 0..200_000 {
+            y = y * one.clone();
+        }
+        y.backward();
+        assert_eq!(x.grad(), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn cat_and_stack_split_gradients() {
+        let a = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]);
+        let b = Tensor::new(vec![5.0, 6.0], &[2, 1]);
+
+        let c = Tensor::cat(&[a.clone(), b.clone()], 1);
+        assert_eq!(c.shape(), vec![2, 3]);
+        assert_eq!(c.data(), vec![1.0, 2.0, 5.0, 3.0, 4.0, 6.0]);
+
+        let w = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3]);
+        (c * w).backward();
+        assert_eq!(a.grad(), vec![1.0, 2.0, 4.0, 5.0]);
+        assert_eq!(b.grad(), vec![3.0, 6.0]);
+
+        let x = Tensor::new(vec![1.0, 2.0], &[2]);
+        let y = Tensor::new(vec![3.0, 4.0], &[2]);
+        let rows = Tensor::stack(&[x.clone(), y.clone()], 0);
+        assert_eq!(rows.shape(), vec![2, 2]);
+        assert_eq!(rows.data(), vec![1.0, 2.0, 3.0, 4.0]);
+        let cols = Tensor::stack(&[x.clone(), y.clone()], 1);
+        assert_eq!(cols.shape(), vec![2, 2]);
+        assert_eq!(cols.data(), vec![1.0, 3.0, 2.0, 4.0]);
+
+        let w = Tensor::new(vec![10.0, 20.0, 30.0, 40.0], &[2, 2]);
+        (cols * w).backward();
+        assert_eq!(x.grad(), vec![10.0, 30.0]);
+        assert_eq!(y.grad(), vec![20.0, 40.0]);
+    }
+
+    #[test]
+    fn reshape_shares_storage_and_transpose_maps_gradients() {
+        let x = Tensor::new((0..6).map(|i| i as f64).collect(), &[2, 3]);
+        let r = x.reshape(&[3, 2]);
+        assert!(Rc::ptr_eq(&r.shared_data(), &x.shared_data()));
+        assert_eq!(x.flatten().shape(), vec![6]);
+
+        let t = x.transpose(0, 1);
+        assert_eq!(t.shape(), vec![3, 2]);
+        assert_eq!(t.data(), vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        assert_eq!(t.data(), transpose(&x.data(), 2, 3));
+
+        let w = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[3, 2]);
+        (t * w.clone() + r.clone() * w).backward();
+        // transpose routes w back into x's layout, reshape passes it through unchanged
+        assert_eq!(x.grad(), vec![2.0, 5.0, 8.0, 6.0, 9.0, 12.0]);
+
+        let cube = Tensor::new((0..24).map(|i| i as f64).collect(), &[2, 3, 4]);
+        let swapped = cube.transpose(0, 2);
+        assert_eq!(swapped.shape(), vec![4, 3, 2]);
+        // element [k, j, i] of the result is element [i, j, k] of the input
+        assert_eq!(swapped.data()[(3 * 3 + 2) * 2 + 1], cube.data()[(3 + 2) * 4 + 3]);
+    }
+
+    #[test]
+    fn reductions_broadcast_and_scatter_gradients() {
+        // [[1, 5, 3], [4, 2, 6]]
+        let x = Tensor::new(vec![1.0, 5.0, 3.0, 4.0, 2.0, 6.0], &[2, 3]);
+
+        let rows = x.sum(1);
+        assert_eq!(rows.shape(), vec![2]);
+        assert_eq!(rows.data(), vec![9.0, 12.0]);
+        assert_eq!(x.sum(0).data(), vec![5.0, 7.0, 9.0]);
+
+        let cols = x.mean(0);
+        assert_eq!(cols.data(), vec![2.5, 3.5, 4.5]);
+        cols.backward();
+        assert_eq!(x.grad(), vec![0.5; 6]);
+        x.zero_grad();
+
+        let m = x.max(1);
+        assert_eq!(m.data(), vec![5.0, 6.0]);
+        m.backward();
+        assert_eq!(x.grad(), vec![0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        x.zero_grad();
+
+        let total = x.mean_all();
+        assert!(total.shape().is_empty());
+        assert_eq!(total.data(), vec![3.5]);
+        total.backward();
+        assert_eq!(x.grad(), vec![1.0 / 6.0; 6]);
+        assert_eq!(x.sum_all().data(), vec![21.0]);
+    }
+
+    #[test]
+    fn gradients_cross_between_scalar_and_tensor_graphs() {
+        // scalar -> tensor -> scalar
+        let a = Value::new(0.5, "a");
+        let b = Value::new(-1.0, "b");
+        let inputs = vec![a.clone() * 2.0, b.clone() + a.clone()];
+        let t = Tensor::from_values(&inputs);
+        let w = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]);
+        let h = t.reshape(&[1, 2]).matmul(&w).tanh();
+
+        let outs = h.unbind();
+        assert_eq!(outs.len(), 2);
+        let loss = outs[0].clone() * 3.0 + outs[1].clone();
+        loss.backward();
+
+        // Same thing entirely in scalars
+        let (x0, x1) = (0.5 * 2.0, -1.0 + 0.5);
+        let h0 = (x0 * 1.0 + x1 * 3.0_f64).tanh();
+        let h1 = (x0 * 2.0 + x1 * 4.0_f64).tanh();
+        let d0 = 3.0 * (1.0 - h0 * h0);
+        let d1 = 1.0 - h1 * h1;
+        let dx0 = d0 * 1.0 + d1 * 2.0;
+        let dx1 = d0 * 3.0 + d1 * 4.0;
+
+        assert!((a.grad() - (2.0 * dx0 + dx1)).abs() < 1e-12);
+        assert!((b.grad() - dx1).abs() < 1e-12);
+        assert!((w.grad()[1] - x0 * d1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bridged_paths_count_each_gradient_once() {
+        // used directly and through from_values -> unbind
+        let x = Value::new(0.5, "x");
+        let y = x.clone() * 1.0;
+        let out = y.clone() + Tensor::from_values(std::slice::from_ref(&y)).unbind()[0].clone();
+        out.backward();
+        assert_eq!(x.grad(), 2.0);
+
+        // the same tensor unbound twice
+        let x = Value::new(0.5, "x");
+        let t = Tensor::from_values(&[x.clone() * 1.0]).tanh();
+        let out = t.unbind()[0].clone() + t.unbind()[0].clone();
+        out.backward();
+        assert!((x.grad() - 2.0 * (1.0 - 0.5f64.tanh().powi(2))).abs() < 1e-12);
+
+        // from a tensor root, into a scalar graph that already holds gradients
+        let x = Value::new(0.5, "x");
+        let y = x.clone() * 3.0;
+        (y.clone() * 1.0).backward();
+        Tensor::from_values(&[y]).backward();
+        assert_eq!(x.grad(), 6.0);
+    }
+
+    #[test]
+    fn elementwise_mul() {
+        let a = Tensor::new(vec![1.0, 2.0, 3.0], &[3]);
+        let b = Tensor::new(vec![4.0, 5.0, 6.0], &[3]);
+        let c = a.clone() * b.clone();
+        assert_eq!(c.data(), vec![4.0, 10.0, 18.0]);
+        c.backward();
+        assert_eq!(a.grad(), b.data());
+        assert_eq!(b.grad(), a.data());
+    }
+
+    #[test]
+    fn fallible_shape_checks() {
+        let a = Tensor::new(vec![1.0; 6], &[2, 3]);
+        let err = a.try_matmul(&a).unwrap_err();
+        assert_eq!(err, ShapeError { op: "matmul", left: vec![2, 3], right: vec![2, 3] });
+        assert_eq!(err.to_string(), "cannot matmul shapes [2, 3] and [2, 3]");
+        assert!(a.try_reshape(&[4]).is_err());
+        assert!(a.try_mul(&Tensor::zeros(&[3])).is_err());
+        assert!(a.try_add(&Tensor::zeros(&[2])).is_err());
+        assert!(a.try_add(&Tensor::zeros(&[0])).is_err());
+        assert!(Tensor::try_new(vec![1.0; 5], &[2, 3]).is_err());
+
+        // the same checks pass the shapes the panicking versions accept
+        assert_eq!(a.try_add(&Tensor::zeros(&[1, 3])).unwrap().shape(), vec![2, 3]);
+        assert_eq!(a.try_matmul(&a.try_reshape(&[3, 2]).unwrap()).unwrap().data(), vec![3.0; 4]);
+    }
+}
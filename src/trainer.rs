@@ -0,0 +1,962 @@
+use crate::data::{Batch, DataLoader, Dataset};
+use crate::diagnostics::Histogram;
+use crate::loss::{log_softmax, per_output_mse};
+use crate::nn::{eval_mode, Module};
+use crate::operators::*;
+use crate::ops::kl_div;
+use crate::optim::Optimizer;
+use crate::scheduler::Schedule;
+use std::time::{Duration, Instant};
+
+/// Maps a model's outputs and the targets to a scalar loss.
+pub type LossFn = Box<dyn Fn(&[Value], &[Value]) -> Value>;
+
+/// What happened during `Trainer::fit`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    /// Mean batch loss of every epoch.
+    pub epoch_losses: Vec<f64>,
+    /// Mean squared error of each model output over every epoch, whatever the training loss is.
+    pub per_output_losses: Vec<Vec<f64>>,
+    /// Variance of the batch losses within every epoch: how noisy the mini-batch estimate of
+    /// the loss is.
+    pub batch_loss_variances: Vec<f64>,
+    /// Variance over an epoch's steps of each parameter's update, averaged over the parameters:
+    /// how much the steps disagree with each other.
+    pub update_variances: Vec<f64>,
+    /// Mean absolute gradient of each parameter group over every epoch, filled in by `GradFlow`.
+    pub grad_flow: Vec<Vec<f64>>,
+    /// Histogram of each parameter group at the end of every epoch, filled in by
+    /// `diagnostics::WeightHistograms`.
+    pub weight_histograms: Vec<Vec<Histogram>>,
+    /// Which criterion ended the run.
+    pub stopped_by: StopReason,
+}
+
+/// Why `Trainer::fit` stopped; the budgets are set with `Trainer::with_time_budget` and friends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StopReason {
+    /// All the requested epochs ran.
+    #[default]
+    Epochs,
+    /// The wall-clock budget ran out.
+    Time,
+    /// The step budget ran out.
+    Steps,
+    /// An epoch's mean loss reached the target.
+    TargetLoss,
+}
+
+/// Hooks into `Trainer`'s loop, added with `Trainer::with_callback`.
+pub trait Callback {
+    /// Called after every optimizer step, while the gradients of that step are still there.
+    fn on_step(&mut self, _model: &dyn Module) {}
+
+    /// Called at the end of every epoch of `fit`, with the history so far.
+    fn on_epoch_end(&mut self, _model: &dyn Module, _history: &mut History) {}
+}
+
+/// Records how much gradient reaches each parameter group (each layer of an `MLP`): the mean
+/// absolute gradient of its parameters, averaged over an epoch's steps, into
+/// `History::grad_flow`. Plot it with `viz::plot_grad_flow` to spot vanishing gradients.
+#[derive(Debug, Clone, Default)]
+pub struct GradFlow {
+    sums: Vec<f64>,
+    steps: usize,
+}
+
+impl GradFlow {
+    pub fn new() -> Self {
+        GradFlow::default()
+    }
+}
+
+impl Callback for GradFlow {
+    fn on_step(&mut self, model: &dyn Module) {
+        let groups = model.parameter_groups();
+        self.sums.resize(groups.len(), 0.0);
+        for (sum, (_, params)) in self.sums.iter_mut().zip(&groups) {
+            *sum += params.iter().map(|p| p.grad().abs()).sum::<f64>() / params.len().max(1) as f64;
+        }
+        self.steps += 1;
+    }
+
+    fn on_epoch_end(&mut self, _model: &dyn Module, history: &mut History) {
+        let steps = self.steps.max(1) as f64;
+        history.grad_flow.push(self.sums.iter().map(|s| s / steps).collect());
+        self.sums.iter_mut().for_each(|s| *s = 0.0);
+        self.steps = 0;
+    }
+}
+
+// Threads and per-thread model replicas of `Trainer::with_data_parallel`
+struct DataParallel<M> {
+    threads: usize,
+    replica: Box<dyn Fn() -> M + Sync>,
+    loss_fn: fn(&[Value], &[Value]) -> Value,
+}
+
+// A learning rate schedule and what it counts
+enum Scheduled {
+    Steps(Box<dyn Schedule>),
+    Epochs(Box<dyn Schedule>),
+}
+
+/// Glues a model, an optimizer and a loss together into a training loop.
+pub struct Trainer<M: Module, O: Optimizer> {
+    pub model: M,
+    pub optimizer: O,
+    loss_fn: LossFn,
+    base_lr: f64,
+    lr_decay: Option<f64>,
+    schedule: Option<Scheduled>,
+    steps: usize,
+    epochs: usize,
+    callbacks: Vec<Box<dyn Callback>>,
+    parallel: Option<DataParallel<M>>,
+    time_budget: Option<Duration>,
+    step_budget: Option<usize>,
+    target_loss: Option<f64>,
+}
+
+impl<M: Module, O: Optimizer> Trainer<M, O> {
+    pub fn new(model: M, optimizer: O, loss_fn: impl Fn(&[Value], &[Value]) -> Value + 'static) -> Self {
+        let base_lr = optimizer.lr();
+        Trainer {
+            model,
+            optimizer,
+            loss_fn: Box::new(loss_fn),
+            base_lr,
+            lr_decay: None,
+            schedule: None,
+            steps: 0,
+            epochs: 0,
+            callbacks: vec![],
+            parallel: None,
+            time_budget: None,
+            step_budget: None,
+            target_loss: None,
+        }
+    }
+
+    /// Stops each `fit` once it has run for `budget`, after the batch that used it up.
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Stops each `fit` after it has made `steps` optimizer updates.
+    pub fn with_step_budget(mut self, steps: usize) -> Self {
+        self.step_budget = Some(steps);
+        self
+    }
+
+    /// Stops `fit` after the first epoch whose mean loss is at most `loss`.
+    pub fn with_target_loss(mut self, loss: f64) -> Self {
+        self.target_loss = Some(loss);
+        self
+    }
+
+    /// Trains every mini-batch data-parallel: the batch is split into up to `threads` shards, each
+    /// thread builds its own graph on a model from `replica` loaded with the current parameter
+    /// values, and their gradients are averaged into one optimizer step on `model`. Graphs cannot
+    /// cross threads, hence the replicas, which must have the same parameters in the same order;
+    /// `loss_fn` replaces the trainer's loss for batches and must be a plain function for the
+    /// same reason. `partial_fit` stays on the calling thread.
+    pub fn with_data_parallel(
+        mut self,
+        threads: usize,
+        replica: impl Fn() -> M + Sync + 'static,
+        loss_fn: fn(&[Value], &[Value]) -> Value,
+    ) -> Self {
+        assert!(threads > 0, "need at least one thread");
+        self.parallel = Some(DataParallel { threads, replica: Box::new(replica), loss_fn });
+        self
+    }
+
+    /// Decays the learning rate after every update as `lr / (1 + decay * steps)`. Replaces any
+    /// schedule.
+    pub fn with_lr_decay(mut self, decay: f64) -> Self {
+        self.lr_decay = Some(decay);
+        self.schedule = None;
+        self
+    }
+
+    /// Sets the learning rate to `schedule.lr(steps)` now and after every optimizer update, e.g.
+    /// `scheduler::CosineWarmRestarts` with a period in steps. Replaces any `with_lr_decay`.
+    pub fn with_schedule(mut self, schedule: impl Schedule + 'static) -> Self {
+        self.optimizer.set_lr(schedule.lr(self.steps));
+        self.schedule = Some(Scheduled::Steps(Box::new(schedule)));
+        self.lr_decay = None;
+        self
+    }
+
+    /// Like `with_schedule`, counting finished epochs of `fit` instead of steps.
+    pub fn with_epoch_schedule(mut self, schedule: impl Schedule + 'static) -> Self {
+        self.optimizer.set_lr(schedule.lr(self.epochs));
+        self.schedule = Some(Scheduled::Epochs(Box::new(schedule)));
+        self.lr_decay = None;
+        self
+    }
+
+    /// Adds a callback; callbacks run in the order they were added.
+    pub fn with_callback(mut self, callback: impl Callback + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Number of optimizer updates made so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Forward pass and loss for one sample, without touching gradients or parameters.
+    pub fn loss(&self, x: &[f64], y: &[f64]) -> Value {
+        self.forward_loss(x, y).1
+    }
+
+    // Model outputs and loss for one sample
+    fn forward_loss(&self, x: &[f64], y: &[f64]) -> (Vec<Value>, Value) {
+        let xs: Vec<Value> = x.iter().map(|v| Value::from(*v)).collect();
+        let ys: Vec<Value> = y.iter().map(|v| Value::from(*v)).collect();
+        let preds = self.model.forward(&xs);
+        assert_eq!(
+            preds.len(), ys.len(),
+            "model produces {} outputs but the target has {}", preds.len(), ys.len()
+        );
+        let loss = (self.loss_fn)(&preds, &ys);
+        (preds, loss)
+    }
+
+    /// Mean squared error of each model output over `dataset`, in `eval_mode`.
+    pub fn evaluate(&self, dataset: &Dataset) -> Vec<f64> {
+        let preds: Vec<Vec<f64>> = eval_mode(|| {
+            dataset
+                .inputs
+                .iter()
+                .map(|x| {
+                    let xs: Vec<Value> = x.iter().map(|v| Value::from(*v)).collect();
+                    self.model.forward(&xs).iter().map(|v| v.data()).collect()
+                })
+                .collect()
+        });
+        per_output_mse(&preds, &dataset.targets)
+    }
+
+    /// One online update from a single sample; returns the loss before the update.
+    pub fn partial_fit(&mut self, x: &[f64], y: &[f64]) -> f64 {
+        self.optimizer.zero_grad();
+        let loss = self.loss(x, y);
+        loss.backward();
+        self.optimizer.step();
+        self.after_step();
+        loss.data()
+    }
+
+    fn after_step(&mut self) {
+        for callback in &mut self.callbacks {
+            callback.on_step(&self.model);
+        }
+        self.model.constrain();
+        self.steps += 1;
+        if let Some(decay) = self.lr_decay {
+            self.optimizer.set_lr(self.base_lr / (1.0 + decay * self.steps as f64));
+        }
+        if let Some(Scheduled::Steps(schedule)) = &self.schedule {
+            self.optimizer.set_lr(schedule.lr(self.steps));
+        }
+    }
+
+    fn after_epoch(&mut self) {
+        self.epochs += 1;
+        if let Some(Scheduled::Epochs(schedule)) = &self.schedule {
+            self.optimizer.set_lr(schedule.lr(self.epochs));
+        }
+    }
+
+    /// One update from a mini-batch, using the mean of the per-sample losses; returns that mean.
+    pub fn train_batch(&mut self, batch: &Batch) -> f64 {
+        self.train_batch_with_outputs(batch).0
+    }
+
+    // Like `train_batch`, but also returns the per-output mean squared error of the batch
+    fn train_batch_with_outputs(&mut self, batch: &Batch) -> (f64, Vec<f64>) {
+        if self.parallel.is_some() {
+            return self.train_batch_parallel(batch);
+        }
+        self.optimizer.zero_grad();
+        let mut total = Value::from(0.0);
+        let mut preds = Vec::with_capacity(batch.len());
+        for (x, y) in batch.inputs.iter().zip(&batch.targets) {
+            let (p, loss) = self.forward_loss(x, y);
+            preds.push(p.iter().map(|v| v.data()).collect::<Vec<f64>>());
+            total = total + loss;
+        }
+        let loss = total / batch.len() as f64;
+        loss.backward();
+        self.optimizer.step();
+        self.after_step();
+        (loss.data(), per_output_mse(&preds, &batch.targets))
+    }
+
+    fn train_batch_parallel(&mut self, batch: &Batch) -> (f64, Vec<f64>) {
+        let parallel = self.parallel.as_ref().unwrap();
+        let params = self.model.parameters();
+        let values: Vec<f64> = params.iter().map(|p| p.data()).collect();
+        let shard_size = batch.len().div_ceil(parallel.threads).max(1);
+        let n = batch.len() as f64;
+
+        // per shard: its share of the mean loss, the gradient of that share and the predictions
+        let shards: Vec<(f64, Vec<f64>, Vec<Vec<f64>>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .inputs
+                .chunks(shard_size)
+                .zip(batch.targets.chunks(shard_size))
+                .map(|(inputs, targets)| {
+                    let values = &values;
+                    scope.spawn(move || {
+                        let model = (parallel.replica)();
+                        let params = model.parameters();
+                        assert_eq!(params.len(), values.len(), "replica has different parameters than the model");
+                        params.iter().zip(values).for_each(|(p, &v)| p.set_data(v));
+                        let mut total = Value::from(0.0);
+                        let mut preds = Vec::with_capacity(inputs.len());
+                        for (x, y) in inputs.iter().zip(targets) {
+                            let xs: Vec<Value> = x.iter().map(|v| Value::from(*v)).collect();
+                            let ys: Vec<Value> = y.iter().map(|v| Value::from(*v)).collect();
+                            let p = model.forward(&xs);
+                            assert_eq!(p.len(), ys.len(), "model produces {} outputs but the target has {}", p.len(), ys.len());
+                            total = total + (parallel.loss_fn)(&p, &ys);
+                            preds.push(p.iter().map(|v| v.data()).collect());
+                        }
+                        let loss = total / n;
+                        loss.backward_retain(false);
+                        (loss.data(), params.iter().map(|p| p.grad()).collect(), preds)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().expect("data-parallel worker panicked")).collect()
+        });
+
+        self.optimizer.zero_grad();
+        for (_, grads, _) in &shards {
+            params.iter().zip(grads).for_each(|(p, g)| p.set_grad(p.grad() + g));
+        }
+        self.optimizer.step();
+        self.after_step();
+        let loss = shards.iter().map(|(l, _, _)| l).sum();
+        let preds: Vec<Vec<f64>> = shards.into_iter().flat_map(|(_, _, p)| p).collect();
+        (loss, per_output_mse(&preds, &batch.targets))
+    }
+
+    /// Trains for `epochs` passes over the loader's batches, or less if a budget or the target
+    /// loss stops it first; `History::stopped_by` says which. An epoch cut short by a budget is
+    /// recorded over the batches it got through.
+    pub fn fit(&mut self, loader: &mut DataLoader, epochs: usize) -> History {
+        let mut history = History::default();
+        let (start, start_steps) = (Instant::now(), self.steps);
+        for _ in 0..epochs {
+            let mut stop = None;
+            let batches = loader.next_epoch();
+            let mut losses = Vec::with_capacity(batches.len());
+            let mut per_output: Vec<f64> = Vec::new();
+            let mut samples = 0;
+            // sum and sum of squares of every parameter's updates
+            let (mut update_sums, mut update_squares): (Vec<f64>, Vec<f64>) = (Vec::new(), Vec::new());
+            for batch in &batches {
+                let params = self.model.parameters();
+                let before: Vec<f64> = params.iter().map(|p| p.data()).collect();
+                let (loss, outputs) = self.train_batch_with_outputs(batch);
+                losses.push(loss);
+                update_sums.resize(params.len(), 0.0);
+                update_squares.resize(params.len(), 0.0);
+                for (i, (p, b)) in params.iter().zip(before).enumerate() {
+                    let delta = p.data() - b;
+                    update_sums[i] += delta;
+                    update_squares[i] += delta * delta;
+                }
+                // per-batch means, weighted back into an epoch mean
+                per_output.resize(outputs.len(), 0.0);
+                for (acc, o) in per_output.iter_mut().zip(outputs) {
+                    *acc += o * batch.len() as f64;
+                }
+                samples += batch.len();
+
+                if self.time_budget.is_some_and(|budget| start.elapsed() >= budget) {
+                    stop = Some(StopReason::Time);
+                } else if self.step_budget.is_some_and(|budget| self.steps - start_steps >= budget) {
+                    stop = Some(StopReason::Steps);
+                }
+                if stop.is_some() {
+                    break;
+                }
+            }
+            let epoch_loss = losses.iter().sum::<f64>() / losses.len().max(1) as f64;
+            history.epoch_losses.push(epoch_loss);
+            history.batch_loss_variances.push(variance(&losses));
+            let steps = losses.len().max(1) as f64;
+            let update_variance = update_sums
+                .iter()
+                .zip(&update_squares)
+                .map(|(s, sq)| (sq / steps - (s / steps).powi(2)).max(0.0))
+                .sum::<f64>()
+                / update_sums.len().max(1) as f64;
+            history.update_variances.push(update_variance);
+            history.per_output_losses.push(per_output.iter().map(|o| o / samples.max(1) as f64).collect());
+            self.after_epoch();
+            for callback in &mut self.callbacks {
+                callback.on_epoch_end(&self.model, &mut history);
+            }
+            if stop.is_none() && self.target_loss.is_some_and(|target| epoch_loss <= target) {
+                stop = Some(StopReason::TargetLoss);
+            }
+            if let Some(reason) = stop {
+                history.stopped_by = reason;
+                break;
+            }
+        }
+        history
+    }
+}
+
+// Population variance, 0 for fewer than two values
+fn variance(xs: &[f64]) -> f64 {
+    let n = xs.len().max(1) as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    xs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n
+}
+
+/// What `TrainingSession::inspect` shows between steps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// Epochs completed.
+    pub epoch: usize,
+    /// Optimizer updates made so far, as `Trainer::steps`.
+    pub steps: usize,
+    /// Loss of the last step, `None` before the first.
+    pub loss: Option<f64>,
+    /// Gradients left by the last step, per parameter group.
+    pub grads: Vec<(String, Vec<f64>)>,
+    pub paused: bool,
+}
+
+type Breakpoint = Box<dyn FnMut(&Snapshot) -> bool>;
+
+/// Training driven one mini-batch at a time instead of by a blocking `fit`, for interactive
+/// frontends: `step` makes one update, `inspect` shows the loss and gradients it left, and
+/// `pause`/`resume` stop and restart `run`. Epochs roll over by themselves; the session's
+/// history gets `epoch_losses` and `batch_loss_variances` of every finished epoch, plus
+/// whatever the trainer's callbacks record.
+pub struct TrainingSession<M: Module, O: Optimizer> {
+    trainer: Trainer<M, O>,
+    loader: DataLoader,
+    // the rest of the current epoch
+    batches: std::vec::IntoIter<Batch>,
+    epoch: usize,
+    // batch losses of the current epoch
+    losses: Vec<f64>,
+    last_loss: Option<f64>,
+    paused: bool,
+    breakpoint: Option<Breakpoint>,
+    history: History,
+}
+
+impl<M: Module, O: Optimizer> TrainingSession<M, O> {
+    pub fn new(trainer: Trainer<M, O>, mut loader: DataLoader) -> Self {
+        let batches = loader.next_epoch();
+        assert!(!batches.is_empty(), "data loader has no batches");
+        TrainingSession {
+            trainer,
+            loader,
+            batches: batches.into_iter(),
+            epoch: 0,
+            losses: vec![],
+            last_loss: None,
+            paused: false,
+            breakpoint: None,
+            history: History::default(),
+        }
+    }
+
+    /// Pauses the session after any step whose snapshot `pause_when` returns true for, e.g.
+    /// once the loss drops below a target.
+    pub fn with_breakpoint(mut self, pause_when: impl FnMut(&Snapshot) -> bool + 'static) -> Self {
+        self.breakpoint = Some(Box::new(pause_when));
+        self
+    }
+
+    /// One update from the next mini-batch; returns its loss, or `None` without training while
+    /// paused.
+    pub fn step(&mut self) -> Option<f64> {
+        if self.paused {
+            return None;
+        }
+        let batch = self.batches.next().expect("an epoch is replaced as soon as it runs out");
+        let loss = self.trainer.train_batch(&batch);
+        self.losses.push(loss);
+        self.last_loss = Some(loss);
+        if self.batches.len() == 0 {
+            self.end_epoch();
+        }
+        if self.breakpoint.is_some() {
+            let snapshot = self.inspect();
+            self.paused = self.breakpoint.as_mut().is_some_and(|pause_when| pause_when(&snapshot));
+        }
+        Some(loss)
+    }
+
+    fn end_epoch(&mut self) {
+        let losses = std::mem::take(&mut self.losses);
+        self.history.epoch_losses.push(losses.iter().sum::<f64>() / losses.len() as f64);
+        self.history.batch_loss_variances.push(variance(&losses));
+        self.trainer.after_epoch();
+        for callback in &mut self.trainer.callbacks {
+            callback.on_epoch_end(&self.trainer.model, &mut self.history);
+        }
+        self.epoch += 1;
+        self.batches = self.loader.next_epoch().into_iter();
+    }
+
+    /// Steps until `max_steps` updates are made or the session is paused; returns the number
+    /// made.
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        (0..max_steps).take_while(|_| self.step().is_some()).count()
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn inspect(&self) -> Snapshot {
+        Snapshot {
+            epoch: self.epoch,
+            steps: self.trainer.steps(),
+            loss: self.last_loss,
+            grads: self
+                .trainer
+                .model
+                .parameter_groups()
+                .into_iter()
+                .map(|(name, params)| (name, params.iter().map(|p| p.grad()).collect()))
+                .collect(),
+            paused: self.paused,
+        }
+    }
+
+    pub fn trainer(&self) -> &Trainer<M, O> {
+        &self.trainer
+    }
+
+    /// Epochs finished so far.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Ends the session, handing back the trainer and the history.
+    pub fn into_parts(self) -> (Trainer<M, O>, History) {
+        (self.trainer, self.history)
+    }
+}
+
+/// Softmax of `teacher`'s outputs at `temperature` for every input, computed without a graph.
+pub fn soft_targets<T: Module>(teacher: &T, inputs: &[Vec<f64>], temperature: f64) -> Vec<Vec<f64>> {
+    no_grad(|| {
+        inputs
+            .iter()
+            .map(|x| {
+                let xs: Vec<Value> = x.iter().map(|v| Value::from(*v)).collect();
+                let logits: Vec<f64> = teacher.forward(&xs).iter().map(|v| v.data() / temperature).collect();
+                let max = logits.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let exps: Vec<f64> = logits.iter().map(|l| crate::math::exp(l - max)).collect();
+                let sum: f64 = exps.iter().sum();
+                exps.iter().map(|e| e / sum).collect()
+            })
+            .collect()
+    })
+}
+
+/// Trains `student` to match `teacher`'s soft outputs on `inputs`.
+///
+/// The loss is `T^2 * KL(teacher_T || student_T)`, where `_T` is a softmax of the outputs divided by
+/// `temperature`; the `T^2` keeps gradient sizes comparable across temperatures (Hinton et al.).
+/// Returns the trained student's `Trainer` and its `History`.
+pub fn distill<T: Module, M: Module, O: Optimizer>(
+    teacher: &T,
+    student: M,
+    optimizer: O,
+    inputs: &[Vec<f64>],
+    temperature: f64,
+    batch_size: usize,
+    epochs: usize,
+) -> (Trainer<M, O>, History) {
+    let targets = soft_targets(teacher, inputs, temperature);
+    let mut loader = DataLoader::new(Dataset::new(inputs.to_vec(), targets), batch_size);
+    let loss = move |preds: &[Value], soft: &[Value]| {
+        let scaled: Vec<Value> = preds.iter().map(|p| p.clone() / temperature).collect();
+        let q: Vec<Value> = log_softmax(&scaled).into_iter().map(Value::exp).collect();
+        kl_div(soft, &q) * (temperature * temperature)
+    };
+    let mut trainer = Trainer::new(student, optimizer, loss);
+    let history = trainer.fit(&mut loader, epochs);
+    (trainer, history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Dataset;
+    use crate::loss::mse;
+    use crate::nn::{Activation, MLP};
+    use crate::optim::SGD;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn online_updates_reduce_loss() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let model = MLP::new(1, vec![4, 1]);
+        let opt = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(model, opt, mse).with_lr_decay(0.001);
+
+        let probe: Vec<f64> = (-4..=4).map(|i| i as f64 / 4.0).collect();
+        let eval = |t: &Trainer<MLP, SGD>| probe.iter().map(|&x| t.loss(&[x], &[0.5 * x]).data()).sum::<f64>();
+        let before = eval(&trainer);
+
+        // stream of samples from y = x / 2, one update each
+        for _ in 0..1000 {
+            let x: f64 = rng.gen_range(-1.0..1.0);
+            trainer.partial_fit(&[x], &[0.5 * x]);
+        }
+
+        assert_eq!(trainer.steps(), 1000);
+        assert!(eval(&trainer) < before);
+        assert!(eval(&trainer) < 0.05);
+        assert!((trainer.optimizer.lr() - 0.1 / 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn spectral_norm_holds_after_every_step() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let model = MLP::with_activations(2, vec![6, 1], Activation::Tanh, Activation::Linear).with_spectral_norm(1.0);
+        // seeded, since power iteration converges slowly for some initializations
+        model.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+        let opt = SGD::new(model.parameters(), 0.5);
+        let mut trainer = Trainer::new(model, opt, mse);
+        for i in 0..50 {
+            let x = [(i % 7) as f64 - 3.0, (i % 5) as f64 - 2.0];
+            trainer.partial_fit(&x, &[4.0 * x[0] - 3.0 * x[1]]);
+            assert!(trainer.model.layers().iter().all(|l| l.spectral_norm() <= 1.0 + 1e-6));
+        }
+    }
+
+    #[test]
+    fn fit_over_mini_batches() {
+        let xs: Vec<Vec<f64>> = (-10..=10).map(|i| vec![i as f64 / 10.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![-0.5 * x[0]]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs, ys), 7).shuffle(1);
+
+        let model = MLP::new(1, vec![4, 1]);
+        let mut rng = StdRng::seed_from_u64(6);
+        model.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+        let opt = SGD::new(model.parameters(), 0.2);
+        let mut trainer = Trainer::new(model, opt, mse);
+        let history = trainer.fit(&mut loader, 60);
+
+        assert_eq!(history.epoch_losses.len(), 60);
+        assert_eq!(trainer.steps(), 180);
+        assert!(history.epoch_losses[59] < history.epoch_losses[0]);
+    }
+
+    #[test]
+    fn budgets_and_target_loss_stop_fit() {
+        let xs: Vec<Vec<f64>> = (-10..=10).map(|i| vec![i as f64 / 10.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![-0.5 * x[0]]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs, ys), 7);
+        let trainer = || {
+            let model = MLP::new(1, vec![4, 1]);
+            let mut rng = StdRng::seed_from_u64(6);
+            model.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+            let opt = SGD::new(model.parameters(), 0.2);
+            Trainer::new(model, opt, mse)
+        };
+
+        let history = trainer().fit(&mut loader, 2);
+        assert_eq!(history.stopped_by, StopReason::Epochs);
+
+        // 3 batches an epoch, so the budget runs out partway through the second
+        let mut budgeted = trainer().with_step_budget(5);
+        let history = budgeted.fit(&mut loader, 100);
+        assert_eq!((history.stopped_by, history.epoch_losses.len(), budgeted.steps()), (StopReason::Steps, 2, 5));
+        // the budget is per call
+        assert_eq!(budgeted.fit(&mut loader, 100).epoch_losses.len(), 2);
+
+        let history = trainer().with_time_budget(Duration::ZERO).fit(&mut loader, 100);
+        assert_eq!((history.stopped_by, history.epoch_losses.len()), (StopReason::Time, 1));
+
+        let full = trainer().fit(&mut loader, 60).epoch_losses;
+        let target = full[20];
+        let history = trainer().with_target_loss(target).fit(&mut loader, 60);
+        assert_eq!(history.stopped_by, StopReason::TargetLoss);
+        assert!(history.epoch_losses.len() <= 21 && *history.epoch_losses.last().unwrap() <= target);
+    }
+
+    #[test]
+    fn schedules_set_the_rate_per_step_or_per_epoch() {
+        use crate::scheduler::{CosineWarmRestarts, Triangular};
+        let xs: Vec<Vec<f64>> = (0..9).map(|i| vec![i as f64 / 9.0]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs.clone(), xs), 3);
+        let trainer = || {
+            let model = MLP::new(1, vec![2, 1]);
+            let opt = SGD::new(model.parameters(), 1.0);
+            Trainer::new(model, opt, mse)
+        };
+
+        let cyclical = Triangular::new(0.01, 0.1, 4);
+        let mut stepped = trainer().with_lr_decay(0.5).with_schedule(cyclical.clone());
+        assert_eq!(stepped.optimizer.lr(), 0.01);
+        stepped.fit(&mut loader, 2);
+        stepped.partial_fit(&[0.5], &[0.5]);
+        assert_eq!(stepped.optimizer.lr(), cyclical.lr(7));
+
+        let sgdr = CosineWarmRestarts::new(0.1, 0.0, 2).with_period_mult(2);
+        let mut epoched = trainer().with_epoch_schedule(sgdr.clone());
+        epoched.fit(&mut loader, 1);
+        assert_eq!(epoched.optimizer.lr(), sgdr.lr(1));
+        epoched.fit(&mut loader, 1);
+        // a warm restart after the first two epochs
+        assert_eq!(epoched.optimizer.lr(), 0.1);
+    }
+
+    #[test]
+    fn batch_noise_is_reported_per_epoch() {
+        let xs: Vec<Vec<f64>> = (0..12).map(|i| vec![i as f64 / 6.0 - 1.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![x[0] * x[0]]).collect();
+        let run = |batch_size: usize| {
+            let model = MLP::new(1, vec![3, 1]);
+            let mut rng = StdRng::seed_from_u64(8);
+            model.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+            let opt = SGD::new(model.parameters(), 0.1);
+            let mut loader = DataLoader::new(Dataset::new(xs.clone(), ys.clone()), batch_size).shuffle(2);
+            Trainer::new(model, opt, mse).fit(&mut loader, 5)
+        };
+
+        // one batch per epoch: a single loss and a single update, so no spread
+        let full = run(12);
+        assert_eq!((full.batch_loss_variances, full.update_variances), (vec![0.0; 5], vec![0.0; 5]));
+        let mini = run(2);
+        assert_eq!((mini.batch_loss_variances.len(), mini.update_variances.len()), (5, 5));
+        assert!(mini.batch_loss_variances.iter().chain(&mini.update_variances).all(|&v| v > 0.0));
+        assert_eq!(variance(&[1.0, 3.0, 2.0, 2.0]), 0.5);
+    }
+
+    #[test]
+    fn sessions_step_pause_and_match_fit() {
+        let xs: Vec<Vec<f64>> = (-10..=10).map(|i| vec![i as f64 / 10.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![0.5 * x[0]]).collect();
+        let loader = || DataLoader::new(Dataset::new(xs.clone(), ys.clone()), 7).shuffle(4);
+        let model = MLP::new(1, vec![3, 1]);
+        let twin = model.deep_copy();
+
+        let opt = SGD::new(model.parameters(), 0.2);
+        let fitted = Trainer::new(model, opt, mse).fit(&mut loader(), 4);
+
+        let opt = SGD::new(twin.parameters(), 0.2);
+        let mut session = TrainingSession::new(Trainer::new(twin, opt, mse), loader())
+            .with_breakpoint(|s| s.steps % 5 == 0);
+        assert_eq!(session.inspect().loss, None);
+        // 3 batches an epoch; the breakpoint stops the runs after steps 5 and 10
+        assert_eq!(session.run(100), 5);
+        assert!(session.is_paused() && session.step().is_none() && session.run(3) == 0);
+        let snapshot = session.inspect();
+        assert_eq!((snapshot.epoch, snapshot.steps), (1, 5));
+        assert_eq!(snapshot.grads.iter().map(|(_, g)| g.len()).collect::<Vec<_>>(), vec![6, 4]);
+        assert!(snapshot.grads.iter().flat_map(|(_, g)| g).any(|g| *g != 0.0));
+
+        session.resume();
+        assert_eq!(session.run(100), 5);
+        session.resume();
+        assert_eq!(session.run(2), 2);
+        session.pause();
+        assert_eq!(session.run(1), 0);
+        let (trainer, history) = session.into_parts();
+        assert_eq!(trainer.steps(), 12);
+        assert_eq!(history.epoch_losses, fitted.epoch_losses);
+        assert_eq!(history.batch_loss_variances, fitted.batch_loss_variances);
+    }
+
+    #[test]
+    fn data_parallel_batches_match_serial_ones() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let xs: Vec<Vec<f64>> = (0..23).map(|_| vec![rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![x[0] * x[1], x[0] - x[1]]).collect();
+        let loader = || DataLoader::new(Dataset::new(xs.clone(), ys.clone()), 10).shuffle(5);
+        let model = MLP::new(2, vec![4, 2]);
+        let twin = model.deep_copy();
+
+        let opt = SGD::new(model.parameters(), 0.1).with_momentum(0.9);
+        let mut serial = Trainer::new(model, opt, mse);
+        let serial_history = serial.fit(&mut loader(), 3);
+
+        let opt = SGD::new(twin.parameters(), 0.1).with_momentum(0.9);
+        let mut parallel = Trainer::new(twin, opt, mse).with_data_parallel(4, || MLP::new(2, vec![4, 2]), mse);
+        let history = parallel.fit(&mut loader(), 3);
+
+        assert_eq!(parallel.steps(), serial.steps());
+        let close = |a: &[f64], b: &[f64]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-12);
+        assert!(close(&history.epoch_losses, &serial_history.epoch_losses));
+        assert!(close(&history.per_output_losses.concat(), &serial_history.per_output_losses.concat()));
+        let data = |t: &Trainer<MLP, SGD>| t.model.parameters().iter().map(|p| p.data()).collect::<Vec<_>>();
+        assert!(close(&data(&parallel), &data(&serial)));
+    }
+
+    #[test]
+    fn callbacks_see_every_step_and_epoch() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct Count(Rc<Cell<(usize, usize)>>);
+        impl Callback for Count {
+            fn on_step(&mut self, model: &dyn Module) {
+                // gradients of the step are still there
+                assert!(model.parameters().iter().any(|p| p.grad() != 0.0));
+                let (s, e) = self.0.get();
+                self.0.set((s + 1, e));
+            }
+            fn on_epoch_end(&mut self, _model: &dyn Module, history: &mut History) {
+                let (s, e) = self.0.get();
+                assert_eq!(history.epoch_losses.len(), e + 1);
+                self.0.set((s, e + 1));
+            }
+        }
+
+        let counts = Rc::new(Cell::new((0, 0)));
+        let xs: Vec<Vec<f64>> = (0..10).map(|i| vec![i as f64 / 10.0]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs.clone(), xs), 4);
+        let model = MLP::new(1, vec![2, 1]);
+        let opt = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(model, opt, mse).with_callback(Count(counts.clone()));
+        trainer.fit(&mut loader, 3);
+        trainer.partial_fit(&[0.5], &[0.5]);
+        assert_eq!(counts.get(), (3 * 3 + 1, 3));
+    }
+
+    #[test]
+    fn grad_flow_is_recorded_per_layer_and_epoch() {
+        let xs: Vec<Vec<f64>> = (-6..=6).map(|i| vec![i as f64 / 6.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![x[0] * x[0]]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs, ys), 4);
+
+        let model = MLP::new(1, vec![3, 3, 3, 1]);
+        let opt = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(model, opt, mse).with_callback(GradFlow::new());
+        let history = trainer.fit(&mut loader, 5);
+
+        assert_eq!(history.grad_flow.len(), 5);
+        assert!(history.grad_flow.iter().all(|epoch| epoch.len() == 4 && epoch.iter().all(|g| *g > 0.0)));
+        assert_eq!(trainer.model.parameter_groups()[2].0, "layer 2");
+    }
+
+    #[test]
+    fn multi_output_regression_reports_each_output() {
+        // two targets per sample: y0 = x / 2, y1 = -x / 4
+        let xs: Vec<Vec<f64>> = (-10..=10).map(|i| vec![i as f64 / 10.0]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![0.5 * x[0], -0.25 * x[0]]).collect();
+        let data = Dataset::new(xs, ys);
+        let mut loader = DataLoader::new(data.clone(), 7).shuffle(2);
+
+        let model = MLP::new(1, vec![6, 2]);
+        let mut rng = StdRng::seed_from_u64(4);
+        model.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+        let opt = SGD::new(model.parameters(), 0.2);
+        let mut trainer = Trainer::new(model, opt, mse);
+        let before = trainer.evaluate(&data);
+        let history = trainer.fit(&mut loader, 80);
+
+        assert_eq!(history.per_output_losses.len(), 80);
+        assert_eq!(history.per_output_losses[0].len(), 2);
+        let after = trainer.evaluate(&data);
+        assert!(after[0] < before[0] && after[1] < before[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "outputs but the target has")]
+    fn target_width_must_match_model() {
+        let model = MLP::new(1, vec![2]);
+        let opt = SGD::new(model.parameters(), 0.1);
+        Trainer::new(model, opt, mse).partial_fit(&[1.0], &[1.0]);
+    }
+
+    #[test]
+    fn student_matches_teacher_soft_outputs() {
+        let mut rng = StdRng::seed_from_u64(9);
+        let inputs: Vec<Vec<f64>> =
+            (0..40).map(|_| vec![rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)]).collect();
+        let teacher = MLP::new(2, vec![8, 8, 3]);
+        let student = MLP::new(2, vec![4, 3]);
+        teacher.parameters().iter().chain(&student.parameters()).for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+        let opt = SGD::new(student.parameters(), 0.5);
+
+        let (student, history) = distill(&teacher, student, opt, &inputs, 2.0, 10, 40);
+        assert_eq!(history.epoch_losses.len(), 40);
+        assert!(history.epoch_losses[39] < history.epoch_losses[0]);
+
+        let soft = soft_targets(&teacher, &inputs, 2.0);
+        assert!(soft.iter().all(|p| (p.iter().sum::<f64>() - 1.0).abs() < 1e-12));
+        // the teacher is left untouched by training
+        assert!(teacher.parameters().iter().all(|p| p.grad() == 0.0));
+        assert_eq!(student.steps(), 160);
+    }
+
+    // Golden loss curves live in `src/golden`, one epoch per line. Run the tests with
+    // UPDATE_GOLDEN=1 to rewrite them after an intended change in training behaviour.
+    const GOLDEN_TOL: f64 = 1e-9;
+
+    fn check_golden(name: &str, losses: &[f64]) {
+        let path = format!("{}/src/golden/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let text: String = losses.iter().map(|l| format!("{:e}\n", l)).collect();
+            std::fs::write(&path, text).unwrap();
+            return;
+        }
+        let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {}: {}", path, e));
+        let golden: Vec<f64> = text.lines().map(|l| l.parse().unwrap()).collect();
+        assert_eq!(losses.len(), golden.len(), "{}: number of epochs changed", name);
+        for (epoch, (&got, &want)) in losses.iter().zip(&golden).enumerate() {
+            assert!(
+                (got - want).abs() <= GOLDEN_TOL * want.abs().max(1.0),
+                "{}: epoch {} loss is {} but the golden value is {}", name, epoch, got, want
+            );
+        }
+    }
+
+    // Fixed-seed regression job: a 1-4-1 tanh MLP on y = sin(2x) with shuffled mini-batches
+    fn golden_job<O: Optimizer>(make_opt: impl FnOnce(Vec<Value>) -> O) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(17);
+        let xs: Vec<Vec<f64>> = (0..24).map(|_| vec![rng.gen_range(-1.0..1.0)]).collect();
+        let ys: Vec<Vec<f64>> = xs.iter().map(|x| vec![(2.0 * x[0]).sin()]).collect();
+        let mut loader = DataLoader::new(Dataset::new(xs, ys), 5).shuffle(3);
+
+        let model = MLP::new(1, vec![4, 1]);
+        model.parameters().iter().for_each(|p| p.set_data(rng.gen_range(-1.0..1.0)));
+        let opt = make_opt(model.parameters());
+        Trainer::new(model, opt, mse).with_lr_decay(0.01).fit(&mut loader, 15).epoch_losses
+    }
+
+    #[test]
+    fn golden_sgd_momentum_losses() {
+        check_golden("sgd_momentum", &golden_job(|p| SGD::new(p, 0.1).with_momentum(0.9)));
+    }
+
+    #[test]
+    fn golden_adam_losses() {
+        check_golden("adam", &golden_job(|p| crate::optim::Adam::new(p, 0.05)));
+    }
+}
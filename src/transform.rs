@@ -0,0 +1,100 @@
+use rand::Rng;
+use rand::rngs::StdRng;
+
+/// A random augmentation applied to one input vector.
+pub trait Transform {
+    fn apply(&self, x: &mut [f64], rng: &mut StdRng);
+}
+
+/// Adds independent uniform noise in `[-amount, amount]` to every coordinate.
+#[derive(Debug, Clone, Copy)]
+pub struct Jitter {
+    pub amount: f64,
+}
+
+impl Transform for Jitter {
+    fn apply(&self, x: &mut [f64], rng: &mut StdRng) {
+        for v in x.iter_mut() {
+            *v += rng.gen_range(-self.amount..=self.amount);
+        }
+    }
+}
+
+/// Multiplies the whole input by one factor drawn from `[min, max]`.
+#[derive(Debug, Clone, Copy)]
+pub struct Scale {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Transform for Scale {
+    fn apply(&self, x: &mut [f64], rng: &mut StdRng) {
+        let factor = rng.gen_range(self.min..=self.max);
+        x.iter_mut().for_each(|v| *v *= factor);
+    }
+}
+
+/// Rotates a 2-D point about the origin by an angle drawn from `[-max_angle, max_angle]` radians.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotate {
+    pub max_angle: f64,
+}
+
+impl Transform for Rotate {
+    fn apply(&self, x: &mut [f64], rng: &mut StdRng) {
+        assert_eq!(x.len(), 2, "Rotate only applies to 2-D points");
+        let (sin, cos) = rng.gen_range(-self.max_angle..=self.max_angle).sin_cos();
+        let (a, b) = (x[0], x[1]);
+        x[0] = a * cos - b * sin;
+        x[1] = a * sin + b * cos;
+    }
+}
+
+/// Transforms applied one after another, in the order they were added.
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline::default()
+    }
+
+    pub fn then(mut self, t: impl Transform + 'static) -> Self {
+        self.steps.push(Box::new(t));
+        self
+    }
+}
+
+impl Transform for Pipeline {
+    fn apply(&self, x: &mut [f64], rng: &mut StdRng) {
+        for step in &self.steps {
+            step.apply(x, rng);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn pipeline_composes_in_order() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut p = [1.0, 0.0];
+        Pipeline::new().then(Scale { min: 2.0, max: 2.0 }).then(Rotate { max_angle: 0.0 }).apply(&mut p, &mut rng);
+        assert_eq!(p, [2.0, 0.0]);
+
+        // rotation keeps the norm
+        let mut q = [3.0, 4.0];
+        Rotate { max_angle: 1.0 }.apply(&mut q, &mut rng);
+        assert!(((q[0] * q[0] + q[1] * q[1]).sqrt() - 5.0).abs() < 1e-12);
+
+        let mut r = [0.0; 3];
+        Jitter { amount: 0.1 }.apply(&mut r, &mut rng);
+        assert!(r.iter().all(|v| v.abs() <= 0.1));
+        assert!(r.iter().any(|v| *v != 0.0));
+    }
+}
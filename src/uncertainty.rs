@@ -0,0 +1,168 @@
+//! First-order uncertainty propagation through the graph: given standard deviations for some
+//! inputs, how uncertain is each node computed from them.
+//!
+//! Inside `track`, every node carries a variance next to its data. Give inputs theirs with
+//! `Value::set_std`, and each op built in the mode linearizes around the current data to set
+//! its own, `var(y) = sum_i (dy/dx_i)^2 var(x_i)` over its parents, with the derivatives taken
+//! from the same backward rules used for training. That is exact for linear graphs and good
+//! while the spreads are small compared to the curvature of the ops they pass through.
+//!
+//! Like interval arithmetic, the mode treats the parents of each op as independent, so an input
+//! reaching a node along several paths (`x * y + x`) is counted once per path rather than with
+//! its correlation. `propagate_std` answers the same question after the fact, one backward pass
+//! per node, and does account for shared inputs. Values split out of a `Tensor` start certain.
+
+use crate::operators::{GraphNode, Value};
+use std::cell::Cell;
+
+thread_local! {
+    static TRACKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f` with uncertainty tracking: every op output built inside gets a variance from its
+/// parents'. Applies to the current thread only; nests.
+pub fn track<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            TRACKING.with(|t| t.set(self.0));
+        }
+    }
+    let _restore = Restore(TRACKING.with(|t| t.replace(true)));
+    f()
+}
+
+/// Whether ops on this thread currently propagate uncertainty (inside `track`).
+pub fn is_tracking() -> bool {
+    TRACKING.with(|t| t.get())
+}
+
+// Sets the variance of a freshly built op output from its parents'
+pub(crate) fn propagate_variance(out: &Value) {
+    let certain = out.borrow().prev().iter().all(|p| p.borrow().variance == 0.0);
+    if certain {
+        return;
+    }
+    let variance = out.local_partials().iter().map(|(p, d)| d * d * p.borrow().variance).sum();
+    out.borrow_mut().variance = variance;
+}
+
+impl Value {
+    /// Standard deviation of the data, 0 unless set with `set_std` or propagated by `track`.
+    pub fn std(&self) -> f64 {
+        self.borrow().variance.sqrt()
+    }
+
+    /// Declares the uncertainty of an input.
+    pub fn set_std(&self, std: f64) {
+        assert!(std >= 0.0, "standard deviations must be non-negative");
+        self.borrow_mut().variance = std * std;
+    }
+
+    /// `data ± k * std`, e.g. `k = 2` for about 95% of the mass of a gaussian.
+    pub fn interval(&self, k: f64) -> (f64, f64) {
+        let (data, std) = (self.data(), self.std());
+        (data - k * std, data + k * std)
+    }
+}
+
+/// Standard deviation of each of `nodes` given independent `inputs`, each a leaf (or any node)
+/// with its standard deviation. Inputs the node does not depend on add nothing. Gradients in
+/// the graph are the same afterwards as before.
+pub fn propagate_std(nodes: &[Value], inputs: &[(Value, f64)]) -> Vec<f64> {
+    assert!(inputs.iter().all(|(_, s)| *s >= 0.0), "standard deviations must be non-negative");
+    // inputs outside the nodes' graphs too, so their stale gradients read as zero
+    let roots: Vec<Value> = nodes.iter().cloned().chain(inputs.iter().map(|(x, _)| x.clone())).collect();
+    let graph = GraphNode::topological_sort_many(&roots);
+    let saved: Vec<f64> = graph.iter().map(|n| n.grad()).collect();
+
+    let stds = nodes
+        .iter()
+        .map(|node| {
+            graph.iter().for_each(|n| n.set_grad(0.0));
+            node.backward();
+            inputs.iter().map(|(x, s)| (x.grad() * s).powi(2)).sum::<f64>().sqrt()
+        })
+        .collect();
+
+    graph.iter().zip(saved).for_each(|(n, g)| n.set_grad(g));
+    stds
+}
+
+/// `data ± k * std` for each of `nodes`, e.g. `k = 2` for about 95% of the mass of a gaussian.
+pub fn propagate_interval(nodes: &[Value], inputs: &[(Value, f64)], k: f64) -> Vec<(f64, f64)> {
+    nodes
+        .iter()
+        .zip(propagate_std(nodes, inputs))
+        .map(|(n, s)| (n.data() - k * s, n.data() + k * s))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_combinations_are_exact() {
+        let (x, y) = (Value::new(1.0, "x"), Value::new(2.0, "y"));
+        let sum = x.clone() * 3.0 + y.clone() * 4.0;
+        let scaled = sum.clone() * 0.5;
+        let stds = propagate_std(&[sum.clone(), scaled.clone(), x.clone()], &[(x.clone(), 1.0), (y.clone(), 1.0)]);
+        assert_eq!(stds, vec![5.0, 2.5, 1.0]);
+        assert_eq!(propagate_interval(&[scaled], &[(x.clone(), 1.0), (y, 1.0)], 2.0), vec![(0.5, 10.5)]);
+        // an input the node does not use, or a certain one, adds nothing
+        sum.set_grad(1.0);
+        assert_eq!(propagate_std(std::slice::from_ref(&x), &[(sum.clone(), 7.0), (x.clone(), 0.0)]), vec![0.0]);
+        assert_eq!(sum.grad(), 1.0);
+    }
+
+    #[test]
+    fn tracked_nodes_carry_their_spread() {
+        let (x, y) = (Value::new(1.0, "x"), Value::new(2.0, "y"));
+        x.set_std(1.0);
+        y.set_std(1.0);
+        let (sum, scaled, squashed) = track(|| {
+            let sum = x.clone() * 3.0 + y.clone() * 4.0;
+            let scaled = sum.clone() * 0.5;
+            (sum.clone(), scaled, (x.clone() * 0.1).tanh())
+        });
+        assert!(!is_tracking());
+        assert_eq!((sum.std(), scaled.std()), (5.0, 2.5));
+        assert_eq!(scaled.interval(2.0), (0.5, 10.5));
+        let slope = 1.0 - squashed.data().powi(2);
+        assert!((squashed.std() - 0.1 * slope).abs() < 1e-12);
+
+        // ops outside the mode carry nothing, and gradients are untouched by it
+        assert_eq!((x.clone() * 3.0).std(), 0.0);
+        assert!(x.grad() == 0.0 && y.grad() == 0.0);
+        // the same parent twice within one op is one fully correlated input
+        assert_eq!(track(|| x.clone() + x.clone()).std(), 2.0);
+    }
+
+    #[test]
+    fn small_spreads_match_sampling_and_grads_survive() {
+        let (x, y) = (Value::new(0.3, "x"), Value::new(-0.8, "y"));
+        let out = (x.clone() * y.clone()).tanh() + x.clone().exp();
+        out.backward();
+        let grads = (x.grad(), y.grad());
+
+        let (sx, sy) = (0.01, 0.02);
+        let std = propagate_std(std::slice::from_ref(&out), &[(x.clone(), sx), (y.clone(), sy)])[0];
+        assert_eq!((x.grad(), y.grad()), grads);
+
+        // spread of the output over a symmetric grid of input perturbations
+        let (x0, y0) = (x.data(), y.data());
+        let mut samples = vec![];
+        for i in -20..=20 {
+            for j in -20..=20 {
+                x.set_data(x0 + sx * i as f64 / 20.0 * 3f64.sqrt());
+                y.set_data(y0 + sy * j as f64 / 20.0 * 3f64.sqrt());
+                out.recompute();
+                samples.push(out.data());
+            }
+        }
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let sampled = (samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64).sqrt();
+        assert!((sampled - std).abs() / std < 0.05, "{} vs {}", sampled, std);
+    }
+}
@@ -0,0 +1,278 @@
+//! Units of measure for physics-flavoured models: a `Quantity` is a `Value` tagged with a unit
+//! such as `kg*m/s^2`, and its ops check and carry units along while the `Value`s underneath
+//! build the graph as usual. Adding metres to seconds is an error, multiplying them gives
+//! `m*s`, and `exp`, `log` and `tanh` want dimensionless arguments.
+
+use crate::operators::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitError {
+    /// The unit string is not a product of `symbol` or `symbol^n` factors joined by `*` and `/`.
+    Parse(String),
+    /// `op` needs both sides in the same unit.
+    Mismatch { op: &'static str, left: Unit, right: Unit },
+    /// `op` needs a dimensionless argument.
+    NotDimensionless { op: &'static str, unit: Unit },
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnitError::Parse(s) => write!(f, "cannot parse unit {:?}", s),
+            UnitError::Mismatch { op, left, right } => write!(f, "cannot {} {} and {}", op, left, right),
+            UnitError::NotDimensionless { op, unit } => write!(f, "{} of a quantity in {}, not dimensionless", op, unit),
+        }
+    }
+}
+
+impl std::error::Error for UnitError {}
+
+/// A product of base units raised to integer powers; the empty product is dimensionless.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Unit(BTreeMap<String, i32>);
+
+impl Unit {
+    pub fn dimensionless() -> Self {
+        Unit::default()
+    }
+
+    /// Parses e.g. `m`, `kg*m/s^2`, `m^-1` or `1` (dimensionless, as is the empty string). Every
+    /// factor after a `/` divides.
+    pub fn parse(s: &str) -> Result<Self, UnitError> {
+        let err = || UnitError::Parse(s.to_string());
+        let mut unit = Unit::default();
+        if s.trim().is_empty() {
+            return Ok(unit);
+        }
+        let mut sign = 1;
+        let mut rest = s;
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let factor = rest[..end].trim();
+            let (symbol, power) = match factor.split_once('^') {
+                Some((symbol, power)) => (symbol.trim(), power.trim().parse::<i32>().map_err(|_| err())?),
+                None => (factor, 1),
+            };
+            if symbol != "1" {
+                if symbol.is_empty() || !symbol.chars().all(|c| c.is_alphabetic() || c == '_') {
+                    return Err(err());
+                }
+                unit.add_power(symbol, sign * power);
+            }
+            match rest[end..].chars().next() {
+                None => return Ok(unit),
+                Some(op) => {
+                    sign = if op == '/' { -1 } else { sign };
+                    rest = &rest[end + 1..];
+                }
+            }
+        }
+    }
+
+    fn add_power(&mut self, symbol: &str, power: i32) {
+        let p = self.0.entry(symbol.to_string()).or_insert(0);
+        *p += power;
+        if *p == 0 {
+            self.0.remove(symbol);
+        }
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn powi(&self, n: i32) -> Unit {
+        Unit(self.0.iter().filter(|_| n != 0).map(|(s, p)| (s.clone(), p * n)).collect())
+    }
+
+    fn combine(&self, other: &Unit, sign: i32) -> Unit {
+        let mut unit = self.clone();
+        for (s, p) in &other.0 {
+            unit.add_power(s, sign * p);
+        }
+        unit
+    }
+}
+
+impl fmt::Display for Unit {
+    /// Positive powers joined by `*`, then each negative one after a `/`; `1` when dimensionless.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factor = |s: &str, p: i32| if p == 1 { s.to_string() } else { format!("{}^{}", s, p) };
+        let num: Vec<String> = self.0.iter().filter(|(_, p)| **p > 0).map(|(s, p)| factor(s, *p)).collect();
+        let den: Vec<String> = self.0.iter().filter(|(_, p)| **p < 0).map(|(s, p)| factor(s, -p)).collect();
+        let num = if num.is_empty() { "1".to_string() } else { num.join("*") };
+        write!(f, "{}", num)?;
+        den.iter().try_for_each(|d| write!(f, "/{}", d))
+    }
+}
+
+/// A `Value` with a unit. Arithmetic works on the values underneath, so gradients flow through
+/// quantities as through plain values; `+` and `-` panic on mismatched units, see `try_add`.
+#[derive(Debug, Clone)]
+pub struct Quantity {
+    value: Value,
+    unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(value: Value, unit: Unit) -> Self {
+        Quantity { value, unit }
+    }
+
+    /// A leaf with `data`, labelled with its unit.
+    pub fn leaf(data: f64, unit: &str) -> Result<Self, UnitError> {
+        let unit = Unit::parse(unit)?;
+        Ok(Quantity::new(Value::new(data, &unit.to_string()), unit))
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn unit(&self) -> &Unit {
+        &self.unit
+    }
+
+    pub fn data(&self) -> f64 {
+        self.value.data()
+    }
+
+    fn same_unit(&self, other: &Quantity, op: &'static str) -> Result<(), UnitError> {
+        if self.unit == other.unit {
+            Ok(())
+        } else {
+            Err(UnitError::Mismatch { op, left: self.unit.clone(), right: other.unit.clone() })
+        }
+    }
+
+    fn dimensionless(&self, op: &'static str) -> Result<(), UnitError> {
+        if self.unit.is_dimensionless() {
+            Ok(())
+        } else {
+            Err(UnitError::NotDimensionless { op, unit: self.unit.clone() })
+        }
+    }
+
+    pub fn try_add(&self, other: &Quantity) -> Result<Quantity, UnitError> {
+        self.same_unit(other, "add")?;
+        Ok(Quantity::new(self.value.clone() + other.value.clone(), self.unit.clone()))
+    }
+
+    pub fn try_sub(&self, other: &Quantity) -> Result<Quantity, UnitError> {
+        self.same_unit(other, "subtract")?;
+        Ok(Quantity::new(self.value.clone() - other.value.clone(), self.unit.clone()))
+    }
+
+    pub fn powi(&self, n: i32) -> Quantity {
+        Quantity::new(self.value.clone().powop(n), self.unit.powi(n))
+    }
+
+    pub fn try_exp(&self) -> Result<Quantity, UnitError> {
+        self.dimensionless("exp")?;
+        Ok(Quantity::new(self.value.clone().exp(), Unit::dimensionless()))
+    }
+
+    pub fn try_log(&self) -> Result<Quantity, UnitError> {
+        self.dimensionless("log")?;
+        Ok(Quantity::new(self.value.clone().log(), Unit::dimensionless()))
+    }
+
+    pub fn try_tanh(&self) -> Result<Quantity, UnitError> {
+        self.dimensionless("tanh")?;
+        Ok(Quantity::new(self.value.clone().tanh(), Unit::dimensionless()))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.value.data(), self.unit)
+    }
+}
+
+impl Add for Quantity {
+    type Output = Quantity;
+
+    fn add(self, other: Quantity) -> Quantity {
+        self.try_add(&other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Quantity;
+
+    fn sub(self, other: Quantity) -> Quantity {
+        self.try_sub(&other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Mul for Quantity {
+    type Output = Quantity;
+
+    fn mul(self, other: Quantity) -> Quantity {
+        Quantity::new(self.value * other.value, self.unit.combine(&other.unit, 1))
+    }
+}
+
+impl Div for Quantity {
+    type Output = Quantity;
+
+    fn div(self, other: Quantity) -> Quantity {
+        Quantity::new(self.value / other.value, self.unit.combine(&other.unit, -1))
+    }
+}
+
+impl Mul<f64> for Quantity {
+    type Output = Quantity;
+
+    fn mul(self, k: f64) -> Quantity {
+        Quantity::new(self.value * k, self.unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(s: &str) -> Unit {
+        Unit::parse(s).unwrap()
+    }
+
+    #[test]
+    fn units_parse_and_print_canonically() {
+        assert_eq!(unit("kg * m / s^2").to_string(), "kg*m/s^2");
+        assert_eq!(unit("m/s/s"), unit("m*s^-2"));
+        assert_eq!(unit("m^-1").to_string(), "1/m");
+        assert!(unit("1").is_dimensionless() && unit("").is_dimensionless() && unit("m/m").is_dimensionless());
+        for bad in ["m^x", "m**s", "2m", "m/"] {
+            assert_eq!(Unit::parse(bad), Err(UnitError::Parse(bad.to_string())));
+        }
+    }
+
+    #[test]
+    fn quantities_carry_units_and_gradients() {
+        let m = Quantity::leaf(2.0, "kg").unwrap();
+        let v = Quantity::leaf(3.0, "m/s").unwrap();
+        let energy = v.powi(2) * m.clone() * 0.5;
+        assert_eq!((energy.data(), energy.unit().to_string()), (9.0, "kg*m^2/s^2".to_string()));
+
+        let work = Quantity::leaf(1.0, "N").unwrap() * Quantity::leaf(4.0, "m").unwrap();
+        assert_eq!(
+            energy.try_add(&work).unwrap_err(),
+            UnitError::Mismatch { op: "add", left: unit("kg*m^2/s^2"), right: unit("N*m") }
+        );
+        let total = energy.clone() + Quantity::leaf(1.0, "kg*m^2*s^-2").unwrap();
+        total.value().backward();
+        // d(m v^2 / 2)/dv = m v
+        assert_eq!((v.value().grad(), m.value().grad()), (6.0, 4.5));
+
+        let ratio = energy.clone() / total;
+        assert_eq!(ratio.try_exp().unwrap().data(), (0.9f64).exp());
+        assert_eq!(
+            energy.try_log().unwrap_err().to_string(),
+            "log of a quantity in kg*m^2/s^2, not dimensionless"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use crate::operators::*;
+use crate::tensor::ShapeError;
+use std::ops::{Add, Index, Mul, Sub};
+
+/// A thin wrapper over `Vec<Value>` with elementwise arithmetic, so layer-level math reads like
+/// array expressions. Binary ops between two vectors panic on a length mismatch; the `try_`
+/// methods return a `ShapeError` instead. A scalar, `f64` or `Value`, broadcasts over every
+/// element.
+#[derive(Debug, Clone)]
+pub struct Vector(pub Vec<Value>);
+
+impl Vector {
+    pub fn new(values: Vec<Value>) -> Self {
+        Vector(values)
+    }
+
+    pub fn from_f64s(xs: &[f64]) -> Self {
+        Vector(xs.iter().map(|x| Value::from(*x)).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Value> {
+        self.0.iter()
+    }
+
+    pub fn data(&self) -> Vec<f64> {
+        self.0.iter().map(|v| v.data()).collect()
+    }
+
+    pub fn into_inner(self) -> Vec<Value> {
+        self.0
+    }
+
+    pub fn sum(&self) -> Value {
+        self.0.iter().fold(Value::from(0.0), |acc, v| acc + v.clone())
+    }
+
+    pub fn dot(&self, other: &Vector) -> Value {
+        (self.clone() * other.clone()).sum()
+    }
+
+    fn zip_with(self, other: Vector, op: &'static str, f: impl Fn(Value, Value) -> Value) -> Result<Vector, ShapeError> {
+        if self.len() != other.len() {
+            return Err(ShapeError { op, left: vec![self.len()], right: vec![other.len()] });
+        }
+        Ok(Vector(self.0.into_iter().zip(other.0).map(|(a, b)| f(a, b)).collect()))
+    }
+}
+
+#[deny(clippy::panic, clippy::unwrap_used, clippy::expect_used, clippy::indexing_slicing)]
+impl Vector {
+    pub fn try_add(self, other: Vector) -> Result<Vector, ShapeError> {
+        self.zip_with(other, "add", |a, b| a + b)
+    }
+
+    pub fn try_sub(self, other: Vector) -> Result<Vector, ShapeError> {
+        self.zip_with(other, "sub", |a, b| a - b)
+    }
+
+    pub fn try_mul(self, other: Vector) -> Result<Vector, ShapeError> {
+        self.zip_with(other, "mul", |a, b| a * b)
+    }
+
+    pub fn try_dot(&self, other: &Vector) -> Result<Value, ShapeError> {
+        Ok(self.clone().try_mul(other.clone())?.sum())
+    }
+}
+
+fn mismatch(e: ShapeError) -> Vector {
+    panic!("Vector {} of mismatched lengths {:?} and {:?}", e.op, e.left, e.right)
+}
+
+impl From<Vec<Value>> for Vector {
+    fn from(values: Vec<Value>) -> Self {
+        Vector(values)
+    }
+}
+
+impl Index<usize> for Vector {
+    type Output = Value;
+
+    fn index(&self, i: usize) -> &Value {
+        &self.0[i]
+    }
+}
+
+impl Add for Vector {
+    type Output = Vector;
+
+    fn add(self, other: Vector) -> Vector {
+        self.try_add(other).unwrap_or_else(mismatch)
+    }
+}
+
+impl Sub for Vector {
+    type Output = Vector;
+
+    fn sub(self, other: Vector) -> Vector {
+        self.try_sub(other).unwrap_or_else(mismatch)
+    }
+}
+
+impl Mul for Vector {
+    type Output = Vector;
+
+    fn mul(self, other: Vector) -> Vector {
+        self.try_mul(other).unwrap_or_else(mismatch)
+    }
+}
+
+impl Add<f64> for Vector {
+    type Output = Vector;
+
+    fn add(self, rhs: f64) -> Vector {
+        Vector(self.0.into_iter().map(|v| v + rhs).collect())
+    }
+}
+
+impl Sub<f64> for Vector {
+    type Output = Vector;
+
+    fn sub(self, rhs: f64) -> Vector {
+        Vector(self.0.into_iter().map(|v| v - rhs).collect())
+    }
+}
+
+impl Mul<f64> for Vector {
+    type Output = Vector;
+
+    fn mul(self, rhs: f64) -> Vector {
+        Vector(self.0.into_iter().map(|v| v * rhs).collect())
+    }
+}
+
+impl Add<Value> for Vector {
+    type Output = Vector;
+
+    fn add(self, rhs: Value) -> Vector {
+        Vector(crate::ops::add_scalar(&self.0, &rhs))
+    }
+}
+
+impl Sub<Value> for Vector {
+    type Output = Vector;
+
+    fn sub(self, rhs: Value) -> Vector {
+        Vector(crate::ops::sub_scalar(&self.0, &rhs))
+    }
+}
+
+impl Mul<Value> for Vector {
+    type Output = Vector;
+
+    fn mul(self, rhs: Value) -> Vector {
+        Vector(crate::ops::mul_scalar(&self.0, &rhs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elementwise_and_broadcast() {
+        let a = Vector::from_f64s(&[1.0, 2.0, 3.0]);
+        let b = Vector::from_f64s(&[4.0, 5.0, 6.0]);
+        assert_eq!((a.clone() + b.clone()).data(), vec![5.0, 7.0, 9.0]);
+        assert_eq!((b.clone() - a.clone()).data(), vec![3.0, 3.0, 3.0]);
+        assert_eq!((a.clone() * b.clone()).data(), vec![4.0, 10.0, 18.0]);
+        assert_eq!((a.clone() * 2.0 - 1.0).data(), vec![1.0, 3.0, 5.0]);
+
+        let s = Value::new(2.0, "s");
+        let scaled = (a.clone() * s.clone() + s.clone() - Value::from(1.0)).data();
+        assert_eq!(scaled, vec![3.0, 5.0, 7.0]);
+        (a * s.clone()).sum().backward();
+        assert_eq!(s.grad(), 6.0);
+    }
+
+    #[test]
+    fn dot_gradients() {
+        let a = Vector::from_f64s(&[1.0, 2.0, 3.0]);
+        let b = Vector::from_f64s(&[4.0, 5.0, 6.0]);
+        let d = a.dot(&b);
+        assert_eq!(d.data(), 32.0);
+        d.backward();
+        let ga: Vec<f64> = a.iter().map(|v| v.grad()).collect();
+        let gb: Vec<f64> = b.iter().map(|v| v.grad()).collect();
+        assert_eq!(ga, b.data());
+        assert_eq!(gb, a.data());
+    }
+
+    #[test]
+    fn try_ops_return_shape_errors() {
+        let a = Vector::from_f64s(&[1.0, 2.0]);
+        let err = a.clone().try_add(Vector::from_f64s(&[1.0])).unwrap_err();
+        assert_eq!((err.left, err.right), (vec![2], vec![1]));
+        assert!(a.try_dot(&Vector::from_f64s(&[])).is_err());
+        assert_eq!(a.try_dot(&a).unwrap().data(), 5.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "mismatched lengths")]
+    fn length_mismatch_panics() {
+        let _ = Vector::from_f64s(&[1.0]) + Vector::from_f64s(&[1.0, 2.0]);
+    }
+}
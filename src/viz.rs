@@ -0,0 +1,199 @@
+//! Local HTTP viewer for graphs too big to read as a DOT dump: an embedded page that lays the
+//! graph out by depth, with pan, zoom and per-node data/grad inspection.
+
+use crate::operators::Value;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+const PAGE: &str = include_str!("viz.html");
+
+/// The graph reachable from `value` as JSON: `{"nodes": [...]}`, parents before children, each
+/// node with `label`, `op`, `data`, `grad` and `parents` as indices into the list.
+pub fn graph_json(value: &Value) -> String {
+    let views = value.graph_view();
+    let index: HashMap<usize, usize> = views.iter().enumerate().map(|(i, v)| (v.id, i)).collect();
+    let mut out = String::from("{\"nodes\":[");
+    for (i, v) in views.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let parents: Vec<String> = v.parents.iter().map(|p| index[p].to_string()).collect();
+        let op = v.op.as_deref().map_or("null".to_string(), json_string);
+        write!(
+            out,
+            "{{\"label\":{},\"op\":{},\"data\":{},\"grad\":{},\"parents\":[{}]}}",
+            json_string(&v.label), op, json_number(v.data), json_number(v.grad), parents.join(",")
+        )
+        .unwrap();
+    }
+    out.push_str("]}");
+    out
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// JSON has no NaN or infinities
+fn json_number(x: f64) -> String {
+    if x.is_finite() { format!("{:?}", x) } else { "null".to_string() }
+}
+
+/// Serves the viewer for the graph of `value` on `http://127.0.0.1:port/` until the process
+/// exits. The graph is snapshotted when called; call again to see later changes.
+pub fn serve(value: &Value, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    serve_on(listener, &graph_json(value), None)
+}
+
+/// `History::grad_flow` as an SVG line chart: one line per parameter group (layer), epochs
+/// across and the mean absolute gradient on a log scale up, so layers whose gradients vanish
+/// sit orders of magnitude below the rest. Zero gradients are left out of the lines.
+#[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+pub fn grad_flow_svg(history: &crate::trainer::History) -> String {
+    const PLOT_W: f64 = 480.0;
+    const PLOT_H: f64 = 300.0;
+    const PLOT_MARGIN: f64 = 40.0;
+    const COLORS: [&str; 6] = ["#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b"];
+
+    let flow = &history.grad_flow;
+    let groups = flow.iter().map(Vec::len).max().unwrap_or(0);
+    let logs: Vec<f64> = flow.iter().flatten().filter(|g| **g > 0.0).map(|g| g.log10()).collect();
+    let lo = logs.iter().cloned().fold(f64::INFINITY, f64::min).floor();
+    let hi = logs.iter().cloned().fold(f64::NEG_INFINITY, f64::max).ceil().max(lo + 1.0);
+    let x = |epoch: usize| PLOT_MARGIN + epoch as f64 * (PLOT_W - 2.0 * PLOT_MARGIN) / flow.len().saturating_sub(1).max(1) as f64;
+    let y = |log: f64| PLOT_H - PLOT_MARGIN - (log - lo) / (hi - lo) * (PLOT_H - 2.0 * PLOT_MARGIN);
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"11\">\
+         <line x1=\"{m}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"#555\"/><line x1=\"{m}\" y1=\"{m}\" x2=\"{m}\" y2=\"{b}\" stroke=\"#555\"/>\
+         <text x=\"{r}\" y=\"{}\" text-anchor=\"end\">epoch</text><text x=\"4\" y=\"{}\">mean |grad|</text>",
+        PLOT_W, PLOT_H, PLOT_H - 8.0, PLOT_MARGIN - 16.0,
+        m = PLOT_MARGIN, b = PLOT_H - PLOT_MARGIN, r = PLOT_W - PLOT_MARGIN
+    );
+    if !logs.is_empty() {
+        for decade in lo as i32..=hi as i32 {
+            write!(svg, "<text x=\"4\" y=\"{:.1}\">1e{}</text>", y(decade as f64) + 4.0, decade).unwrap();
+        }
+    }
+    for group in 0..groups {
+        let color = COLORS[group % COLORS.len()];
+        let points: Vec<String> = flow
+            .iter()
+            .enumerate()
+            .filter_map(|(epoch, row)| row.get(group).filter(|g| **g > 0.0).map(|g| format!("{:.1},{:.1}", x(epoch), y(g.log10()))))
+            .collect();
+        write!(
+            svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\"/><text x=\"{}\" y=\"{}\" fill=\"{}\">layer {}</text>",
+            points.join(" "), color, PLOT_W - PLOT_MARGIN - 56.0, PLOT_MARGIN + 14.0 * group as f64, color, group
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Writes `grad_flow_svg(history)` to `path`.
+#[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+pub fn plot_grad_flow(history: &crate::trainer::History, path: impl AsRef<std::path::Path>) -> io::Result<()> {
+    std::fs::write(path, grad_flow_svg(history))
+}
+
+// Answers requests on `listener`, stopping after `limit` of them if given
+fn serve_on(listener: TcpListener, json: &str, limit: Option<usize>) -> io::Result<()> {
+    for (n, stream) in listener.incoming().enumerate() {
+        // a client hanging up early is its problem, not the server's
+        let _ = respond(stream?, json);
+        if limit.is_some_and(|l| n + 1 >= l) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, json: &str) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = match path {
+        "/" | "/index.html" => ("200 OK", "text/html; charset=utf-8", PAGE),
+        "/graph.json" => ("200 OK", "application/json", json),
+        _ => ("404 Not Found", "text/plain", "not found"),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_page_and_graph() {
+        let x = Value::new(2.0, "x \"in\"");
+        let out = (x.clone() * 3.0).tanh();
+        out.backward();
+        let json = graph_json(&out);
+        assert!(json.starts_with("{\"nodes\":[") && json.contains("\"label\":\"x \\\"in\\\"\""));
+        assert_eq!(json.matches("\"parents\"").count(), 4);
+        assert!(json.contains("\"op\":\"tanh\"") && json.contains("\"parents\":[2]"));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let served = json.clone();
+        let server = std::thread::spawn(move || serve_on(listener, &served, Some(3)));
+
+        assert!(get(port, "/").contains("<svg id=\"view\">"));
+        let graph = get(port, "/graph.json");
+        assert!(graph.starts_with("HTTP/1.1 200 OK") && graph.ends_with(&json));
+        assert!(get(port, "/nope").starts_with("HTTP/1.1 404"));
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(feature = "nn", feature = "optim", feature = "datasets"))]
+    fn grad_flow_chart() {
+        let history = crate::trainer::History {
+            grad_flow: vec![vec![1e-1, 1e-4], vec![5e-2, 0.0], vec![2e-2, 1e-5]],
+            ..Default::default()
+        };
+        let svg = grad_flow_svg(&history);
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        // the zero gradient is dropped from the second line
+        let lines: Vec<&str> = svg.split("points=\"").skip(1).map(|s| s.split('"').next().unwrap()).collect();
+        assert_eq!((lines[0].split(' ').count(), lines[1].split(' ').count()), (3, 2));
+        assert!(svg.contains(">1e-5<") && svg.contains(">1e-1<") && svg.contains(">layer 1<"));
+
+        let path = std::env::temp_dir().join(format!("grad_flow_{}.svg", std::process::id()));
+        plot_grad_flow(&history, &path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), svg);
+        std::fs::remove_file(path).unwrap();
+    }
+}